@@ -1,10 +1,21 @@
+use std::sync::{Arc, RwLock};
+
+use base64::{engine::general_purpose, Engine as _};
 use ehttp::Request;
 use serde::de::DeserializeOwned;
 pub use shared;
+use shared::biscuit::{attenuate, verify_biscuit_info, Attenuation, PublicKey, PublicKeys};
 use shared::{
-    AuthenticationResponse, AuthenticationToken, BiscuitInfo, CreateEmailPasswordData,
-    CreatedUserEmailPasswordData, ItemAmount, ItemId, LoginEmailPasswordData, RefreshToken,
-    RefreshTokenString, UserId, UserItemModify,
+    AppId, AppPolicy, AppServiceAuthenticationResponse, AppServiceAuthenticationToken,
+    AuthenticationResponse, AuthenticationToken, BiscuitInfo, ChangePasswordData,
+    CreateEmailPasswordData, CreateGuestData, CreatedAppCredentials, CurrentSessionData,
+    DetachEmailPasswordData, ForgotPasswordData, GuestLoginData, GuestSignup, GuestSignupResponse,
+    IntrospectTokenData, IntrospectionResponse, ItemAmount, ItemAmountError, ItemBounds, ItemId,
+    ItemTransaction, LedgerPage, LoginAppCredentialsData, LoginEmailPasswordData, LoginMethod,
+    MergeUsersData, OauthCodeData, PublicKeysResponse, RefreshError, RefreshToken,
+    RefreshTokenFamilyId, RefreshTokenString, ResetPasswordData, RevokeTokenData, Session,
+    SessionLabelData, TwoFactorChallenge, TwoFactorCodeData, TwoFactorEnrollment,
+    TwoFactorLoginData, UserId, UserItemModify, UserItemSend, VerifyEmailData,
 };
 use thiserror::Error;
 
@@ -16,6 +27,8 @@ type RequestResult<T> = Result<T, RequestError>;
 #[derive(Clone, Debug)]
 pub struct BackpackClient {
     url: String,
+    /// Server root public keys, fetched again when a biscuit is signed by an unknown key.
+    public_keys: Arc<RwLock<PublicKeys>>,
 }
 
 #[derive(Error, Debug)]
@@ -26,17 +39,64 @@ pub enum RequestError {
     HttpError(String),
     #[error("Error 4xx or 5xx")]
     StatusError { status: u16, bytes: Vec<u8> },
+    /// Rate limited, the request can be retried after the given delay.
+    #[error("Too many requests")]
+    TooManyRequests { retry_after_seconds: Option<u64> },
     #[error("Error due to wrong usage of API.")]
     ClientError(String),
     #[error("No authentication token")]
     NoAuthToken,
+    #[error("Invalid biscuit: {0}")]
+    InvalidBiscuit(String),
+    #[error("Refresh refused: {0:?}")]
+    RefreshError(RefreshError),
+    /// Correct password for an admin with two-factor authentication:
+    /// finish the login with [`BackpackClient::login_two_factor`].
+    #[error("Two-factor authentication required")]
+    TwoFactorRequired(TwoFactorChallenge),
+    /// A change of item amounts was refused, such as an insufficient balance.
+    #[error("Item amount refused: {0:?}")]
+    ItemAmountError(ItemAmountError),
     #[error("other error")]
     Other(String),
 }
 
+/// Optional parameters of [`BackpackClient::modify_item`], all unset by default.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ModifyItemOptions<'a> {
+    /// Recorded in the item ledger, see [`BackpackClient::get_user_history`].
+    pub reason: Option<&'a str>,
+    /// Retrying with the same key, such as a random uuid generated for the modification,
+    /// doesn't apply it twice: see [`UserItemModify::idempotency_key`].
+    pub idempotency_key: Option<&'a str>,
+}
+
+/// Optional parameters of [`BackpackClient::send_item`], all unset by default.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SendItemOptions<'a> {
+    /// Recorded in the item ledger, see [`BackpackClient::get_user_history`].
+    pub reason: Option<&'a str>,
+}
+
+/// Percent-encodes a value to be used in a query string.
+fn encode_query_value(value: &str) -> String {
+    value
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{b:02X}"),
+        })
+        .collect()
+}
+
 impl BackpackClient {
     pub fn new(url: String) -> Self {
-        Self { url }
+        Self {
+            url,
+            public_keys: Default::default(),
+        }
     }
     pub fn get_url(&self) -> &'_ str {
         &self.url
@@ -70,6 +130,14 @@ impl BackpackClient {
         match response {
             Err(error) => Err(RequestError::HttpError(error)),
             Ok(response) => {
+                if response.status == 429 {
+                    return Err(RequestError::TooManyRequests {
+                        retry_after_seconds: response
+                            .headers
+                            .get("Retry-After")
+                            .and_then(|seconds| seconds.parse().ok()),
+                    });
+                }
                 if (400..=599).contains(&response.status) {
                     return Err(RequestError::StatusError {
                         status: response.status,
@@ -82,6 +150,26 @@ impl BackpackClient {
         }
     }
 
+    /// Two-factor challenges are refusals with a body, let callers tell them apart.
+    fn two_factor_challenge(err: RequestError) -> RequestError {
+        if let RequestError::StatusError { status: 401, bytes } = &err {
+            if let Ok(challenge) = serde_json::from_slice(bytes) {
+                return RequestError::TwoFactorRequired(challenge);
+            }
+        }
+        err
+    }
+
+    /// Refused changes of item amounts have a body, let callers tell them apart.
+    fn item_amount_error(err: RequestError) -> RequestError {
+        if let RequestError::StatusError { status: 409, bytes } = &err {
+            if let Ok(item_amount_error) = serde_json::from_slice(bytes) {
+                return RequestError::ItemAmountError(item_amount_error);
+            }
+        }
+        err
+    }
+
     fn parse<T: DeserializeOwned + 'static>(bytes: Vec<u8>) -> RequestResult<T> {
         serde_json::from_slice(&bytes).map_err(|err| err.into())
     }
@@ -90,10 +178,8 @@ impl BackpackClient {
         "Bearer ".to_string() + std::str::from_utf8(biscuit_raw).unwrap_or_default()
     }
 
-    pub async fn signup(
-        &self,
-        data: &CreateEmailPasswordData,
-    ) -> RequestResult<CreatedUserEmailPasswordData> {
+    /// The password is sent by email, the response is the same if the email is already used.
+    pub async fn signup(&self, data: &CreateEmailPasswordData) -> RequestResult<()> {
         match serde_json::to_vec(&data) {
             Err(err) => Err(err.into()),
             Ok(data) => {
@@ -101,7 +187,8 @@ impl BackpackClient {
                     self.url.clone() + "/authentication/email_password/create",
                     data,
                 );
-                Self::parse(Self::make_request(request).await?)
+                Self::make_request(request).await?;
+                Ok(())
             }
         }
     }
@@ -113,12 +200,205 @@ impl BackpackClient {
                     self.url.clone() + "/authentication/email_password/login",
                     data,
                 );
-                let response = Self::make_request(request).await?;
+                let response = Self::make_request(request)
+                    .await
+                    .map_err(Self::two_factor_challenge)?;
                 dbg!("login ok!");
                 self.handle_authentication_response(response).await
             }
         }
     }
+    /// Verifies an email with the code sent by email on signup.
+    pub async fn verify_email(&self, data: &VerifyEmailData) -> RequestResult<()> {
+        let url = format!(
+            "{}/authentication/email_password/verify?email={}&code={}",
+            self.url,
+            encode_query_value(&data.email),
+            encode_query_value(&data.code)
+        );
+        Self::make_request(ehttp::Request::get(url)).await?;
+        Ok(())
+    }
+    /// Sends a new verification code by email, if the email is not verified yet.
+    pub async fn resend_verification(&self, data: &CreateEmailPasswordData) -> RequestResult<()> {
+        match serde_json::to_vec(&data) {
+            Err(err) => Err(err.into()),
+            Ok(data) => {
+                let request = ehttp::Request::post(
+                    self.url.clone() + "/authentication/email_password/resend_verification",
+                    data,
+                );
+                Self::make_request(request).await?;
+                Ok(())
+            }
+        }
+    }
+    /// Sends a single-use password reset token by email, if an account exists for it.
+    pub async fn forgot_password(&self, data: &ForgotPasswordData) -> RequestResult<()> {
+        match serde_json::to_vec(&data) {
+            Err(err) => Err(err.into()),
+            Ok(data) => {
+                let request = ehttp::Request::post(
+                    self.url.clone() + "/authentication/email_password/forgot_password",
+                    data,
+                );
+                Self::make_request(request).await?;
+                Ok(())
+            }
+        }
+    }
+    /// Sets a new password with the token received by email,
+    /// all refresh tokens of the user are revoked.
+    pub async fn reset_password(&self, data: &ResetPasswordData) -> RequestResult<()> {
+        match serde_json::to_vec(&data) {
+            Err(err) => Err(err.into()),
+            Ok(data) => {
+                let request = ehttp::Request::post(
+                    self.url.clone() + "/authentication/email_password/reset_password",
+                    data,
+                );
+                Self::make_request(request).await?;
+                Ok(())
+            }
+        }
+    }
+    /// Finishes a login through a third party identity provider (`"github"`...),
+    /// with the code and the `state` it provided on callback.
+    ///
+    /// `state` is also sent as the `oauth_state` cookie, as a browser redirected
+    /// by `/authorize` would: the server refuses the login if they don't match.
+    pub async fn login_oauth(
+        &self,
+        provider: &str,
+        code: &str,
+        state: &str,
+        as_app_user: Option<AppId>,
+    ) -> RequestResult<AuthenticationToken> {
+        let mut url = format!(
+            "{}/authentication/oauth/{}/callback?code={}&state={}",
+            self.url,
+            provider,
+            encode_query_value(code),
+            encode_query_value(state)
+        );
+        if let Some(app_id) = as_app_user {
+            url += &format!("&as_app_user={}", *app_id);
+        }
+        let request = Request {
+            headers: ehttp::headers(&[("Cookie", &format!("oauth_state={state}"))]),
+            ..ehttp::Request::get(url)
+        };
+        let response = Self::make_request(request)
+            .await
+            .map_err(Self::two_factor_challenge)?;
+        self.handle_authentication_response(response).await
+    }
+    /// Finishes an admin login refused with [`RequestError::TwoFactorRequired`],
+    /// with a code from the authenticator app or a recovery code.
+    pub async fn login_two_factor(
+        &self,
+        data: &TwoFactorLoginData,
+    ) -> RequestResult<AuthenticationToken> {
+        match serde_json::to_vec(&data) {
+            Err(err) => Err(err.into()),
+            Ok(data) => {
+                let request = ehttp::Request::post(
+                    self.url.clone() + "/authentication/two_factor/login",
+                    data,
+                );
+                let response = Self::make_request(request).await?;
+                self.handle_authentication_response(response).await
+            }
+        }
+    }
+    /// Signs up a guest, without email, logged in as a user of the app.
+    ///
+    /// The device secret should be stored to log in again with [`BackpackClient::login_guest`].
+    pub async fn signup_guest(&self, data: &CreateGuestData) -> RequestResult<GuestSignup> {
+        match serde_json::to_vec(&data) {
+            Err(err) => Err(err.into()),
+            Ok(data) => {
+                let request =
+                    ehttp::Request::post(self.url.clone() + "/authentication/guest/create", data);
+                let response: GuestSignupResponse =
+                    Self::parse(Self::make_request(request).await?)?;
+                Ok(GuestSignup {
+                    device_secret: response.device_secret,
+                    authentication_token: self
+                        .authentication_token_from(response.authentication)
+                        .await?,
+                })
+            }
+        }
+    }
+    pub async fn login_guest(&self, data: &GuestLoginData) -> RequestResult<AuthenticationToken> {
+        match serde_json::to_vec(&data) {
+            Err(err) => Err(err.into()),
+            Ok(data) => {
+                let request =
+                    ehttp::Request::post(self.url.clone() + "/authentication/guest/login", data);
+                let response = Self::make_request(request).await?;
+                self.handle_authentication_response(response).await
+            }
+        }
+    }
+    /// Attaches an email/password to a guest, keeping their items: the password is sent by email,
+    /// the device secret can't be used anymore.
+    pub async fn upgrade_guest_email_password(
+        &self,
+        biscuit_raw: &[u8],
+        data: &CreateEmailPasswordData,
+    ) -> RequestResult<()> {
+        match serde_json::to_vec(&data) {
+            Err(err) => Err(err.into()),
+            Ok(data) => {
+                let request = Request {
+                    headers: ehttp::headers(&[(
+                        AUTHORIZATION,
+                        &Self::get_auth_bearer_header(biscuit_raw),
+                    )]),
+                    ..ehttp::Request::post(
+                        self.url.clone() + "/authenticated/guest/upgrade/email_password",
+                        data,
+                    )
+                };
+                Self::make_request(request).await?;
+                Ok(())
+            }
+        }
+    }
+    /// Attaches an identity to a guest, keeping their items, with the code its identity provider
+    /// (`"github"`...) gave on callback: the device secret can't be used anymore.
+    pub async fn upgrade_guest_oauth(
+        &self,
+        biscuit_raw: &[u8],
+        provider: &str,
+        code: &str,
+    ) -> RequestResult<()> {
+        let data = OauthCodeData {
+            code: code.to_string(),
+        };
+        match serde_json::to_vec(&data) {
+            Err(err) => Err(err.into()),
+            Ok(data) => {
+                let request = Request {
+                    headers: ehttp::headers(&[(
+                        AUTHORIZATION,
+                        &Self::get_auth_bearer_header(biscuit_raw),
+                    )]),
+                    ..ehttp::Request::post(
+                        format!(
+                            "{}/authenticated/guest/upgrade/oauth/{}",
+                            self.url, provider
+                        ),
+                        data,
+                    )
+                };
+                Self::make_request(request).await?;
+                Ok(())
+            }
+        }
+    }
     pub async fn refresh(
         &self,
         biscuit_raw: &[u8],
@@ -137,8 +417,40 @@ impl BackpackClient {
                         data,
                     )
                 };
-                self.handle_authentication_response(Self::make_request(request).await?)
-                    .await
+                let response = Self::make_request(request).await.map_err(|err| {
+                    // Concurrent refreshes are expected, let callers tell them apart.
+                    if let RequestError::StatusError { bytes, .. } = &err {
+                        if let Ok(refresh_error) = serde_json::from_slice(bytes) {
+                            return RequestError::RefreshError(refresh_error);
+                        }
+                    }
+                    err
+                })?;
+                self.handle_authentication_response(response).await
+            }
+        }
+    }
+    /// Revokes the session of this refresh token, works with an expired authentication token.
+    pub async fn logout(
+        &self,
+        biscuit_raw: &[u8],
+        refresh_token: &RefreshToken,
+    ) -> RequestResult<()> {
+        let data = CurrentSessionData {
+            refresh_token: refresh_token.refresh_token.clone(),
+        };
+        match serde_json::to_vec(&data) {
+            Err(err) => Err(err.into()),
+            Ok(data) => {
+                let request = Request {
+                    headers: ehttp::headers(&[(
+                        AUTHORIZATION,
+                        &Self::get_auth_bearer_header(biscuit_raw),
+                    )]),
+                    ..ehttp::Request::post(format!("{}/authentication/auth/logout", self.url), data)
+                };
+                Self::make_request(request).await?;
+                Ok(())
             }
         }
     }
@@ -146,13 +458,16 @@ impl BackpackClient {
         &self,
         response: Vec<u8>,
     ) -> Result<AuthenticationToken, RequestError> {
-        let authentication_response: AuthenticationResponse = Self::parse(response)?;
+        self.authentication_token_from(Self::parse(response)?).await
+    }
+    async fn authentication_token_from(
+        &self,
+        authentication_response: AuthenticationResponse,
+    ) -> Result<AuthenticationToken, RequestError> {
         let biscuit_raw = authentication_response.auth_token.as_bytes();
 
         let biscuit_raw_saved = biscuit_raw.to_vec();
-        // FIXME: this whoami call could be avoided by decrypting the biscuit with server public key.
-        // self is cloned because it could be destroyed during the network call.
-        let biscuit_info = self.whoami(biscuit_raw).await;
+        let biscuit_info = self.decode_biscuit(biscuit_raw).await;
         match dbg!(biscuit_info) {
             Err(e) => Err(e),
             Ok(biscuit_info) => Ok(AuthenticationToken {
@@ -163,7 +478,80 @@ impl BackpackClient {
         }
     }
 
-    /// FIXME: this route should be avoidable by decrypting biscuit information with server public key.
+    /// Authenticates a game server with its app credentials.
+    ///
+    /// There is no refresh token: call this again when the token expires.
+    pub async fn login_app_credentials(
+        &self,
+        data: &LoginAppCredentialsData,
+    ) -> RequestResult<AppServiceAuthenticationToken> {
+        match serde_json::to_vec(&data) {
+            Err(err) => Err(err.into()),
+            Ok(data) => {
+                let request = ehttp::Request::post(
+                    self.url.clone() + "/authentication/app_credentials/login",
+                    data,
+                );
+                let authentication_response: AppServiceAuthenticationResponse =
+                    Self::parse(Self::make_request(request).await?)?;
+                let raw_biscuit = authentication_response.auth_token.into_bytes();
+                let biscuit_info = self.decode_biscuit(&raw_biscuit).await?;
+                Ok(AppServiceAuthenticationToken {
+                    raw_biscuit,
+                    biscuit_info,
+                })
+            }
+        }
+    }
+
+    /// Requests the current and retired root public keys of the server.
+    pub async fn get_public_keys(&self) -> RequestResult<PublicKeys> {
+        let request = ehttp::Request::get(self.url.clone() + "/authentication/public_keys");
+        let response: PublicKeysResponse = Self::parse(Self::make_request(request).await?)?;
+        let public_keys = response
+            .public_keys
+            .into_iter()
+            .map(|key| {
+                general_purpose::STANDARD
+                    .decode(key.public_key)
+                    .ok()
+                    .and_then(|bytes| PublicKey::from_bytes(&bytes).ok())
+                    .map(|public_key| (key.id, public_key))
+                    .ok_or_else(|| RequestError::Other("invalid public key".to_string()))
+            })
+            .collect::<RequestResult<_>>()?;
+        let public_keys = PublicKeys(public_keys);
+        *self.public_keys.write().unwrap() = public_keys.clone();
+        Ok(public_keys)
+    }
+    /// Verifies the biscuit with the server public keys and reads its information,
+    /// without asking the server through [`BackpackClient::whoami`].
+    ///
+    /// Public keys are only requested if the biscuit can't be verified with the known ones,
+    /// on first use or after a root key rotation.
+    pub async fn decode_biscuit(&self, biscuit_raw: &[u8]) -> RequestResult<BiscuitInfo> {
+        let known_keys = self.public_keys.read().unwrap().clone();
+        if let Ok(biscuit_info) = verify_biscuit_info(biscuit_raw, &known_keys) {
+            return Ok(biscuit_info);
+        }
+        let public_keys = self.get_public_keys().await?;
+        verify_biscuit_info(biscuit_raw, &public_keys).map_err(RequestError::InvalidBiscuit)
+    }
+    /// Restricts a biscuit offline, to hand it to a game server or a mini-game:
+    /// the attenuated biscuit is only accepted on item routes, within the [`Attenuation`].
+    pub async fn attenuate(
+        &self,
+        biscuit_raw: &[u8],
+        attenuation: &Attenuation,
+    ) -> RequestResult<Vec<u8>> {
+        let known_keys = self.public_keys.read().unwrap().clone();
+        if let Ok(attenuated) = attenuate(biscuit_raw, &known_keys, attenuation) {
+            return Ok(attenuated);
+        }
+        let public_keys = self.get_public_keys().await?;
+        attenuate(biscuit_raw, &public_keys, attenuation).map_err(RequestError::InvalidBiscuit)
+    }
+    /// Asks the server, [`BackpackClient::decode_biscuit`] avoids this network call.
     /// Also, sending auth data could be done via secure http-only cookie.
     pub async fn whoami(&self, biscuit_raw: &[u8]) -> RequestResult<BiscuitInfo> {
         let request = Request {
@@ -172,6 +560,32 @@ impl BackpackClient {
         };
         Self::parse(Self::make_request(request).await?)
     }
+    /// For game servers, with their app service biscuit: decodes a biscuit received from a player,
+    /// telling whether it's still valid and its rights on the app items.
+    pub async fn introspect(
+        &self,
+        biscuit_raw: &[u8],
+        token_raw: &[u8],
+    ) -> RequestResult<IntrospectionResponse> {
+        let data = IntrospectTokenData {
+            token: std::str::from_utf8(token_raw)
+                .unwrap_or_default()
+                .to_string(),
+        };
+        match serde_json::to_vec(&data) {
+            Err(err) => Err(err.into()),
+            Ok(data) => {
+                let request = Request {
+                    headers: ehttp::headers(&[(
+                        AUTHORIZATION,
+                        &Self::get_auth_bearer_header(biscuit_raw),
+                    )]),
+                    ..ehttp::Request::post(self.url.clone() + "/authenticated/introspect", data)
+                };
+                Self::parse(Self::make_request(request).await?)
+            }
+        }
+    }
     pub async fn delete(&self, biscuit_raw: &[u8]) -> RequestResult<()> {
         let request = Request {
             headers: ehttp::headers(&[(AUTHORIZATION, &Self::get_auth_bearer_header(biscuit_raw))]),
@@ -181,15 +595,40 @@ impl BackpackClient {
         Self::make_request(request).await?;
         Ok(())
     }
-
-    pub async fn modify_item(
+    pub async fn change_password(
         &self,
         biscuit_raw: &[u8],
-        item_id: ItemId,
-        amount: i32,
-        user_id: UserId,
-    ) -> RequestResult<i32> {
-        match serde_json::to_vec(&UserItemModify { amount }) {
+        data: &ChangePasswordData,
+    ) -> RequestResult<()> {
+        match serde_json::to_vec(&data) {
+            Err(err) => Err(err.into()),
+            Ok(data) => {
+                let request = Request {
+                    headers: ehttp::headers(&[(
+                        AUTHORIZATION,
+                        &Self::get_auth_bearer_header(biscuit_raw),
+                    )]),
+                    ..ehttp::Request::post(self.url.clone() + "/authenticated/user/password", data)
+                };
+                Self::make_request(request).await?;
+                Ok(())
+            }
+        }
+    }
+    pub async fn get_sessions(&self, biscuit_raw: &[u8]) -> RequestResult<Vec<Session>> {
+        let request = Request {
+            headers: ehttp::headers(&[(AUTHORIZATION, &Self::get_auth_bearer_header(biscuit_raw))]),
+            ..ehttp::Request::get(self.url.clone() + "/authenticated/user/sessions")
+        };
+        Self::parse(Self::make_request(request).await?)
+    }
+    pub async fn set_session_label(
+        &self,
+        biscuit_raw: &[u8],
+        session_id: RefreshTokenFamilyId,
+        label: Option<String>,
+    ) -> RequestResult<()> {
+        match serde_json::to_vec(&SessionLabelData { label }) {
             Err(err) => Err(err.into()),
             Ok(data) => {
                 let request = Request {
@@ -199,45 +638,612 @@ impl BackpackClient {
                     )]),
                     ..ehttp::Request::post(
                         format!(
-                            "{}/authenticated/item/{}/user/{}/modify",
-                            self.url, *item_id, *user_id
+                            "{}/authenticated/user/sessions/{}/label",
+                            self.url, *session_id
                         ),
                         data,
                     )
                 };
-                Self::parse(Self::make_request(request).await?)
+                Self::make_request(request).await?;
+                Ok(())
             }
         }
     }
-
-    pub async fn get_items(
+    pub async fn revoke_session(
         &self,
         biscuit_raw: &[u8],
-        user_id: &UserId,
-    ) -> RequestResult<Vec<ItemAmount>> {
+        session_id: RefreshTokenFamilyId,
+    ) -> RequestResult<()> {
         let request = Request {
             headers: ehttp::headers(&[(AUTHORIZATION, &Self::get_auth_bearer_header(biscuit_raw))]),
+            method: "DELETE".to_owned(),
             ..ehttp::Request::get(format!(
-                "{}/authenticated/item/user/{}",
-                self.url, user_id.0
+                "{}/authenticated/user/sessions/{}",
+                self.url, *session_id
             ))
         };
-        Self::parse(Self::make_request(request).await?)
+        Self::make_request(request).await?;
+        Ok(())
     }
-
-    pub async fn get_item(
+    /// Revokes all sessions, except the one of this refresh token.
+    pub async fn revoke_other_sessions(
         &self,
         biscuit_raw: &[u8],
-        user_id: &UserId,
-        item_id: ItemId,
-    ) -> RequestResult<ItemAmount> {
+        refresh_token: &RefreshToken,
+    ) -> RequestResult<()> {
+        let data = CurrentSessionData {
+            refresh_token: refresh_token.refresh_token.clone(),
+        };
+        match serde_json::to_vec(&data) {
+            Err(err) => Err(err.into()),
+            Ok(data) => {
+                let request = Request {
+                    headers: ehttp::headers(&[(
+                        AUTHORIZATION,
+                        &Self::get_auth_bearer_header(biscuit_raw),
+                    )]),
+                    ..ehttp::Request::post(
+                        self.url.clone() + "/authenticated/user/sessions/revoke_others",
+                        data,
+                    )
+                };
+                Self::make_request(request).await?;
+                Ok(())
+            }
+        }
+    }
+    pub async fn get_login_methods(&self, biscuit_raw: &[u8]) -> RequestResult<Vec<LoginMethod>> {
         let request = Request {
             headers: ehttp::headers(&[(AUTHORIZATION, &Self::get_auth_bearer_header(biscuit_raw))]),
-            ..ehttp::Request::get(format!(
-                "{}/authenticated/item/{}/user/{}",
-                self.url, user_id.0, *item_id
-            ))
+            ..ehttp::Request::get(self.url.clone() + "/authenticated/user/identities")
         };
         Self::parse(Self::make_request(request).await?)
     }
+    /// Attaches an email/password to the user: the password is sent by email.
+    pub async fn attach_email_password(
+        &self,
+        biscuit_raw: &[u8],
+        data: &CreateEmailPasswordData,
+    ) -> RequestResult<()> {
+        match serde_json::to_vec(&data) {
+            Err(err) => Err(err.into()),
+            Ok(data) => {
+                let request = Request {
+                    headers: ehttp::headers(&[(
+                        AUTHORIZATION,
+                        &Self::get_auth_bearer_header(biscuit_raw),
+                    )]),
+                    ..ehttp::Request::post(
+                        self.url.clone() + "/authenticated/user/identities/email_password",
+                        data,
+                    )
+                };
+                Self::make_request(request).await?;
+                Ok(())
+            }
+        }
+    }
+    /// Fails if this is the last login method of the user.
+    pub async fn detach_email_password(
+        &self,
+        biscuit_raw: &[u8],
+        email: &str,
+    ) -> RequestResult<()> {
+        let data = DetachEmailPasswordData {
+            email: email.to_string(),
+        };
+        match serde_json::to_vec(&data) {
+            Err(err) => Err(err.into()),
+            Ok(data) => {
+                let request = Request {
+                    headers: ehttp::headers(&[(
+                        AUTHORIZATION,
+                        &Self::get_auth_bearer_header(biscuit_raw),
+                    )]),
+                    method: "DELETE".to_owned(),
+                    ..ehttp::Request::post(
+                        self.url.clone() + "/authenticated/user/identities/email_password",
+                        data,
+                    )
+                };
+                Self::make_request(request).await?;
+                Ok(())
+            }
+        }
+    }
+    /// Attaches an identity to the user, with the code its identity provider (`"github"`...)
+    /// gave on callback.
+    pub async fn attach_oauth(
+        &self,
+        biscuit_raw: &[u8],
+        provider: &str,
+        code: &str,
+    ) -> RequestResult<()> {
+        let data = OauthCodeData {
+            code: code.to_string(),
+        };
+        match serde_json::to_vec(&data) {
+            Err(err) => Err(err.into()),
+            Ok(data) => {
+                let request = Request {
+                    headers: ehttp::headers(&[(
+                        AUTHORIZATION,
+                        &Self::get_auth_bearer_header(biscuit_raw),
+                    )]),
+                    ..ehttp::Request::post(
+                        format!(
+                            "{}/authenticated/user/identities/oauth/{}",
+                            self.url, provider
+                        ),
+                        data,
+                    )
+                };
+                Self::make_request(request).await?;
+                Ok(())
+            }
+        }
+    }
+    /// Fails if this is the last login method of the user.
+    pub async fn detach_oauth(
+        &self,
+        biscuit_raw: &[u8],
+        provider: &str,
+        external_id: &str,
+    ) -> RequestResult<()> {
+        let request = Request {
+            headers: ehttp::headers(&[(AUTHORIZATION, &Self::get_auth_bearer_header(biscuit_raw))]),
+            method: "DELETE".to_owned(),
+            ..ehttp::Request::get(format!(
+                "{}/authenticated/user/identities/oauth/{}/{}",
+                self.url, provider, external_id
+            ))
+        };
+        Self::make_request(request).await?;
+        Ok(())
+    }
+    /// Merges the user of `other_biscuit_raw` into the user of `biscuit_raw`: items, app admin
+    /// rights, login methods and sessions are moved, then the other user is deleted.
+    ///
+    /// Refused with [`RequestError::ItemAmountError`] if merged amounts would get out of their item bounds.
+    pub async fn merge_users(
+        &self,
+        biscuit_raw: &[u8],
+        other_biscuit_raw: &[u8],
+    ) -> RequestResult<()> {
+        let data = MergeUsersData {
+            other_auth_token: std::str::from_utf8(other_biscuit_raw)
+                .unwrap_or_default()
+                .to_string(),
+        };
+        match serde_json::to_vec(&data) {
+            Err(err) => Err(err.into()),
+            Ok(data) => {
+                let request = Request {
+                    headers: ehttp::headers(&[(
+                        AUTHORIZATION,
+                        &Self::get_auth_bearer_header(biscuit_raw),
+                    )]),
+                    ..ehttp::Request::post(self.url.clone() + "/authenticated/user/merge", data)
+                };
+                Self::make_request(request)
+                    .await
+                    .map_err(Self::item_amount_error)?;
+                Ok(())
+            }
+        }
+    }
+    /// Revokes `token_raw`, a biscuit of the same user, and the biscuits attenuated from it.
+    pub async fn revoke_token(&self, biscuit_raw: &[u8], token_raw: &[u8]) -> RequestResult<()> {
+        self.post_revoke_token(biscuit_raw, "/authenticated/user/revoke_token", token_raw)
+            .await
+    }
+    /// Revokes all biscuits of the user, including `biscuit_raw`; sessions can still be refreshed.
+    pub async fn revoke_all_tokens(&self, biscuit_raw: &[u8]) -> RequestResult<()> {
+        let request = Request {
+            headers: ehttp::headers(&[(AUTHORIZATION, &Self::get_auth_bearer_header(biscuit_raw))]),
+            ..ehttp::Request::post(
+                self.url.clone() + "/authenticated/user/revoke_tokens",
+                vec![],
+            )
+        };
+        Self::make_request(request).await?;
+        Ok(())
+    }
+    async fn post_revoke_token(
+        &self,
+        biscuit_raw: &[u8],
+        route: &str,
+        token_raw: &[u8],
+    ) -> RequestResult<()> {
+        let data = RevokeTokenData {
+            token: std::str::from_utf8(token_raw)
+                .unwrap_or_default()
+                .to_string(),
+        };
+        match serde_json::to_vec(&data) {
+            Err(err) => Err(err.into()),
+            Ok(data) => {
+                let request = Request {
+                    headers: ehttp::headers(&[(
+                        AUTHORIZATION,
+                        &Self::get_auth_bearer_header(biscuit_raw),
+                    )]),
+                    ..ehttp::Request::post(self.url.clone() + route, data)
+                };
+                Self::make_request(request).await?;
+                Ok(())
+            }
+        }
+    }
+
+    /// See [`ModifyItemOptions`] for the reason and the idempotency key of the modification.
+    pub async fn modify_item(
+        &self,
+        biscuit_raw: &[u8],
+        item_id: ItemId,
+        amount: i32,
+        user_id: UserId,
+        options: ModifyItemOptions<'_>,
+    ) -> RequestResult<i32> {
+        match serde_json::to_vec(&UserItemModify {
+            amount,
+            reason: options.reason.map(str::to_owned),
+            idempotency_key: options.idempotency_key.map(str::to_owned),
+        }) {
+            Err(err) => Err(err.into()),
+            Ok(data) => {
+                let request = Request {
+                    headers: ehttp::headers(&[(
+                        AUTHORIZATION,
+                        &Self::get_auth_bearer_header(biscuit_raw),
+                    )]),
+                    ..ehttp::Request::post(
+                        format!(
+                            "{}/authenticated/item/{}/user/{}/modify",
+                            self.url, *item_id, *user_id
+                        ),
+                        data,
+                    )
+                };
+                Self::parse(
+                    Self::make_request(request)
+                        .await
+                        .map_err(Self::item_amount_error)?,
+                )
+            }
+        }
+    }
+    /// Sends `amount` of the item from `user_id` to `user_to_send_to`,
+    /// returns the new amount of `user_id`.
+    pub async fn send_item(
+        &self,
+        biscuit_raw: &[u8],
+        item_id: ItemId,
+        amount: i32,
+        user_id: UserId,
+        user_to_send_to: UserId,
+        options: SendItemOptions<'_>,
+    ) -> RequestResult<i32> {
+        match serde_json::to_vec(&UserItemSend {
+            amount,
+            user_to_send_to,
+            reason: options.reason.map(str::to_owned),
+        }) {
+            Err(err) => Err(err.into()),
+            Ok(data) => {
+                let request = Request {
+                    headers: ehttp::headers(&[(
+                        AUTHORIZATION,
+                        &Self::get_auth_bearer_header(biscuit_raw),
+                    )]),
+                    ..ehttp::Request::post(
+                        format!(
+                            "{}/authenticated/item/{}/user/{}/send_item",
+                            self.url, *item_id, *user_id
+                        ),
+                        data,
+                    )
+                };
+                Self::parse(
+                    Self::make_request(request)
+                        .await
+                        .map_err(Self::item_amount_error)?,
+                )
+            }
+        }
+    }
+
+    /// Applies all the operations of the transaction or none of them,
+    /// returns the new amounts in the order of the operations.
+    pub async fn apply_item_transaction(
+        &self,
+        biscuit_raw: &[u8],
+        transaction: &ItemTransaction,
+    ) -> RequestResult<Vec<i32>> {
+        match serde_json::to_vec(transaction) {
+            Err(err) => Err(err.into()),
+            Ok(data) => {
+                let request = Request {
+                    headers: ehttp::headers(&[(
+                        AUTHORIZATION,
+                        &Self::get_auth_bearer_header(biscuit_raw),
+                    )]),
+                    ..ehttp::Request::post(
+                        format!("{}/authenticated/item/transaction", self.url),
+                        data,
+                    )
+                };
+                Self::parse(
+                    Self::make_request(request)
+                        .await
+                        .map_err(Self::item_amount_error)?,
+                )
+            }
+        }
+    }
+
+    pub async fn get_items(
+        &self,
+        biscuit_raw: &[u8],
+        user_id: &UserId,
+    ) -> RequestResult<Vec<ItemAmount>> {
+        let request = Request {
+            headers: ehttp::headers(&[(AUTHORIZATION, &Self::get_auth_bearer_header(biscuit_raw))]),
+            ..ehttp::Request::get(format!(
+                "{}/authenticated/item/user/{}",
+                self.url, user_id.0
+            ))
+        };
+        Self::parse(Self::make_request(request).await?)
+    }
+
+    pub async fn get_item(
+        &self,
+        biscuit_raw: &[u8],
+        user_id: &UserId,
+        item_id: ItemId,
+    ) -> RequestResult<ItemAmount> {
+        let request = Request {
+            headers: ehttp::headers(&[(AUTHORIZATION, &Self::get_auth_bearer_header(biscuit_raw))]),
+            ..ehttp::Request::get(format!(
+                "{}/authenticated/item/{}/user/{}",
+                self.url, user_id.0, *item_id
+            ))
+        };
+        Self::parse(Self::make_request(request).await?)
+    }
+
+    /// Item ledger entries of the user, most recent first.
+    ///
+    /// Pass the [`LedgerPage::next_before`] of a page as `before` to get the next one.
+    pub async fn get_user_history(
+        &self,
+        biscuit_raw: &[u8],
+        user_id: &UserId,
+        before: Option<i64>,
+        limit: Option<i64>,
+    ) -> RequestResult<LedgerPage> {
+        let request = Request {
+            headers: ehttp::headers(&[(AUTHORIZATION, &Self::get_auth_bearer_header(biscuit_raw))]),
+            ..ehttp::Request::get(format!(
+                "{}/authenticated/item/user/{}/history{}",
+                self.url,
+                user_id.0,
+                Self::history_query(before, limit)
+            ))
+        };
+        Self::parse(Self::make_request(request).await?)
+    }
+
+    /// Item ledger entries of the item, most recent first, only those of the user for user biscuits.
+    pub async fn get_item_history(
+        &self,
+        biscuit_raw: &[u8],
+        item_id: ItemId,
+        before: Option<i64>,
+        limit: Option<i64>,
+    ) -> RequestResult<LedgerPage> {
+        let request = Request {
+            headers: ehttp::headers(&[(AUTHORIZATION, &Self::get_auth_bearer_header(biscuit_raw))]),
+            ..ehttp::Request::get(format!(
+                "{}/authenticated/item/{}/history{}",
+                self.url,
+                *item_id,
+                Self::history_query(before, limit)
+            ))
+        };
+        Self::parse(Self::make_request(request).await?)
+    }
+
+    fn history_query(before: Option<i64>, limit: Option<i64>) -> String {
+        let parameters: Vec<String> = [("before", before), ("limit", limit)]
+            .into_iter()
+            .filter_map(|(name, value)| Some(format!("{}={}", name, value?)))
+            .collect();
+        if parameters.is_empty() {
+            String::new()
+        } else {
+            format!("?{}", parameters.join("&"))
+        }
+    }
+
+    /// The secret and recovery codes are not retrievable later,
+    /// two-factor authentication is enabled by [`BackpackClient::confirm_two_factor`].
+    pub async fn enroll_two_factor(
+        &self,
+        biscuit_raw: &[u8],
+    ) -> RequestResult<TwoFactorEnrollment> {
+        let request = Request {
+            headers: ehttp::headers(&[(AUTHORIZATION, &Self::get_auth_bearer_header(biscuit_raw))]),
+            ..ehttp::Request::post(format!("{}/admin/two_factor/enroll", self.url), vec![])
+        };
+        Self::parse(Self::make_request(request).await?)
+    }
+    /// Enables two-factor authentication with a first code from the authenticator app.
+    pub async fn confirm_two_factor(&self, biscuit_raw: &[u8], code: &str) -> RequestResult<()> {
+        self.post_two_factor_code(biscuit_raw, "confirm", code)
+            .await
+    }
+    /// Needs a biscuit from [`BackpackClient::login_two_factor`].
+    pub async fn disable_two_factor(&self, biscuit_raw: &[u8], code: &str) -> RequestResult<()> {
+        self.post_two_factor_code(biscuit_raw, "disable", code)
+            .await
+    }
+    async fn post_two_factor_code(
+        &self,
+        biscuit_raw: &[u8],
+        route: &str,
+        code: &str,
+    ) -> RequestResult<()> {
+        let data = TwoFactorCodeData {
+            code: code.to_string(),
+        };
+        match serde_json::to_vec(&data) {
+            Err(err) => Err(err.into()),
+            Ok(data) => {
+                let request = Request {
+                    headers: ehttp::headers(&[(
+                        AUTHORIZATION,
+                        &Self::get_auth_bearer_header(biscuit_raw),
+                    )]),
+                    ..ehttp::Request::post(format!("{}/admin/two_factor/{}", self.url, route), data)
+                };
+                Self::make_request(request).await?;
+                Ok(())
+            }
+        }
+    }
+    /// Revokes a biscuit of the admin, or of a user or app service of an app they administer.
+    pub async fn admin_revoke_token(
+        &self,
+        biscuit_raw: &[u8],
+        token_raw: &[u8],
+    ) -> RequestResult<()> {
+        self.post_revoke_token(biscuit_raw, "/admin/token/revoke", token_raw)
+            .await
+    }
+
+    pub async fn create_app(&self, biscuit_raw: &[u8], name: &str) -> RequestResult<AppId> {
+        match serde_json::to_vec(&serde_json::json!({ "name": name })) {
+            Err(err) => Err(err.into()),
+            Ok(data) => {
+                let request = Request {
+                    headers: ehttp::headers(&[(
+                        AUTHORIZATION,
+                        &Self::get_auth_bearer_header(biscuit_raw),
+                    )]),
+                    ..ehttp::Request::post(format!("{}/admin/app", self.url), data)
+                };
+                Self::parse(Self::make_request(request).await?)
+            }
+        }
+    }
+
+    pub async fn create_item(
+        &self,
+        biscuit_raw: &[u8],
+        app_id: AppId,
+        name: &str,
+    ) -> RequestResult<ItemId> {
+        match serde_json::to_vec(&serde_json::json!({ "name": name })) {
+            Err(err) => Err(err.into()),
+            Ok(data) => {
+                let request = Request {
+                    headers: ehttp::headers(&[(
+                        AUTHORIZATION,
+                        &Self::get_auth_bearer_header(biscuit_raw),
+                    )]),
+                    ..ehttp::Request::post(format!("{}/admin/item/app/{}", self.url, *app_id), data)
+                };
+                Self::parse(Self::make_request(request).await?)
+            }
+        }
+    }
+
+    /// The returned secret is not retrievable later, store it safely on your game server.
+    pub async fn create_app_credentials(
+        &self,
+        biscuit_raw: &[u8],
+        app_id: AppId,
+    ) -> RequestResult<CreatedAppCredentials> {
+        let request = Request {
+            headers: ehttp::headers(&[(AUTHORIZATION, &Self::get_auth_bearer_header(biscuit_raw))]),
+            ..ehttp::Request::post(
+                format!("{}/admin/app/{}/credentials", self.url, *app_id),
+                vec![],
+            )
+        };
+        Self::parse(Self::make_request(request).await?)
+    }
+
+    /// Biscuits already issued with these credentials are revoked too.
+    pub async fn revoke_app_credentials(
+        &self,
+        biscuit_raw: &[u8],
+        credentials: &CreatedAppCredentials,
+    ) -> RequestResult<()> {
+        let request = Request {
+            headers: ehttp::headers(&[(AUTHORIZATION, &Self::get_auth_bearer_header(biscuit_raw))]),
+            method: "DELETE".to_owned(),
+            ..ehttp::Request::get(format!(
+                "{}/admin/app/{}/credentials/{}",
+                self.url, *credentials.app_id, *credentials.id
+            ))
+        };
+        Self::make_request(request).await?;
+        Ok(())
+    }
+
+    pub async fn set_app_policy(
+        &self,
+        biscuit_raw: &[u8],
+        app_id: AppId,
+        policy: &AppPolicy,
+    ) -> RequestResult<()> {
+        match serde_json::to_vec(&policy) {
+            Err(err) => Err(err.into()),
+            Ok(data) => {
+                let request = Request {
+                    headers: ehttp::headers(&[(
+                        AUTHORIZATION,
+                        &Self::get_auth_bearer_header(biscuit_raw),
+                    )]),
+                    method: "PUT".to_owned(),
+                    ..ehttp::Request::post(
+                        format!("{}/admin/app/{}/policy", self.url, *app_id),
+                        data,
+                    )
+                };
+                Self::make_request(request).await?;
+                Ok(())
+            }
+        }
+    }
+
+    /// Changes are then refused with an [`ItemAmountError`] when out of these bounds.
+    pub async fn set_item_bounds(
+        &self,
+        biscuit_raw: &[u8],
+        item_id: ItemId,
+        bounds: &ItemBounds,
+    ) -> RequestResult<()> {
+        match serde_json::to_vec(&bounds) {
+            Err(err) => Err(err.into()),
+            Ok(data) => {
+                let request = Request {
+                    headers: ehttp::headers(&[(
+                        AUTHORIZATION,
+                        &Self::get_auth_bearer_header(biscuit_raw),
+                    )]),
+                    method: "PUT".to_owned(),
+                    ..ehttp::Request::post(
+                        format!("{}/admin/item/{}/bounds", self.url, *item_id),
+                        data,
+                    )
+                };
+                Self::make_request(request).await?;
+                Ok(())
+            }
+        }
+    }
 }
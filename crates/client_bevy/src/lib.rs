@@ -10,9 +10,10 @@ use std::{
 };
 
 // Internal
+use backpack_client::ModifyItemOptions;
 use shared::{
-    AuthenticationToken, CreateEmailPasswordData, ItemAmount, ItemId, LoginEmailPasswordData, User,
-    UserId,
+    AuthenticationToken, CreateEmailPasswordData, CreateGuestData, GuestLoginData, GuestSignup,
+    ItemAmount, ItemId, ItemTransaction, LoginEmailPasswordData, User, UserId,
 };
 
 pub struct BackpackClientPlugin;
@@ -24,10 +25,16 @@ impl Plugin for BackpackClientPlugin {
         app.add_systems(Update, handle_login_tasks);
         app.add_event::<SignupTaskResultEvent>();
         app.add_systems(Update, handle_signup_tasks);
+        app.add_event::<GuestSignupTaskResultEvent>();
+        app.add_systems(Update, handle_guest_signup_tasks);
         app.add_event::<GetItemsTaskResultEvent>();
         app.add_systems(Update, handle_get_items_tasks);
         app.add_event::<ModifyItemTaskResultEvent>();
         app.add_systems(Update, handle_modify_item_tasks);
+        app.add_event::<ItemTransactionTaskResultEvent>();
+        app.add_systems(Update, handle_item_transaction_tasks);
+        app.add_event::<LogoutTaskResultEvent>();
+        app.add_systems(Update, handle_logout_tasks);
         app.add_systems(PostUpdate, read_new_refresh_token_and_swap_it);
     }
 }
@@ -73,7 +80,8 @@ impl<T> Default for ClientTask<T> {
 }
 
 fn read_new_refresh_token_and_swap_it(mut authentication: ResMut<BackpackClientAuthRefresh>) {
-    let Some(mut pending_refresh_token) = authentication.pending_refreshed_auth_token.try_lock() else {
+    let Some(mut pending_refresh_token) = authentication.pending_refreshed_auth_token.try_lock()
+    else {
         return;
     };
     if let Some(new_token) = pending_refresh_token.take() {
@@ -188,9 +196,61 @@ fn handle_login_tasks(
     }
 }
 #[derive(Component, Default)]
-pub struct SignupTask(ClientTask<shared::CreatedUserEmailPasswordData>);
+pub struct LogoutTask(ClientTask<()>);
 #[derive(Debug, Event)]
-pub struct SignupTaskResultEvent(pub Result<shared::CreatedUserEmailPasswordData, RequestError>);
+pub struct LogoutTaskResultEvent(pub Result<(), RequestError>);
+
+/// Revokes the current session on the server, and forgets the authentication token.
+pub fn bevy_logout(
+    commands: &mut Commands,
+    client: &BackpackClient,
+    authentication: &mut BackpackClientAuthRefresh,
+) -> Result<(), RequestError> {
+    let Some(current_authentication_token) = authentication.current_authentication_token.take()
+    else {
+        return Err(RequestError::NoAuthToken);
+    };
+    let thread_pool = IoTaskPool::get();
+    let client = client.clone();
+    let task = LogoutTask::default();
+    let fill_result_rwlock = task.0.result.clone();
+    thread_pool
+        .spawn(async move {
+            let response = client
+                .logout(
+                    &current_authentication_token.raw_biscuit,
+                    &current_authentication_token.refresh_token,
+                )
+                .await;
+            *fill_result_rwlock.write().unwrap() = Some(response);
+        })
+        .detach();
+    commands.spawn(task);
+    Ok(())
+}
+fn handle_logout_tasks(
+    mut commands: Commands,
+    mut tasks: Query<(Entity, &LogoutTask)>,
+    mut result_event: EventWriter<LogoutTaskResultEvent>,
+) {
+    for (entity, task) in &mut tasks {
+        let Ok(mut guard) = task.0.result.try_write() else {
+            continue;
+        };
+        if guard.as_ref().is_none() {
+            continue;
+        }
+        if let Some(received) = guard.take().take() {
+            result_event.send(LogoutTaskResultEvent(received));
+            // Task is complete, so remove task component from entity
+            commands.entity(entity).remove::<LogoutTask>();
+        }
+    }
+}
+#[derive(Component, Default)]
+pub struct SignupTask(ClientTask<()>);
+#[derive(Debug, Event)]
+pub struct SignupTaskResultEvent(pub Result<(), RequestError>);
 
 pub fn bevy_signup(
     commands: &mut Commands,
@@ -231,6 +291,85 @@ fn handle_signup_tasks(
     }
 }
 
+#[derive(Component, Default)]
+pub struct GuestSignupTask(ClientTask<GuestSignup>);
+#[derive(Debug, Event)]
+pub struct GuestSignupTaskResultEvent(pub Result<GuestSignup, RequestError>);
+
+/// Signs up a guest for the app, and logs them in: the device secret from the result event
+/// should be stored to log in again with [`bevy_login_guest`].
+pub fn bevy_signup_guest(
+    commands: &mut Commands,
+    client: &BackpackClient,
+    authentication: &BackpackClientAuthRefresh,
+    data: CreateGuestData,
+) {
+    let thread_pool = IoTaskPool::get();
+    let task = GuestSignupTask::default();
+    let fill_result_rwlock = task.0.result.clone();
+    let client = client.clone();
+
+    let mutex_to_update_auth_token = authentication.pending_refreshed_auth_token.clone();
+    thread_pool
+        .spawn(async move {
+            let response = client.signup_guest(&data).await;
+            if let Ok(guest_signup) = &response {
+                let mut auth_token_update = mutex_to_update_auth_token.lock().await;
+                *auth_token_update = Some(guest_signup.authentication_token.clone());
+            }
+
+            *fill_result_rwlock.write().unwrap() = Some(response);
+        })
+        .detach();
+    commands.spawn(task);
+}
+fn handle_guest_signup_tasks(
+    mut commands: Commands,
+    mut tasks: Query<(Entity, &GuestSignupTask)>,
+    mut result_event: EventWriter<GuestSignupTaskResultEvent>,
+) {
+    for (entity, task) in &mut tasks {
+        let Ok(mut guard) = task.0.result.try_write() else {
+            continue;
+        };
+        if guard.as_ref().is_none() {
+            continue;
+        }
+        if let Some(received) = guard.take().take() {
+            result_event.send(GuestSignupTaskResultEvent(received));
+            // Task is complete, so remove task component from entity
+            commands.entity(entity).remove::<GuestSignupTask>();
+        }
+    }
+}
+
+/// Logs a guest in with their device secret, the result is sent as a [`LoginTaskResultEvent`].
+pub fn bevy_login_guest(
+    commands: &mut Commands,
+    client: &BackpackClient,
+    authentication: &BackpackClientAuthRefresh,
+    data: GuestLoginData,
+) {
+    let thread_pool = IoTaskPool::get();
+    let task = LoginTask::default();
+    let fill_result_rwlock = task.0.result.clone();
+    let client = client.clone();
+
+    let mutex_to_update_auth_token = authentication.pending_refreshed_auth_token.clone();
+    thread_pool
+        .spawn(async move {
+            let response = client.login_guest(&data).await;
+            if let Ok(new_token) = &response {
+                let mut auth_token_update = mutex_to_update_auth_token.lock().await;
+                *auth_token_update = Some(new_token.clone());
+            }
+
+            *fill_result_rwlock.write().unwrap() = Some(response);
+        })
+        .detach();
+    commands.spawn(task);
+}
+
 #[derive(Component, Default)]
 pub struct GetItemsTask(ClientTask<Vec<ItemAmount>>);
 #[derive(Debug, Event)]
@@ -243,7 +382,8 @@ pub fn bevy_get_items(
     authentication: &BackpackClientAuthRefresh,
     user_id: &UserId,
 ) -> Result<(), RequestError> {
-    let Some(current_authentication_token) = authentication.current_authentication_token.clone() else {
+    let Some(current_authentication_token) = authentication.current_authentication_token.clone()
+    else {
         return Err(RequestError::NoAuthToken);
     };
     let thread_pool = IoTaskPool::get();
@@ -311,6 +451,7 @@ fn handle_get_items_tasks(
 
 #[derive(Component, Default)]
 pub struct ModifyItemTask(ClientTask<(ItemId, UserId, i32)>);
+/// Refused amounts, such as an insufficient balance, are a [`RequestError::ItemAmountError`].
 #[derive(Debug, Event)]
 pub struct ModifyItemTaskResultEvent(pub Result<(ItemId, UserId, i32), RequestError>);
 
@@ -323,7 +464,8 @@ pub fn bevy_modify_item(
     amount: i32,
     user_id: &UserId,
 ) -> Result<(), RequestError> {
-    let Some(current_authentication_token) = authentication.current_authentication_token.clone() else {
+    let Some(current_authentication_token) = authentication.current_authentication_token.clone()
+    else {
         return Err(RequestError::NoAuthToken);
     };
     let thread_pool = IoTaskPool::get();
@@ -347,7 +489,13 @@ pub fn bevy_modify_item(
                 amount: i32,
             ) -> Result<(ItemId, UserId, i32), RequestError> {
                 client
-                    .modify_item(&authentication_token.raw_biscuit, data.0, amount, data.1)
+                    .modify_item(
+                        &authentication_token.raw_biscuit,
+                        data.0,
+                        amount,
+                        data.1,
+                        ModifyItemOptions::default(),
+                    )
                     .await
                     .map(|r| (data.0, data.1, r))
             }
@@ -391,6 +539,78 @@ fn handle_modify_item_tasks(
     }
 }
 
+#[derive(Component, Default)]
+pub struct ItemTransactionTask(ClientTask<Vec<i32>>);
+/// New amounts in the order of the operations, none of them is applied on error.
+#[derive(Debug, Event)]
+pub struct ItemTransactionTaskResultEvent(pub Result<Vec<i32>, RequestError>);
+
+pub fn bevy_item_transaction(
+    commands: &mut Commands,
+    time: &Time,
+    client: &BackpackClient,
+    authentication: &BackpackClientAuthRefresh,
+    transaction: ItemTransaction,
+) -> Result<(), RequestError> {
+    let Some(current_authentication_token) = authentication.current_authentication_token.clone()
+    else {
+        return Err(RequestError::NoAuthToken);
+    };
+    let thread_pool = IoTaskPool::get();
+    let client = client.clone();
+    let task = ItemTransactionTask::default();
+    let fill_result_rwlock = task.0.result.clone();
+
+    // TODO: #22 fix wasm
+    let unix_now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs() as i64;
+    let mutex_to_update_auth_token = authentication.pending_refreshed_auth_token.clone();
+    thread_pool
+        .spawn(async move {
+            let current_authentication_token = check_refresh_and_report_token(
+                unix_now,
+                &client,
+                &current_authentication_token,
+                &mutex_to_update_auth_token,
+            )
+            .await
+            .map(|auth| auth.unwrap_or(current_authentication_token));
+            *fill_result_rwlock.write().unwrap() = Some(match current_authentication_token {
+                Err(err) => Err(err),
+                Ok(authentication_token) => {
+                    client
+                        .apply_item_transaction(&authentication_token.raw_biscuit, &transaction)
+                        .await
+                }
+            });
+        })
+        .detach();
+    commands.spawn(task);
+    Ok(())
+}
+
+fn handle_item_transaction_tasks(
+    mut commands: Commands,
+    mut tasks: Query<(Entity, &mut ItemTransactionTask)>,
+    mut result_event: EventWriter<ItemTransactionTaskResultEvent>,
+) {
+    for (entity, task) in &mut tasks {
+        let Ok(mut guard) = task.0.result.try_write() else {
+            continue;
+        };
+        if guard.as_ref().is_none() {
+            continue;
+        }
+        if let Some(received) = guard.take().take() {
+            result_event.send(ItemTransactionTaskResultEvent(received));
+            // Task is complete, so remove task component from entity
+            commands.entity(entity).remove::<ItemTransactionTask>();
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
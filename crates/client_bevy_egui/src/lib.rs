@@ -9,7 +9,8 @@ use bevy_pkv::*;
 
 pub use backpack_client_bevy::*;
 use shared::{
-    AppId, AuthenticationToken, CreateEmailPasswordData, ItemAmount, LoginEmailPasswordData, Role,
+    AppId, AuthenticationToken, CreateEmailPasswordData, CreateGuestData, GuestLoginData,
+    ItemAmount, LoginEmailPasswordData, Role,
 };
 
 pub struct AuthPlugin {
@@ -85,6 +86,7 @@ impl Plugin for AuthPlugin {
             handle_authentication_change.run_if(resource_changed::<BackpackClientAuthRefresh>()),
         );
         app.add_systems(Update, handle_signup_result);
+        app.add_systems(Update, handle_guest_signup_result);
         app.add_state::<PopupSignupSuccess>();
         app.add_systems(
             Update,
@@ -120,14 +122,17 @@ fn ui_auth(
     mut authentication: ResMut<BackpackClientAuthRefresh>,
     backpack_role: Res<BackpackRole>,
     backpack: Res<BackpackCom>,
+    pkv: Res<PkvStore>,
     login_task: Query<Entity, With<LoginTask>>,
     signup_task: Query<Entity, With<SignupTask>>,
+    guest_signup_task: Query<Entity, With<GuestSignupTask>>,
 ) {
     egui::Window::new("Auth").show(ctxs.ctx_mut(), |ui| {
         //ui.label(format!("current role: {:?}", auth_data));
         if authentication.current_authentication_token.is_some() {
             if ui.button("Disconnect").clicked() {
-                authentication.set(None);
+                // Revokes the session server side too, the token is forgotten either way.
+                let _ = bevy_logout(&mut commands, &backpack.client, &mut authentication);
             }
             return;
         }
@@ -176,9 +181,51 @@ fn ui_auth(
                 },
             );
         }
+        // Guests can only play as users of an app.
+        let Role::User(app_id) = backpack_role.0 else {
+            return;
+        };
+        if !login_task.is_empty() || !guest_signup_task.is_empty() {
+            return;
+        }
+        ui.separator();
+        if let Ok(device_secret) = pkv.get::<String>(GUEST_DEVICE_SECRET_KEY) {
+            if ui.button("Continue as guest").clicked() {
+                bevy_login_guest(
+                    &mut commands,
+                    &backpack.client,
+                    &authentication,
+                    GuestLoginData { device_secret },
+                );
+            }
+        } else if ui.button("Play as guest").clicked() {
+            bevy_signup_guest(
+                &mut commands,
+                &backpack.client,
+                &authentication,
+                CreateGuestData { app_id },
+            );
+        }
     });
 }
 
+/// Until the guest attaches an email, it's their only way to log in again.
+const GUEST_DEVICE_SECRET_KEY: &str = "guest_device_secret";
+
+fn handle_guest_signup_result(
+    mut events: EventReader<GuestSignupTaskResultEvent>,
+    mut pkv: ResMut<PkvStore>,
+) {
+    for res in events.iter() {
+        match &res.0 {
+            Ok(guest_signup) => pkv
+                .set(GUEST_DEVICE_SECRET_KEY, &guest_signup.device_secret)
+                .expect("failed to store guest device secret."),
+            Err(err) => error!("Guest signup failed: {err}"),
+        }
+    }
+}
+
 /// Called only when BackpackClientAuthRefresh has changed.
 fn handle_authentication_change(
     mut pkv: ResMut<PkvStore>,
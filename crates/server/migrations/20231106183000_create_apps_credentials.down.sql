@@ -0,0 +1,8 @@
+BEGIN;
+
+CREATE TEMPORARY TABLE service_users ON COMMIT DROP AS
+    SELECT service_user_id AS id FROM apps_credentials;
+DROP TABLE IF EXISTS apps_credentials;
+DELETE FROM users WHERE id IN (SELECT id FROM service_users);
+
+COMMIT;
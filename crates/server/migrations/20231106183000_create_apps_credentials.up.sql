@@ -0,0 +1,19 @@
+CREATE TABLE apps_credentials (
+    id SERIAL PRIMARY KEY,
+    app_id INT NOT NULL REFERENCES apps (id) ON UPDATE CASCADE ON DELETE CASCADE,
+    /*
+    The secret is only returned on creation, we keep a hash to verify it.
+    */
+    secret_hash TEXT NOT NULL,
+    /*
+    Admin who created these credentials.
+    */
+    created_by INT NOT NULL REFERENCES users (id) ON UPDATE CASCADE ON DELETE CASCADE,
+    /*
+    User dedicated to these credentials, app service biscuits are issued for it,
+    so they never act as the admin who created them.
+    */
+    service_user_id INT NOT NULL REFERENCES users (id) ON UPDATE CASCADE ON DELETE CASCADE,
+    created_at TIMESTAMP NOT NULL,
+    revoked BOOLEAN NOT NULL DEFAULT FALSE
+);
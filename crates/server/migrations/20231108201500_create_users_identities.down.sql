@@ -0,0 +1,15 @@
+BEGIN;
+
+CREATE TABLE users_github(
+   id INT PRIMARY KEY,
+   login TEXT NOT NULL,
+   user_id int NOT NULL,
+   FOREIGN KEY(user_id) REFERENCES users(id)
+);
+
+INSERT INTO users_github (id, login, user_id)
+SELECT external_id::INT, login, user_id FROM users_identities WHERE provider = 'github';
+
+DROP TABLE IF EXISTS users_identities;
+
+COMMIT;
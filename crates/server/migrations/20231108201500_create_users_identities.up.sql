@@ -0,0 +1,26 @@
+BEGIN;
+
+CREATE TABLE users_identities(
+   /*
+   Name of the identity provider (github...).
+   */
+   provider VARCHAR(50) NOT NULL,
+   /*
+   Unique id of the user for this provider.
+   */
+   external_id TEXT NOT NULL,
+   login TEXT NOT NULL,
+   /*
+   Reference to the user connected with that identity.
+   */
+   user_id INT NOT NULL REFERENCES users (id) ON UPDATE CASCADE ON DELETE CASCADE,
+   CONSTRAINT user_identity_pkey PRIMARY KEY (provider, external_id)
+);
+
+-- github is now one of the identity providers.
+INSERT INTO users_identities (provider, external_id, login, user_id)
+SELECT 'github', id::TEXT, login, user_id FROM users_github;
+
+DROP TABLE users_github;
+
+COMMIT;
@@ -0,0 +1,9 @@
+BEGIN;
+
+ALTER TABLE users_email_password
+  DROP COLUMN IF EXISTS verification_code_hash,
+  DROP COLUMN IF EXISTS verification_code_expiration_date;
+
+ALTER TABLE apps DROP COLUMN IF EXISTS require_verified_email;
+
+COMMIT;
@@ -0,0 +1,16 @@
+BEGIN;
+
+/*
+Only the hash of the verification code is stored, it is looked up by hash.
+*/
+ALTER TABLE users_email_password
+  ADD COLUMN verification_code_hash VARCHAR(64),
+  ADD COLUMN verification_code_expiration_date TIMESTAMP;
+
+/*
+When true, users with an unverified email cannot log in as a user of this app.
+*/
+ALTER TABLE apps
+  ADD COLUMN require_verified_email BOOLEAN NOT NULL DEFAULT FALSE;
+
+COMMIT;
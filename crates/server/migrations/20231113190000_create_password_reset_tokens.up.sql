@@ -0,0 +1,9 @@
+CREATE TABLE password_reset_tokens (
+    id SERIAL PRIMARY KEY,
+    reset_token VARCHAR(255) NOT NULL UNIQUE,
+    email_password_id INT NOT NULL REFERENCES users_email_password (id) ON UPDATE CASCADE ON DELETE CASCADE,
+    expiration_date TIMESTAMP NOT NULL,
+    -- A reset token can only be used once.
+    used BOOLEAN NOT NULL DEFAULT FALSE,
+    created_at TIMESTAMP NOT NULL
+);
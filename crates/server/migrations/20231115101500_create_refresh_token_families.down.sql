@@ -0,0 +1,2 @@
+ALTER TABLE refresh_tokens DROP COLUMN IF EXISTS family_id;
+DROP TABLE IF EXISTS refresh_token_families;
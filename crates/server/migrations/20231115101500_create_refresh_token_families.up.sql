@@ -0,0 +1,21 @@
+-- A family groups the refresh tokens rotated from a same login.
+BEGIN;
+
+CREATE TABLE refresh_token_families (
+    id SERIAL PRIMARY KEY,
+    user_id INT NOT NULL REFERENCES users (id) ON UPDATE CASCADE ON DELETE CASCADE,
+    revoked BOOLEAN NOT NULL DEFAULT FALSE,
+    created_at TIMESTAMP NOT NULL
+);
+
+ALTER TABLE refresh_tokens ADD COLUMN family_id INT REFERENCES refresh_token_families (id) ON UPDATE CASCADE ON DELETE CASCADE;
+
+-- Existing refresh tokens each get their own family.
+INSERT INTO refresh_token_families (id, user_id, revoked, created_at)
+SELECT id, user_id, revoked, created_at FROM refresh_tokens;
+UPDATE refresh_tokens SET family_id = id;
+SELECT setval(pg_get_serial_sequence('refresh_token_families', 'id'), COALESCE(MAX(id), 0) + 1, false) FROM refresh_token_families;
+
+ALTER TABLE refresh_tokens ALTER COLUMN family_id SET NOT NULL;
+
+COMMIT;
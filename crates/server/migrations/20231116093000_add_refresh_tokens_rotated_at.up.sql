@@ -0,0 +1,2 @@
+-- Set when a refresh token is exchanged for a new one, to tell concurrent refreshes apart from reuse.
+ALTER TABLE refresh_tokens ADD COLUMN rotated_at TIMESTAMP;
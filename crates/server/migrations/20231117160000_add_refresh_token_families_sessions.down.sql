@@ -0,0 +1,3 @@
+ALTER TABLE refresh_token_families
+    DROP COLUMN IF EXISTS app_id,
+    DROP COLUMN IF EXISTS label;
@@ -0,0 +1,12 @@
+-- Refresh token families are exposed to users as sessions.
+BEGIN;
+
+-- Existing sessions don't record their app: they would be refreshed as admin sessions,
+-- their users log in again instead.
+UPDATE refresh_token_families SET revoked = true;
+
+ALTER TABLE refresh_token_families
+    ADD COLUMN app_id INT REFERENCES apps (id) ON UPDATE CASCADE ON DELETE CASCADE,
+    ADD COLUMN label VARCHAR(255);
+
+COMMIT;
@@ -0,0 +1,8 @@
+BEGIN;
+
+ALTER TABLE apps DROP COLUMN IF EXISTS require_admin_two_factor;
+DROP TABLE IF EXISTS two_factor_challenges;
+DROP TABLE IF EXISTS two_factor_recovery_codes;
+DROP TABLE IF EXISTS users_two_factor;
+
+COMMIT;
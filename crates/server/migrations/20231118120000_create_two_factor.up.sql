@@ -0,0 +1,40 @@
+BEGIN;
+
+-- TOTP secret of a user, shared with their authenticator app.
+CREATE TABLE users_two_factor (
+    user_id INT PRIMARY KEY REFERENCES users (id) ON UPDATE CASCADE ON DELETE CASCADE,
+    -- Base32 encoded, as in the provisioning uri.
+    secret VARCHAR(64) NOT NULL,
+    -- Enabled once the user confirmed a code from their authenticator app.
+    enabled BOOLEAN NOT NULL DEFAULT FALSE,
+    -- Last accepted time step, so a code cannot be used twice.
+    last_used_step BIGINT,
+    created_at TIMESTAMP NOT NULL
+);
+
+-- Single-use codes to log in when the authenticator app is lost.
+CREATE TABLE two_factor_recovery_codes (
+    id SERIAL PRIMARY KEY,
+    user_id INT NOT NULL REFERENCES users (id) ON UPDATE CASCADE ON DELETE CASCADE,
+    code_hash VARCHAR(64) NOT NULL,
+    used BOOLEAN NOT NULL DEFAULT FALSE
+);
+
+-- Given after a correct password to a user with two-factor authentication,
+-- to be sent back along a code to finish the admin login.
+CREATE TABLE two_factor_challenges (
+    id SERIAL PRIMARY KEY,
+    challenge_token VARCHAR(255) NOT NULL UNIQUE,
+    user_id INT NOT NULL REFERENCES users (id) ON UPDATE CASCADE ON DELETE CASCADE,
+    expiration_date TIMESTAMP NOT NULL,
+    failed_attempts INT NOT NULL DEFAULT 0,
+    used BOOLEAN NOT NULL DEFAULT FALSE
+);
+
+/*
+When true, co-admins can only administer this app when logged in with two-factor authentication.
+*/
+ALTER TABLE apps
+  ADD COLUMN require_admin_two_factor BOOLEAN NOT NULL DEFAULT FALSE;
+
+COMMIT;
@@ -0,0 +1,16 @@
+/*
+Users who signed up without any email or identity, from a device of a player.
+The row is removed once the guest attaches an email/password or an identity.
+*/
+CREATE TABLE users_guests (
+    user_id INT PRIMARY KEY REFERENCES users (id) ON UPDATE CASCADE ON DELETE CASCADE,
+    /*
+    The device secret is only returned on signup, its sha256 is kept to find the guest back.
+    */
+    device_secret_hash VARCHAR(64) NOT NULL UNIQUE,
+    /*
+    Guests can only log in as users of the app they signed up for.
+    */
+    app_id INT NOT NULL REFERENCES apps (id) ON UPDATE CASCADE ON DELETE CASCADE,
+    created_at TIMESTAMP NOT NULL
+);
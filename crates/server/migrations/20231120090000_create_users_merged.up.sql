@@ -0,0 +1,12 @@
+/*
+Users merged into another one: their sessions were moved by the merge,
+and can still be refreshed with the authentication tokens issued before.
+*/
+CREATE TABLE users_merged (
+    /*
+    No reference, the merged user is deleted.
+    */
+    user_id INT PRIMARY KEY,
+    merged_into INT NOT NULL REFERENCES users (id) ON UPDATE CASCADE ON DELETE CASCADE,
+    merged_at TIMESTAMP NOT NULL
+);
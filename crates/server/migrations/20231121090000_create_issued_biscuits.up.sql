@@ -0,0 +1,16 @@
+/*
+Authentication biscuits issued to users, by the revocation identifier of their authority block,
+and biscuits revoked before their expiration.
+
+Revoked biscuits can also be attenuated ones, never issued by the server.
+*/
+CREATE TABLE issued_biscuits (
+    revocation_id BYTEA PRIMARY KEY,
+    /*
+    No reference, biscuits of deleted users stay revoked until they expire.
+    */
+    user_id INT NOT NULL,
+    expiration_date TIMESTAMP NOT NULL,
+    revoked_at TIMESTAMP
+);
+CREATE INDEX issued_biscuits_user_id ON issued_biscuits (user_id);
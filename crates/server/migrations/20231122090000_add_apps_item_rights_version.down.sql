@@ -0,0 +1 @@
+ALTER TABLE apps DROP COLUMN IF EXISTS item_rights_version;
@@ -0,0 +1,5 @@
+/*
+Incremented when rights of the app on items, or of its admins, are removed or restricted:
+item rights baked in biscuits with a previous version are not trusted anymore.
+*/
+ALTER TABLE apps ADD COLUMN item_rights_version INT NOT NULL DEFAULT 0;
@@ -0,0 +1 @@
+ALTER TABLE users_items DROP CONSTRAINT IF EXISTS users_items_amount_non_negative;
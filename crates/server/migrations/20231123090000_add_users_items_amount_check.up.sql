@@ -0,0 +1,11 @@
+/*
+Amounts never drop below zero, transfers check the balance of the sender.
+Negative amounts left by previous versions are reset.
+*/
+BEGIN;
+
+UPDATE users_items SET amount = 0 WHERE amount < 0;
+ALTER TABLE users_items
+  ADD CONSTRAINT users_items_amount_non_negative CHECK (amount >= 0);
+
+COMMIT;
@@ -0,0 +1,8 @@
+BEGIN;
+
+ALTER TABLE items DROP CONSTRAINT IF EXISTS items_amount_bounds;
+ALTER TABLE items DROP COLUMN IF EXISTS max_change;
+ALTER TABLE items DROP COLUMN IF EXISTS max_amount;
+ALTER TABLE items DROP COLUMN IF EXISTS min_amount;
+
+COMMIT;
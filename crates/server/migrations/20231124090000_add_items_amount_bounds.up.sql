@@ -0,0 +1,19 @@
+/*
+Bounds of the amount of the item for each user, set by admins of its app.
+Changes moving an amount out of its bounds are refused.
+*/
+BEGIN;
+
+ALTER TABLE items ADD COLUMN min_amount INT NOT NULL DEFAULT 0;
+-- No maximum when NULL.
+ALTER TABLE items ADD COLUMN max_amount INT;
+-- Maximum change of an amount in a single request, no maximum when NULL.
+ALTER TABLE items ADD COLUMN max_change INT;
+ALTER TABLE items
+  ADD CONSTRAINT items_amount_bounds CHECK (
+    min_amount >= 0
+    AND (max_amount IS NULL OR max_amount >= min_amount)
+    AND (max_change IS NULL OR max_change > 0)
+  );
+
+COMMIT;
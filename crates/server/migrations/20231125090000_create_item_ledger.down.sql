@@ -0,0 +1,6 @@
+BEGIN;
+
+DROP TABLE IF EXISTS item_ledger;
+DROP FUNCTION IF EXISTS item_ledger_refuse_update;
+
+COMMIT;
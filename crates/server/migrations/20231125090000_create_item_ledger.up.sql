@@ -0,0 +1,35 @@
+/*
+Every change of the amount of an item for a user, never updated.
+Entries are only removed with their user or their item.
+*/
+BEGIN;
+
+CREATE TABLE item_ledger (
+    id BIGSERIAL PRIMARY KEY,
+    item_id INT NOT NULL REFERENCES items (id) ON UPDATE CASCADE ON DELETE CASCADE,
+    user_id INT NOT NULL REFERENCES users (id) ON UPDATE CASCADE ON DELETE CASCADE,
+    -- User of the token which made the change, kept after their deletion.
+    actor_user_id INT NOT NULL,
+    -- App of the token which made the change, NULL for admin tokens.
+    app_id INT,
+    delta INT NOT NULL,
+    -- Amount of the user after the change.
+    balance INT NOT NULL,
+    reason TEXT,
+    created_at TIMESTAMP NOT NULL
+);
+
+CREATE INDEX item_ledger_user_id ON item_ledger (user_id, id);
+CREATE INDEX item_ledger_item_id ON item_ledger (item_id, id);
+
+CREATE FUNCTION item_ledger_refuse_update() RETURNS trigger AS $$
+BEGIN
+    RAISE EXCEPTION 'item_ledger is append-only';
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER item_ledger_append_only
+    BEFORE UPDATE ON item_ledger
+    FOR EACH ROW EXECUTE FUNCTION item_ledger_refuse_update();
+
+COMMIT;
@@ -0,0 +1,20 @@
+/*
+Results of item modifications sent with an idempotency key,
+so retries of a request are not applied twice.
+Keys are scoped by the user of the token sending them, and forgotten after a retention delay.
+*/
+BEGIN;
+
+CREATE TABLE item_idempotency_keys (
+    actor_user_id INT NOT NULL REFERENCES users (id) ON UPDATE CASCADE ON DELETE CASCADE,
+    key TEXT NOT NULL,
+    item_id INT NOT NULL REFERENCES items (id) ON UPDATE CASCADE ON DELETE CASCADE,
+    user_id INT NOT NULL REFERENCES users (id) ON UPDATE CASCADE ON DELETE CASCADE,
+    amount INT NOT NULL,
+    -- Amount of the user after the modification, set in the transaction applying it.
+    new_amount INT,
+    created_at TIMESTAMP NOT NULL,
+    PRIMARY KEY (actor_user_id, key)
+);
+
+COMMIT;
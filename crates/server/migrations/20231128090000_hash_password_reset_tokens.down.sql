@@ -0,0 +1,8 @@
+-- Hashed tokens cannot be recovered, pending resets are dropped.
+BEGIN;
+
+DELETE FROM password_reset_tokens;
+
+ALTER TABLE password_reset_tokens RENAME COLUMN reset_token_hash TO reset_token;
+
+COMMIT;
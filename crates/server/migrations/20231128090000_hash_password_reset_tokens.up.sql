@@ -0,0 +1,11 @@
+/*
+Reset tokens are stored hashed, like recovery codes: a leaked table cannot be used to reset passwords.
+*/
+BEGIN;
+
+ALTER TABLE password_reset_tokens RENAME COLUMN reset_token TO reset_token_hash;
+
+UPDATE password_reset_tokens
+    SET reset_token_hash = encode(sha256(convert_to(reset_token_hash, 'UTF8')), 'hex');
+
+COMMIT;
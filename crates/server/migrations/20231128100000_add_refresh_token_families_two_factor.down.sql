@@ -0,0 +1,2 @@
+ALTER TABLE refresh_token_families
+    DROP COLUMN IF EXISTS two_factor;
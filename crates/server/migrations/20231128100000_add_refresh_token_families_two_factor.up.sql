@@ -0,0 +1,3 @@
+-- Whether the session was opened with two-factor authentication, kept by the tokens it is refreshed into.
+ALTER TABLE refresh_token_families
+    ADD COLUMN two_factor BOOLEAN NOT NULL DEFAULT FALSE;
@@ -0,0 +1,30 @@
+-- Keys of transactions cannot be stored for a single modification, they are dropped.
+BEGIN;
+
+DELETE FROM item_idempotency_keys
+WHERE cardinality(item_ids) <> 1
+OR NOT EXISTS (SELECT 1 FROM items WHERE items.id = item_ids[1])
+OR NOT EXISTS (SELECT 1 FROM users WHERE users.id = user_ids[1]);
+
+ALTER TABLE item_idempotency_keys
+    ADD COLUMN item_id INT REFERENCES items (id) ON UPDATE CASCADE ON DELETE CASCADE,
+    ADD COLUMN user_id INT REFERENCES users (id) ON UPDATE CASCADE ON DELETE CASCADE,
+    ADD COLUMN amount INT,
+    ADD COLUMN new_amount INT;
+
+UPDATE item_idempotency_keys SET
+    item_id = item_ids[1],
+    user_id = user_ids[1],
+    amount = amounts[1],
+    new_amount = new_amounts[1];
+
+ALTER TABLE item_idempotency_keys
+    ALTER COLUMN item_id SET NOT NULL,
+    ALTER COLUMN user_id SET NOT NULL,
+    ALTER COLUMN amount SET NOT NULL,
+    DROP COLUMN item_ids,
+    DROP COLUMN user_ids,
+    DROP COLUMN amounts,
+    DROP COLUMN new_amounts;
+
+COMMIT;
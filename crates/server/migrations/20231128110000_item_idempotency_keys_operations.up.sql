@@ -0,0 +1,28 @@
+/*
+Idempotency keys also cover item transactions: the operations applied with a key,
+and the amounts they resulted in, are stored in the order of the request.
+*/
+BEGIN;
+
+ALTER TABLE item_idempotency_keys
+    ADD COLUMN item_ids INT[],
+    ADD COLUMN user_ids INT[],
+    ADD COLUMN amounts INT[],
+    ADD COLUMN new_amounts INT[];
+
+UPDATE item_idempotency_keys SET
+    item_ids = ARRAY[item_id],
+    user_ids = ARRAY[user_id],
+    amounts = ARRAY[amount],
+    new_amounts = CASE WHEN new_amount IS NULL THEN NULL ELSE ARRAY[new_amount] END;
+
+ALTER TABLE item_idempotency_keys
+    ALTER COLUMN item_ids SET NOT NULL,
+    ALTER COLUMN user_ids SET NOT NULL,
+    ALTER COLUMN amounts SET NOT NULL,
+    DROP COLUMN item_id,
+    DROP COLUMN user_id,
+    DROP COLUMN amount,
+    DROP COLUMN new_amount;
+
+COMMIT;
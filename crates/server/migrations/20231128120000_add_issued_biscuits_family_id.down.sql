@@ -0,0 +1,2 @@
+ALTER TABLE issued_biscuits
+    DROP COLUMN IF EXISTS family_id;
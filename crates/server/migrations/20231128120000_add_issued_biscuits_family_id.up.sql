@@ -0,0 +1,4 @@
+-- Session the biscuit was issued in, revoked with it. No reference, like `user_id`.
+ALTER TABLE issued_biscuits
+    ADD COLUMN family_id INT;
+CREATE INDEX issued_biscuits_family_id ON issued_biscuits (family_id);
@@ -0,0 +1 @@
+DROP INDEX IF EXISTS issued_biscuits_expiration_date;
@@ -0,0 +1,2 @@
+-- Expired biscuits are deleted on each reload of the revocation list.
+CREATE INDEX issued_biscuits_expiration_date ON issued_biscuits (expiration_date);
@@ -7,30 +7,65 @@ use actix_web_httpauth::extractors::{
 };
 use biscuit_auth::{
     builder::{fact, Term},
-    Biscuit, KeyPair,
+    Biscuit,
 };
 use serde::Serialize;
+use sqlx::PgPool;
 
-use crate::biscuit::parse_biscuit_info;
+use crate::biscuit::{parse_biscuit_info, RootKeys};
+use crate::revocation::RevocationList;
 use crate::time::MockableDateTime;
 use shared::{BiscuitInfo, Role};
 
-#[tracing::instrument(name = "validate biscuit as user or admin", skip_all)]
-pub async fn validator(
+/// Biscuits are refused if the revocation list can't be loaded.
+async fn is_revoked(req: &ServiceRequest, biscuit: &Biscuit) -> bool {
+    let connection = req.app_data::<web::Data<PgPool>>().unwrap();
+    let revocation_list = req.app_data::<web::Data<RevocationList>>().unwrap();
+    revocation_list
+        .is_revoked(connection, biscuit)
+        .await
+        .unwrap_or(true)
+}
+
+/// Authorizes the biscuit, then inserts its [`BiscuitInfo`] in the request if `accept` allows its role.
+async fn validate_role(
     req: ServiceRequest,
     credentials: BearerAuth,
+    accept: impl Fn(&Role) -> bool,
 ) -> Result<ServiceRequest, (Error, ServiceRequest)> {
-    let root = req.app_data::<web::Data<KeyPair>>().unwrap();
+    let root = req.app_data::<web::Data<RootKeys>>().unwrap();
     let time = req.app_data::<web::Data<MockableDateTime>>().unwrap();
-    if let Some(biscuit_info) = Biscuit::from_base64(credentials.token(), |_| root.public())
+    if let Some((biscuit, biscuit_info)) = root
+        .parse(credentials.token())
         .ok()
-        .and_then(|biscuit| authorize(&biscuit, time))
+        .and_then(|biscuit| authorize(&biscuit, time).map(|biscuit_info| (biscuit, biscuit_info)))
     {
-        req.extensions_mut().insert(biscuit_info);
-        Ok(req)
-    } else {
-        Err((AuthenticationError::from(Config::default()).into(), req))
+        if accept(&biscuit_info.role) && !is_revoked(&req, &biscuit).await {
+            req.extensions_mut().insert(biscuit_info);
+            return Ok(req);
+        }
     }
+    Err((AuthenticationError::from(Config::default()).into(), req))
+}
+
+/// App services are refused: routes using this validator act on the user of the biscuit.
+#[tracing::instrument(name = "validate biscuit as user or admin", skip_all)]
+pub async fn validator(
+    req: ServiceRequest,
+    credentials: BearerAuth,
+) -> Result<ServiceRequest, (Error, ServiceRequest)> {
+    validate_role(req, credentials, |role| {
+        !matches!(role, Role::AppService(_))
+    })
+    .await
+}
+
+#[tracing::instrument(name = "validate biscuit as user, admin or app service", skip_all)]
+pub async fn validator_any_role(
+    req: ServiceRequest,
+    credentials: BearerAuth,
+) -> Result<ServiceRequest, (Error, ServiceRequest)> {
+    validate_role(req, credentials, |_| true).await
 }
 
 #[tracing::instrument(name = "validate biscuit as admin", skip_all)]
@@ -38,13 +73,14 @@ pub async fn validator_admin(
     req: ServiceRequest,
     credentials: BearerAuth,
 ) -> Result<ServiceRequest, (Error, ServiceRequest)> {
-    let root = req.app_data::<web::Data<KeyPair>>().unwrap();
+    let root = req.app_data::<web::Data<RootKeys>>().unwrap();
     let time = req.app_data::<web::Data<MockableDateTime>>().unwrap();
-    if let Some(biscuit_info) = Biscuit::from_base64(credentials.token(), |_| root.public())
-        .ok()
-        .and_then(|biscuit| authorize(&biscuit, time.get_ref()))
+    if let Some((biscuit, biscuit_info)) =
+        root.parse(credentials.token()).ok().and_then(|biscuit| {
+            authorize(&biscuit, time.get_ref()).map(|biscuit_info| (biscuit, biscuit_info))
+        })
     {
-        if biscuit_info.role == Role::Admin {
+        if biscuit_info.role == Role::Admin && !is_revoked(&req, &biscuit).await {
             req.extensions_mut().insert(biscuit_info);
             return Ok(req);
         }
@@ -62,6 +98,10 @@ pub fn authorize(token: &Biscuit, time: &MockableDateTime) -> Option<BiscuitInfo
     );
     authorizer.add_fact(dbg!(time_fact)).map_err(|_| ()).ok()?;
     authorizer.allow().map_err(|_| ()).ok()?;
+    // Read before `authorize`, which loads the facts of attenuated blocks.
+    let biscuit_info = parse_biscuit_info(&mut authorizer)
+        .map_err(|err| dbg!(err))
+        .ok()?;
     dbg!("allowed");
     authorizer
         .authorize()
@@ -70,9 +110,31 @@ pub fn authorize(token: &Biscuit, time: &MockableDateTime) -> Option<BiscuitInfo
         })
         .ok()?;
     dbg!("authorized");
-    parse_biscuit_info(&mut authorizer)
-        .map_err(|err| dbg!(err))
-        .ok()
+    Some(biscuit_info)
+}
+
+/// Checks the signature and reads the biscuit without evaluating its checks:
+/// routes using this validator must authorize the biscuit, inserted in the request,
+/// with facts about the request, see [`crate::item_policy`].
+#[tracing::instrument(name = "decode biscuit, deferring its checks to the route", skip_all)]
+pub async fn validator_deferred(
+    req: ServiceRequest,
+    credentials: BearerAuth,
+) -> Result<ServiceRequest, (Error, ServiceRequest)> {
+    let root = req.app_data::<web::Data<RootKeys>>().unwrap();
+    let time = req.app_data::<web::Data<MockableDateTime>>().unwrap();
+    if let Some((biscuit, biscuit_info)) =
+        root.parse(credentials.token()).ok().and_then(|biscuit| {
+            decode_without_authorization(&biscuit, time).map(|biscuit_info| (biscuit, biscuit_info))
+        })
+    {
+        if !is_revoked(&req, &biscuit).await {
+            req.extensions_mut().insert(biscuit_info);
+            req.extensions_mut().insert(biscuit);
+            return Ok(req);
+        }
+    }
+    Err((AuthenticationError::from(Config::default()).into(), req))
 }
 
 #[tracing::instrument(name = "decode biscuit but doesn't check inner data", skip_all)]
@@ -80,9 +142,10 @@ pub async fn validator_no_check(
     req: ServiceRequest,
     credentials: BearerAuth,
 ) -> Result<ServiceRequest, (Error, ServiceRequest)> {
-    let root = req.app_data::<web::Data<KeyPair>>().unwrap();
+    let root = req.app_data::<web::Data<RootKeys>>().unwrap();
     let time = req.app_data::<web::Data<MockableDateTime>>().unwrap();
-    if let Some(biscuit_info) = Biscuit::from_base64(credentials.token(), |_| root.public())
+    if let Some(biscuit_info) = root
+        .parse(credentials.token())
         .ok()
         .and_then(|biscuit| decode_without_authorization(&biscuit, time))
     {
@@ -3,18 +3,24 @@ use std::time::UNIX_EPOCH;
 use actix_web::cookie::time::{format_description::well_known::Rfc3339, Duration, OffsetDateTime};
 use biscuit_auth::{
     builder::{date, fact, BiscuitBuilder, Fact, Term},
-    Authorizer, Biscuit, KeyPair,
+    error, Biscuit, KeyPair,
 };
 use serde::{Deserialize, Serialize};
 
 use super::models::app::AppId;
 use super::models::user::UserId;
+use crate::item_rights::BakedItemRights;
 use crate::time::MockableDateTime;
 
-use shared::{BiscuitInfo, Role};
+pub use shared::biscuit::{parse_biscuit_info, parse_user_id, PublicKeys};
+use shared::Role;
 
 pub const AUTHENTICATION_TOKEN_TTL: i64 = 30;
 pub const REFRESH_TOKEN_TTL: i64 = 30 * 24 * 3600;
+/// App services have no refresh token, they log in again with their credentials.
+pub const APP_SERVICE_TOKEN_TTL: i64 = 10 * 60;
+/// Time to enter a two-factor code after a correct password.
+pub const TWO_FACTOR_CHALLENGE_TTL: i64 = 5 * 60;
 
 /// Contains a biscuit token.
 #[derive(Debug, Serialize, Deserialize)]
@@ -23,6 +29,56 @@ pub struct TokenReply {
     pub token: String,
 }
 
+/// Root keys signing and verifying biscuits.
+///
+/// New biscuits are signed by the key with the highest id, which they carry.
+/// Previous keys stay in the verification set, so a rotation doesn't invalidate issued biscuits.
+pub struct RootKeys {
+    signing_key_id: u32,
+    signing_key: KeyPair,
+    public_keys: PublicKeys,
+}
+
+impl RootKeys {
+    /// Returns `None` if `private_keys` is empty.
+    ///
+    /// `retired_public_keys` are only used for verification, for keys whose private part was discarded.
+    pub fn new(
+        private_keys: Vec<(u32, KeyPair)>,
+        retired_public_keys: PublicKeys,
+    ) -> Option<RootKeys> {
+        let mut public_keys = retired_public_keys;
+        let mut signing = None;
+        for (id, key) in private_keys {
+            public_keys.0.insert(id, key.public());
+            match signing {
+                Some((signing_key_id, _)) if signing_key_id > id => {}
+                _ => signing = Some((id, key)),
+            }
+        }
+        let (signing_key_id, signing_key) = signing?;
+        Some(RootKeys {
+            signing_key_id,
+            signing_key,
+            public_keys,
+        })
+    }
+    pub fn signing_key_id(&self) -> u32 {
+        self.signing_key_id
+    }
+    pub fn signing_key(&self) -> &KeyPair {
+        &self.signing_key
+    }
+    /// Public keys of all root keys accepted for verification, by id.
+    pub fn public_keys(&self) -> &PublicKeys {
+        &self.public_keys
+    }
+    /// Checks the biscuit signature against the root key it was signed with.
+    pub fn parse(&self, token: &str) -> Result<Biscuit, error::Token> {
+        Biscuit::from_base64(token, &self.public_keys)
+    }
+}
+
 pub struct AuthorizedWrapper<T>(pub T);
 
 impl<T> std::ops::Deref for AuthorizedWrapper<T> {
@@ -37,58 +93,6 @@ pub trait BiscuitBaker<T> {
     fn bake(builder: BiscuitBuilder, ingredient: T) -> BiscuitBuilder;
 }
 
-fn parse_role(authorizer: &mut Authorizer) -> Result<Role, String> {
-    let admin: Option<Vec<(bool,)>> = authorizer
-        .query("data($is_admin) <- is_admin($is_admin)")
-        .ok();
-    match admin {
-        Some(res) if !res.is_empty() && res[0].0 => Ok(Role::Admin),
-        _ => {
-            let app_id: Vec<(String,)> = authorizer
-                .query("data($app_id) <- user_app_id($app_id)")
-                .map_err(|_| "query app_id error")?;
-            Ok(Role::User(shared::AppId(
-                app_id
-                    .get(0)
-                    .ok_or("get(0) error")?
-                    .0
-                    .as_str()
-                    .parse::<i32>()
-                    .map_err(|_| "parse error")?,
-            )))
-        }
-    }
-}
-
-fn parse_user_id(authorizer: &mut Authorizer) -> Result<UserId, String> {
-    let res: Vec<(String,)> = authorizer
-        .query("data($id) <- user($id)")
-        .map_err(|_| "query error")?;
-    Ok(UserId::from(
-        res.get(0)
-            .ok_or("get(0) error")?
-            .0
-            .as_str()
-            .parse::<i32>()
-            .map_err(|_| "parse error")?,
-    ))
-}
-
-fn parse_expiration_date(authorizer: &mut Authorizer) -> Result<i64, String> {
-    let res: Vec<(i64,)> = authorizer
-        .query("data($unix_timestamp) <- expiration_date($unix_timestamp)")
-        .map_err(|err| err.to_string())?;
-    Ok(res.get(0).ok_or("get(0) error")?.0)
-}
-
-pub fn parse_biscuit_info(authorizer: &mut Authorizer) -> Result<BiscuitInfo, String> {
-    Ok(BiscuitInfo {
-        expiration_date_unix_timestamp: parse_expiration_date(authorizer)?,
-        user_id: parse_user_id(authorizer)?.0,
-        role: parse_role(authorizer)?,
-    })
-}
-
 impl<'a> BiscuitBaker<UserId> for BiscuitBuilder<'a> {
     fn bake(mut builder: BiscuitBuilder, ingredient: UserId) -> BiscuitBuilder {
         builder
@@ -117,22 +121,50 @@ impl<'a> BiscuitBaker<Role> for BiscuitBuilder<'a> {
                     ))
                     .unwrap();
             }
+            Role::AppService(app_id) => {
+                builder
+                    .add_authority_fact(Fact::new(
+                        "app_service_id".to_string(),
+                        vec![Term::Str((app_id).to_string())],
+                    ))
+                    .unwrap();
+            }
         }
         builder
     }
 }
 
+/// Marks a login completed with two-factor authentication.
+pub struct TwoFactorVerified;
+
+impl<'a> BiscuitBaker<TwoFactorVerified> for BiscuitBuilder<'a> {
+    fn bake(mut builder: BiscuitBuilder, _ingredient: TwoFactorVerified) -> BiscuitBuilder {
+        builder
+            .add_authority_fact(Fact::new("two_factor".to_string(), vec![Term::Bool(true)]))
+            .unwrap();
+        builder
+    }
+}
+
 impl UserId {
+    /// `item_rights` should be loaded for the same role, see [`BakedItemRights::load`].
     pub fn create_biscuit(
         &self,
-        root: &KeyPair,
+        root: &RootKeys,
         role: Role,
+        two_factor: bool,
+        item_rights: BakedItemRights,
         expiration_date: OffsetDateTime,
     ) -> Biscuit {
-        let mut builder = Biscuit::builder(root);
+        let mut builder = Biscuit::builder(root.signing_key());
+        builder.set_root_key_id(root.signing_key_id());
 
         builder = BiscuitBuilder::bake(builder, *self);
         builder = BiscuitBuilder::bake(builder, role);
+        if two_factor {
+            builder = BiscuitBuilder::bake(builder, TwoFactorVerified);
+        }
+        builder = BiscuitBuilder::bake(builder, item_rights);
         builder
             .add_authority_fact(
                 format!(r#"expiration_date({})"#, expiration_date.unix_timestamp()).as_str(),
@@ -161,9 +193,12 @@ pub fn authorize_user_only(token: &Biscuit, time: &MockableDateTime) -> Option<U
     );
     authorizer.add_fact(time_fact).map_err(|_| ()).ok()?;
     authorizer.allow().map_err(|_| ()).ok()?;
+    // Read before `authorize`, which loads the facts of attenuated blocks.
+    let user_id = parse_user_id(&mut authorizer)
+        .map(UserId::from)
+        .map_err(|_| "authorize error")
+        .ok()?;
     authorizer.authorize().map_err(|_| ()).ok()?;
 
-    dbg!(parse_user_id(&mut authorizer)
-        .map_err(|_| "authorize error")
-        .ok())
+    dbg!(Some(user_id))
 }
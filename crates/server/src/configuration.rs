@@ -1,12 +1,14 @@
 use base64::{engine::general_purpose, Engine as _};
-use biscuit_auth::{KeyPair, PrivateKey};
+use biscuit_auth::{KeyPair, PrivateKey, PublicKey};
 use secrecy::{ExposeSecret, Secret};
 use serde::Deserialize;
 use sqlx::{
     postgres::{PgConnectOptions, PgSslMode},
     ConnectOptions,
 };
+use thiserror::Error;
 
+use crate::biscuit::{PublicKeys, RootKeys};
 use crate::time::MockableDateTime;
 
 #[derive(Deserialize, Debug, Clone)]
@@ -14,15 +16,108 @@ pub struct Settings {
     pub database: DatabaseSettings,
     pub application_host: String,
     pub application_port: u16,
-    pub private_key: Option<String>,
+    /// Url used to reach this server from outside, for links sent by email.
+    pub public_url: String,
+    /// Refuses to start without configured root keys, instead of generating one,
+    /// and without an smtp server, instead of writing emails to an outbox.
+    pub production: bool,
+    /// Private root keys, the one with the highest id signs new biscuits.
+    pub root_keys: Vec<RootKeySettings>,
+    /// Public part of root keys rotated out, still accepted until the biscuits they signed expire.
+    pub retired_root_public_keys: Vec<RetiredRootKeySettings>,
     pub github_admin_app: OAuth,
+    pub mailer: MailerSettings,
+    pub rate_limit: RateLimitSettings,
     pub time: MockableDateTime,
 }
 
+#[derive(Deserialize, Debug, Clone)]
+pub struct RateLimitSettings {
+    /// Reads client ips from the `X-Forwarded-For` header, in its rightmost entry:
+    /// the server must only be reachable through a single proxy appending to it.
+    pub behind_proxy: bool,
+    pub per_ip: RequestLimit,
+    /// Only counts authentication requests, where emails are sent, per email and client ip:
+    /// others can't use up the limit of a user by knowing their email.
+    pub per_email: RequestLimit,
+    /// Counts requests of each app user and app service, authenticated by their bearer token:
+    /// each user of an app has its own limit.
+    pub per_app: RequestLimit,
+    pub login_lockout: LoginLockoutSettings,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct RequestLimit {
+    pub max_requests: u32,
+    pub period_seconds: u64,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct LoginLockoutSettings {
+    /// Bad passwords for an email from an ip before its logins from this ip are locked out.
+    pub failures_before_lockout: u32,
+    /// Duration of the first lockout, doubled by each further bad password.
+    pub base_lockout_seconds: u64,
+    /// Failures are also forgotten after this delay.
+    pub max_lockout_seconds: u64,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct RootKeySettings {
+    pub id: u32,
+    /// Base64 of the private key bytes.
+    pub private_key: Secret<String>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct RetiredRootKeySettings {
+    pub id: u32,
+    /// Base64 of the public key bytes.
+    pub public_key: String,
+}
+
+#[derive(Error, Debug)]
+pub enum RootKeysError {
+    #[error("root key {0} is not a base64 encoded private key")]
+    InvalidPrivateKey(u32),
+    #[error("retired root key {0} is not a base64 encoded public key")]
+    InvalidPublicKey(u32),
+    #[error("root key id {0} is configured more than once")]
+    DuplicateId(u32),
+    #[error("no root key configured, set BISCUIT_KEY or BISCUIT_KEYS")]
+    Missing,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub enum MailerSettings {
+    Smtp(SmtpSettings),
+    /// Emails are not sent but written to a file, or to stdout.
+    Outbox(OutboxSettings),
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct SmtpSettings {
+    pub host: String,
+    /// Address emails are sent from.
+    pub from: String,
+    pub username: String,
+    pub password: Secret<String>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct OutboxSettings {
+    /// File emails are appended to, stdout if `None`.
+    pub path: Option<String>,
+}
+
 #[derive(Deserialize, Debug, Clone)]
 pub struct OAuth {
     pub client_id: String,
     pub client_secret: String,
+    /// Base url for the authorization flow, can be set to a mock provider for testing.
+    pub oauth_url: String,
+    /// Base url to retrieve the user identity, can be set to a mock provider for testing.
+    pub api_url: String,
 }
 
 #[derive(Deserialize, Debug, Clone)]
@@ -64,17 +159,45 @@ impl DatabaseSettings {
 }
 
 impl Settings {
-    pub fn get_keypair(&self) -> KeyPair {
-        self.private_key
-            .as_ref()
-            .and_then(|pk_string| general_purpose::STANDARD.decode(pk_string).ok())
-            .and_then(|pk_bytes| PrivateKey::from_bytes(&pk_bytes).ok())
-            .map(KeyPair::from)
-            .unwrap_or_else(|| {
-                dbg!("Creating new private key.");
-                KeyPair::new()
-                // What you should store into a file, or env:
-                // dbg!(general_purpose::STANDARD.encode(key.private().to_bytes()));
-            })
+    /// Fails on malformed keys, and when no key is configured in production.
+    ///
+    /// Otherwise, a new key is generated, invalidating all biscuits on restart.
+    pub fn get_root_keys(&self) -> Result<RootKeys, RootKeysError> {
+        let mut private_keys = Vec::new();
+        let mut public_keys = PublicKeys::default();
+        for key in &self.retired_root_public_keys {
+            let public_key = general_purpose::STANDARD
+                .decode(&key.public_key)
+                .ok()
+                .and_then(|bytes| PublicKey::from_bytes(&bytes).ok())
+                .ok_or(RootKeysError::InvalidPublicKey(key.id))?;
+            if public_keys.0.insert(key.id, public_key).is_some() {
+                return Err(RootKeysError::DuplicateId(key.id));
+            }
+        }
+        for key in &self.root_keys {
+            let key_pair = general_purpose::STANDARD
+                .decode(key.private_key.expose_secret())
+                .ok()
+                .and_then(|bytes| PrivateKey::from_bytes(&bytes).ok())
+                .map(KeyPair::from)
+                .ok_or(RootKeysError::InvalidPrivateKey(key.id))?;
+            if public_keys.0.contains_key(&key.id)
+                || private_keys.iter().any(|(id, _)| *id == key.id)
+            {
+                return Err(RootKeysError::DuplicateId(key.id));
+            }
+            private_keys.push((key.id, key_pair));
+        }
+        if private_keys.is_empty() {
+            if self.production {
+                return Err(RootKeysError::Missing);
+            }
+            tracing::warn!("No root key configured, creating a new one.");
+            // What you should store into a file, or env:
+            // general_purpose::STANDARD.encode(key.private().to_bytes())
+            private_keys.push((0, KeyPair::new()));
+        }
+        RootKeys::new(private_keys, public_keys).ok_or(RootKeysError::Missing)
     }
 }
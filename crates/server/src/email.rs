@@ -0,0 +1,150 @@
+//! Emails sent by backpack, through the [`Mailer`] configured in [`MailerSettings`].
+
+use std::{fs::OpenOptions, io::Write, sync::Mutex};
+
+use actix_web::web;
+use lettre::{
+    message::Mailbox, transport::smtp::authentication::Credentials, Message, SmtpTransport,
+    Transport,
+};
+use secrecy::ExposeSecret;
+use thiserror::Error;
+
+use crate::configuration::{MailerSettings, OutboxSettings, SmtpSettings};
+
+#[derive(Error, Debug)]
+pub enum MailerError {
+    #[error("invalid email address")]
+    Address(#[from] lettre::address::AddressError),
+    #[error("could not build the email")]
+    Message(#[from] lettre::error::Error),
+    #[error("smtp transport failed")]
+    Smtp(#[from] lettre::transport::smtp::Error),
+    #[error("could not write to the outbox")]
+    Outbox(#[from] std::io::Error),
+    #[error("mailer thread failed")]
+    Blocking(#[from] actix_web::error::BlockingError),
+    #[error("no smtp server configured, set BACKPACK_EMAIL_HOST, BACKPACK_EMAIL and BACKPACK_EMAIL_PASSWORD")]
+    OutboxInProduction,
+}
+
+pub trait Mailer: Send + Sync {
+    /// Sends an email from backpack's address.
+    fn send_email(&self, to: &str, subject: &str, body: String) -> Result<(), MailerError>;
+}
+
+/// Sends the email from a thread pool, as mailers may block.
+pub async fn send_email(
+    mailer: &web::Data<dyn Mailer>,
+    to: &str,
+    subject: &str,
+    body: String,
+) -> Result<(), MailerError> {
+    let mailer = mailer.clone();
+    let (to, subject) = (to.to_string(), subject.to_string());
+    web::block(move || mailer.send_email(&to, &subject, body)).await?
+}
+
+/// Builds the mailer described by the settings.
+///
+/// Fails with an outbox in production: emails contain passwords and reset tokens.
+pub fn mailer_from_settings(
+    settings: &MailerSettings,
+    production: bool,
+) -> Result<Box<dyn Mailer>, MailerError> {
+    Ok(match settings {
+        MailerSettings::Smtp(smtp) => Box::new(SmtpMailer::new(smtp)?),
+        MailerSettings::Outbox(_) if production => return Err(MailerError::OutboxInProduction),
+        MailerSettings::Outbox(outbox) => Box::new(OutboxMailer::new(outbox)),
+    })
+}
+
+fn build_message(
+    from: &Mailbox,
+    to: &str,
+    subject: &str,
+    body: String,
+) -> Result<Message, MailerError> {
+    Ok(Message::builder()
+        .from(from.clone())
+        .reply_to(from.clone())
+        .to(to.parse()?)
+        .subject(subject)
+        .body(body)?)
+}
+
+pub struct SmtpMailer {
+    from: Mailbox,
+    transport: SmtpTransport,
+}
+
+impl SmtpMailer {
+    pub fn new(settings: &SmtpSettings) -> Result<Self, MailerError> {
+        let creds = Credentials::new(
+            settings.username.clone(),
+            settings.password.expose_secret().clone(),
+        );
+        Ok(Self {
+            from: settings.from.parse()?,
+            transport: SmtpTransport::relay(&settings.host)?
+                .credentials(creds)
+                .build(),
+        })
+    }
+}
+
+impl Mailer for SmtpMailer {
+    fn send_email(&self, to: &str, subject: &str, body: String) -> Result<(), MailerError> {
+        let email = build_message(&self.from, to, subject, body)?;
+        self.transport.send(&email)?;
+        Ok(())
+    }
+}
+
+/// Writes emails to a file, or to stdout, instead of sending them.
+///
+/// Meant for development and tests.
+pub struct OutboxMailer {
+    from: Mailbox,
+    path: Option<String>,
+    // Avoids interleaving emails sent concurrently.
+    lock: Mutex<()>,
+}
+
+impl OutboxMailer {
+    pub fn new(settings: &OutboxSettings) -> Self {
+        Self {
+            from: Mailbox::new(
+                Some("Backpack".to_string()),
+                "backpack@localhost".parse().expect("valid address"),
+            ),
+            path: settings.path.clone(),
+            lock: Mutex::new(()),
+        }
+    }
+}
+
+impl Mailer for OutboxMailer {
+    fn send_email(&self, to: &str, subject: &str, body: String) -> Result<(), MailerError> {
+        // Fails the same way as other mailers on invalid addresses.
+        let to: Mailbox = to.parse()?;
+        // Kept readable rather than encoded like a real email, to easily find links or codes.
+        let formatted = format!(
+            "From: {}\nTo: {to}\nSubject: {subject}\n\n{body}\n\n",
+            self.from
+        );
+        let _guard = self
+            .lock
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        match &self.path {
+            Some(path) => OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)?
+                .write_all(formatted.as_bytes())?,
+            None => std::io::stdout().write_all(formatted.as_bytes())?,
+        }
+        Ok(())
+    }
+}
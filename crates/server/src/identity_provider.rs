@@ -0,0 +1,139 @@
+//! Third party authentication (OAuth), each provider gives us an [`ExternalIdentity`],
+//! which is linked to a [`UserId`](crate::models::user::UserId).
+
+use std::{collections::HashMap, future::Future, pin::Pin};
+
+use serde::Deserialize;
+use thiserror::Error;
+
+use crate::{configuration::Settings, models::user_identity::ExternalIdentity};
+
+pub type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+#[derive(Error, Debug)]
+pub enum IdentityProviderError {
+    #[error("request to identity provider failed")]
+    Request(#[from] reqwest::Error),
+    #[error("identity provider refused the code: {0}")]
+    InvalidCode(String),
+}
+
+pub trait IdentityProvider: Send + Sync {
+    /// Unique name, used in routes and to store identities.
+    fn name(&self) -> &'static str;
+    /// Where the user should be redirected to authenticate,
+    /// `state` is sent back to the callback by the provider.
+    fn authorize_url(&self, state: &str) -> String;
+    /// Exchanges the code received on callback for the identity of the user.
+    fn fetch_identity<'a>(
+        &'a self,
+        code: &'a str,
+    ) -> BoxFuture<'a, Result<ExternalIdentity, IdentityProviderError>>;
+}
+
+/// Configured identity providers, by name.
+#[derive(Default)]
+pub struct IdentityProviders(HashMap<&'static str, Box<dyn IdentityProvider>>);
+
+impl IdentityProviders {
+    /// Providers with a `"disabled"` client id are not registered.
+    pub fn from_settings(settings: &Settings) -> Self {
+        let mut providers = Self::default();
+        if settings.github_admin_app.client_id != "disabled" {
+            providers.register(Box::new(GithubProvider::new(
+                settings.github_admin_app.clone(),
+            )));
+        }
+        providers
+    }
+    pub fn register(&mut self, provider: Box<dyn IdentityProvider>) {
+        self.0.insert(provider.name(), provider);
+    }
+    pub fn get(&self, name: &str) -> Option<&dyn IdentityProvider> {
+        self.0.get(name).map(|provider| provider.as_ref())
+    }
+}
+
+pub struct GithubProvider {
+    settings: crate::configuration::OAuth,
+    client: reqwest::Client,
+}
+
+#[derive(Deserialize)]
+struct GithubOauthResponse {
+    access_token: Option<String>,
+    error: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct GithubUser {
+    login: String,
+    id: u32,
+}
+
+impl GithubProvider {
+    pub fn new(settings: crate::configuration::OAuth) -> Self {
+        Self {
+            settings,
+            client: reqwest::Client::new(),
+        }
+    }
+}
+
+impl IdentityProvider for GithubProvider {
+    fn name(&self) -> &'static str {
+        "github"
+    }
+
+    fn authorize_url(&self, state: &str) -> String {
+        format!(
+            "{}/login/oauth/authorize?client_id={}&state={}",
+            self.settings.oauth_url, self.settings.client_id, state
+        )
+    }
+
+    fn fetch_identity<'a>(
+        &'a self,
+        code: &'a str,
+    ) -> BoxFuture<'a, Result<ExternalIdentity, IdentityProviderError>> {
+        Box::pin(async move {
+            let mut params = HashMap::new();
+            params.insert("client_id", self.settings.client_id.as_str());
+            params.insert("client_secret", self.settings.client_secret.as_str());
+            params.insert("code", code);
+
+            let response = self
+                .client
+                .post(format!(
+                    "{}/login/oauth/access_token",
+                    self.settings.oauth_url
+                ))
+                .form(&params)
+                .header("Accept", "application/json")
+                .send()
+                .await?
+                .json::<GithubOauthResponse>()
+                .await?;
+            let Some(github_bearer) = response.access_token else {
+                return Err(IdentityProviderError::InvalidCode(
+                    response.error.unwrap_or_default(),
+                ));
+            };
+            let gh_user = self
+                .client
+                .get(format!("{}/user", self.settings.api_url))
+                .bearer_auth(github_bearer)
+                .header("user-agent", "backpack")
+                .send()
+                .await?
+                .error_for_status()?
+                .json::<GithubUser>()
+                .await?;
+            Ok(ExternalIdentity {
+                provider: self.name().to_string(),
+                external_id: gh_user.id.to_string(),
+                login: gh_user.login,
+            })
+        })
+    }
+}
@@ -0,0 +1,502 @@
+use biscuit_auth::{
+    builder::{fact, Term},
+    Authorizer, Biscuit,
+};
+use sqlx::PgPool;
+use thiserror::Error;
+use time::OffsetDateTime;
+
+use crate::models::{
+    app::AppId,
+    item::{ItemId, ItemOfApp},
+    user::UserId,
+};
+
+/// Who can operate on the items of a user, in addition to the facts of the biscuit
+/// (`user`, `is_admin`, `user_app_id`, `app_service_id`, `two_factor`).
+///
+/// Facts provided by [`ItemFacts`] and [`ItemRequest`]:
+/// - `item_app($app)`: the app has rights on the requested items,
+/// - `app_admin($user, $app)`: the user administers the app,
+/// - `admin_two_factor_required($app, $required)`: the app policy for its admins,
+/// - `operation($operation)`, `item($item)`, `target_user($user)` and `amount($amount)`:
+///   the request, `amount` being the change of the target user's amount,
+/// - `current_item_rights_version($app, $version)`: the app rights baked in the biscuit
+///   are up to date, see [`BakedItemRights`](crate::item_rights::BakedItemRights).
+///
+/// The checks of attenuated biscuits are evaluated with the same facts,
+/// see [`Attenuation`](shared::biscuit::Attenuation).
+pub const ITEM_POLICIES: &str = r#"
+// Items can be read by any authenticated user.
+allow if operation("read");
+
+// Rights baked in the biscuit, trusted while they are up to date.
+fresh_item_rights($app) <- item_rights_version($app, $version),
+    current_item_rights_version($app, $version);
+item_app($app) <- item($item), app_item($app, $item), fresh_item_rights($app);
+app_admin($user, $app) <- user($user), administered_app($app, $required), fresh_item_rights($app);
+admin_two_factor_required($app, $required) <- administered_app($app, $required),
+    fresh_item_rights($app);
+
+// Admins of an app having rights on the item, logged in with two-factor authentication
+// when the app requires it, only on their own items.
+item_admin($app) <- is_admin(true), user($user), app_admin($user, $app), item_app($app),
+    admin_two_factor_required($app, false);
+item_admin($app) <- is_admin(true), two_factor(true), user($user), app_admin($user, $app),
+    item_app($app);
+allow if item_admin($app), user($user), target_user($user);
+
+// Users logged in on an app having rights on the item, only on their own items.
+allow if user_app_id($app), item_app($app), user($user), target_user($user);
+
+// App services of an app having rights on the item, on items of any user.
+allow if app_service_id($app), item_app($app);
+
+deny if true;
+"#;
+
+#[derive(Error, Debug)]
+pub enum ItemPolicyError {
+    #[error("no policy allows this operation")]
+    Denied,
+    #[error("invalid datalog")]
+    Datalog(#[from] biscuit_auth::error::Token),
+}
+
+/// Operations on the items of a user.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ItemOperation {
+    Read,
+    Modify,
+    Send,
+}
+
+impl ItemOperation {
+    fn name(&self) -> &'static str {
+        match self {
+            ItemOperation::Read => "read",
+            ItemOperation::Modify => "modify",
+            ItemOperation::Send => "send",
+        }
+    }
+}
+
+/// An operation requested on the items of a user.
+#[derive(Clone, Copy, Debug)]
+pub struct ItemRequest {
+    pub operation: ItemOperation,
+    /// `None` when reading several items.
+    pub item: Option<ItemId>,
+    /// User owning the items, `None` when reading items of any user.
+    pub target_user: Option<UserId>,
+    /// Change of the amount of the target user: negative when sending, `0` when reading.
+    pub amount: i32,
+}
+
+/// Facts about an item and the apps of the authenticated user, loaded from the database.
+#[derive(Debug, Default)]
+pub struct ItemFacts {
+    /// Apps having rights on the item.
+    pub item_apps: Vec<AppId>,
+    /// Apps having rights on the item administered by the user,
+    /// with whether they require two-factor authentication from their admins.
+    pub admin_apps: Vec<(AppId, bool)>,
+    /// Apps whose rights baked in the biscuit are up to date, with their version.
+    pub fresh_item_rights: Vec<(AppId, i32)>,
+}
+
+impl ItemFacts {
+    pub async fn load(
+        connection: &PgPool,
+        user_id: UserId,
+        item_id: ItemId,
+    ) -> Result<ItemFacts, sqlx::Error> {
+        let item_apps = AppId::get_all_for_item(connection, item_id)
+            .await?
+            .into_iter()
+            .map(|app| app.app_id)
+            .collect::<Vec<_>>();
+        let mut admin_apps = vec![];
+        for app in AppId::get_all_for_user(user_id, connection).await? {
+            if item_apps.contains(&app.app_id) {
+                let policy = app.app_id.get_policy(connection).await?;
+                admin_apps.push((app.app_id, policy.require_admin_two_factor));
+            }
+        }
+        Ok(ItemFacts {
+            item_apps,
+            admin_apps,
+            fresh_item_rights: vec![],
+        })
+    }
+    /// Same as [`ItemFacts::load`], for an item loaded with its app.
+    pub fn for_item_of_app(item: &ItemOfApp) -> ItemFacts {
+        ItemFacts {
+            item_apps: vec![item.app_id],
+            admin_apps: item
+                .admin_two_factor_required
+                .map(|required| (item.app_id, required))
+                .into_iter()
+                .collect(),
+            fresh_item_rights: vec![],
+        }
+    }
+    /// Facts when requesting all items of an app.
+    pub fn for_app(app_id: AppId) -> ItemFacts {
+        ItemFacts {
+            item_apps: vec![app_id],
+            admin_apps: vec![],
+            fresh_item_rights: vec![],
+        }
+    }
+}
+
+fn add_item_facts(
+    authorizer: &mut Authorizer,
+    user_id: UserId,
+    facts: &ItemFacts,
+    request: &ItemRequest,
+) -> Result<(), biscuit_auth::error::Token> {
+    for app_id in &facts.item_apps {
+        authorizer.add_fact(fact("item_app", &[Term::Str((**app_id).to_string())]))?;
+    }
+    for (app_id, two_factor_required) in &facts.admin_apps {
+        authorizer.add_fact(fact(
+            "app_admin",
+            &[
+                Term::Str((*user_id).to_string()),
+                Term::Str((**app_id).to_string()),
+            ],
+        ))?;
+        authorizer.add_fact(fact(
+            "admin_two_factor_required",
+            &[
+                Term::Str((**app_id).to_string()),
+                Term::Bool(*two_factor_required),
+            ],
+        ))?;
+    }
+    for (app_id, version) in &facts.fresh_item_rights {
+        authorizer.add_fact(fact(
+            "current_item_rights_version",
+            &[
+                Term::Str((**app_id).to_string()),
+                Term::Integer(*version as i64),
+            ],
+        ))?;
+    }
+    authorizer.add_fact(fact(
+        "operation",
+        &[Term::Str(request.operation.name().to_string())],
+    ))?;
+    if let Some(item_id) = request.item {
+        authorizer.add_fact(fact("item", &[Term::Integer(*item_id as i64)]))?;
+    }
+    if let Some(target_user) = request.target_user {
+        authorizer.add_fact(fact(
+            "target_user",
+            &[Term::Str((*target_user).to_string())],
+        ))?;
+    }
+    authorizer.add_fact(fact("amount", &[Term::Integer(request.amount as i64)]))?;
+    Ok(())
+}
+
+/// Evaluates [`ITEM_POLICIES`] and the checks of the biscuit of the authenticated user, `user_id`.
+pub fn authorize_item_operation(
+    token: &Biscuit,
+    now: OffsetDateTime,
+    user_id: UserId,
+    facts: &ItemFacts,
+    request: &ItemRequest,
+) -> Result<(), ItemPolicyError> {
+    let mut authorizer = token.authorizer()?;
+    authorizer.add_fact(fact("time", &[Term::Date(now.unix_timestamp() as u64)]))?;
+    add_item_facts(&mut authorizer, user_id, facts, request)?;
+    authorizer.add_code(ITEM_POLICIES)?;
+    authorizer
+        .authorize()
+        .map_err(|_| ItemPolicyError::Denied)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use biscuit_auth::KeyPair;
+    use shared::{
+        biscuit::{attenuate, Attenuation, PublicKeys},
+        Role,
+    };
+    use time::Duration;
+
+    use super::*;
+    use crate::{
+        auth_user::authorize,
+        biscuit::RootKeys,
+        item_rights::{AppItemRights, BakedItemRights},
+        time::MockableDateTime,
+    };
+
+    const USER: i32 = 1;
+    const OTHER_USER: i32 = 2;
+    const APP: i32 = 10;
+    const OTHER_APP: i32 = 11;
+    const ITEM: i32 = 20;
+
+    fn root() -> RootKeys {
+        RootKeys::new(vec![(0, KeyPair::new())], PublicKeys::default()).unwrap()
+    }
+
+    fn token(role: Role, two_factor: bool) -> Biscuit {
+        token_signed_by(&root(), role, two_factor)
+    }
+
+    fn token_signed_by(root: &RootKeys, role: Role, two_factor: bool) -> Biscuit {
+        token_with_rights(root, role, two_factor, BakedItemRights::default())
+    }
+
+    fn token_with_rights(
+        root: &RootKeys,
+        role: Role,
+        two_factor: bool,
+        item_rights: BakedItemRights,
+    ) -> Biscuit {
+        UserId::from(USER).create_biscuit(
+            root,
+            role,
+            two_factor,
+            item_rights,
+            OffsetDateTime::now_utc() + Duration::minutes(1),
+        )
+    }
+
+    fn rights_of(
+        app: i32,
+        version: i32,
+        admin_two_factor_required: Option<bool>,
+    ) -> BakedItemRights {
+        BakedItemRights(vec![AppItemRights {
+            app_id: AppId::from(app),
+            version,
+            items: vec![ItemId(ITEM)],
+            admin_two_factor_required,
+        }])
+    }
+
+    fn fresh(app: i32, version: i32) -> ItemFacts {
+        ItemFacts {
+            fresh_item_rights: vec![(AppId::from(app), version)],
+            ..Default::default()
+        }
+    }
+
+    fn attenuated(role: Role, attenuation: &Attenuation) -> Biscuit {
+        let root = root();
+        let token = token_signed_by(&root, role, false).to_base64().unwrap();
+        let attenuated = attenuate(token.as_bytes(), root.public_keys(), attenuation).unwrap();
+        root.parse(std::str::from_utf8(&attenuated).unwrap())
+            .unwrap()
+    }
+
+    fn item_of(app: i32) -> ItemFacts {
+        ItemFacts {
+            item_apps: vec![AppId::from(app)],
+            ..Default::default()
+        }
+    }
+
+    fn modify(target_user: i32) -> ItemRequest {
+        ItemRequest {
+            operation: ItemOperation::Modify,
+            item: Some(ItemId(ITEM)),
+            target_user: Some(UserId::from(target_user)),
+            amount: 1,
+        }
+    }
+
+    fn modify_by(item: i32, amount: i32) -> ItemRequest {
+        ItemRequest {
+            item: Some(ItemId(item)),
+            amount,
+            ..modify(USER)
+        }
+    }
+
+    fn is_allowed(token: &Biscuit, facts: &ItemFacts, request: &ItemRequest) -> bool {
+        authorize_item_operation(
+            token,
+            OffsetDateTime::now_utc(),
+            UserId::from(USER),
+            facts,
+            request,
+        )
+        .is_ok()
+    }
+
+    #[test]
+    fn users_operate_on_their_own_items_of_their_app() {
+        let token = token(Role::User(shared::AppId(APP)), false);
+        assert!(is_allowed(&token, &item_of(APP), &modify(USER)));
+        assert!(!is_allowed(&token, &item_of(APP), &modify(OTHER_USER)));
+        assert!(!is_allowed(&token, &item_of(OTHER_APP), &modify(USER)));
+    }
+
+    #[test]
+    fn app_services_operate_on_items_of_their_app() {
+        let token = token(Role::AppService(shared::AppId(APP)), false);
+        assert!(is_allowed(&token, &item_of(APP), &modify(OTHER_USER)));
+        assert!(!is_allowed(
+            &token,
+            &item_of(OTHER_APP),
+            &modify(OTHER_USER)
+        ));
+    }
+
+    #[test]
+    fn admins_operate_on_their_own_items_of_apps_they_administer() {
+        let token = token(Role::Admin, false);
+        let administered = ItemFacts {
+            item_apps: vec![AppId::from(APP)],
+            admin_apps: vec![(AppId::from(APP), false)],
+            ..Default::default()
+        };
+        assert!(is_allowed(&token, &administered, &modify(USER)));
+        assert!(!is_allowed(&token, &administered, &modify(OTHER_USER)));
+        assert!(!is_allowed(&token, &item_of(APP), &modify(USER)));
+    }
+
+    #[test]
+    fn admins_need_two_factor_when_app_requires_it() {
+        let facts = ItemFacts {
+            item_apps: vec![AppId::from(APP)],
+            admin_apps: vec![(AppId::from(APP), true)],
+            ..Default::default()
+        };
+        assert!(!is_allowed(
+            &token(Role::Admin, false),
+            &facts,
+            &modify(USER)
+        ));
+        assert!(is_allowed(&token(Role::Admin, true), &facts, &modify(USER)));
+    }
+
+    #[test]
+    fn attenuated_tokens_are_restricted_to_their_checks() {
+        let attenuation = Attenuation {
+            items: vec![shared::ItemId(ITEM)],
+            only_decrement: true,
+            max_amount: Some(10),
+            ..Default::default()
+        };
+        let token = attenuated(Role::User(shared::AppId(APP)), &attenuation);
+        assert!(is_allowed(&token, &item_of(APP), &modify_by(ITEM, -10)));
+        assert!(!is_allowed(&token, &item_of(APP), &modify_by(ITEM, 1)));
+        assert!(!is_allowed(&token, &item_of(APP), &modify_by(ITEM, -11)));
+        assert!(!is_allowed(&token, &item_of(APP), &modify_by(ITEM + 1, -1)));
+        let read = ItemRequest {
+            operation: ItemOperation::Read,
+            amount: 0,
+            ..modify(USER)
+        };
+        assert!(is_allowed(&token, &item_of(APP), &read));
+    }
+
+    #[test]
+    fn attenuated_tokens_expire() {
+        let attenuation = Attenuation {
+            expiration_date_unix_timestamp: Some(
+                (OffsetDateTime::now_utc() - Duration::seconds(1)).unix_timestamp() as u64,
+            ),
+            ..Default::default()
+        };
+        let token = attenuated(Role::User(shared::AppId(APP)), &attenuation);
+        assert!(!is_allowed(&token, &item_of(APP), &modify(USER)));
+    }
+
+    #[test]
+    fn baked_rights_are_trusted_while_up_to_date() {
+        let user_token = token_with_rights(
+            &root(),
+            Role::User(shared::AppId(APP)),
+            false,
+            rights_of(APP, 1, None),
+        );
+        assert!(is_allowed(&user_token, &fresh(APP, 1), &modify(USER)));
+        assert!(!is_allowed(&user_token, &fresh(APP, 2), &modify(USER)));
+        assert!(!is_allowed(
+            &user_token,
+            &fresh(APP, 1),
+            &modify_by(ITEM + 1, 1)
+        ));
+
+        let admin_token =
+            token_with_rights(&root(), Role::Admin, false, rights_of(APP, 1, Some(false)));
+        assert!(is_allowed(&admin_token, &fresh(APP, 1), &modify(USER)));
+        let two_factor_admin_token =
+            token_with_rights(&root(), Role::Admin, false, rights_of(APP, 1, Some(true)));
+        assert!(!is_allowed(
+            &two_factor_admin_token,
+            &fresh(APP, 1),
+            &modify(USER)
+        ));
+    }
+
+    #[test]
+    fn facts_of_attenuated_blocks_are_ignored() {
+        let root = root();
+        let token = token_with_rights(
+            &root,
+            Role::User(shared::AppId(APP)),
+            false,
+            rights_of(APP, 1, None),
+        );
+        let mut block = token.create_block();
+        for forged in [
+            format!(r#"user("{OTHER_USER}")"#),
+            "is_admin(true)".to_string(),
+            "two_factor(true)".to_string(),
+            format!(r#"user_app_id("{OTHER_APP}")"#),
+            format!(r#"item_rights_version("{OTHER_APP}", 1)"#),
+            format!(r#"app_item("{OTHER_APP}", {})"#, ITEM + 1),
+            format!(r#"administered_app("{OTHER_APP}", false)"#),
+        ] {
+            block.add_fact(forged.as_str()).unwrap();
+        }
+        let forged = token.append(block).unwrap();
+        let time: MockableDateTime = serde_json::from_str("null").unwrap();
+
+        let biscuit_info = authorize(&forged, &time).expect("forged facts don't add checks");
+        assert_eq!(biscuit_info.user_id, shared::UserId(USER));
+        assert_eq!(biscuit_info.role, Role::User(shared::AppId(APP)));
+        assert!(!biscuit_info.two_factor);
+
+        let baked = BakedItemRights::from_biscuit(&forged).unwrap();
+        assert_eq!(baked.0.len(), 1);
+        assert_eq!(baked.0[0].app_id, AppId::from(APP));
+        assert_eq!(
+            baked.0[0]
+                .items
+                .iter()
+                .map(|item| **item)
+                .collect::<Vec<_>>(),
+            vec![ITEM]
+        );
+
+        let forged_fresh = ItemFacts {
+            fresh_item_rights: vec![(AppId::from(OTHER_APP), 1)],
+            ..Default::default()
+        };
+        assert!(!is_allowed(&forged, &forged_fresh, &modify_by(ITEM + 1, 1)));
+        assert!(is_allowed(&forged, &fresh(APP, 1), &modify(USER)));
+        // The forged `user` fact doesn't give access to the items of that user.
+        assert!(!is_allowed(&forged, &fresh(APP, 1), &modify(OTHER_USER)));
+        assert!(!is_allowed(
+            &forged,
+            &forged_fresh,
+            &ItemRequest {
+                item: Some(ItemId(ITEM + 1)),
+                ..modify(OTHER_USER)
+            }
+        ));
+    }
+}
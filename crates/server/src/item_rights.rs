@@ -0,0 +1,224 @@
+//! Item rights baked in biscuits at issue time, so item routes don't query the database for them.
+//!
+//! Rights are versioned by app: removing or restricting rights of an app increments its version,
+//! rights baked with a previous version are then ignored by [`ITEM_POLICIES`](crate::item_policy::ITEM_POLICIES)
+//! and looked up in the database, until the biscuit is refreshed.
+//! Rights missing from a biscuit, such as items created after it was issued, are looked up too.
+
+use std::{collections::HashMap, sync::RwLock};
+
+use biscuit_auth::{
+    builder::{BiscuitBuilder, Fact, Term},
+    Biscuit,
+};
+use shared::Role;
+use sqlx::PgPool;
+use time::{Duration, OffsetDateTime};
+
+use crate::{
+    biscuit::BiscuitBaker,
+    models::{
+        app::AppId,
+        item::{ItemId, ItemWithName},
+        user::UserId,
+    },
+    time::MockableDateTime,
+};
+
+/// Versions changed by other server instances are taken into account after at most this delay.
+pub const ITEM_RIGHTS_VERSIONS_RELOAD_INTERVAL: i64 = 10;
+
+/// Rights of an app on its items, as of `version`.
+#[derive(Debug)]
+pub struct AppItemRights {
+    pub app_id: AppId,
+    pub version: i32,
+    pub items: Vec<ItemId>,
+    /// `Some` for apps administered by the user, with whether they require
+    /// two-factor authentication from their admins.
+    pub admin_two_factor_required: Option<bool>,
+}
+
+/// Item rights of the apps of a biscuit: its app for users and app services,
+/// the apps they administer for admins.
+///
+/// Baked as `item_rights_version($app, $version)`, `app_item($app, $item)`
+/// and `administered_app($app, $two_factor_required)` facts.
+#[derive(Debug, Default)]
+pub struct BakedItemRights(pub Vec<AppItemRights>);
+
+impl BakedItemRights {
+    pub async fn load(
+        connection: &PgPool,
+        user_id: UserId,
+        role: Role,
+    ) -> Result<BakedItemRights, sqlx::Error> {
+        let apps = match role {
+            Role::User(app_id) | Role::AppService(app_id) => vec![(AppId::from(app_id), None)],
+            Role::Admin => {
+                let mut apps = vec![];
+                for app in AppId::get_all_for_user(user_id, connection).await? {
+                    let policy = app.app_id.get_policy(connection).await?;
+                    apps.push((app.app_id, Some(policy.require_admin_two_factor)));
+                }
+                apps
+            }
+        };
+        let mut rights = vec![];
+        for (app_id, admin_two_factor_required) in apps {
+            // Read before the items: rights removed in between increment the version again.
+            let Some(version) = app_id.get_item_rights_version(connection).await? else {
+                continue;
+            };
+            let items = ItemWithName::get_for_app(connection, app_id)
+                .await?
+                .into_iter()
+                .map(|item| item.id)
+                .collect();
+            rights.push(AppItemRights {
+                app_id,
+                version,
+                items,
+                admin_two_factor_required,
+            });
+        }
+        Ok(BakedItemRights(rights))
+    }
+
+    /// Whether the item is one of the baked items of an app with up to date rights.
+    pub fn covers(&self, fresh_apps: &[(AppId, i32)], item_id: ItemId) -> bool {
+        self.0.iter().any(|rights| {
+            fresh_apps
+                .iter()
+                .any(|(app_id, _)| *app_id == rights.app_id)
+                && rights.items.iter().any(|item| **item == *item_id)
+        })
+    }
+
+    /// Reads the rights baked in a biscuit, without their admin part.
+    ///
+    /// Only the authority block is read: the authorizer is not run, so the facts
+    /// of attenuated blocks are not loaded.
+    pub fn from_biscuit(token: &Biscuit) -> Result<BakedItemRights, biscuit_auth::error::Token> {
+        let mut authorizer = token.authorizer()?;
+        let versions: Vec<(String, i64)> =
+            authorizer.query("data($app, $version) <- item_rights_version($app, $version)")?;
+        let items: Vec<(String, i64)> =
+            authorizer.query("data($app, $item) <- app_item($app, $item)")?;
+        Ok(BakedItemRights(
+            versions
+                .into_iter()
+                .filter_map(|(app, version)| {
+                    Some(AppItemRights {
+                        app_id: AppId::from(app.parse::<i32>().ok()?),
+                        version: version as i32,
+                        items: items
+                            .iter()
+                            .filter(|(item_app, _)| *item_app == app)
+                            .map(|(_, item)| ItemId(*item as i32))
+                            .collect(),
+                        admin_two_factor_required: None,
+                    })
+                })
+                .collect(),
+        ))
+    }
+}
+
+impl<'a> BiscuitBaker<BakedItemRights> for BiscuitBuilder<'a> {
+    fn bake(mut builder: BiscuitBuilder, ingredient: BakedItemRights) -> BiscuitBuilder {
+        for rights in ingredient.0 {
+            let app = Term::Str((*rights.app_id).to_string());
+            builder
+                .add_authority_fact(Fact::new(
+                    "item_rights_version".to_string(),
+                    vec![app.clone(), Term::Integer(rights.version as i64)],
+                ))
+                .unwrap();
+            for item_id in rights.items {
+                builder
+                    .add_authority_fact(Fact::new(
+                        "app_item".to_string(),
+                        vec![app.clone(), Term::Integer(*item_id as i64)],
+                    ))
+                    .unwrap();
+            }
+            if let Some(two_factor_required) = rights.admin_two_factor_required {
+                builder
+                    .add_authority_fact(Fact::new(
+                        "administered_app".to_string(),
+                        vec![app.clone(), Term::Bool(two_factor_required)],
+                    ))
+                    .unwrap();
+            }
+        }
+        builder
+    }
+}
+
+struct Versions {
+    by_app: HashMap<i32, i32>,
+    loaded_at: Option<OffsetDateTime>,
+}
+
+/// Current item rights version of each app, kept in memory.
+pub struct ItemRightsVersions {
+    time: MockableDateTime,
+    versions: RwLock<Versions>,
+}
+
+impl ItemRightsVersions {
+    /// Loaded from the database on first use.
+    pub fn new(time: MockableDateTime) -> Self {
+        Self {
+            time,
+            versions: RwLock::new(Versions {
+                by_app: HashMap::new(),
+                loaded_at: None,
+            }),
+        }
+    }
+
+    async fn reload_if_stale(&self, connection: &PgPool) -> Result<(), sqlx::Error> {
+        let now = self.time.now_utc();
+        let is_stale = self
+            .versions
+            .read()
+            .unwrap()
+            .loaded_at
+            .map_or(true, |loaded_at| {
+                now - loaded_at >= Duration::seconds(ITEM_RIGHTS_VERSIONS_RELOAD_INTERVAL)
+            });
+        if is_stale {
+            let by_app = AppId::get_all_item_rights_versions(connection).await?;
+            let mut versions = self.versions.write().unwrap();
+            versions.by_app = by_app
+                .into_iter()
+                .map(|(app_id, version)| (*app_id, version))
+                .collect();
+            versions.loaded_at = Some(now);
+        }
+        Ok(())
+    }
+
+    /// Apps whose baked rights are up to date, with their version.
+    pub async fn fresh_apps(
+        &self,
+        connection: &PgPool,
+        baked: &BakedItemRights,
+    ) -> Result<Vec<(AppId, i32)>, sqlx::Error> {
+        self.reload_if_stale(connection).await?;
+        let versions = self.versions.read().unwrap();
+        Ok(baked
+            .0
+            .iter()
+            .filter(|rights| versions.by_app.get(&*rights.app_id) == Some(&rights.version))
+            .map(|rights| (rights.app_id, rights.version))
+            .collect())
+    }
+
+    /// Distrusts the baked rights of the app until the next reload, after its rights changed.
+    pub fn invalidate(&self, app_id: AppId) {
+        self.versions.write().unwrap().by_app.remove(&*app_id);
+    }
+}
@@ -1,11 +1,18 @@
 pub mod auth_user;
 pub mod biscuit;
 pub mod configuration;
+pub mod email;
+pub mod identity_provider;
+pub mod item_policy;
+pub mod item_rights;
 pub mod models;
 pub mod random_names;
+pub mod rate_limit;
+pub mod revocation;
 pub mod routes;
 pub mod telemetry;
 pub mod time;
+pub mod totp;
 
 use actix_cors::Cors;
 use actix_web::{
@@ -15,8 +22,13 @@ use actix_web::{
     App, HttpServer,
 };
 use configuration::{DatabaseSettings, Settings};
+use email::{mailer_from_settings, Mailer};
+use identity_provider::IdentityProviders;
+use item_rights::ItemRightsVersions;
+use rate_limit::{RateLimiter, RateLimiting};
+use revocation::RevocationList;
 use sqlx::{postgres::PgPoolOptions, PgPool};
-use std::net::TcpListener;
+use std::{net::TcpListener, sync::Arc};
 
 pub fn get_connection_pool(configuration: &DatabaseSettings) -> PgPool {
     PgPoolOptions::new()
@@ -31,7 +43,22 @@ pub fn run(
 ) -> Result<Server, std::io::Error> {
     let time = Data::new(settings.time.clone());
     let config = Data::new(settings);
-    let root = Data::new(config.get_keypair());
+    let root = Data::new(
+        config
+            .get_root_keys()
+            .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidInput, err))?,
+    );
+    let identity_providers = Data::new(IdentityProviders::from_settings(&config));
+    let rate_limiter = Data::new(RateLimiter::new(
+        config.rate_limit.clone(),
+        config.time.clone(),
+    ));
+    let revocation_list = Data::new(RevocationList::new(config.time.clone()));
+    let item_rights_versions = Data::new(ItemRightsVersions::new(config.time.clone()));
+    let mailer: Data<dyn Mailer> = Data::from(Arc::<dyn Mailer>::from(
+        mailer_from_settings(&config.mailer, config.production)
+            .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidInput, err))?,
+    ));
     let connection = Data::new(connection_pool);
 
     let server = HttpServer::new(move || {
@@ -46,6 +73,12 @@ pub fn run(
             .app_data(config.clone())
             .app_data(root.clone())
             .app_data(time.clone())
+            .app_data(identity_providers.clone())
+            .app_data(mailer.clone())
+            .app_data(rate_limiter.clone())
+            .app_data(revocation_list.clone())
+            .app_data(item_rights_versions.clone())
+            .wrap(RateLimiting::new(rate_limiter.clone(), root.clone()))
             .wrap(Logger::default())
             .wrap(cors)
             .service(
@@ -60,7 +93,6 @@ pub fn run(
             //
             //
             //.service(domains::config::config(config.clone()))
-            .service(models::oauth_github::oauth_github())
             .service(routes::oauth::routes())
 
         //
@@ -1,8 +1,15 @@
 pub mod app;
+pub mod app_credentials;
 pub mod email_password;
+pub mod guest;
+pub mod issued_biscuit;
 pub mod item;
-pub mod oauth_github;
+pub mod item_idempotency_key;
+pub mod item_ledger;
+pub mod login_method;
+pub mod password_reset_token;
 pub mod refresh_token;
+pub mod two_factor;
 pub mod user;
-pub mod user_github;
+pub mod user_identity;
 pub mod user_item;
@@ -1,4 +1,5 @@
 use serde::{Deserialize, Serialize};
+use shared::AppPolicy;
 use sqlx::PgPool;
 
 use super::user::UserId;
@@ -133,6 +134,99 @@ impl AppId {
         Ok(())
     }
 
+    pub async fn get_policy(&self, pool: &PgPool) -> Result<AppPolicy, sqlx::Error> {
+        let rec = sqlx::query!(
+            r#"
+            SELECT require_verified_email, require_admin_two_factor FROM apps WHERE id = $1
+            "#,
+            **self,
+        )
+        .fetch_one(pool)
+        .await?;
+        Ok(AppPolicy {
+            require_verified_email: rec.require_verified_email,
+            require_admin_two_factor: rec.require_admin_two_factor,
+        })
+    }
+
+    /// Apps the user can administer: those requiring two-factor authentication
+    /// from their admins are left out if the user didn't log in with it.
+    pub async fn get_all_administrable_for_user(
+        user: UserId,
+        two_factor: bool,
+        pool: &PgPool,
+    ) -> Result<Vec<AppWithName>, sqlx::Error> {
+        let rec = sqlx::query!(
+            r#"
+        SELECT app_id, name
+        FROM apps_admins
+        JOIN apps
+        ON apps.id = app_id
+        WHERE user_id = $1
+        AND ($2 OR NOT require_admin_two_factor)
+            "#,
+            *user,
+            two_factor,
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(rec
+            .into_iter()
+            .map(|r| AppWithName {
+                name: r.name,
+                app_id: AppId::from(r.app_id),
+            })
+            .collect())
+    }
+
+    /// Changing the two-factor requirement of admins invalidates the item rights baked in biscuits.
+    pub async fn set_policy(&self, pool: &PgPool, policy: &AppPolicy) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            r#"
+            UPDATE apps SET require_verified_email = $2, require_admin_two_factor = $3,
+            item_rights_version = item_rights_version
+                + (require_admin_two_factor IS DISTINCT FROM $3)::INT
+            WHERE id = $1
+            "#,
+            **self,
+            policy.require_verified_email,
+            policy.require_admin_two_factor,
+        )
+        .execute(pool)
+        .await?;
+        Ok(())
+    }
+
+    /// `None` if the app doesn't exist.
+    pub async fn get_item_rights_version(&self, pool: &PgPool) -> Result<Option<i32>, sqlx::Error> {
+        let rec = sqlx::query!(
+            r#"
+            SELECT item_rights_version FROM apps WHERE id = $1
+            "#,
+            **self,
+        )
+        .fetch_optional(pool)
+        .await?;
+        Ok(rec.map(|r| r.item_rights_version))
+    }
+
+    pub async fn get_all_item_rights_versions(
+        pool: &PgPool,
+    ) -> Result<Vec<(AppId, i32)>, sqlx::Error> {
+        let rec = sqlx::query!(
+            r#"
+            SELECT id, item_rights_version FROM apps
+            "#,
+        )
+        .fetch_all(pool)
+        .await?;
+        Ok(rec
+            .into_iter()
+            .map(|r| (AppId::from(r.id), r.item_rights_version))
+            .collect())
+    }
+
     pub async fn get_all_for_item(
         pool: &PgPool,
         item_id: super::item::ItemId,
@@ -0,0 +1,140 @@
+use serde::{Deserialize, Serialize};
+use shared::AppCredentialsId;
+use sqlx::PgPool;
+use time::{OffsetDateTime, PrimitiveDateTime};
+
+use super::{app::AppId, user::UserId};
+
+#[derive(Serialize, Deserialize)]
+pub struct AppCredentials {
+    pub id: AppCredentialsId,
+    pub app_id: AppId,
+    pub secret_hash: String,
+    pub created_by: UserId,
+    /// User of the app service biscuits issued for these credentials.
+    pub service_user: UserId,
+    pub created_at: OffsetDateTime,
+    pub revoked: bool,
+}
+
+impl From<AppCredentials> for shared::AppCredentials {
+    fn from(value: AppCredentials) -> Self {
+        Self {
+            id: value.id,
+            app_id: value.app_id.0,
+            created_by: value.created_by.0,
+            service_user_id: value.service_user.0,
+            created_at_unix_timestamp: value.created_at.unix_timestamp(),
+            revoked: value.revoked,
+        }
+    }
+}
+
+impl AppCredentials {
+    /// Creates the credentials with their service user.
+    pub async fn create(
+        pool: &PgPool,
+        app_id: AppId,
+        secret_hash: &str,
+        created_by: UserId,
+        created_at: OffsetDateTime,
+    ) -> Result<AppCredentialsId, sqlx::Error> {
+        let mut transaction = pool.begin().await?;
+        let service_user = sqlx::query!(
+            r#"
+    INSERT INTO users ( name ) VALUES ( $1 ) RETURNING id
+            "#,
+            format!("App {} service", *app_id),
+        )
+        .fetch_one(&mut transaction)
+        .await?;
+        let rec = sqlx::query!(
+            r#"
+    INSERT INTO apps_credentials
+        ( app_id, secret_hash, created_by, service_user_id, created_at, revoked )
+    VALUES ( $1, $2, $3, $4, $5, false ) RETURNING id
+            "#,
+            *app_id,
+            secret_hash,
+            *created_by,
+            service_user.id,
+            PrimitiveDateTime::new(created_at.date(), created_at.time()),
+        )
+        .fetch_one(&mut transaction)
+        .await?;
+        transaction.commit().await?;
+
+        Ok(AppCredentialsId(rec.id))
+    }
+    pub async fn get(pool: &PgPool, id: AppCredentialsId) -> Result<AppCredentials, sqlx::Error> {
+        sqlx::query!(
+            r#"
+            SELECT id, app_id, secret_hash, created_by, service_user_id, created_at, revoked
+            FROM apps_credentials
+            WHERE id = $1
+            "#,
+            *id,
+        )
+        .fetch_one(pool)
+        .await
+        .map(|r| AppCredentials {
+            id: AppCredentialsId(r.id),
+            app_id: AppId::from(r.app_id),
+            secret_hash: r.secret_hash,
+            created_by: UserId::from(r.created_by),
+            service_user: UserId::from(r.service_user_id),
+            created_at: r.created_at.assume_utc(),
+            revoked: r.revoked,
+        })
+    }
+    pub async fn get_all_for_app(
+        pool: &PgPool,
+        app_id: AppId,
+    ) -> Result<Vec<AppCredentials>, sqlx::Error> {
+        let rec = sqlx::query!(
+            r#"
+            SELECT id, app_id, secret_hash, created_by, service_user_id, created_at, revoked
+            FROM apps_credentials
+            WHERE app_id = $1
+            ORDER BY id
+            "#,
+            *app_id,
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(rec
+            .into_iter()
+            .map(|r| AppCredentials {
+                id: AppCredentialsId(r.id),
+                app_id: AppId::from(r.app_id),
+                secret_hash: r.secret_hash,
+                created_by: UserId::from(r.created_by),
+                service_user: UserId::from(r.service_user_id),
+                created_at: r.created_at.assume_utc(),
+                revoked: r.revoked,
+            })
+            .collect())
+    }
+    /// Returns the service user of the credentials, whose biscuits should be revoked too.
+    ///
+    /// Returns an error if no credentials with this id exist for the given app.
+    pub async fn revoke(
+        pool: &PgPool,
+        app_id: AppId,
+        id: AppCredentialsId,
+    ) -> Result<UserId, sqlx::Error> {
+        sqlx::query!(
+            r#"
+            UPDATE apps_credentials SET revoked = true
+            WHERE id = $1 AND app_id = $2
+            RETURNING service_user_id
+            "#,
+            *id,
+            *app_id,
+        )
+        .fetch_one(pool)
+        .await
+        .map(|rec| UserId::from(rec.service_user_id))
+    }
+}
@@ -1,10 +1,19 @@
 use sqlx::PgPool;
+use time::{OffsetDateTime, PrimitiveDateTime};
 
-use super::user::UserId;
+use super::{password_reset_token::hash_reset_token, user::UserId};
 
 #[derive(Debug, Clone, Copy)]
 pub struct EmailPasswordId(pub i32);
 
+#[derive(Debug, Clone)]
+pub struct EmailPassword {
+    pub id: EmailPasswordId,
+    pub password_hash: String,
+    pub is_verified: bool,
+    pub user_id: UserId,
+}
+
 /// Checks if a email exists as an email/password record.
 pub async fn exist(connection: &PgPool, email: &str) -> bool {
     sqlx::query!(
@@ -15,25 +24,65 @@ pub async fn exist(connection: &PgPool, email: &str) -> bool {
     .await
     .is_ok()
 }
-/// Checks if a email exists as an email/password record and return its id, password hash and user_id.
-pub async fn find(
-    connection: &PgPool,
-    email: &str,
-) -> Result<(EmailPasswordId, String, UserId), sqlx::Error> {
+/// Checks if a email exists as an email/password record and return it.
+pub async fn find(connection: &PgPool, email: &str) -> Result<EmailPassword, sqlx::Error> {
     sqlx::query!(
-        "SELECT id, password_hash, user_id FROM users_email_password WHERE email = $1",
+        "SELECT id, password_hash, is_verified, user_id FROM users_email_password WHERE email = $1",
         email
     )
     .fetch_one(connection)
     .await
-    .map(|rec| {
-        (
-            EmailPasswordId(rec.id),
-            rec.password_hash,
-            UserId::from(rec.user_id),
-        )
+    .map(|rec| EmailPassword {
+        id: EmailPasswordId(rec.id),
+        password_hash: rec.password_hash,
+        is_verified: rec.is_verified,
+        user_id: UserId::from(rec.user_id),
     })
 }
+/// Finds the email/password record of a user.
+pub async fn find_by_user(
+    connection: &PgPool,
+    user_id: UserId,
+) -> Result<EmailPassword, sqlx::Error> {
+    sqlx::query!(
+        "SELECT id, password_hash, is_verified, user_id FROM users_email_password WHERE user_id = $1",
+        *user_id
+    )
+    .fetch_one(connection)
+    .await
+    .map(|rec| EmailPassword {
+        id: EmailPasswordId(rec.id),
+        password_hash: rec.password_hash,
+        is_verified: rec.is_verified,
+        user_id: UserId::from(rec.user_id),
+    })
+}
+/// Whether one of the emails of the user is verified.
+pub async fn has_verified_email(connection: &PgPool, user_id: UserId) -> Result<bool, sqlx::Error> {
+    sqlx::query!(
+        r#"SELECT EXISTS (
+            SELECT 1 FROM users_email_password WHERE user_id = $1 AND is_verified
+        ) AS "verified!""#,
+        *user_id
+    )
+    .fetch_one(connection)
+    .await
+    .map(|rec| rec.verified)
+}
+pub async fn set_password_hash(
+    connection: &PgPool,
+    id: EmailPasswordId,
+    password_hash: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "UPDATE users_email_password SET password_hash = $2 WHERE id = $1",
+        id.0,
+        password_hash,
+    )
+    .execute(connection)
+    .await?;
+    Ok(())
+}
 /// Meant to be used with another query following, to link it to this authentication method.
 /// FIXME: This API could be reworked to be misuse resistant.
 pub async fn create(
@@ -57,3 +106,95 @@ pub async fn create(
     .map(|_| true)
     .is_ok()
 }
+/// Creates a user with its email/password record, in a single transaction.
+///
+/// Returns `None` if the email is already used, even by a concurrent signup: nothing is created then.
+pub async fn create_with_user(
+    pool: &PgPool,
+    name: &str,
+    email: &str,
+    password_hash: &str,
+) -> Result<Option<UserId>, sqlx::Error> {
+    let mut transaction = pool.begin().await?;
+    let user = sqlx::query!(
+        r#"
+            INSERT INTO users (name) VALUES ($1)
+            RETURNING id
+        "#,
+        name,
+    )
+    .fetch_one(&mut transaction)
+    .await?;
+    let created = sqlx::query!(
+        r#"
+            INSERT INTO users_email_password (email, password_hash, is_verified, user_id) VALUES ($1, $2, $3, $4)
+            ON CONFLICT (email) DO NOTHING
+            RETURNING user_id
+        "#,
+        email,
+        password_hash,
+        false,
+        user.id,
+    )
+    .fetch_optional(&mut transaction)
+    .await?;
+    let Some(created) = created else {
+        transaction.rollback().await?;
+        return Ok(None);
+    };
+    transaction.commit().await?;
+    Ok(Some(UserId::from(created.user_id)))
+}
+/// Removes an email/password record, its user is kept.
+pub async fn delete(connection: &PgPool, email: &str) -> Result<(), sqlx::Error> {
+    sqlx::query!("DELETE FROM users_email_password WHERE email = $1", email)
+        .execute(connection)
+        .await?;
+    Ok(())
+}
+/// Replaces any previous verification code for this email, only its hash is stored.
+pub async fn set_verification_code(
+    connection: &PgPool,
+    email: &str,
+    code: &str,
+    expiration_date: OffsetDateTime,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+            UPDATE users_email_password
+            SET verification_code_hash = $2, verification_code_expiration_date = $3
+            WHERE email = $1
+            RETURNING id
+        "#,
+        email,
+        hash_reset_token(code),
+        PrimitiveDateTime::new(expiration_date.date(), expiration_date.time()),
+    )
+    .fetch_one(connection)
+    .await?;
+    Ok(())
+}
+/// Flags the email as verified if the code is correct and not expired, the code can only be used once.
+pub async fn verify(
+    connection: &PgPool,
+    email: &str,
+    code: &str,
+    now: OffsetDateTime,
+) -> Result<UserId, sqlx::Error> {
+    sqlx::query!(
+        r#"
+            UPDATE users_email_password
+            SET is_verified = true, verification_code_hash = NULL, verification_code_expiration_date = NULL
+            WHERE email = $1
+            AND verification_code_hash = $2
+            AND verification_code_expiration_date > $3
+            RETURNING user_id
+        "#,
+        email,
+        hash_reset_token(code),
+        PrimitiveDateTime::new(now.date(), now.time()),
+    )
+    .fetch_one(connection)
+    .await
+    .map(|rec| UserId::from(rec.user_id))
+}
@@ -0,0 +1,107 @@
+use data_encoding::HEXLOWER;
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+use time::{OffsetDateTime, PrimitiveDateTime};
+
+use super::{app::AppId, user::UserId};
+
+/// A user without email nor identity, who logs in with the secret stored on their device.
+#[derive(Debug, Clone, Copy)]
+pub struct Guest {
+    pub user_id: UserId,
+    /// Guests can only log in as users of this app.
+    pub app_id: AppId,
+}
+
+/// Device secrets are random enough for a fast hash, which allows looking guests up by hash.
+pub fn hash_device_secret(device_secret: &str) -> String {
+    HEXLOWER.encode(&Sha256::digest(device_secret.as_bytes()))
+}
+
+impl Guest {
+    /// Creates the user and its guest record.
+    pub async fn create(
+        pool: &PgPool,
+        name: &str,
+        device_secret_hash: &str,
+        app_id: AppId,
+        created_at: OffsetDateTime,
+    ) -> Result<Guest, sqlx::Error> {
+        let mut transaction = pool.begin().await?;
+        let user = sqlx::query!(
+            r#"
+            INSERT INTO users (name) VALUES ($1)
+            RETURNING id
+            "#,
+            name,
+        )
+        .fetch_one(&mut transaction)
+        .await?;
+        sqlx::query!(
+            r#"
+            INSERT INTO users_guests ( user_id, device_secret_hash, app_id, created_at )
+            VALUES ( $1, $2, $3, $4 )
+            "#,
+            user.id,
+            device_secret_hash,
+            *app_id,
+            PrimitiveDateTime::new(created_at.date(), created_at.time()),
+        )
+        .execute(&mut transaction)
+        .await?;
+        transaction.commit().await?;
+        Ok(Guest {
+            user_id: UserId::from(user.id),
+            app_id,
+        })
+    }
+
+    pub async fn find(pool: &PgPool, device_secret_hash: &str) -> Result<Guest, sqlx::Error> {
+        sqlx::query!(
+            r#"
+            SELECT user_id, app_id FROM users_guests WHERE device_secret_hash = $1
+            "#,
+            device_secret_hash,
+        )
+        .fetch_one(pool)
+        .await
+        .map(|rec| Guest {
+            user_id: UserId::from(rec.user_id),
+            app_id: AppId::from(rec.app_id),
+        })
+    }
+
+    /// Returns `None` for users who are not guests, or not anymore.
+    pub async fn get(pool: &PgPool, user_id: UserId) -> Result<Option<Guest>, sqlx::Error> {
+        sqlx::query!(
+            r#"
+            SELECT user_id, app_id FROM users_guests WHERE user_id = $1
+            "#,
+            *user_id,
+        )
+        .fetch_optional(pool)
+        .await
+        .map(|rec| {
+            rec.map(|r| Guest {
+                user_id: UserId::from(r.user_id),
+                app_id: AppId::from(r.app_id),
+            })
+        })
+    }
+
+    /// Makes the user a regular one, once they attached an identity, or an email/password
+    /// they verified or logged in with: the device secret can't be used anymore, their items are kept.
+    ///
+    /// Does nothing for users who are not guests.
+    pub async fn upgrade(pool: &PgPool, user_id: UserId) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            r#"
+            DELETE FROM users_guests WHERE user_id = $1
+            "#,
+            *user_id,
+        )
+        .execute(pool)
+        .await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,146 @@
+use shared::RefreshTokenFamilyId;
+use sqlx::PgPool;
+use time::{OffsetDateTime, PrimitiveDateTime};
+
+use super::user::UserId;
+
+/// Records a biscuit issued to the user, so it can be revoked with all the other biscuits of the user,
+/// or of its session.
+pub async fn record(
+    pool: &PgPool,
+    revocation_id: &[u8],
+    user_id: UserId,
+    family_id: Option<RefreshTokenFamilyId>,
+    expiration_date: OffsetDateTime,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO issued_biscuits ( revocation_id, user_id, family_id, expiration_date )
+        VALUES ( $1, $2, $3, $4 )
+        "#,
+        revocation_id,
+        *user_id,
+        family_id.map(|family_id| *family_id),
+        PrimitiveDateTime::new(expiration_date.date(), expiration_date.time()),
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Revokes a biscuit, attenuated ones are recorded there.
+pub async fn revoke(
+    pool: &PgPool,
+    revocation_id: &[u8],
+    user_id: UserId,
+    expiration_date: OffsetDateTime,
+    now: OffsetDateTime,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO issued_biscuits ( revocation_id, user_id, expiration_date, revoked_at )
+        VALUES ( $1, $2, $3, $4 )
+        ON CONFLICT (revocation_id) DO UPDATE SET revoked_at = COALESCE(issued_biscuits.revoked_at, $4)
+        "#,
+        revocation_id,
+        *user_id,
+        PrimitiveDateTime::new(expiration_date.date(), expiration_date.time()),
+        PrimitiveDateTime::new(now.date(), now.time()),
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Revokes all unexpired biscuits issued to the user, returns their revocation identifiers.
+pub async fn revoke_all_for_user(
+    pool: &PgPool,
+    user_id: UserId,
+    now: OffsetDateTime,
+) -> Result<Vec<Vec<u8>>, sqlx::Error> {
+    let rec = sqlx::query!(
+        r#"
+        UPDATE issued_biscuits SET revoked_at = $2
+        WHERE user_id = $1 AND revoked_at IS NULL AND expiration_date > $2
+        RETURNING revocation_id
+        "#,
+        *user_id,
+        PrimitiveDateTime::new(now.date(), now.time()),
+    )
+    .fetch_all(pool)
+    .await?;
+    Ok(rec.into_iter().map(|r| r.revocation_id).collect())
+}
+
+/// Revokes all unexpired biscuits issued in the session, returns their revocation identifiers.
+pub async fn revoke_all_for_family(
+    pool: &PgPool,
+    family_id: RefreshTokenFamilyId,
+    now: OffsetDateTime,
+) -> Result<Vec<Vec<u8>>, sqlx::Error> {
+    let rec = sqlx::query!(
+        r#"
+        UPDATE issued_biscuits SET revoked_at = $2
+        WHERE family_id = $1 AND revoked_at IS NULL AND expiration_date > $2
+        RETURNING revocation_id
+        "#,
+        *family_id,
+        PrimitiveDateTime::new(now.date(), now.time()),
+    )
+    .fetch_all(pool)
+    .await?;
+    Ok(rec.into_iter().map(|r| r.revocation_id).collect())
+}
+
+/// Revokes all unexpired biscuits issued in the other sessions of the user,
+/// returns their revocation identifiers.
+pub async fn revoke_all_for_user_except_family(
+    pool: &PgPool,
+    user_id: UserId,
+    family_id: RefreshTokenFamilyId,
+    now: OffsetDateTime,
+) -> Result<Vec<Vec<u8>>, sqlx::Error> {
+    let rec = sqlx::query!(
+        r#"
+        UPDATE issued_biscuits SET revoked_at = $3
+        WHERE user_id = $1 AND family_id <> $2 AND revoked_at IS NULL AND expiration_date > $3
+        RETURNING revocation_id
+        "#,
+        *user_id,
+        *family_id,
+        PrimitiveDateTime::new(now.date(), now.time()),
+    )
+    .fetch_all(pool)
+    .await?;
+    Ok(rec.into_iter().map(|r| r.revocation_id).collect())
+}
+
+/// Revocation identifiers of the revoked biscuits which are not expired yet.
+pub async fn get_all_revoked(
+    pool: &PgPool,
+    now: OffsetDateTime,
+) -> Result<Vec<Vec<u8>>, sqlx::Error> {
+    let rec = sqlx::query!(
+        r#"
+        SELECT revocation_id FROM issued_biscuits
+        WHERE revoked_at IS NOT NULL AND expiration_date > $1
+        "#,
+        PrimitiveDateTime::new(now.date(), now.time()),
+    )
+    .fetch_all(pool)
+    .await?;
+    Ok(rec.into_iter().map(|r| r.revocation_id).collect())
+}
+
+/// Deletes the biscuits expired before `now`: they are refused anyway, revoked or not.
+pub async fn delete_expired(pool: &PgPool, now: OffsetDateTime) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"
+        DELETE FROM issued_biscuits WHERE expiration_date <= $1
+        "#,
+        PrimitiveDateTime::new(now.date(), now.time()),
+    )
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected())
+}
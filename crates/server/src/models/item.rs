@@ -1,4 +1,5 @@
 use serde::{Deserialize, Serialize};
+use shared::ItemBounds;
 use sqlx::PgPool;
 
 use super::{app::AppId, user::UserId};
@@ -15,7 +16,17 @@ impl std::ops::Deref for ItemId {
 }
 
 impl ItemId {
+    /// Invalidates the item rights of its app baked in biscuits.
     pub async fn delete(&self, pool: &PgPool) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            r#"
+            UPDATE apps SET item_rights_version = item_rights_version + 1
+            WHERE id = (SELECT app_id FROM items WHERE id = $1)
+            "#,
+            self.0,
+        )
+        .execute(pool)
+        .await?;
         let _rec = sqlx::query!(
             r#"
                 DELETE FROM items
@@ -27,6 +38,23 @@ impl ItemId {
         .await?;
         Ok(())
     }
+
+    /// Current amounts out of the new bounds are kept, they can only move towards them.
+    pub async fn set_bounds(&self, pool: &PgPool, bounds: &ItemBounds) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            r#"
+            UPDATE items SET min_amount = $1, max_amount = $2, max_change = $3
+            WHERE id = $4
+            "#,
+            bounds.min_amount,
+            bounds.max_amount,
+            bounds.max_change,
+            self.0,
+        )
+        .execute(pool)
+        .await?;
+        Ok(())
+    }
 }
 
 pub async fn create(name: &str, app_id: AppId, connection: &PgPool) -> Result<ItemId, sqlx::Error> {
@@ -76,6 +104,7 @@ impl UserId {
 pub struct ItemFull {
     pub item: ItemWithName,
     pub app_id: AppId,
+    pub bounds: ItemBounds,
 }
 #[derive(Serialize, Deserialize)]
 pub struct ItemWithName {
@@ -123,7 +152,7 @@ impl ItemFull {
     pub async fn get(id: ItemId, connection: &PgPool) -> Option<ItemFull> {
         sqlx::query!(
             r#"
-            SELECT id, name, app_id FROM items WHERE id = $1
+            SELECT id, name, app_id, min_amount, max_amount, max_change FROM items WHERE id = $1
             "#,
             id.0,
         )
@@ -135,6 +164,11 @@ impl ItemFull {
                 name: r.name,
             },
             app_id: AppId::from(r.app_id),
+            bounds: ItemBounds {
+                min_amount: r.min_amount,
+                max_amount: r.max_amount,
+                max_change: r.max_change,
+            },
         })
         .ok()
     }
@@ -162,3 +196,47 @@ impl ItemWithName {
             .collect())
     }
 }
+
+/// An item with what the item policies need to know about its app.
+#[derive(Debug, Clone, Copy)]
+pub struct ItemOfApp {
+    pub id: ItemId,
+    pub app_id: AppId,
+    /// Whether the app requires two-factor authentication from its admins,
+    /// `None` if the user doesn't administer it.
+    pub admin_two_factor_required: Option<bool>,
+}
+
+impl ItemOfApp {
+    /// Items of these apps, with whether `user_id` administers them, in a single query.
+    pub async fn get_all_for_apps(
+        connection: &PgPool,
+        app_ids: &[AppId],
+        user_id: UserId,
+    ) -> Result<Vec<ItemOfApp>, sqlx::Error> {
+        let app_ids: Vec<i32> = app_ids.iter().map(|app_id| **app_id).collect();
+        let rec = sqlx::query!(
+            r#"
+            SELECT items.id, items.app_id, apps.require_admin_two_factor,
+                apps_admins.user_id IS NOT NULL AS "is_admin!"
+            FROM items
+            JOIN apps ON apps.id = items.app_id
+            LEFT JOIN apps_admins ON apps_admins.app_id = items.app_id AND apps_admins.user_id = $2
+            WHERE items.app_id = ANY($1)
+            ORDER BY items.id
+            "#,
+            &app_ids,
+            *user_id,
+        )
+        .fetch_all(connection)
+        .await?;
+        Ok(rec
+            .into_iter()
+            .map(|r| ItemOfApp {
+                id: ItemId(r.id),
+                app_id: AppId::from(r.app_id),
+                admin_two_factor_required: r.is_admin.then_some(r.require_admin_two_factor),
+            })
+            .collect())
+    }
+}
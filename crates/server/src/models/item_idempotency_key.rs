@@ -0,0 +1,121 @@
+use sqlx::{Postgres, Transaction};
+use time::{Duration, OffsetDateTime, PrimitiveDateTime};
+
+use super::{item::ItemId, user::UserId};
+
+/// Keys are forgotten after this delay: a request sent again with the same key is then applied again.
+pub const IDEMPOTENCY_KEY_RETENTION_HOURS: i64 = 24;
+
+/// Longest idempotency key accepted, in bytes.
+pub const IDEMPOTENCY_KEY_MAX_LENGTH: usize = 255;
+
+/// Modifications previously applied with the same key, a single one or those of a transaction.
+pub struct StoredModification {
+    /// `(item, user, amount)`, in the order of the request.
+    pub operations: Vec<(ItemId, UserId, i32)>,
+    /// In the order of `operations`.
+    pub new_amounts: Vec<i32>,
+}
+
+impl StoredModification {
+    /// Whether the key is sent again for the same modifications, and not reused for other ones.
+    pub fn is_same_request(&self, operations: &[(ItemId, UserId, i32)]) -> bool {
+        self.operations.len() == operations.len()
+            && self.operations.iter().zip(operations).all(
+                |((stored_item, stored_user, stored_amount), (item_id, user_id, amount))| {
+                    **stored_item == **item_id
+                        && *stored_user == *user_id
+                        && stored_amount == amount
+                },
+            )
+    }
+}
+
+/// Claims the key of `actor` for modifications, in the transaction applying them.
+///
+/// Returns the modifications previously applied with this key, if any.
+/// Concurrent requests with the same key wait for the first one to commit or roll back.
+pub async fn claim(
+    transaction: &mut Transaction<'_, Postgres>,
+    actor: UserId,
+    key: &str,
+    operations: &[(ItemId, UserId, i32)],
+    now: OffsetDateTime,
+) -> Result<Option<StoredModification>, sqlx::Error> {
+    let expired_before = now - Duration::hours(IDEMPOTENCY_KEY_RETENTION_HOURS);
+    sqlx::query!(
+        r#"
+        DELETE FROM item_idempotency_keys
+        WHERE actor_user_id = $1 AND created_at < $2
+        "#,
+        *actor,
+        PrimitiveDateTime::new(expired_before.date(), expired_before.time()),
+    )
+    .execute(&mut *transaction)
+    .await?;
+    let item_ids: Vec<i32> = operations.iter().map(|(item_id, _, _)| **item_id).collect();
+    let user_ids: Vec<i32> = operations.iter().map(|(_, user_id, _)| **user_id).collect();
+    let amounts: Vec<i32> = operations.iter().map(|(_, _, amount)| *amount).collect();
+    let claimed = sqlx::query!(
+        r#"
+        INSERT INTO item_idempotency_keys
+            ( actor_user_id, key, item_ids, user_ids, amounts, created_at )
+        VALUES ( $1, $2, $3, $4, $5, $6 )
+        ON CONFLICT DO NOTHING
+        RETURNING key
+        "#,
+        *actor,
+        key,
+        &item_ids,
+        &user_ids,
+        &amounts,
+        PrimitiveDateTime::new(now.date(), now.time()),
+    )
+    .fetch_optional(&mut *transaction)
+    .await?;
+    if claimed.is_some() {
+        return Ok(None);
+    }
+    let rec = sqlx::query!(
+        r#"
+        SELECT item_ids, user_ids, amounts, new_amounts AS "new_amounts!"
+        FROM item_idempotency_keys
+        WHERE actor_user_id = $1 AND key = $2
+        "#,
+        *actor,
+        key,
+    )
+    .fetch_one(&mut *transaction)
+    .await?;
+    Ok(Some(StoredModification {
+        operations: rec
+            .item_ids
+            .into_iter()
+            .zip(rec.user_ids)
+            .zip(rec.amounts)
+            .map(|((item_id, user_id), amount)| (ItemId(item_id), UserId::from(user_id), amount))
+            .collect(),
+        new_amounts: rec.new_amounts,
+    }))
+}
+
+/// Stores the results of the modifications the key was claimed for.
+pub async fn complete(
+    transaction: &mut Transaction<'_, Postgres>,
+    actor: UserId,
+    key: &str,
+    new_amounts: &[i32],
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE item_idempotency_keys SET new_amounts = $3
+        WHERE actor_user_id = $1 AND key = $2
+        "#,
+        *actor,
+        key,
+        new_amounts,
+    )
+    .execute(&mut *transaction)
+    .await?;
+    Ok(())
+}
@@ -0,0 +1,164 @@
+use sqlx::{PgPool, Postgres, Transaction};
+use time::{OffsetDateTime, PrimitiveDateTime};
+
+use super::{app::AppId, item::ItemId, user::UserId};
+
+/// Entries returned in a single page at most, by the history routes.
+pub const LEDGER_PAGE_MAX_SIZE: i64 = 100;
+
+/// Who changed item amounts and why, recorded in the ledger with each change.
+#[derive(Debug, Clone)]
+pub struct ItemChange {
+    pub actor: UserId,
+    /// `None` for admin tokens.
+    pub app_id: Option<AppId>,
+    pub reason: Option<String>,
+    pub at: OffsetDateTime,
+}
+
+pub struct LedgerEntry {
+    pub id: i64,
+    pub item_id: ItemId,
+    pub user_id: UserId,
+    pub actor_user_id: UserId,
+    pub app_id: Option<AppId>,
+    pub delta: i32,
+    pub balance: i32,
+    pub reason: Option<String>,
+    pub created_at: OffsetDateTime,
+}
+
+impl From<LedgerEntry> for shared::LedgerEntry {
+    fn from(value: LedgerEntry) -> Self {
+        Self {
+            id: value.id,
+            item_id: shared::ItemId(*value.item_id),
+            user_id: value.user_id.0,
+            actor_user_id: value.actor_user_id.0,
+            app_id: value.app_id.map(|app_id| app_id.0),
+            delta: value.delta,
+            balance: value.balance,
+            reason: value.reason,
+            created_at_unix_timestamp: value.created_at.unix_timestamp(),
+        }
+    }
+}
+
+/// Appends an entry, in the transaction changing the amount.
+pub async fn record(
+    transaction: &mut Transaction<'_, Postgres>,
+    item_id: ItemId,
+    user_id: UserId,
+    delta: i32,
+    balance: i32,
+    change: &ItemChange,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO item_ledger
+            ( item_id, user_id, actor_user_id, app_id, delta, balance, reason, created_at )
+        VALUES ( $1, $2, $3, $4, $5, $6, $7, $8 )
+        "#,
+        *item_id,
+        *user_id,
+        *change.actor,
+        change.app_id.map(|app_id| *app_id),
+        delta,
+        balance,
+        change.reason,
+        PrimitiveDateTime::new(change.at.date(), change.at.time()),
+    )
+    .execute(&mut *transaction)
+    .await?;
+    Ok(())
+}
+
+impl LedgerEntry {
+    /// Entries of the user older than `before`, most recent first,
+    /// restricted to items of `app_ids` when given.
+    pub async fn get_for_user(
+        pool: &PgPool,
+        user_id: UserId,
+        app_ids: Option<&[AppId]>,
+        before: Option<i64>,
+        limit: i64,
+    ) -> Result<Vec<LedgerEntry>, sqlx::Error> {
+        let app_ids: Option<Vec<i32>> =
+            app_ids.map(|app_ids| app_ids.iter().map(|app_id| **app_id).collect());
+        let rec = sqlx::query!(
+            r#"
+        SELECT item_ledger.id, item_id, user_id, actor_user_id, item_ledger.app_id,
+            delta, balance, reason, item_ledger.created_at
+        FROM item_ledger
+        JOIN items ON items.id = item_id
+        WHERE user_id = $1
+        AND ($2::int[] IS NULL OR items.app_id = ANY($2))
+        AND ($3::bigint IS NULL OR item_ledger.id < $3)
+        ORDER BY item_ledger.id DESC
+        LIMIT $4
+            "#,
+            *user_id,
+            app_ids.as_deref(),
+            before,
+            limit,
+        )
+        .fetch_all(pool)
+        .await?;
+        Ok(rec
+            .into_iter()
+            .map(|r| LedgerEntry {
+                id: r.id,
+                item_id: ItemId(r.item_id),
+                user_id: UserId::from(r.user_id),
+                actor_user_id: UserId::from(r.actor_user_id),
+                app_id: r.app_id.map(AppId::from),
+                delta: r.delta,
+                balance: r.balance,
+                reason: r.reason,
+                created_at: r.created_at.assume_utc(),
+            })
+            .collect())
+    }
+
+    /// Entries of the item older than `before`, most recent first,
+    /// restricted to those of `user_id` when given.
+    pub async fn get_for_item(
+        pool: &PgPool,
+        item_id: ItemId,
+        user_id: Option<UserId>,
+        before: Option<i64>,
+        limit: i64,
+    ) -> Result<Vec<LedgerEntry>, sqlx::Error> {
+        let rec = sqlx::query!(
+            r#"
+        SELECT id, item_id, user_id, actor_user_id, app_id, delta, balance, reason, created_at
+        FROM item_ledger
+        WHERE item_id = $1
+        AND ($2::int IS NULL OR user_id = $2)
+        AND ($3::bigint IS NULL OR id < $3)
+        ORDER BY id DESC
+        LIMIT $4
+            "#,
+            *item_id,
+            user_id.map(|user_id| *user_id),
+            before,
+            limit,
+        )
+        .fetch_all(pool)
+        .await?;
+        Ok(rec
+            .into_iter()
+            .map(|r| LedgerEntry {
+                id: r.id,
+                item_id: ItemId(r.item_id),
+                user_id: UserId::from(r.user_id),
+                actor_user_id: UserId::from(r.actor_user_id),
+                app_id: r.app_id.map(AppId::from),
+                delta: r.delta,
+                balance: r.balance,
+                reason: r.reason,
+                created_at: r.created_at.assume_utc(),
+            })
+            .collect())
+    }
+}
@@ -0,0 +1,134 @@
+use shared::{AppId, LoginMethod};
+use sqlx::{PgPool, Postgres, Transaction};
+use thiserror::Error;
+
+use super::user::UserId;
+
+#[derive(Error, Debug)]
+pub enum DetachError {
+    #[error("login method not found")]
+    NotFound,
+    /// The user would not be able to log in anymore.
+    #[error("last login method of the user")]
+    LastLoginMethod,
+    #[error("database error")]
+    Database(#[from] sqlx::Error),
+}
+
+/// Email/passwords, identities and guest device secret of a user.
+pub async fn get_all_for_user(
+    pool: &PgPool,
+    user_id: UserId,
+) -> Result<Vec<LoginMethod>, sqlx::Error> {
+    let email_passwords = sqlx::query!(
+        r#"
+        SELECT email, is_verified FROM users_email_password WHERE user_id = $1 ORDER BY id
+        "#,
+        *user_id,
+    )
+    .fetch_all(pool)
+    .await?;
+    let identities = sqlx::query!(
+        r#"
+        SELECT provider, external_id, login FROM users_identities
+        WHERE user_id = $1 ORDER BY provider, external_id
+        "#,
+        *user_id,
+    )
+    .fetch_all(pool)
+    .await?;
+    let guest = sqlx::query!(
+        r#"
+        SELECT app_id FROM users_guests WHERE user_id = $1
+        "#,
+        *user_id,
+    )
+    .fetch_optional(pool)
+    .await?;
+    Ok(email_passwords
+        .into_iter()
+        .map(|r| LoginMethod::EmailPassword {
+            email: r.email,
+            is_verified: r.is_verified,
+        })
+        .chain(identities.into_iter().map(|r| LoginMethod::Oauth {
+            provider: r.provider,
+            external_id: r.external_id,
+            login: r.login,
+        }))
+        .chain(guest.map(|r| LoginMethod::Guest {
+            app_id: AppId(r.app_id),
+        }))
+        .collect())
+}
+
+/// Commits the removal of a login method, unless it was the last one of the user.
+async fn commit_unless_last(
+    mut transaction: Transaction<'_, Postgres>,
+    user_id: UserId,
+) -> Result<(), DetachError> {
+    let remaining = sqlx::query!(
+        r#"
+        SELECT
+            (SELECT COUNT(*) FROM users_email_password WHERE user_id = $1)
+            + (SELECT COUNT(*) FROM users_identities WHERE user_id = $1)
+            + (SELECT COUNT(*) FROM users_guests WHERE user_id = $1)
+            AS "count!"
+        "#,
+        *user_id,
+    )
+    .fetch_one(&mut transaction)
+    .await?;
+    if remaining.count == 0 {
+        transaction.rollback().await?;
+        return Err(DetachError::LastLoginMethod);
+    }
+    transaction.commit().await?;
+    Ok(())
+}
+
+pub async fn detach_email_password(
+    pool: &PgPool,
+    user_id: UserId,
+    email: &str,
+) -> Result<(), DetachError> {
+    let mut transaction = pool.begin().await?;
+    let deleted = sqlx::query!(
+        r#"
+        DELETE FROM users_email_password WHERE user_id = $1 AND email = $2
+        "#,
+        *user_id,
+        email,
+    )
+    .execute(&mut transaction)
+    .await?
+    .rows_affected();
+    if deleted == 0 {
+        return Err(DetachError::NotFound);
+    }
+    commit_unless_last(transaction, user_id).await
+}
+
+pub async fn detach_identity(
+    pool: &PgPool,
+    user_id: UserId,
+    provider: &str,
+    external_id: &str,
+) -> Result<(), DetachError> {
+    let mut transaction = pool.begin().await?;
+    let deleted = sqlx::query!(
+        r#"
+        DELETE FROM users_identities WHERE user_id = $1 AND provider = $2 AND external_id = $3
+        "#,
+        *user_id,
+        provider,
+        external_id,
+    )
+    .execute(&mut transaction)
+    .await?
+    .rows_affected();
+    if deleted == 0 {
+        return Err(DetachError::NotFound);
+    }
+    commit_unless_last(transaction, user_id).await
+}
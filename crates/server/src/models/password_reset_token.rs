@@ -0,0 +1,94 @@
+use data_encoding::HEXLOWER;
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+use time::{OffsetDateTime, PrimitiveDateTime};
+
+use super::{email_password::EmailPasswordId, user::UserId};
+
+/// Reset tokens are random enough for a fast hash, which allows looking them up by hash.
+pub fn hash_reset_token(reset_token: &str) -> String {
+    HEXLOWER.encode(&Sha256::digest(reset_token.as_bytes()))
+}
+
+/// Stores a new reset token for this email/password, previous ones are still valid until they expire.
+///
+/// Only the hash of the token is stored.
+pub async fn create(
+    pool: &PgPool,
+    email_password_id: EmailPasswordId,
+    reset_token: &str,
+    expiration_date: OffsetDateTime,
+    created_at: OffsetDateTime,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+    INSERT INTO password_reset_tokens ( reset_token_hash, email_password_id, expiration_date, used, created_at )
+    VALUES ( $1, $2, $3, false, $4 ) RETURNING id
+        "#,
+        hash_reset_token(reset_token),
+        email_password_id.0,
+        PrimitiveDateTime::new(expiration_date.date(), expiration_date.time()),
+        PrimitiveDateTime::new(created_at.date(), created_at.time()),
+    )
+    .fetch_one(pool)
+    .await?;
+    Ok(())
+}
+
+/// Consumes the reset token if it's valid, sets the new password
+/// and revokes all refresh tokens of the user, so existing sessions have to log in again.
+///
+/// Returns [`sqlx::Error::RowNotFound`] if the token is unknown, expired or already used.
+pub async fn reset_password(
+    pool: &PgPool,
+    reset_token: &str,
+    password_hash: &str,
+    now: OffsetDateTime,
+) -> Result<UserId, sqlx::Error> {
+    let mut transaction = pool.begin().await?;
+    let rec = sqlx::query!(
+        r#"
+            UPDATE password_reset_tokens SET used = true
+            WHERE reset_token_hash = $1
+            AND used = false
+            AND expiration_date > $2
+            RETURNING email_password_id
+        "#,
+        hash_reset_token(reset_token),
+        PrimitiveDateTime::new(now.date(), now.time()),
+    )
+    .fetch_one(&mut transaction)
+    .await?;
+    let user_id = sqlx::query!(
+        r#"
+            UPDATE users_email_password SET password_hash = $2
+            WHERE id = $1
+            RETURNING user_id
+        "#,
+        rec.email_password_id,
+        password_hash,
+    )
+    .fetch_one(&mut transaction)
+    .await
+    .map(|rec| UserId::from(rec.user_id))?;
+    sqlx::query!(
+        r#"
+            UPDATE refresh_token_families SET revoked = true
+            WHERE user_id = $1
+        "#,
+        *user_id,
+    )
+    .execute(&mut transaction)
+    .await?;
+    sqlx::query!(
+        r#"
+            UPDATE refresh_tokens SET revoked = true
+            WHERE user_id = $1
+        "#,
+        *user_id,
+    )
+    .execute(&mut transaction)
+    .await?;
+    transaction.commit().await?;
+    Ok(user_id)
+}
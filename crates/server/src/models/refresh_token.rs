@@ -1,35 +1,68 @@
 use serde::{Deserialize, Serialize};
-use shared::{RefreshTokenId, RefreshTokenString};
+use shared::{RefreshTokenFamilyId, RefreshTokenId, RefreshTokenString};
 use sqlx::PgPool;
+use thiserror::Error;
 use time::{OffsetDateTime, PrimitiveDateTime};
 
-use super::user::UserId;
+use super::{app::AppId, user::UserId};
 
-#[derive(Serialize, Deserialize)]
+/// Longest session label accepted, in characters.
+pub const SESSION_LABEL_MAX_LENGTH: usize = 255;
+
+#[derive(Debug, Serialize, Deserialize)]
 pub struct RefreshToken {
     pub id: RefreshTokenId,
     pub refresh_token: RefreshTokenString,
     pub user_id: UserId,
+    pub family_id: RefreshTokenFamilyId,
+    /// App of the family, `None` for admin sessions: the role of the tokens it is refreshed into.
+    pub app_id: Option<AppId>,
+    /// Whether the family was opened with two-factor authentication.
+    pub two_factor: bool,
     pub expiration_date: OffsetDateTime,
     pub revoked: bool,
+    /// Set when the whole family is revoked, as opposed to this token only being rotated.
+    pub family_revoked: bool,
+    /// When this token was exchanged for a new one.
+    pub rotated_at: Option<OffsetDateTime>,
     pub created_at: OffsetDateTime,
 }
 
+#[derive(Error, Debug)]
+pub enum RotationError {
+    #[error("refresh token not found")]
+    NotFound,
+    #[error("refresh token expired")]
+    Expired,
+    /// Contains the revoked token, to find out whether it was rotated or its family revoked.
+    #[error("refresh token already revoked")]
+    Revoked(RefreshToken),
+    #[error("database error")]
+    Database(#[from] sqlx::Error),
+}
+
 impl RefreshToken {
     pub async fn create(
         pool: &PgPool,
         refresh_token: RefreshTokenString,
         user_id: UserId,
+        family_id: RefreshTokenFamilyId,
         expiration_date: OffsetDateTime,
         created_at: OffsetDateTime,
     ) -> Result<RefreshToken, sqlx::Error> {
         let rec = sqlx::query!(
             r#"
-    INSERT INTO refresh_tokens ( refresh_token, user_id, expiration_date, revoked, created_at )
-    VALUES ( $1, $2, $3, false, $4 ) RETURNING id
+    WITH inserted AS (
+        INSERT INTO refresh_tokens ( refresh_token, user_id, family_id, expiration_date, revoked, created_at )
+        VALUES ( $1, $2, $3, $4, false, $5 ) RETURNING id, family_id
+    )
+    SELECT inserted.id, refresh_token_families.app_id, refresh_token_families.two_factor
+    FROM inserted
+    JOIN refresh_token_families ON refresh_token_families.id = inserted.family_id
             "#,
             &*refresh_token,
             *user_id,
+            *family_id,
             PrimitiveDateTime::new(expiration_date.date(), expiration_date.time()),
             PrimitiveDateTime::new(created_at.date(), created_at.time())
         )
@@ -40,11 +73,91 @@ impl RefreshToken {
             id: RefreshTokenId(rec.id),
             refresh_token,
             user_id,
+            family_id,
+            app_id: rec.app_id.map(AppId::from),
+            two_factor: rec.two_factor,
             expiration_date,
             revoked: false,
+            family_revoked: false,
+            rotated_at: None,
             created_at,
         })
     }
+    /// Revokes the refresh token and creates its replacement in the same family, in a single transaction.
+    ///
+    /// Concurrent rotations of a same token are serialized by the database,
+    /// so exactly one of them succeeds, the others get [`RotationError::Revoked`].
+    pub async fn rotate(
+        pool: &PgPool,
+        refresh_token: &RefreshTokenString,
+        user_id: UserId,
+        new_refresh_token: RefreshTokenString,
+        expiration_date: OffsetDateTime,
+        now: OffsetDateTime,
+    ) -> Result<RefreshToken, RotationError> {
+        let now_primitive = PrimitiveDateTime::new(now.date(), now.time());
+        let mut transaction = pool.begin().await?;
+        let rotated = sqlx::query!(
+            r#"
+            UPDATE refresh_tokens SET revoked = true, rotated_at = $3
+            FROM refresh_token_families
+            WHERE refresh_token_families.id = family_id
+            AND refresh_token_families.revoked = false
+            AND refresh_token = $1
+            AND refresh_tokens.user_id = $2
+            AND refresh_tokens.revoked = false
+            AND expiration_date > $3
+            RETURNING family_id, refresh_token_families.app_id, refresh_token_families.two_factor
+            "#,
+            &refresh_token.0,
+            *user_id,
+            now_primitive,
+        )
+        .fetch_optional(&mut transaction)
+        .await?;
+        let Some(rotated) = rotated else {
+            transaction.rollback().await?;
+            let refresh_token = Self::get(pool, refresh_token, user_id)
+                .await
+                .map_err(|err| match err {
+                    sqlx::Error::RowNotFound => RotationError::NotFound,
+                    err => err.into(),
+                })?;
+            return Err(if refresh_token.revoked || refresh_token.family_revoked {
+                RotationError::Revoked(refresh_token)
+            } else {
+                RotationError::Expired
+            });
+        };
+        let rec = sqlx::query!(
+            r#"
+    INSERT INTO refresh_tokens ( refresh_token, user_id, family_id, expiration_date, revoked, created_at )
+    VALUES ( $1, $2, $3, $4, false, $5 ) RETURNING id
+            "#,
+            &*new_refresh_token,
+            *user_id,
+            rotated.family_id,
+            PrimitiveDateTime::new(expiration_date.date(), expiration_date.time()),
+            now_primitive,
+        )
+        .fetch_one(&mut transaction)
+        .await?;
+        transaction.commit().await?;
+
+        Ok(RefreshToken {
+            id: RefreshTokenId(rec.id),
+            refresh_token: new_refresh_token,
+            user_id,
+            family_id: RefreshTokenFamilyId(rotated.family_id),
+            app_id: rotated.app_id.map(AppId::from),
+            two_factor: rotated.two_factor,
+            expiration_date,
+            revoked: false,
+            family_revoked: false,
+            rotated_at: None,
+            created_at: now,
+        })
+    }
     pub async fn get(
         pool: &PgPool,
         refresh_token: &RefreshTokenString,
@@ -52,16 +165,22 @@ impl RefreshToken {
     ) -> Result<RefreshToken, sqlx::Error> {
         sqlx::query!(
             r#"
-            SELECT 
-                id,
+            SELECT
+                refresh_tokens.id,
                 refresh_token,
-                user_id,
+                refresh_tokens.user_id,
+                family_id,
+                refresh_token_families.app_id,
+                refresh_token_families.two_factor,
                 expiration_date,
-                revoked,
-                created_at
+                refresh_tokens.revoked,
+                refresh_token_families.revoked AS family_revoked,
+                rotated_at,
+                refresh_tokens.created_at
             FROM refresh_tokens
+            JOIN refresh_token_families ON refresh_token_families.id = family_id
             WHERE refresh_token = $1
-            AND user_id = $2
+            AND refresh_tokens.user_id = $2
             "#,
             &refresh_token.0,
             *user_id,
@@ -72,24 +191,205 @@ impl RefreshToken {
             id: RefreshTokenId(r.id),
             refresh_token: RefreshTokenString(r.refresh_token),
             user_id: UserId::from(r.user_id),
+            family_id: RefreshTokenFamilyId(r.family_id),
+            app_id: r.app_id.map(AppId::from),
+            two_factor: r.two_factor,
             expiration_date: r.expiration_date.assume_utc(),
             revoked: r.revoked,
+            family_revoked: r.family_revoked,
+            rotated_at: r.rotated_at.map(PrimitiveDateTime::assume_utc),
             created_at: r.created_at.assume_utc(),
         })
     }
-    pub async fn revoke(
+}
+
+/// Refresh tokens are rotated on each use, a family groups the tokens originating from a same login.
+///
+/// Revoked tokens are kept, so presenting an already rotated token can be detected,
+/// and its whole family revoked.
+///
+/// Families are exposed to users as their sessions.
+pub struct RefreshTokenFamily {
+    pub id: RefreshTokenFamilyId,
+    pub app_id: Option<AppId>,
+    pub label: Option<String>,
+    pub created_at: OffsetDateTime,
+    /// Expiration of its current refresh token.
+    pub expiration_date: OffsetDateTime,
+}
+
+impl From<RefreshTokenFamily> for shared::Session {
+    fn from(value: RefreshTokenFamily) -> Self {
+        Self {
+            id: value.id,
+            app_id: value.app_id.map(|app_id| app_id.0),
+            label: value.label,
+            created_at_unix_timestamp: value.created_at.unix_timestamp(),
+            expiration_date_unix_timestamp: value.expiration_date.unix_timestamp(),
+        }
+    }
+}
+
+impl RefreshTokenFamily {
+    pub async fn create(
         pool: &PgPool,
-        refresh_token_id: RefreshTokenId,
-    ) -> Result<(), sqlx::Error> {
+        user_id: UserId,
+        app_id: Option<AppId>,
+        two_factor: bool,
+        created_at: OffsetDateTime,
+    ) -> Result<RefreshTokenFamilyId, sqlx::Error> {
         sqlx::query!(
             r#"
-            UPDATE refresh_tokens SET revoked = true
+    INSERT INTO refresh_token_families ( user_id, app_id, two_factor, revoked, created_at )
+    VALUES ( $1, $2, $3, false, $4 ) RETURNING id
+            "#,
+            *user_id,
+            app_id.map(|app_id| *app_id),
+            two_factor,
+            PrimitiveDateTime::new(created_at.date(), created_at.time())
+        )
+        .fetch_one(pool)
+        .await
+        .map(|rec| RefreshTokenFamilyId(rec.id))
+    }
+    /// Families of the user with a refresh token still usable.
+    pub async fn get_all_active_for_user(
+        pool: &PgPool,
+        user_id: UserId,
+        now: OffsetDateTime,
+    ) -> Result<Vec<RefreshTokenFamily>, sqlx::Error> {
+        sqlx::query!(
+            r#"
+            SELECT
+                refresh_token_families.id,
+                app_id,
+                label,
+                refresh_token_families.created_at,
+                MAX(expiration_date) AS "expiration_date!"
+            FROM refresh_token_families
+            JOIN refresh_tokens ON refresh_tokens.family_id = refresh_token_families.id
+            WHERE refresh_token_families.user_id = $1
+            AND refresh_token_families.revoked = false
+            AND refresh_tokens.revoked = false
+            AND expiration_date > $2
+            GROUP BY refresh_token_families.id
+            ORDER BY refresh_token_families.created_at
+            "#,
+            *user_id,
+            PrimitiveDateTime::new(now.date(), now.time()),
+        )
+        .fetch_all(pool)
+        .await
+        .map(|recs| {
+            recs.into_iter()
+                .map(|r| RefreshTokenFamily {
+                    id: RefreshTokenFamilyId(r.id),
+                    app_id: r.app_id.map(AppId::from),
+                    label: r.label,
+                    created_at: r.created_at.assume_utc(),
+                    expiration_date: r.expiration_date.assume_utc(),
+                })
+                .collect()
+        })
+    }
+    /// Returns `false` if the family does not belong to the user.
+    pub async fn set_label(
+        pool: &PgPool,
+        user_id: UserId,
+        family_id: RefreshTokenFamilyId,
+        label: Option<&str>,
+    ) -> Result<bool, sqlx::Error> {
+        sqlx::query!(
+            r#"
+            UPDATE refresh_token_families SET label = $3
             WHERE id = $1
+            AND user_id = $2
             "#,
-            refresh_token_id.0
+            *family_id,
+            *user_id,
+            label,
         )
         .execute(pool)
+        .await
+        .map(|result| result.rows_affected() == 1)
+    }
+    /// Revokes the family and all its tokens.
+    pub async fn revoke(pool: &PgPool, family_id: RefreshTokenFamilyId) -> Result<(), sqlx::Error> {
+        let mut transaction = pool.begin().await?;
+        sqlx::query!(
+            r#"
+            UPDATE refresh_token_families SET revoked = true
+            WHERE id = $1
+            "#,
+            *family_id
+        )
+        .execute(&mut transaction)
+        .await?;
+        sqlx::query!(
+            r#"
+            UPDATE refresh_tokens SET revoked = true
+            WHERE family_id = $1
+            "#,
+            *family_id
+        )
+        .execute(&mut transaction)
+        .await?;
+        transaction.commit().await?;
+        Ok(())
+    }
+    /// Same as [`RefreshTokenFamily::revoke`], returns `false` if the family does not belong to the user.
+    pub async fn revoke_for_user(
+        pool: &PgPool,
+        user_id: UserId,
+        family_id: RefreshTokenFamilyId,
+    ) -> Result<bool, sqlx::Error> {
+        let owned = sqlx::query!(
+            r#"
+            SELECT id FROM refresh_token_families
+            WHERE id = $1
+            AND user_id = $2
+            "#,
+            *family_id,
+            *user_id,
+        )
+        .fetch_optional(pool)
+        .await?
+        .is_some();
+        if owned {
+            Self::revoke(pool, family_id).await?;
+        }
+        Ok(owned)
+    }
+    /// Revokes all families of the user, except the given one.
+    pub async fn revoke_all_except(
+        pool: &PgPool,
+        user_id: UserId,
+        family_id: RefreshTokenFamilyId,
+    ) -> Result<(), sqlx::Error> {
+        let mut transaction = pool.begin().await?;
+        sqlx::query!(
+            r#"
+            UPDATE refresh_token_families SET revoked = true
+            WHERE user_id = $1
+            AND id <> $2
+            "#,
+            *user_id,
+            *family_id
+        )
+        .execute(&mut transaction)
+        .await?;
+        sqlx::query!(
+            r#"
+            UPDATE refresh_tokens SET revoked = true
+            WHERE user_id = $1
+            AND family_id <> $2
+            "#,
+            *user_id,
+            *family_id
+        )
+        .execute(&mut transaction)
         .await?;
+        transaction.commit().await?;
         Ok(())
     }
 }
@@ -0,0 +1,269 @@
+use sqlx::PgPool;
+use time::{OffsetDateTime, PrimitiveDateTime};
+
+use super::user::UserId;
+use crate::totp;
+
+/// Login attempts allowed for a challenge, a new one needs the password again.
+pub const MAX_CHALLENGE_FAILED_ATTEMPTS: i32 = 5;
+
+/// TOTP configuration of a user, see [`crate::totp`].
+pub struct TwoFactor {
+    pub user_id: UserId,
+    /// Base32 encoded.
+    pub secret: String,
+    /// Codes are only required once the user confirmed a first one.
+    pub enabled: bool,
+}
+
+impl TwoFactor {
+    pub async fn get(pool: &PgPool, user_id: UserId) -> Result<Option<TwoFactor>, sqlx::Error> {
+        sqlx::query!(
+            r#"
+            SELECT user_id, secret, enabled FROM users_two_factor WHERE user_id = $1
+            "#,
+            *user_id,
+        )
+        .fetch_optional(pool)
+        .await
+        .map(|rec| {
+            rec.map(|r| TwoFactor {
+                user_id: UserId::from(r.user_id),
+                secret: r.secret,
+                enabled: r.enabled,
+            })
+        })
+    }
+
+    /// Stores a new secret and recovery codes, replacing a previous enrolment not confirmed yet.
+    ///
+    /// Returns `false` if two-factor authentication is already enabled.
+    pub async fn start_enrollment(
+        pool: &PgPool,
+        user_id: UserId,
+        secret: &str,
+        recovery_code_hashes: &[String],
+        created_at: OffsetDateTime,
+    ) -> Result<bool, sqlx::Error> {
+        let mut transaction = pool.begin().await?;
+        let replaced = sqlx::query!(
+            r#"
+            INSERT INTO users_two_factor ( user_id, secret, enabled, created_at )
+            VALUES ( $1, $2, false, $3 )
+            ON CONFLICT (user_id) DO UPDATE
+            SET secret = $2, last_used_step = NULL, created_at = $3
+            WHERE users_two_factor.enabled = false
+            "#,
+            *user_id,
+            secret,
+            PrimitiveDateTime::new(created_at.date(), created_at.time()),
+        )
+        .execute(&mut transaction)
+        .await?
+        .rows_affected()
+            == 1;
+        if !replaced {
+            return Ok(false);
+        }
+        sqlx::query!(
+            r#"
+            DELETE FROM two_factor_recovery_codes WHERE user_id = $1
+            "#,
+            *user_id,
+        )
+        .execute(&mut transaction)
+        .await?;
+        sqlx::query!(
+            r#"
+            INSERT INTO two_factor_recovery_codes ( user_id, code_hash )
+            SELECT $1, * FROM UNNEST($2::VARCHAR[])
+            "#,
+            *user_id,
+            recovery_code_hashes,
+        )
+        .execute(&mut transaction)
+        .await?;
+        transaction.commit().await?;
+        Ok(true)
+    }
+
+    pub async fn enable(pool: &PgPool, user_id: UserId) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            r#"
+            UPDATE users_two_factor SET enabled = true WHERE user_id = $1
+            "#,
+            *user_id,
+        )
+        .execute(pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Removes the secret and recovery codes.
+    pub async fn delete(pool: &PgPool, user_id: UserId) -> Result<(), sqlx::Error> {
+        let mut transaction = pool.begin().await?;
+        sqlx::query!(
+            r#"
+            DELETE FROM two_factor_recovery_codes WHERE user_id = $1
+            "#,
+            *user_id,
+        )
+        .execute(&mut transaction)
+        .await?;
+        sqlx::query!(
+            r#"
+            DELETE FROM users_two_factor WHERE user_id = $1
+            "#,
+            *user_id,
+        )
+        .execute(&mut transaction)
+        .await?;
+        transaction.commit().await
+    }
+
+    /// Records the time step of an accepted code.
+    ///
+    /// Returns `false` if this step or a later one was already used: the code is a replay.
+    pub async fn use_step(pool: &PgPool, user_id: UserId, step: i64) -> Result<bool, sqlx::Error> {
+        sqlx::query!(
+            r#"
+            UPDATE users_two_factor SET last_used_step = $2
+            WHERE user_id = $1
+            AND (last_used_step IS NULL OR last_used_step < $2)
+            "#,
+            *user_id,
+            step,
+        )
+        .execute(pool)
+        .await
+        .map(|result| result.rows_affected() == 1)
+    }
+
+    /// Accepts a code from the authenticator app, only once.
+    pub async fn verify_totp(
+        &self,
+        pool: &PgPool,
+        code: &str,
+        now: OffsetDateTime,
+    ) -> Result<bool, sqlx::Error> {
+        match totp::verify(&self.secret, code, now.unix_timestamp()) {
+            Some(step) => TwoFactor::use_step(pool, self.user_id, step).await,
+            None => Ok(false),
+        }
+    }
+
+    /// Accepts a code from the authenticator app or an unused recovery code, only once.
+    pub async fn verify_code(
+        &self,
+        pool: &PgPool,
+        code: &str,
+        now: OffsetDateTime,
+    ) -> Result<bool, sqlx::Error> {
+        if self.verify_totp(pool, code, now).await? {
+            return Ok(true);
+        }
+        TwoFactor::use_recovery_code(pool, self.user_id, &totp::hash_recovery_code(code)).await
+    }
+
+    /// Returns `false` if the recovery code is unknown or already used.
+    pub async fn use_recovery_code(
+        pool: &PgPool,
+        user_id: UserId,
+        code_hash: &str,
+    ) -> Result<bool, sqlx::Error> {
+        sqlx::query!(
+            r#"
+            UPDATE two_factor_recovery_codes SET used = true
+            WHERE user_id = $1
+            AND code_hash = $2
+            AND used = false
+            "#,
+            *user_id,
+            code_hash,
+        )
+        .execute(pool)
+        .await
+        .map(|result| result.rows_affected() > 0)
+    }
+}
+
+pub mod challenge {
+    use super::*;
+
+    pub async fn create(
+        pool: &PgPool,
+        challenge_token: &str,
+        user_id: UserId,
+        expiration_date: OffsetDateTime,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            r#"
+    INSERT INTO two_factor_challenges ( challenge_token, user_id, expiration_date )
+    VALUES ( $1, $2, $3 )
+            "#,
+            challenge_token,
+            *user_id,
+            PrimitiveDateTime::new(expiration_date.date(), expiration_date.time()),
+        )
+        .execute(pool)
+        .await?;
+        Ok(())
+    }
+
+    /// User of a challenge still usable.
+    ///
+    /// Returns [`sqlx::Error::RowNotFound`] if the challenge is unknown, expired, used,
+    /// or had too many failed attempts.
+    pub async fn find(
+        pool: &PgPool,
+        challenge_token: &str,
+        now: OffsetDateTime,
+    ) -> Result<UserId, sqlx::Error> {
+        sqlx::query!(
+            r#"
+            SELECT user_id FROM two_factor_challenges
+            WHERE challenge_token = $1
+            AND used = false
+            AND expiration_date > $2
+            AND failed_attempts < $3
+            "#,
+            challenge_token,
+            PrimitiveDateTime::new(now.date(), now.time()),
+            MAX_CHALLENGE_FAILED_ATTEMPTS,
+        )
+        .fetch_one(pool)
+        .await
+        .map(|rec| UserId::from(rec.user_id))
+    }
+
+    pub async fn record_failed_attempt(
+        pool: &PgPool,
+        challenge_token: &str,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            r#"
+            UPDATE two_factor_challenges SET failed_attempts = failed_attempts + 1
+            WHERE challenge_token = $1
+            "#,
+            challenge_token,
+        )
+        .execute(pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Returns `false` if the challenge was already used, by a concurrent request.
+    pub async fn consume(pool: &PgPool, challenge_token: &str) -> Result<bool, sqlx::Error> {
+        sqlx::query!(
+            r#"
+            UPDATE two_factor_challenges SET used = true
+            WHERE challenge_token = $1
+            AND used = false
+            "#,
+            challenge_token,
+        )
+        .execute(pool)
+        .await
+        .map(|result| result.rows_affected() == 1)
+    }
+}
@@ -1,5 +1,8 @@
 use serde::{Deserialize, Serialize};
 use sqlx::PgPool;
+use time::PrimitiveDateTime;
+
+use super::{item_ledger::ItemChange, user_item::AmountError};
 
 // TODO: #25 when async traits we can remove this wrapper and add behaviour directly to shared::UserId
 // When this is removed, also remove the From implementations and adapt the `UserId::from(` to `UserId(` or just plain assignment
@@ -76,4 +79,115 @@ impl UserId {
         .await
         .map(|_| ())
     }
+
+    /// Returns the user this one was merged into, or itself if it was not merged.
+    pub async fn resolve_merged(self, connection: &PgPool) -> Result<UserId, sqlx::Error> {
+        Ok(sqlx::query!(
+            r#"
+            SELECT merged_into FROM users_merged WHERE user_id = $1
+            "#,
+            *self,
+        )
+        .fetch_optional(connection)
+        .await?
+        .map_or(self, |r| UserId::from(r.merged_into)))
+    }
+    /// Moves everything of this user into another one, then deletes this user, in one transaction:
+    /// - item amounts are added to those of the other user, recorded in its item ledger,
+    ///   the ledger entries of this user are deleted with it,
+    /// - app admin rights, app credentials, sessions and login methods are moved,
+    /// - two-factor authentication is not moved, the other user keeps theirs.
+    ///
+    /// Nothing changes if an amount of the other user would get out of its item bounds.
+    pub async fn merge_into(
+        self,
+        connection: &PgPool,
+        into: UserId,
+        change: &ItemChange,
+    ) -> Result<(), AmountError> {
+        let mut transaction = connection.begin().await?;
+        self.move_amounts(&mut transaction, into, change).await?;
+        sqlx::query!(
+            r#"
+            INSERT INTO apps_admins (user_id, app_id)
+            SELECT $2, app_id FROM apps_admins WHERE user_id = $1
+            ON CONFLICT DO NOTHING
+            "#,
+            *self,
+            *into,
+        )
+        .execute(&mut transaction)
+        .await?;
+        sqlx::query!(
+            "UPDATE apps_credentials SET created_by = $2 WHERE created_by = $1",
+            *self,
+            *into,
+        )
+        .execute(&mut transaction)
+        .await?;
+        sqlx::query!(
+            "UPDATE refresh_token_families SET user_id = $2 WHERE user_id = $1",
+            *self,
+            *into,
+        )
+        .execute(&mut transaction)
+        .await?;
+        sqlx::query!(
+            "UPDATE refresh_tokens SET user_id = $2 WHERE user_id = $1",
+            *self,
+            *into,
+        )
+        .execute(&mut transaction)
+        .await?;
+        sqlx::query!(
+            "UPDATE users_email_password SET user_id = $2 WHERE user_id = $1",
+            *self,
+            *into,
+        )
+        .execute(&mut transaction)
+        .await?;
+        sqlx::query!(
+            "UPDATE users_identities SET user_id = $2 WHERE user_id = $1",
+            *self,
+            *into,
+        )
+        .execute(&mut transaction)
+        .await?;
+        // A guest receiving other login methods is not a guest anymore.
+        sqlx::query!(
+            r#"
+            DELETE FROM users_guests WHERE user_id = $1
+            AND (
+                EXISTS (SELECT 1 FROM users_email_password WHERE user_id = $1)
+                OR EXISTS (SELECT 1 FROM users_identities WHERE user_id = $1)
+            )
+            "#,
+            *into,
+        )
+        .execute(&mut transaction)
+        .await?;
+        sqlx::query!(
+            "UPDATE users_merged SET merged_into = $2 WHERE merged_into = $1",
+            *self,
+            *into,
+        )
+        .execute(&mut transaction)
+        .await?;
+        sqlx::query!(
+            r#"
+            INSERT INTO users_merged (user_id, merged_into, merged_at) VALUES ($1, $2, $3)
+            "#,
+            *self,
+            *into,
+            PrimitiveDateTime::new(change.at.date(), change.at.time()),
+        )
+        .execute(&mut transaction)
+        .await?;
+        // Remaining data of this user, like its guest device secret, is deleted in cascade.
+        sqlx::query!("DELETE FROM users WHERE id = $1", *self)
+            .execute(&mut transaction)
+            .await?;
+        transaction.commit().await?;
+        Ok(())
+    }
 }
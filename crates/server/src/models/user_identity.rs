@@ -0,0 +1,96 @@
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+
+use super::user::UserId;
+
+/// An identity from a third party identity provider.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ExternalIdentity {
+    /// Name of the [`IdentityProvider`](crate::identity_provider::IdentityProvider).
+    pub provider: String,
+    /// Unique id of the user for this provider.
+    pub external_id: String,
+    /// Human readable name, it can change over time.
+    pub login: String,
+}
+
+impl ExternalIdentity {
+    /// Returns the user linked to this identity, updating its login if it changed.
+    pub async fn get_user(&self, connection: &PgPool) -> Result<Option<UserId>, sqlx::Error> {
+        let rec = sqlx::query!(
+            r#"
+            UPDATE users_identities SET login = $3
+            WHERE provider = $1 AND external_id = $2
+            RETURNING user_id
+            "#,
+            self.provider,
+            self.external_id,
+            self.login,
+        )
+        .fetch_optional(connection)
+        .await?;
+        Ok(rec.map(|record| UserId::from(record.user_id)))
+    }
+
+    /// Returns the user linked to this identity, creating it with `name` on first login.
+    ///
+    /// Concurrent first logins get the same user: the one linked first is kept,
+    /// the others are rolled back.
+    pub async fn get_or_create_user(
+        &self,
+        pool: &PgPool,
+        name: &str,
+    ) -> Result<UserId, sqlx::Error> {
+        if let Some(user_id) = self.get_user(pool).await? {
+            return Ok(user_id);
+        }
+        let mut transaction = pool.begin().await?;
+        let user = sqlx::query!(
+            r#"
+            INSERT INTO users (name) VALUES ($1)
+            RETURNING id
+            "#,
+            name,
+        )
+        .fetch_one(&mut transaction)
+        .await?;
+        let linked = sqlx::query!(
+            r#"
+            INSERT INTO users_identities (provider, external_id, login, user_id)
+            VALUES ($1, $2, $3, $4)
+            ON CONFLICT (provider, external_id) DO NOTHING
+            RETURNING user_id
+            "#,
+            self.provider,
+            self.external_id,
+            self.login,
+            user.id,
+        )
+        .fetch_optional(&mut transaction)
+        .await?;
+        if let Some(linked) = linked {
+            transaction.commit().await?;
+            return Ok(UserId::from(linked.user_id));
+        }
+        transaction.rollback().await?;
+        self.get_user(pool).await?.ok_or(sqlx::Error::RowNotFound)
+    }
+
+    /// Meant to be used with another query following, to link it to this authentication method.
+    /// FIXME: This API could be reworked to be misuse resistant.
+    pub async fn create(&self, connection: &PgPool, account: UserId) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            r#"
+            INSERT INTO users_identities (provider, external_id, login, user_id)
+            VALUES ($1, $2, $3, $4)
+            "#,
+            self.provider,
+            self.external_id,
+            self.login,
+            *account,
+        )
+        .execute(connection)
+        .await?;
+        Ok(())
+    }
+}
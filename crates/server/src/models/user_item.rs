@@ -1,49 +1,246 @@
-use sqlx::PgPool;
+use shared::{ItemAmountError, ItemBounds};
+use sqlx::{PgPool, Postgres, Transaction};
+use thiserror::Error;
 
-use super::{item::ItemId, user::UserId};
+use super::{
+    item::ItemId,
+    item_idempotency_key,
+    item_ledger::{self, ItemChange},
+    user::UserId,
+};
+
+#[derive(Error, Debug)]
+pub enum AmountError {
+    #[error("amount change refused: {0:?}")]
+    Refused(ItemAmountError),
+    /// The idempotency key was already used by the actor for another modification.
+    #[error("idempotency key reused for another request")]
+    IdempotencyKeyReused,
+    #[error("database error")]
+    Database(#[from] sqlx::Error),
+}
+
+/// Checks a change of `amount` to the `balance` of the user against the item bounds.
+fn check_bounds(
+    bounds: &ItemBounds,
+    user: UserId,
+    balance: i32,
+    amount: i32,
+) -> Result<(), ItemAmountError> {
+    if let Some(max_change) = bounds.max_change {
+        if (amount as i64).abs() > max_change as i64 {
+            return Err(ItemAmountError::ChangeTooLarge { max_change });
+        }
+    }
+    let new_amount = balance as i64 + amount as i64;
+    if amount < 0 && new_amount < bounds.min_amount as i64 {
+        return Err(ItemAmountError::InsufficientBalance {
+            user_id: user.0,
+            balance,
+        });
+    }
+    if let Some(max_amount) = bounds.max_amount {
+        if amount > 0 && new_amount > max_amount as i64 {
+            return Err(ItemAmountError::MaximumExceeded {
+                user_id: user.0,
+                balance,
+                max_amount,
+            });
+        }
+    }
+    if new_amount > i32::MAX as i64 {
+        return Err(ItemAmountError::Overflow { user_id: user.0 });
+    }
+    Ok(())
+}
+
+/// Applies a change of `amount` for each `(item, user)`, in a single transaction: nothing changes on error.
+///
+/// A transaction sent again with the same `idempotency_key` by the same actor
+/// is not applied again, as for [`ItemId::modify_amount`].
+///
+/// Returns the new amounts, in the order of `operations`.
+pub async fn apply_transaction(
+    operations: &[(ItemId, UserId, i32)],
+    change: &ItemChange,
+    idempotency_key: Option<&str>,
+    pool: &PgPool,
+) -> Result<Vec<i32>, AmountError> {
+    let mut transaction = pool.begin().await?;
+    if let Some(stored) = claim(&mut transaction, change, idempotency_key, operations).await? {
+        return Ok(stored);
+    }
+    // Locked in the same order by all transactions, so overlapping ones don't deadlock.
+    let mut locks: Vec<(i32, i32)> = operations
+        .iter()
+        .map(|(item_id, user, _)| (**item_id, **user))
+        .collect();
+    locks.sort_unstable();
+    locks.dedup();
+    for (item_id, user) in locks {
+        ItemId(item_id)
+            .lock_amount(&mut transaction, UserId::from(user))
+            .await?;
+    }
+    let mut new_amounts = Vec::with_capacity(operations.len());
+    for (item_id, user, amount) in operations {
+        new_amounts.push(
+            item_id
+                .increment_amount_raw(&mut transaction, *user, *amount, change)
+                .await?,
+        );
+    }
+    if let Some(key) = idempotency_key {
+        item_idempotency_key::complete(&mut transaction, change.actor, key, &new_amounts).await?;
+    }
+    transaction.commit().await?;
+    Ok(new_amounts)
+}
+
+/// Returns the new amounts of the modifications previously applied with the key, if any.
+async fn claim(
+    transaction: &mut Transaction<'_, Postgres>,
+    change: &ItemChange,
+    idempotency_key: Option<&str>,
+    operations: &[(ItemId, UserId, i32)],
+) -> Result<Option<Vec<i32>>, AmountError> {
+    let Some(key) = idempotency_key else {
+        return Ok(None);
+    };
+    match item_idempotency_key::claim(transaction, change.actor, key, operations, change.at).await?
+    {
+        None => Ok(None),
+        Some(stored) if stored.is_same_request(operations) => Ok(Some(stored.new_amounts)),
+        Some(_) => Err(AmountError::IdempotencyKeyReused),
+    }
+}
 
 impl ItemId {
+    /// Amounts stay within the item bounds, see [`ItemBounds`].
+    ///
+    /// A modification sent again with the same `idempotency_key` by the same actor
+    /// is not applied again: the new amount it resulted in is returned.
+    /// Refused modifications are not stored, they changed nothing.
     pub async fn modify_amount(
         &self,
         user: UserId,
         amount: i32,
+        change: &ItemChange,
+        idempotency_key: Option<&str>,
         pool: &PgPool,
-    ) -> Result<i32, sqlx::Error> {
-        let rec = dbg!(self.increment_amount_raw(user, amount, pool).await);
-        match rec {
-            Ok(amount) => Ok(amount),
-            Err(_err) => dbg!(self.create_item_to_user_relation(user, amount, pool).await),
+    ) -> Result<i32, AmountError> {
+        let mut transaction = pool.begin().await?;
+        let operation = [(*self, user, amount)];
+        if let Some(stored) = claim(&mut transaction, change, idempotency_key, &operation).await? {
+            return Ok(stored[0]);
+        }
+        let new_amount = self
+            .increment_amount_raw(&mut transaction, user, amount, change)
+            .await?;
+        if let Some(key) = idempotency_key {
+            item_idempotency_key::complete(&mut transaction, change.actor, key, &[new_amount])
+                .await?;
         }
+        transaction.commit().await?;
+        Ok(new_amount)
     }
 
-    async fn create_item_to_user_relation(
+    /// Moves `amount` from `from` to `to`, in a single transaction: nothing changes on error.
+    ///
+    /// Returns the new amount of `from`.
+    pub async fn send_amount(
         &self,
-        user: UserId,
+        from: UserId,
+        to: UserId,
         amount: i32,
+        change: &ItemChange,
         pool: &PgPool,
+    ) -> Result<i32, AmountError> {
+        let mut transaction = pool.begin().await?;
+        // Locked in the same order by all transfers, so opposite ones don't deadlock.
+        let (first, second) = if *from <= *to { (from, to) } else { (to, from) };
+        self.lock_amount(&mut transaction, first).await?;
+        self.lock_amount(&mut transaction, second).await?;
+        let new_amount = self
+            .increment_amount_raw(&mut transaction, from, -amount, change)
+            .await?;
+        self.increment_amount_raw(&mut transaction, to, amount, change)
+            .await?;
+        transaction.commit().await?;
+        Ok(new_amount)
+    }
+
+    /// Returns the amount of the user, locked until the end of the transaction.
+    async fn lock_amount(
+        &self,
+        transaction: &mut Transaction<'_, Postgres>,
+        user: UserId,
     ) -> Result<i32, sqlx::Error> {
-        let rec = sqlx::query!(
+        sqlx::query!(
             r#"
     INSERT INTO users_items ( user_id, item_id, amount )
-    VALUES ( $1, $2, $3 )
-    RETURNING amount
+    VALUES ( $1, $2, 0 )
+    ON CONFLICT DO NOTHING
             "#,
             *user,
             self.0,
-            amount
         )
-        .fetch_one(pool)
+        .execute(&mut *transaction)
+        .await?;
+        let rec = sqlx::query!(
+            r#"
+    SELECT amount FROM users_items
+    WHERE user_id = $1 AND item_id = $2
+    FOR UPDATE
+            "#,
+            *user,
+            self.0,
+        )
+        .fetch_one(&mut *transaction)
         .await?;
-
         Ok(rec.amount)
     }
-    /// Can also be used to subtract.
+
+    async fn bounds(
+        &self,
+        transaction: &mut Transaction<'_, Postgres>,
+    ) -> Result<ItemBounds, sqlx::Error> {
+        sqlx::query_as!(
+            ItemBounds,
+            r#"
+    SELECT min_amount, max_amount, max_change FROM items WHERE id = $1
+            "#,
+            self.0,
+        )
+        .fetch_one(&mut *transaction)
+        .await
+    }
+
+    /// Can also be used to subtract, down to the item minimum.
+    ///
+    /// The change is recorded in the item ledger.
     async fn increment_amount_raw(
         &self,
+        transaction: &mut Transaction<'_, Postgres>,
         user: UserId,
         amount: i32,
-        pool: &sqlx::Pool<sqlx::Postgres>,
-    ) -> Result<i32, sqlx::Error> {
+        change: &ItemChange,
+    ) -> Result<i32, AmountError> {
+        let bounds = self.bounds(transaction).await?;
+        self.increment_amount_within(transaction, user, amount, change, &bounds)
+            .await
+    }
+
+    async fn increment_amount_within(
+        &self,
+        transaction: &mut Transaction<'_, Postgres>,
+        user: UserId,
+        amount: i32,
+        change: &ItemChange,
+        bounds: &ItemBounds,
+    ) -> Result<i32, AmountError> {
+        let balance = self.lock_amount(transaction, user).await?;
+        check_bounds(bounds, user, balance, amount).map_err(AmountError::Refused)?;
         let rec = sqlx::query!(
             r#"
 UPDATE users_items SET amount = amount + $1
@@ -54,8 +251,43 @@ UPDATE users_items SET amount = amount + $1
             *user,
             self.0
         )
-        .fetch_one(pool)
+        .fetch_one(&mut *transaction)
         .await?;
+        item_ledger::record(transaction, *self, user, amount, rec.amount, change).await?;
         Ok(rec.amount)
     }
 }
+
+impl UserId {
+    /// Adds the item amounts of this user to those of `into`, each one recorded in the ledger.
+    ///
+    /// Amounts stay within the item bounds, except `max_change`: they were already granted.
+    pub(super) async fn move_amounts(
+        self,
+        transaction: &mut Transaction<'_, Postgres>,
+        into: UserId,
+        change: &ItemChange,
+    ) -> Result<(), AmountError> {
+        let amounts = sqlx::query!(
+            r#"
+    SELECT item_id, amount FROM users_items
+    WHERE user_id = $1 AND amount <> 0
+    ORDER BY item_id
+            "#,
+            *self,
+        )
+        .fetch_all(&mut *transaction)
+        .await?;
+        for rec in amounts {
+            let item_id = ItemId(rec.item_id);
+            let bounds = ItemBounds {
+                max_change: None,
+                ..item_id.bounds(transaction).await?
+            };
+            item_id
+                .increment_amount_within(transaction, into, rec.amount, change, &bounds)
+                .await?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,393 @@
+//! Rate limiting per ip, per email and per app user, and lockout of logins after bad passwords.
+//!
+//! Counters are kept in memory, so they are not shared between server instances.
+
+use std::{
+    collections::HashMap,
+    future::{ready, Future, Ready},
+    hash::Hash,
+    net::{IpAddr, SocketAddr},
+    pin::Pin,
+    rc::Rc,
+    sync::Mutex,
+};
+
+use actix_web::{
+    body::{EitherBody, MessageBody},
+    dev::{forward_ready, Payload, Service, ServiceRequest, ServiceResponse, Transform},
+    http::{
+        header::{self, X_FORWARDED_FOR},
+        Method,
+    },
+    web, Error, HttpRequest, HttpResponse,
+};
+use serde::Deserialize;
+use shared::Role;
+use time::{Duration, OffsetDateTime};
+
+use crate::{
+    auth_user::decode_without_authorization,
+    biscuit::RootKeys,
+    configuration::{RateLimitSettings, RequestLimit},
+    time::MockableDateTime,
+};
+
+/// Request bodies are only read on these routes, to find their email.
+const INSPECTED_PATH_PREFIX: &str = "/api/v1/authentication/";
+/// Expired counters are removed when there are more than this many, see [`PrunedMap`].
+const PRUNE_THRESHOLD: usize = 10_000;
+
+type LocalBoxFuture<T> = Pin<Box<dyn Future<Output = T>>>;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum RateLimitKey {
+    Ip(IpAddr),
+    /// By client ip too, like [`RateLimiter::login_failures`].
+    Email(String, Option<IpAddr>),
+    /// `(app, user)`: users of an app don't share a limit, one of them could use it up otherwise.
+    App(i32, i32),
+}
+
+struct Window {
+    started_at: OffsetDateTime,
+    count: u32,
+}
+
+struct LoginFailures {
+    count: u32,
+    last_failure_at: OffsetDateTime,
+    locked_until: Option<OffsetDateTime>,
+}
+
+/// Time to wait before retrying.
+#[derive(Debug, Clone, Copy)]
+pub struct RetryAfter(pub Duration);
+
+impl RetryAfter {
+    /// `429 Too Many Requests`, with the delay in seconds in the `Retry-After` header.
+    pub fn response(self) -> HttpResponse {
+        // Rounded up, so retrying after the delay succeeds.
+        let seconds = self.0.whole_seconds() + i64::from(self.0.subsec_nanoseconds() > 0);
+        HttpResponse::TooManyRequests()
+            .insert_header((header::RETRY_AFTER, seconds.max(1).to_string()))
+            .finish()
+    }
+}
+
+/// Counters, whose expired entries are removed at most once per interval:
+/// keys include emails chosen by clients, which could keep the map over [`PRUNE_THRESHOLD`],
+/// and a scan of the whole map on each request would hold the lock every request waits for.
+struct PrunedMap<K, V> {
+    entries: HashMap<K, V>,
+    pruned_at: Option<OffsetDateTime>,
+}
+
+impl<K: Eq + Hash, V> PrunedMap<K, V> {
+    fn new() -> Self {
+        Self {
+            entries: HashMap::new(),
+            pruned_at: None,
+        }
+    }
+
+    /// Keeps the entries `is_live` accepts, if there are too many and they were not pruned
+    /// within `interval`.
+    fn prune(
+        &mut self,
+        now: OffsetDateTime,
+        interval: Duration,
+        is_live: impl FnMut(&K, &mut V) -> bool,
+    ) {
+        if self.entries.len() <= PRUNE_THRESHOLD
+            || self
+                .pruned_at
+                .map_or(false, |pruned_at| now - pruned_at < interval)
+        {
+            return;
+        }
+        self.entries.retain(is_live);
+        self.pruned_at = Some(now);
+    }
+}
+
+pub struct RateLimiter {
+    settings: RateLimitSettings,
+    time: MockableDateTime,
+    windows: Mutex<PrunedMap<RateLimitKey, Window>>,
+    /// By email and client ip, so others can't lock a user out by knowing their email.
+    login_failures: Mutex<PrunedMap<(String, Option<IpAddr>), LoginFailures>>,
+}
+
+impl RateLimiter {
+    pub fn new(settings: RateLimitSettings, time: MockableDateTime) -> Self {
+        Self {
+            settings,
+            time,
+            windows: Mutex::new(PrunedMap::new()),
+            login_failures: Mutex::new(PrunedMap::new()),
+        }
+    }
+
+    /// Counts a request for each given key, fails if one of them exceeded its limit.
+    fn check(
+        &self,
+        ip: Option<IpAddr>,
+        email: Option<&str>,
+        app_user: Option<(i32, i32)>,
+    ) -> Result<(), RetryAfter> {
+        let keys = [
+            ip.map(|ip| (RateLimitKey::Ip(ip), &self.settings.per_ip)),
+            email.map(|email| {
+                (
+                    RateLimitKey::Email(normalize_email(email), ip),
+                    &self.settings.per_email,
+                )
+            }),
+            app_user.map(|(app_id, user_id)| {
+                (RateLimitKey::App(app_id, user_id), &self.settings.per_app)
+            }),
+        ];
+        let now = self.time.now_utc();
+        let longest_period = seconds(
+            [
+                &self.settings.per_ip,
+                &self.settings.per_email,
+                &self.settings.per_app,
+            ]
+            .iter()
+            .map(|limit| limit.period_seconds)
+            .max()
+            .unwrap_or_default(),
+        );
+        let mut windows = self.windows.lock().unwrap();
+        // Windows expire after a period: pruning more often would not remove many more.
+        windows.prune(now, longest_period, |_, window| {
+            now - window.started_at < longest_period
+        });
+        for (key, limit) in keys.into_iter().flatten() {
+            hit(&mut windows.entries, key, limit, now)?;
+        }
+        Ok(())
+    }
+
+    /// Fails while logins to this email from this ip are locked out after bad passwords.
+    pub fn check_login_lockout(&self, email: &str, ip: Option<IpAddr>) -> Result<(), RetryAfter> {
+        let now = self.time.now_utc();
+        let login_failures = self.login_failures.lock().unwrap();
+        match login_failures
+            .entries
+            .get(&(normalize_email(email), ip))
+            .and_then(|failures| failures.locked_until)
+        {
+            Some(locked_until) if locked_until > now => Err(RetryAfter(locked_until - now)),
+            _ => Ok(()),
+        }
+    }
+
+    /// Locks out logins to this email from this ip after too many failures,
+    /// for longer after each failure.
+    pub fn record_login_failure(&self, email: &str, ip: Option<IpAddr>) {
+        let now = self.time.now_utc();
+        let lockout = &self.settings.login_lockout;
+        let forget_after = seconds(lockout.max_lockout_seconds);
+        let mut login_failures = self.login_failures.lock().unwrap();
+        login_failures.prune(now, forget_after, |_, failures| {
+            now - failures.last_failure_at <= forget_after
+        });
+        let failures = login_failures
+            .entries
+            .entry((normalize_email(email), ip))
+            .or_insert(LoginFailures {
+                count: 0,
+                last_failure_at: now,
+                locked_until: None,
+            });
+        if now - failures.last_failure_at > forget_after {
+            failures.count = 0;
+        }
+        failures.count += 1;
+        failures.last_failure_at = now;
+        if failures.count >= lockout.failures_before_lockout {
+            let doublings = failures.count - lockout.failures_before_lockout;
+            let lockout_seconds = lockout
+                .base_lockout_seconds
+                .saturating_mul(2u64.saturating_pow(doublings))
+                .min(lockout.max_lockout_seconds);
+            failures.locked_until = Some(now + seconds(lockout_seconds));
+        }
+    }
+
+    /// Forgets the failures and the email requests of this email from this ip.
+    pub fn record_login_success(&self, email: &str, ip: Option<IpAddr>) {
+        let email = normalize_email(email);
+        self.windows
+            .lock()
+            .unwrap()
+            .entries
+            .remove(&RateLimitKey::Email(email.clone(), ip));
+        self.login_failures
+            .lock()
+            .unwrap()
+            .entries
+            .remove(&(email, ip));
+    }
+
+    /// With a proxy, the rightmost `X-Forwarded-For` entry is the one it appended:
+    /// entries on its left are sent by clients, and can be forged.
+    pub fn client_ip(&self, req: &HttpRequest) -> Option<IpAddr> {
+        if !self.settings.behind_proxy {
+            return req.peer_addr().map(|addr| addr.ip());
+        }
+        let last_hop = req
+            .headers()
+            .get_all(X_FORWARDED_FOR)
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .last()?
+            .trim();
+        last_hop
+            .parse::<IpAddr>()
+            .ok()
+            .or_else(|| last_hop.parse::<SocketAddr>().ok().map(|addr| addr.ip()))
+    }
+}
+
+fn hit(
+    windows: &mut HashMap<RateLimitKey, Window>,
+    key: RateLimitKey,
+    limit: &RequestLimit,
+    now: OffsetDateTime,
+) -> Result<(), RetryAfter> {
+    let period = seconds(limit.period_seconds);
+    let window = windows.entry(key).or_insert(Window {
+        started_at: now,
+        count: 0,
+    });
+    if now - window.started_at >= period {
+        *window = Window {
+            started_at: now,
+            count: 0,
+        };
+    }
+    if window.count >= limit.max_requests {
+        return Err(RetryAfter(window.started_at + period - now));
+    }
+    window.count += 1;
+    Ok(())
+}
+
+fn seconds(seconds: u64) -> Duration {
+    Duration::seconds(seconds.try_into().unwrap_or(i64::MAX))
+}
+
+fn normalize_email(email: &str) -> String {
+    email.trim().to_lowercase()
+}
+
+/// Fields identifying who a request is for, read from authentication request bodies.
+///
+/// The app of `as_app_user` is not counted: anyone could exhaust the limit of an app with it.
+#[derive(Deserialize)]
+struct InspectedBody {
+    email: Option<String>,
+}
+
+/// Middleware applying the limits of [`RateLimiter`],
+/// answering `429 Too Many Requests` with a `Retry-After` header.
+pub struct RateLimiting {
+    limiter: web::Data<RateLimiter>,
+    root: web::Data<RootKeys>,
+}
+
+impl RateLimiting {
+    pub fn new(limiter: web::Data<RateLimiter>, root: web::Data<RootKeys>) -> Self {
+        Self { limiter, root }
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for RateLimiting
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Transform = RateLimitingMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RateLimitingMiddleware {
+            service: Rc::new(service),
+            limiter: self.limiter.clone(),
+            root: self.root.clone(),
+        }))
+    }
+}
+
+pub struct RateLimitingMiddleware<S> {
+    service: Rc<S>,
+    limiter: web::Data<RateLimiter>,
+    root: web::Data<RootKeys>,
+}
+
+impl<S, B> Service<ServiceRequest> for RateLimitingMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Future = LocalBoxFuture<Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, mut req: ServiceRequest) -> Self::Future {
+        let service = Rc::clone(&self.service);
+        let limiter = self.limiter.clone();
+        let root = self.root.clone();
+        Box::pin(async move {
+            let ip = limiter.client_ip(req.request());
+            let app_user = bearer_app_user(&req, &root, &limiter.time);
+            let mut email = None;
+            if req.method() == Method::POST && req.path().starts_with(INSPECTED_PATH_PREFIX) {
+                let body = req.extract::<web::Bytes>().await?;
+                if let Ok(inspected) = serde_json::from_slice::<InspectedBody>(&body) {
+                    email = inspected.email;
+                }
+                req.set_payload(Payload::from(body));
+            }
+            if let Err(retry_after) = limiter.check(ip, email.as_deref(), app_user) {
+                return Ok(req
+                    .into_response(retry_after.response())
+                    .map_into_right_body());
+            }
+            service
+                .call(req)
+                .await
+                .map(ServiceResponse::map_into_left_body)
+        })
+    }
+}
+
+/// App and user of a valid bearer biscuit, even expired, as the request is counted before its authorization.
+fn bearer_app_user(
+    req: &ServiceRequest,
+    root: &RootKeys,
+    time: &MockableDateTime,
+) -> Option<(i32, i32)> {
+    let token = req
+        .headers()
+        .get(header::AUTHORIZATION)?
+        .to_str()
+        .ok()?
+        .strip_prefix("Bearer ")?;
+    let biscuit = root.parse(token).ok()?;
+    let biscuit_info = decode_without_authorization(&biscuit, time)?;
+    match biscuit_info.role {
+        Role::User(app_id) | Role::AppService(app_id) => Some((app_id.0, biscuit_info.user_id.0)),
+        Role::Admin => None,
+    }
+}
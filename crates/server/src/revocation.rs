@@ -0,0 +1,215 @@
+//! Revocation of authentication biscuits before their expiration.
+//!
+//! Revoked biscuits are kept in memory, so validators don't query the database on each request;
+//! they are reloaded periodically, for revocations by other server instances.
+//! Expired biscuits are deleted from the database on reload, by a single request at a time.
+
+use std::{collections::HashSet, sync::RwLock};
+
+use biscuit_auth::Biscuit;
+use shared::{BiscuitInfo, RefreshTokenFamilyId};
+use sqlx::PgPool;
+use time::{Duration, OffsetDateTime, PrimitiveDateTime};
+use tokio::sync::Mutex;
+
+use crate::{
+    auth_user::decode_without_authorization,
+    biscuit::RootKeys,
+    models::{issued_biscuit, user::UserId},
+    time::MockableDateTime,
+};
+
+/// Revocations by other server instances are taken into account after at most this delay.
+pub const REVOCATION_LIST_RELOAD_INTERVAL: i64 = 10;
+
+/// Records a biscuit issued to the user, in the session `family_id` if any,
+/// see [`RevocationList::revoke_all_for_user`] and [`RevocationList::revoke_all_for_session`].
+pub async fn record_issued(
+    connection: &PgPool,
+    biscuit: &Biscuit,
+    user_id: UserId,
+    family_id: Option<RefreshTokenFamilyId>,
+    expiration_date: OffsetDateTime,
+) -> Result<(), sqlx::Error> {
+    let Some(revocation_id) = biscuit.revocation_identifiers().into_iter().next() else {
+        return Ok(());
+    };
+    issued_biscuit::record(
+        connection,
+        &revocation_id,
+        user_id,
+        family_id,
+        expiration_date,
+    )
+    .await
+}
+
+/// Parses a biscuit to revoke: signed by this server, attenuated or not, and not expired.
+pub fn parse_revocable(
+    root: &RootKeys,
+    time: &MockableDateTime,
+    token: &str,
+) -> Option<(Biscuit, BiscuitInfo)> {
+    let biscuit = root.parse(token).ok()?;
+    let biscuit_info = decode_without_authorization(&biscuit, time)?;
+    Some((biscuit, biscuit_info))
+}
+
+struct Revoked {
+    revocation_ids: HashSet<Vec<u8>>,
+    loaded_at: Option<OffsetDateTime>,
+    /// Revoked while a reload is in progress: they may be missing from the reloaded ones.
+    revoked_during_reload: Option<Vec<Vec<u8>>>,
+}
+
+impl Revoked {
+    fn insert(&mut self, revocation_ids: impl IntoIterator<Item = Vec<u8>>) {
+        for revocation_id in revocation_ids {
+            if let Some(revoked_during_reload) = &mut self.revoked_during_reload {
+                revoked_during_reload.push(revocation_id.clone());
+            }
+            self.revocation_ids.insert(revocation_id);
+        }
+    }
+}
+
+pub struct RevocationList {
+    time: MockableDateTime,
+    revoked: RwLock<Revoked>,
+    /// Held while reloading, so concurrent requests don't all reload.
+    reloading: Mutex<()>,
+}
+
+impl RevocationList {
+    /// Loaded from the database on first use.
+    pub fn new(time: MockableDateTime) -> Self {
+        Self {
+            time,
+            revoked: RwLock::new(Revoked {
+                revocation_ids: HashSet::new(),
+                loaded_at: None,
+                revoked_during_reload: None,
+            }),
+            reloading: Mutex::new(()),
+        }
+    }
+
+    /// `None` if never loaded.
+    fn is_stale(&self, now: OffsetDateTime) -> Option<bool> {
+        self.revoked
+            .read()
+            .unwrap()
+            .loaded_at
+            .map(|loaded_at| now - loaded_at >= Duration::seconds(REVOCATION_LIST_RELOAD_INTERVAL))
+    }
+
+    async fn reload_if_stale(&self, connection: &PgPool) -> Result<(), sqlx::Error> {
+        let now = self.time.now_utc();
+        let _reloading = match self.is_stale(now) {
+            Some(false) => return Ok(()),
+            // Other requests keep using the stale list meanwhile.
+            Some(true) => match self.reloading.try_lock() {
+                Ok(reloading) => reloading,
+                Err(_) => return Ok(()),
+            },
+            // There is no list to use yet.
+            None => self.reloading.lock().await,
+        };
+        if self.is_stale(now) == Some(false) {
+            return Ok(());
+        }
+        self.revoked.write().unwrap().revoked_during_reload = Some(Vec::new());
+        // Every issued biscuit is recorded, expired ones are deleted so the table doesn't grow.
+        issued_biscuit::delete_expired(connection, now).await?;
+        let revocation_ids = issued_biscuit::get_all_revoked(connection, now).await?;
+        let mut revoked = self.revoked.write().unwrap();
+        let revoked_during_reload = revoked.revoked_during_reload.take().unwrap_or_default();
+        revoked.revocation_ids = revocation_ids.into_iter().collect();
+        revoked.revocation_ids.extend(revoked_during_reload);
+        revoked.loaded_at = Some(now);
+        Ok(())
+    }
+
+    /// A biscuit is revoked if any of its blocks is: revoking a biscuit revokes its attenuations.
+    pub async fn is_revoked(
+        &self,
+        connection: &PgPool,
+        biscuit: &Biscuit,
+    ) -> Result<bool, sqlx::Error> {
+        self.reload_if_stale(connection).await?;
+        let revoked = self.revoked.read().unwrap();
+        Ok(biscuit
+            .revocation_identifiers()
+            .iter()
+            .any(|revocation_id| revoked.revocation_ids.contains(revocation_id)))
+    }
+
+    /// Revokes the biscuit, and biscuits attenuated from it, but not the one it was attenuated from.
+    pub async fn revoke(
+        &self,
+        connection: &PgPool,
+        biscuit: &Biscuit,
+        biscuit_info: &BiscuitInfo,
+    ) -> Result<(), sqlx::Error> {
+        let Some(revocation_id) = biscuit.revocation_identifiers().into_iter().last() else {
+            return Ok(());
+        };
+        let expiration_date =
+            OffsetDateTime::from_unix_timestamp(biscuit_info.expiration_date_unix_timestamp)
+                .unwrap_or(PrimitiveDateTime::MAX.assume_utc());
+        issued_biscuit::revoke(
+            connection,
+            &revocation_id,
+            UserId::from(biscuit_info.user_id),
+            expiration_date,
+            self.time.now_utc(),
+        )
+        .await?;
+        self.revoked.write().unwrap().insert([revocation_id]);
+        Ok(())
+    }
+
+    /// Revokes all unexpired biscuits issued to the user, for a deleted or compromised account.
+    pub async fn revoke_all_for_user(
+        &self,
+        connection: &PgPool,
+        user_id: UserId,
+    ) -> Result<(), sqlx::Error> {
+        let revocation_ids =
+            issued_biscuit::revoke_all_for_user(connection, user_id, self.time.now_utc()).await?;
+        self.revoked.write().unwrap().insert(revocation_ids);
+        Ok(())
+    }
+
+    /// Revokes all unexpired biscuits issued in a session, once the session is revoked:
+    /// they would stay valid until they expire otherwise.
+    pub async fn revoke_all_for_session(
+        &self,
+        connection: &PgPool,
+        family_id: RefreshTokenFamilyId,
+    ) -> Result<(), sqlx::Error> {
+        let revocation_ids =
+            issued_biscuit::revoke_all_for_family(connection, family_id, self.time.now_utc())
+                .await?;
+        self.revoked.write().unwrap().insert(revocation_ids);
+        Ok(())
+    }
+
+    /// Same as [`RevocationList::revoke_all_for_session`], for all the sessions of the user but one.
+    pub async fn revoke_all_for_other_sessions(
+        &self,
+        connection: &PgPool,
+        user_id: UserId,
+        family_id: RefreshTokenFamilyId,
+    ) -> Result<(), sqlx::Error> {
+        let revocation_ids = issued_biscuit::revoke_all_for_user_except_family(
+            connection,
+            user_id,
+            family_id,
+            self.time.now_utc(),
+        )
+        .await?;
+        self.revoked.write().unwrap().insert(revocation_ids);
+        Ok(())
+    }
+}
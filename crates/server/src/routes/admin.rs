@@ -1,16 +1,19 @@
 use actix_web::{dev::HttpServiceFactory, web};
 use actix_web_httpauth::middleware::HttpAuthentication;
-use biscuit_auth::KeyPair;
 
-use crate::auth_user::validator_admin;
+use crate::{auth_user::validator_admin, biscuit::RootKeys};
 
 mod app;
 mod item;
+mod token;
+mod two_factor;
 
-pub fn config(kp: web::Data<KeyPair>) -> impl HttpServiceFactory {
+pub fn config(kp: web::Data<RootKeys>) -> impl HttpServiceFactory {
     web::scope("/admin")
         .app_data(kp)
         .wrap(HttpAuthentication::bearer(validator_admin))
         .service(app::config())
         .service(item::config())
+        .service(token::config())
+        .service(two_factor::config())
 }
@@ -2,18 +2,42 @@ use std::fmt::Display;
 
 use actix_web::{dev::HttpServiceFactory, web, HttpResponse, Responder};
 use actix_web::{HttpMessage, HttpRequest};
+use bcrypt::{hash, DEFAULT_COST};
+use rand::{distributions::Alphanumeric, Rng};
 use serde::Deserialize;
+use shared::{AppCredentialsId, AppPolicy, CreatedAppCredentials};
 use sqlx::PgPool;
 
+use crate::item_rights::ItemRightsVersions;
 use crate::models::app::AppAdmin;
 use crate::models::app::AppId;
+use crate::models::app_credentials::AppCredentials;
+use crate::revocation::RevocationList;
+use crate::time::MockableDateTime;
 use shared::BiscuitInfo;
 
 pub(super) fn config() -> impl HttpServiceFactory {
-    web::resource("/app")
-        .route(web::post().to(create_app))
-        .route(web::get().to(get_apps_for_admin))
-        .route(web::delete().to(delete_app))
+    web::scope("/app")
+        .service(
+            web::resource("")
+                .route(web::post().to(create_app))
+                .route(web::get().to(get_apps_for_admin))
+                .route(web::delete().to(delete_app)),
+        )
+        .service(
+            web::resource("/{app_id}/credentials")
+                .route(web::post().to(create_app_credentials))
+                .route(web::get().to(get_app_credentials)),
+        )
+        .route(
+            "/{app_id}/credentials/{credentials_id}",
+            web::delete().to(revoke_app_credentials),
+        )
+        .service(
+            web::resource("/{app_id}/policy")
+                .route(web::get().to(get_app_policy))
+                .route(web::put().to(set_app_policy)),
+        )
 }
 
 #[derive(Debug, Deserialize, Clone)]
@@ -37,7 +61,7 @@ async fn create_app(
     req_data: web::Json<CreateAppData>,
     req: HttpRequest,
 ) -> impl Responder {
-    let Some(user) = req.extensions().get::<BiscuitInfo>().map(|b| {b.user_id}) else {
+    let Some(user) = req.extensions().get::<BiscuitInfo>().map(|b| b.user_id) else {
         return HttpResponse::Unauthorized().finish();
     };
     let app_id = AppId::create(&connection, &req_data.name).await.unwrap();
@@ -53,7 +77,7 @@ async fn create_app(
 
 #[tracing::instrument(name = "Get Apps for admin", skip_all)]
 async fn get_apps_for_admin(connection: web::Data<PgPool>, req: HttpRequest) -> impl Responder {
-    let Some(user) = req.extensions().get::<BiscuitInfo>().map(|b| {b.user_id}) else {
+    let Some(user) = req.extensions().get::<BiscuitInfo>().map(|b| b.user_id) else {
         return HttpResponse::Unauthorized().finish();
     };
 
@@ -81,18 +105,194 @@ async fn delete_app(
     app_id: web::Json<DeleteAppData>,
     req: HttpRequest,
 ) -> impl Responder {
-    let Some(user) = req.extensions().get::<BiscuitInfo>().map(|b| {b.user_id}) else {
+    let Some(biscuit) = req.extensions().get::<BiscuitInfo>().cloned() else {
         return HttpResponse::Unauthorized().finish();
     };
     let app = AppId::from(app_id.id);
 
-    let Ok(apps) = AppId::get_all_for_user(user.into(), &connection).await
-    else {
+    let Ok(is_admin) = is_admin_of_app(&connection, &biscuit, app).await else {
         return HttpResponse::InternalServerError().finish();
     };
-    if apps.iter().any(|a| a.app_id == app) {
+    if is_admin {
         app.delete(&connection).await.unwrap();
         return HttpResponse::Ok().finish();
     }
     HttpResponse::Unauthorized().finish()
 }
+
+/// Apps requiring two-factor authentication from their admins are refused to logins without it.
+async fn is_admin_of_app(
+    connection: &PgPool,
+    biscuit: &BiscuitInfo,
+    app: AppId,
+) -> Result<bool, sqlx::Error> {
+    Ok(AppId::get_all_administrable_for_user(
+        biscuit.user_id.into(),
+        biscuit.two_factor,
+        connection,
+    )
+    .await?
+    .iter()
+    .any(|a| a.app_id == app))
+}
+
+/// Creates new credentials for a game server to authenticate as the app.
+///
+/// Previous credentials are kept valid, to allow rotation without downtime:
+/// revoke them when the game servers use the new ones.
+#[tracing::instrument(name = "Create app credentials", skip_all, fields(app_id=%&*app_id))]
+async fn create_app_credentials(
+    connection: web::Data<PgPool>,
+    time: web::Data<MockableDateTime>,
+    app_id: web::Path<i32>,
+    req: HttpRequest,
+) -> impl Responder {
+    let Some(biscuit) = req.extensions().get::<BiscuitInfo>().cloned() else {
+        return HttpResponse::Unauthorized().finish();
+    };
+    let app = AppId::from(*app_id);
+    match is_admin_of_app(&connection, &biscuit, app).await {
+        Ok(true) => {}
+        Ok(false) => return HttpResponse::Unauthorized().finish(),
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    }
+    let secret: String = rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(64)
+        .map(char::from)
+        .collect();
+    let Ok(secret_hash) = hash(&secret, DEFAULT_COST) else {
+        return HttpResponse::InternalServerError().finish();
+    };
+    let Ok(id) = AppCredentials::create(
+        &connection,
+        app,
+        &secret_hash,
+        biscuit.user_id.into(),
+        time.now_utc(),
+    )
+    .await
+    else {
+        return HttpResponse::InternalServerError().finish();
+    };
+    HttpResponse::Created().json(CreatedAppCredentials {
+        id,
+        app_id: app.0,
+        secret,
+    })
+}
+
+#[tracing::instrument(name = "Get app credentials", skip_all, fields(app_id=%&*app_id))]
+async fn get_app_credentials(
+    connection: web::Data<PgPool>,
+    app_id: web::Path<i32>,
+    req: HttpRequest,
+) -> impl Responder {
+    let Some(biscuit) = req.extensions().get::<BiscuitInfo>().cloned() else {
+        return HttpResponse::Unauthorized().finish();
+    };
+    let app = AppId::from(*app_id);
+    match is_admin_of_app(&connection, &biscuit, app).await {
+        Ok(true) => {}
+        Ok(false) => return HttpResponse::Unauthorized().finish(),
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    }
+    if let Ok(credentials) = AppCredentials::get_all_for_app(&connection, app).await {
+        HttpResponse::Ok().json(
+            credentials
+                .into_iter()
+                .map(shared::AppCredentials::from)
+                .collect::<Vec<_>>(),
+        )
+    } else {
+        HttpResponse::InternalServerError().finish()
+    }
+}
+
+#[tracing::instrument(
+    name = "Revoke app credentials",
+    skip_all,
+    fields(app_id=%path.0, credentials_id=%path.1)
+)]
+async fn revoke_app_credentials(
+    connection: web::Data<PgPool>,
+    revocation_list: web::Data<RevocationList>,
+    path: web::Path<(i32, i32)>,
+    req: HttpRequest,
+) -> impl Responder {
+    let Some(biscuit) = req.extensions().get::<BiscuitInfo>().cloned() else {
+        return HttpResponse::Unauthorized().finish();
+    };
+    let app = AppId::from(path.0);
+    match is_admin_of_app(&connection, &biscuit, app).await {
+        Ok(true) => {}
+        Ok(false) => return HttpResponse::Unauthorized().finish(),
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    }
+    let service_user =
+        match AppCredentials::revoke(&connection, app, AppCredentialsId(path.1)).await {
+            Ok(service_user) => service_user,
+            Err(sqlx::Error::RowNotFound) => return HttpResponse::NotFound().finish(),
+            Err(_) => return HttpResponse::InternalServerError().finish(),
+        };
+    // Biscuits already issued with the credentials would stay valid until they expire otherwise.
+    match revocation_list
+        .revoke_all_for_user(&connection, service_user)
+        .await
+    {
+        Ok(_) => HttpResponse::Ok().finish(),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+#[tracing::instrument(name = "Get app policy", skip_all, fields(app_id=%&*app_id))]
+async fn get_app_policy(
+    connection: web::Data<PgPool>,
+    app_id: web::Path<i32>,
+    req: HttpRequest,
+) -> impl Responder {
+    let Some(biscuit) = req.extensions().get::<BiscuitInfo>().cloned() else {
+        return HttpResponse::Unauthorized().finish();
+    };
+    let app = AppId::from(*app_id);
+    match is_admin_of_app(&connection, &biscuit, app).await {
+        Ok(true) => {}
+        Ok(false) => return HttpResponse::Unauthorized().finish(),
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    }
+    if let Ok(policy) = app.get_policy(&connection).await {
+        HttpResponse::Ok().json(policy)
+    } else {
+        HttpResponse::InternalServerError().finish()
+    }
+}
+
+#[tracing::instrument(name = "Set app policy", skip_all, fields(app_id=%&*app_id))]
+async fn set_app_policy(
+    connection: web::Data<PgPool>,
+    item_rights_versions: web::Data<ItemRightsVersions>,
+    app_id: web::Path<i32>,
+    policy: web::Json<AppPolicy>,
+    req: HttpRequest,
+) -> impl Responder {
+    let Some(biscuit) = req.extensions().get::<BiscuitInfo>().cloned() else {
+        return HttpResponse::Unauthorized().finish();
+    };
+    let app = AppId::from(*app_id);
+    match is_admin_of_app(&connection, &biscuit, app).await {
+        Ok(true) => {}
+        Ok(false) => return HttpResponse::Unauthorized().finish(),
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    }
+    if policy.require_admin_two_factor && !biscuit.two_factor {
+        // Admins should not lock themselves out.
+        return HttpResponse::Forbidden()
+            .body("Log in with two-factor authentication to require it from admins.");
+    }
+    if app.set_policy(&connection, &policy).await.is_ok() {
+        item_rights_versions.invalidate(app);
+        HttpResponse::Ok().finish()
+    } else {
+        HttpResponse::InternalServerError().finish()
+    }
+}
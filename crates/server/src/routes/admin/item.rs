@@ -6,14 +6,19 @@ use actix_web::{
 use serde::{Deserialize, Serialize};
 use sqlx::PgPool;
 
-use shared::BiscuitInfo;
+use shared::{BiscuitInfo, ItemBounds};
 
-use crate::models::{app::AppId, item::create, user::UserId};
+use crate::models::{
+    app::AppId,
+    item::{create, ItemFull, ItemId},
+    user::UserId,
+};
 
 pub fn config() -> impl HttpServiceFactory {
     web::scope("/item")
         .route("/app/{app_id}", web::post().to(create_item))
         .route("/{item_id}", web::delete().to(delete_item))
+        .route("/{item_id}/bounds", web::put().to(set_item_bounds))
 }
 
 #[derive(Deserialize, Serialize)]
@@ -33,7 +38,10 @@ async fn create_item(
     app_id: web::Path<i32>,
 ) -> impl Responder {
     let user = biscuit.user_id;
-    let Ok(owned_apps) = AppId::get_all_for_user(UserId::from(user), &connection).await else {
+    let Ok(owned_apps) =
+        AppId::get_all_administrable_for_user(UserId::from(user), biscuit.two_factor, &connection)
+            .await
+    else {
         return HttpResponse::Unauthorized().body("no apps for user");
     };
     if !owned_apps
@@ -65,3 +73,43 @@ async fn delete_item(_connection: web::Data<PgPool>, _item_id: web::Path<i32>) -
     }
     */
 }
+
+#[tracing::instrument(
+    name = "Set item bounds",
+    skip_all,
+    fields(biscuit=%&*biscuit, item_id=%&*item_id)
+)]
+async fn set_item_bounds(
+    connection: web::Data<PgPool>,
+    bounds: web::Json<ItemBounds>,
+    biscuit: ReqData<BiscuitInfo>,
+    item_id: web::Path<i32>,
+) -> impl Responder {
+    let item_id = ItemId(*item_id);
+    let Some(item) = ItemFull::get(item_id, &connection).await else {
+        return HttpResponse::NotFound().body("item not found");
+    };
+    let Ok(owned_apps) = AppId::get_all_administrable_for_user(
+        UserId::from(biscuit.user_id),
+        biscuit.two_factor,
+        &connection,
+    )
+    .await
+    else {
+        return HttpResponse::Unauthorized().body("no apps for user");
+    };
+    if !owned_apps.iter().any(|app| app.app_id == item.app_id) {
+        return HttpResponse::Unauthorized().body("app not authorized for user");
+    }
+    if bounds.min_amount < 0
+        || bounds.max_amount.is_some_and(|max| max < bounds.min_amount)
+        || bounds.max_change.is_some_and(|max_change| max_change <= 0)
+    {
+        return HttpResponse::BadRequest().body("invalid bounds");
+    }
+    if item_id.set_bounds(&connection, &bounds).await.is_ok() {
+        HttpResponse::Ok().finish()
+    } else {
+        HttpResponse::InternalServerError().finish()
+    }
+}
@@ -0,0 +1,53 @@
+use actix_web::{dev::HttpServiceFactory, web, HttpResponse, Responder};
+use shared::{BiscuitInfo, RevokeTokenData, Role};
+use sqlx::PgPool;
+
+use crate::{
+    biscuit::RootKeys,
+    models::{app::AppId, user::UserId},
+    revocation::{parse_revocable, RevocationList},
+    time::MockableDateTime,
+};
+
+pub(super) fn config() -> impl HttpServiceFactory {
+    web::scope("/token").route("/revoke", web::post().to(revoke_token))
+}
+
+/// Revokes an authentication token of the admin, or of a user or app service of an app they administer.
+#[tracing::instrument(name = "Admin revoke token", skip_all)]
+async fn revoke_token(
+    connection: web::Data<PgPool>,
+    root: web::Data<RootKeys>,
+    time: web::Data<MockableDateTime>,
+    revocation_list: web::Data<RevocationList>,
+    account: web::ReqData<BiscuitInfo>,
+    req_data: web::Json<RevokeTokenData>,
+) -> impl Responder {
+    let Some((token, token_info)) = parse_revocable(&root, &time, &req_data.token) else {
+        return HttpResponse::BadRequest().body("Invalid authentication token.");
+    };
+    if token_info.user_id != account.user_id {
+        let (Role::User(app_id) | Role::AppService(app_id)) = token_info.role else {
+            return HttpResponse::Forbidden().body("This token belongs to another admin.");
+        };
+        let Ok(apps) = AppId::get_all_administrable_for_user(
+            UserId::from(account.user_id),
+            account.two_factor,
+            &connection,
+        )
+        .await
+        else {
+            return HttpResponse::InternalServerError().finish();
+        };
+        if !apps.iter().any(|app| app.app_id == AppId::from(app_id)) {
+            return HttpResponse::Forbidden().body("You are not an admin of this token's app.");
+        }
+    }
+    match revocation_list
+        .revoke(&connection, &token, &token_info)
+        .await
+    {
+        Ok(_) => HttpResponse::Ok().finish(),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
@@ -0,0 +1,118 @@
+use actix_web::{dev::HttpServiceFactory, web, HttpResponse, Responder};
+use shared::{BiscuitInfo, TwoFactorCodeData, TwoFactorEnrollment};
+use sqlx::PgPool;
+
+use crate::{
+    models::{two_factor::TwoFactor, user::UserId},
+    time::MockableDateTime,
+    totp,
+};
+
+pub(super) fn config() -> impl HttpServiceFactory {
+    web::scope("/two_factor")
+        .route("/enroll", web::post().to(enroll))
+        .route("/confirm", web::post().to(confirm))
+        .route("/disable", web::post().to(disable))
+}
+
+/// Generates a new TOTP secret and recovery codes, enabled once a first code is confirmed.
+///
+/// Enrolling again before confirming replaces the previous secret.
+#[tracing::instrument(name = "Enroll two-factor", skip_all)]
+async fn enroll(
+    connection: web::Data<PgPool>,
+    time: web::Data<MockableDateTime>,
+    account: web::ReqData<BiscuitInfo>,
+) -> impl Responder {
+    let user_id = UserId::from(account.user_id);
+    let Some(user) = user_id.get(&connection).await else {
+        return HttpResponse::InternalServerError().finish();
+    };
+    let secret = totp::generate_secret();
+    let recovery_codes = totp::generate_recovery_codes();
+    let recovery_code_hashes: Vec<String> = recovery_codes
+        .iter()
+        .map(|code| totp::hash_recovery_code(code))
+        .collect();
+    match TwoFactor::start_enrollment(
+        &connection,
+        user_id,
+        &secret,
+        &recovery_code_hashes,
+        time.now_utc(),
+    )
+    .await
+    {
+        Ok(true) => HttpResponse::Created().json(TwoFactorEnrollment {
+            provisioning_uri: totp::provisioning_uri(&secret, &user.name),
+            secret,
+            recovery_codes,
+        }),
+        Ok(false) => HttpResponse::Conflict().body("Two-factor authentication is already enabled."),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+/// Enables two-factor authentication with a first code from the authenticator app:
+/// next admin logins will need a code.
+#[tracing::instrument(name = "Confirm two-factor", skip_all)]
+async fn confirm(
+    connection: web::Data<PgPool>,
+    time: web::Data<MockableDateTime>,
+    account: web::ReqData<BiscuitInfo>,
+    req_data: web::Json<TwoFactorCodeData>,
+) -> impl Responder {
+    let user_id = UserId::from(account.user_id);
+    let two_factor = match TwoFactor::get(&connection, user_id).await {
+        Ok(Some(two_factor)) if !two_factor.enabled => two_factor,
+        Ok(_) => return HttpResponse::BadRequest().body("No two-factor enrolment to confirm."),
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+    match two_factor
+        .verify_totp(&connection, &req_data.code, time.now_utc())
+        .await
+    {
+        Ok(true) => {}
+        Ok(false) => return HttpResponse::Unauthorized().finish(),
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    }
+    if TwoFactor::enable(&connection, user_id).await.is_ok() {
+        HttpResponse::Ok().finish()
+    } else {
+        HttpResponse::InternalServerError().finish()
+    }
+}
+
+/// Needs a login with two-factor authentication, and a code or recovery code.
+#[tracing::instrument(name = "Disable two-factor", skip_all)]
+async fn disable(
+    connection: web::Data<PgPool>,
+    time: web::Data<MockableDateTime>,
+    account: web::ReqData<BiscuitInfo>,
+    req_data: web::Json<TwoFactorCodeData>,
+) -> impl Responder {
+    let user_id = UserId::from(account.user_id);
+    let two_factor = match TwoFactor::get(&connection, user_id).await {
+        Ok(Some(two_factor)) if two_factor.enabled => two_factor,
+        Ok(_) => {
+            return HttpResponse::BadRequest().body("Two-factor authentication is not enabled.")
+        }
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+    if !account.two_factor {
+        return HttpResponse::Forbidden().body("Log in with two-factor authentication first.");
+    }
+    match two_factor
+        .verify_code(&connection, &req_data.code, time.now_utc())
+        .await
+    {
+        Ok(true) => {}
+        Ok(false) => return HttpResponse::Unauthorized().finish(),
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    }
+    if TwoFactor::delete(&connection, user_id).await.is_ok() {
+        HttpResponse::Ok().finish()
+    } else {
+        HttpResponse::InternalServerError().finish()
+    }
+}
@@ -1,20 +1,31 @@
 use actix_web::{dev::HttpServiceFactory, web};
 use actix_web_httpauth::middleware::HttpAuthentication;
-use biscuit_auth::KeyPair;
 
-use crate::auth_user::validator;
+use crate::{auth_user::validator, biscuit::RootKeys};
 
 mod app;
+mod guest;
+mod identity;
+mod introspect;
 mod item;
 mod user;
 mod whoami;
 
-pub fn config(kp: web::Data<KeyPair>) -> impl HttpServiceFactory {
+pub fn config(kp: web::Data<RootKeys>) -> impl HttpServiceFactory {
     web::scope("/authenticated")
         .app_data(kp)
-        .wrap(HttpAuthentication::bearer(validator))
+        // Item routes evaluate the biscuit checks themselves, with facts about the requested
+        // items: attenuated biscuits are only accepted there. Facts of attenuated blocks are
+        // ignored, see `facts_of_attenuated_blocks_are_ignored` in `item_policy`.
         .service(item::config())
-        .service(app::config())
+        // Registered before the scope below, they accept app services too.
         .service(whoami::config())
-        .service(user::config())
+        .service(introspect::config())
+        .service(
+            web::scope("")
+                .wrap(HttpAuthentication::bearer(validator))
+                .service(app::config())
+                .service(user::config())
+                .service(guest::config()),
+        )
 }
@@ -0,0 +1,99 @@
+use actix_web::{dev::HttpServiceFactory, web, HttpResponse, Responder};
+use shared::{BiscuitInfo, CreateEmailPasswordData, OauthCodeData};
+use sqlx::PgPool;
+
+use crate::{
+    configuration::Settings,
+    email::Mailer,
+    identity_provider::IdentityProviders,
+    models::{guest::Guest, user::UserId},
+    routes::authentication::{
+        email_password::{attach_email_password, CHECK_INBOX_MESSAGE},
+        oauth::attach_identity,
+    },
+    time::MockableDateTime,
+};
+
+pub(crate) fn config() -> impl HttpServiceFactory {
+    web::scope("/guest")
+        .route(
+            "/upgrade/email_password",
+            web::post().to(upgrade_with_email_password),
+        )
+        .route(
+            "/upgrade/oauth/{provider}",
+            web::post().to(upgrade_with_oauth),
+        )
+}
+
+async fn get_guest(connection: &PgPool, account: &BiscuitInfo) -> Result<Guest, HttpResponse> {
+    match Guest::get(connection, UserId::from(account.user_id)).await {
+        Ok(Some(guest)) => Ok(guest),
+        Ok(None) => Err(HttpResponse::BadRequest().body("Only guests can be upgraded.")),
+        Err(_) => Err(HttpResponse::InternalServerError().finish()),
+    }
+}
+
+/// Attaches an email/password to the guest, the password is sent by email as on signup.
+///
+/// Like signup, the answer is the same if the email is already used.
+///
+/// The guest keeps their device secret until the email is verified or used to log in:
+/// the password is only sent to this email, a mistyped one would lock them out of their items.
+#[tracing::instrument(name = "Upgrade guest with email/password", skip_all)]
+async fn upgrade_with_email_password(
+    connection: web::Data<PgPool>,
+    config: web::Data<Settings>,
+    time: web::Data<MockableDateTime>,
+    mailer: web::Data<dyn Mailer>,
+    account: web::ReqData<BiscuitInfo>,
+    req_data: web::Json<CreateEmailPasswordData>,
+) -> impl Responder {
+    let guest = match get_guest(&connection, &account).await {
+        Ok(guest) => guest,
+        Err(response) => return response,
+    };
+    if let Err(response) = attach_email_password(
+        &connection,
+        &config,
+        &time,
+        &mailer,
+        guest.user_id,
+        &req_data.email,
+    )
+    .await
+    {
+        return response;
+    }
+    HttpResponse::Accepted().body(CHECK_INBOX_MESSAGE)
+}
+
+/// Attaches an identity to the guest, with the code its identity provider gave on callback.
+#[tracing::instrument(name = "Upgrade guest with oauth", skip_all, fields(provider=%&*provider))]
+async fn upgrade_with_oauth(
+    provider: web::Path<String>,
+    connection: web::Data<PgPool>,
+    identity_providers: web::Data<IdentityProviders>,
+    account: web::ReqData<BiscuitInfo>,
+    req_data: web::Json<OauthCodeData>,
+) -> impl Responder {
+    let guest = match get_guest(&connection, &account).await {
+        Ok(guest) => guest,
+        Err(response) => return response,
+    };
+    if let Err(response) = attach_identity(
+        &connection,
+        &identity_providers,
+        &provider,
+        &req_data.code,
+        guest.user_id,
+    )
+    .await
+    {
+        return response;
+    }
+    if Guest::upgrade(&connection, guest.user_id).await.is_err() {
+        return HttpResponse::InternalServerError().finish();
+    }
+    HttpResponse::Ok().finish()
+}
@@ -0,0 +1,207 @@
+use actix_web::{web, HttpResponse, Responder};
+use shared::{
+    BiscuitInfo, CreateEmailPasswordData, DetachEmailPasswordData, MergeUsersData, OauthCodeData,
+    Role,
+};
+use sqlx::PgPool;
+
+use super::item::amount_error_response;
+use crate::{
+    auth_user::authorize,
+    biscuit::RootKeys,
+    configuration::Settings,
+    email::Mailer,
+    identity_provider::IdentityProviders,
+    models::{
+        app::AppId,
+        guest::Guest,
+        item_ledger::ItemChange,
+        login_method::{self, DetachError},
+        two_factor::TwoFactor,
+        user::UserId,
+    },
+    revocation::RevocationList,
+    routes::authentication::{
+        email_password::{attach_email_password, CHECK_INBOX_MESSAGE},
+        oauth::attach_identity,
+    },
+    time::MockableDateTime,
+};
+
+fn detach_response(detached: Result<(), DetachError>) -> HttpResponse {
+    match detached {
+        Ok(_) => HttpResponse::Ok().finish(),
+        Err(DetachError::NotFound) => HttpResponse::NotFound().finish(),
+        Err(DetachError::LastLoginMethod) => {
+            HttpResponse::Conflict().body("The last login method of a user cannot be detached.")
+        }
+        Err(DetachError::Database(_)) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+#[tracing::instrument(name = "Get login methods", skip_all)]
+pub(super) async fn get_login_methods(
+    connection: web::Data<PgPool>,
+    account: web::ReqData<BiscuitInfo>,
+) -> impl Responder {
+    match login_method::get_all_for_user(&connection, UserId::from(account.user_id)).await {
+        Ok(login_methods) => HttpResponse::Ok().json(login_methods),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+/// The password is sent by email, the answer is the same if the email is already used.
+///
+/// A guest attaching an email is not a guest anymore once the email is verified or used to log in.
+#[tracing::instrument(name = "Attach email/password", skip_all)]
+pub(super) async fn attach_email_password_method(
+    connection: web::Data<PgPool>,
+    config: web::Data<Settings>,
+    time: web::Data<MockableDateTime>,
+    mailer: web::Data<dyn Mailer>,
+    account: web::ReqData<BiscuitInfo>,
+    req_data: web::Json<CreateEmailPasswordData>,
+) -> impl Responder {
+    if let Err(response) = attach_email_password(
+        &connection,
+        &config,
+        &time,
+        &mailer,
+        UserId::from(account.user_id),
+        &req_data.email,
+    )
+    .await
+    {
+        return response;
+    }
+    HttpResponse::Accepted().body(CHECK_INBOX_MESSAGE)
+}
+
+#[tracing::instrument(name = "Detach email/password", skip_all)]
+pub(super) async fn detach_email_password_method(
+    connection: web::Data<PgPool>,
+    account: web::ReqData<BiscuitInfo>,
+    req_data: web::Json<DetachEmailPasswordData>,
+) -> impl Responder {
+    detach_response(
+        login_method::detach_email_password(
+            &connection,
+            UserId::from(account.user_id),
+            &req_data.email,
+        )
+        .await,
+    )
+}
+
+/// A guest attaching an identity is not a guest anymore.
+#[tracing::instrument(name = "Attach oauth identity", skip_all, fields(provider=%&*provider))]
+pub(super) async fn attach_oauth_method(
+    provider: web::Path<String>,
+    connection: web::Data<PgPool>,
+    identity_providers: web::Data<IdentityProviders>,
+    account: web::ReqData<BiscuitInfo>,
+    req_data: web::Json<OauthCodeData>,
+) -> impl Responder {
+    let user_id = UserId::from(account.user_id);
+    if let Err(response) = attach_identity(
+        &connection,
+        &identity_providers,
+        &provider,
+        &req_data.code,
+        user_id,
+    )
+    .await
+    {
+        return response;
+    }
+    if Guest::upgrade(&connection, user_id).await.is_err() {
+        return HttpResponse::InternalServerError().finish();
+    }
+    HttpResponse::Ok().finish()
+}
+
+#[tracing::instrument(
+    name = "Detach oauth identity",
+    skip_all,
+    fields(provider=%path.0)
+)]
+pub(super) async fn detach_oauth_method(
+    path: web::Path<(String, String)>,
+    connection: web::Data<PgPool>,
+    account: web::ReqData<BiscuitInfo>,
+) -> impl Responder {
+    detach_response(
+        login_method::detach_identity(&connection, UserId::from(account.user_id), &path.0, &path.1)
+            .await,
+    )
+}
+
+/// Merges the user of another authentication token into the current user, see [`UserId::merge_into`].
+///
+/// The other user is deleted: their authentication tokens are revoked,
+/// their sessions can still be refreshed, as the current user.
+///
+/// Refused with `409 Conflict` and an [`ItemAmountError`](shared::ItemAmountError)
+/// if merged amounts would get out of their item bounds.
+#[tracing::instrument(name = "Merge users", skip_all)]
+pub(super) async fn merge_user(
+    connection: web::Data<PgPool>,
+    root: web::Data<RootKeys>,
+    time: web::Data<MockableDateTime>,
+    revocation_list: web::Data<RevocationList>,
+    account: web::ReqData<BiscuitInfo>,
+    req_data: web::Json<MergeUsersData>,
+) -> impl Responder {
+    let Some((other_biscuit, other)) = root
+        .parse(&req_data.other_auth_token)
+        .ok()
+        .and_then(|biscuit| authorize(&biscuit, &time).map(|other| (biscuit, other)))
+    else {
+        return HttpResponse::Unauthorized()
+            .body("Invalid authentication token for the other user.");
+    };
+    // Refused if the revocation list can't be loaded, as by the validators.
+    if revocation_list
+        .is_revoked(&connection, &other_biscuit)
+        .await
+        .unwrap_or(true)
+    {
+        return HttpResponse::Unauthorized()
+            .body("Invalid authentication token for the other user.");
+    }
+    if let Role::AppService(_) = other.role {
+        return HttpResponse::BadRequest().body("App services cannot be merged.");
+    }
+    if other.user_id == account.user_id {
+        return HttpResponse::BadRequest().body("A user cannot be merged into itself.");
+    }
+    let other_user = UserId::from(other.user_id);
+    // Merging gives access to everything of the other user, it needs as strong a login.
+    match TwoFactor::get(&connection, other_user).await {
+        Ok(Some(two_factor)) if two_factor.enabled && !other.two_factor => {
+            return HttpResponse::Forbidden()
+                .body("Log in the other user with two-factor authentication first.");
+        }
+        Ok(_) => {}
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    }
+    let change = ItemChange {
+        actor: UserId::from(account.user_id),
+        app_id: account.role.to_option().map(AppId::from),
+        reason: Some(format!("Merged user {}", *other_user)),
+        at: time.now_utc(),
+    };
+    if let Err(err) = other_user
+        .merge_into(&connection, UserId::from(account.user_id), &change)
+        .await
+    {
+        return amount_error_response(err);
+    }
+    match revocation_list
+        .revoke_all_for_user(&connection, other_user)
+        .await
+    {
+        Ok(_) => HttpResponse::Ok().finish(),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
@@ -0,0 +1,204 @@
+use actix_web::{dev::HttpServiceFactory, web, HttpResponse, Responder};
+use actix_web_httpauth::middleware::HttpAuthentication;
+use biscuit_auth::Biscuit;
+use shared::{BiscuitInfo, IntrospectTokenData, IntrospectionResponse, ItemRights, Role};
+use sqlx::PgPool;
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+
+use crate::{
+    auth_user::{authorize, decode_without_authorization, validator_any_role},
+    biscuit::RootKeys,
+    item_policy::{
+        authorize_item_operation, ItemFacts, ItemOperation, ItemPolicyError, ItemRequest,
+    },
+    models::{
+        app::AppId,
+        item::{ItemId, ItemOfApp},
+        user::UserId,
+    },
+    revocation::RevocationList,
+    time::MockableDateTime,
+};
+
+/// Game servers introspect the tokens of their players: app services are accepted here.
+pub(crate) fn config() -> impl HttpServiceFactory {
+    web::resource("/introspect")
+        .wrap(HttpAuthentication::bearer(validator_any_role))
+        .route(web::post().to(introspect))
+}
+
+/// Tokens can be introspected by their user, by app services of their app
+/// and by admins of their app.
+///
+/// App services only introspect tokens of their app, even those of their own service user.
+async fn can_introspect(
+    connection: &PgPool,
+    account: &BiscuitInfo,
+    token_info: &BiscuitInfo,
+) -> Result<bool, sqlx::Error> {
+    let is_same_user = account.user_id == token_info.user_id;
+    let token_app = match token_info.role {
+        Role::User(app) | Role::AppService(app) => Some(app),
+        Role::Admin => None,
+    };
+    match account.role {
+        Role::AppService(app) => Ok(token_app == Some(app)),
+        Role::User(_) => Ok(is_same_user),
+        Role::Admin if is_same_user => Ok(true),
+        Role::Admin => {
+            let Some(token_app) = token_app else {
+                return Ok(false);
+            };
+            Ok(AppId::get_all_administrable_for_user(
+                UserId::from(account.user_id),
+                account.two_factor,
+                connection,
+            )
+            .await?
+            .iter()
+            .any(|app| app.app_id == AppId::from(token_app)))
+        }
+    }
+}
+
+/// Evaluates [`ITEM_POLICIES`](crate::item_policy::ITEM_POLICIES) and the token checks
+/// for each item of the apps of the token.
+async fn get_item_rights(
+    connection: &PgPool,
+    time: &MockableDateTime,
+    token: &Biscuit,
+    token_info: &BiscuitInfo,
+) -> Result<Vec<ItemRights>, HttpResponse> {
+    let user_id = UserId::from(token_info.user_id);
+    let (apps, target_user) = match token_info.role {
+        Role::User(app_id) => (vec![AppId::from(app_id)], Some(user_id)),
+        Role::AppService(app_id) => (vec![AppId::from(app_id)], None),
+        // Admins operate on their own items of the apps they administer, as on item routes.
+        Role::Admin => match AppId::get_all_for_user(user_id, connection).await {
+            Ok(apps) => (
+                apps.into_iter().map(|app| app.app_id).collect(),
+                Some(user_id),
+            ),
+            Err(_) => return Err(HttpResponse::InternalServerError().finish()),
+        },
+    };
+    let Ok(items) = ItemOfApp::get_all_for_apps(connection, &apps, user_id).await else {
+        return Err(HttpResponse::InternalServerError().finish());
+    };
+    let now = time.now_utc();
+    let mut item_rights = vec![];
+    for item in items {
+        let facts = ItemFacts::for_item_of_app(&item);
+        let is_allowed = |operation: ItemOperation, amount: i32| {
+            let request = ItemRequest {
+                operation,
+                item: Some(item.id),
+                target_user,
+                amount,
+            };
+            match authorize_item_operation(token, now, user_id, &facts, &request) {
+                Ok(()) => Ok(true),
+                Err(ItemPolicyError::Denied) => Ok(false),
+                Err(ItemPolicyError::Datalog(_)) => {
+                    Err(HttpResponse::InternalServerError().finish())
+                }
+            }
+        };
+        let ItemId(item_id) = item.id;
+        item_rights.push(ItemRights {
+            item_id: shared::ItemId(item_id),
+            read: is_allowed(ItemOperation::Read, 0)?,
+            increase: is_allowed(ItemOperation::Modify, 1)?,
+            decrease: is_allowed(ItemOperation::Modify, -1)?,
+            send: is_allowed(ItemOperation::Send, -1)?,
+        });
+    }
+    Ok(item_rights)
+}
+
+/// Earliest date of the `time` checks of all the blocks of the token: the expiration
+/// of the authority block and of attenuations, see [`Attenuation`](shared::biscuit::Attenuation).
+fn earliest_expiration(token: &Biscuit) -> Option<OffsetDateTime> {
+    (0..token.block_count())
+        .filter_map(|index| token.print_block_source(index))
+        .flat_map(|source| {
+            source
+                .split(|c| c == ';' || c == '\n')
+                .filter_map(|statement| {
+                    let date = statement
+                        .trim()
+                        .strip_prefix("check if time($time), $time < ")?;
+                    OffsetDateTime::parse(date.trim(), &Rfc3339).ok()
+                })
+                .collect::<Vec<_>>()
+        })
+        .min()
+}
+
+/// Decodes a token received by a game server from one of its players, see [`IntrospectionResponse`].
+///
+/// Tokens not signed by this server are reported as inactive, without information.
+#[tracing::instrument(name = "Introspect token", skip_all)]
+async fn introspect(
+    connection: web::Data<PgPool>,
+    root: web::Data<RootKeys>,
+    time: web::Data<MockableDateTime>,
+    revocation_list: web::Data<RevocationList>,
+    account: web::ReqData<BiscuitInfo>,
+    req_data: web::Json<IntrospectTokenData>,
+) -> impl Responder {
+    let Some((token, token_info)) = root.parse(&req_data.token).ok().and_then(|token| {
+        decode_without_authorization(&token, &time).map(|token_info| (token, token_info))
+    }) else {
+        return HttpResponse::Ok().json(IntrospectionResponse {
+            active: false,
+            biscuit_info: None,
+            expires_in_seconds: 0,
+            item_rights: vec![],
+        });
+    };
+    match can_introspect(&connection, &account, &token_info).await {
+        Ok(true) => {}
+        Ok(false) => {
+            return HttpResponse::Forbidden().body("You are not allowed to introspect this token.")
+        }
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    }
+    let Ok(is_revoked) = revocation_list.is_revoked(&connection, &token).await else {
+        return HttpResponse::InternalServerError().finish();
+    };
+    // Attenuations can expire before the token they were attenuated from.
+    let expiration_date = earliest_expiration(&token).map_or(
+        token_info.expiration_date_unix_timestamp,
+        |expiration_date| {
+            expiration_date
+                .unix_timestamp()
+                .min(token_info.expiration_date_unix_timestamp)
+        },
+    );
+    let expires_in_seconds = (expiration_date - time.now_utc().unix_timestamp()).max(0);
+    let mut item_rights = if expires_in_seconds > 0 && !is_revoked {
+        match get_item_rights(&connection, &time, &token, &token_info).await {
+            Ok(item_rights) => item_rights,
+            Err(response) => return response,
+        }
+    } else {
+        vec![]
+    };
+    // Attenuated tokens check the requested operation, they only pass their checks on item routes:
+    // they are active while their checks, such as their expiration, allow an item operation.
+    let active = !is_revoked
+        && (authorize(&token, &time).is_some()
+            || item_rights
+                .iter()
+                .any(|rights| rights.read || rights.increase || rights.decrease || rights.send));
+    if !active {
+        item_rights.clear();
+    }
+    HttpResponse::Ok().json(IntrospectionResponse {
+        active,
+        biscuit_info: Some(token_info),
+        expires_in_seconds,
+        item_rights,
+    })
+}
@@ -1,19 +1,43 @@
 use std::fmt::Display;
 
 use actix_web::web::ReqData;
-use actix_web::{dev::HttpServiceFactory, web, HttpResponse, Responder};
+use actix_web::{dev::HttpServiceFactory, web, HttpRequest, HttpResponse, Responder};
+use actix_web_httpauth::middleware::HttpAuthentication;
 use serde::Deserialize;
 use sqlx::PgPool;
 
+use crate::auth_user::validator_deferred;
+use crate::item_policy::{
+    authorize_item_operation, ItemFacts, ItemOperation, ItemPolicyError, ItemRequest,
+};
+use crate::item_rights::{BakedItemRights, ItemRightsVersions};
 use crate::models::app::AppId;
 use crate::models::item::{ItemAmount, ItemFull, ItemId, ItemWithName};
+use crate::models::item_idempotency_key::IDEMPOTENCY_KEY_MAX_LENGTH;
+use crate::models::item_ledger::{ItemChange, LedgerEntry, LEDGER_PAGE_MAX_SIZE};
 use crate::models::user::UserId;
-use shared::BiscuitInfo;
+use crate::models::user_item::{apply_transaction, AmountError};
+use crate::time::MockableDateTime;
+use biscuit_auth::Biscuit;
+use shared::{BiscuitInfo, ItemTransaction, LedgerPage, Role};
+
+/// Longest reason recorded in the item ledger, in bytes.
+const REASON_MAX_LENGTH: usize = 256;
+
+/// Most operations in a single item transaction.
+const TRANSACTION_MAX_OPERATIONS: usize = 32;
+
+/// Header of modifications and transactions which can be safely retried, see [`ItemId::modify_amount`].
+const IDEMPOTENCY_KEY_HEADER: &str = "Idempotency-Key";
 
 pub(crate) fn config() -> impl HttpServiceFactory {
     web::scope("/item")
+        .wrap(HttpAuthentication::bearer(validator_deferred))
+        .route("/transaction", web::post().to(item_transaction))
         .route("/{item_id}", web::get().to(get_item))
         .route("/user/{user_id}", web::get().to(get_user_items))
+        .route("/user/{user_id}/history", web::get().to(get_user_history))
+        .route("/{item_id}/history", web::get().to(get_item_history))
         .route("/{item_id}/user/{user_id}", web::get().to(get_user_item))
         .route(
             "/{item_id}/user/{user_id}/modify",
@@ -29,6 +53,8 @@ pub(crate) fn config() -> impl HttpServiceFactory {
 #[derive(Deserialize)]
 pub struct UserItemModify {
     pub amount: i32,
+    pub reason: Option<String>,
+    pub idempotency_key: Option<String>,
 }
 
 impl Display for UserItemModify {
@@ -37,13 +63,142 @@ impl Display for UserItemModify {
     }
 }
 
+/// Evaluates the item policies and the biscuit checks, with facts about the requested items:
+/// those of `facts`, or those baked in the biscuit when up to date,
+/// or else loaded from the database for the requested item.
+async fn authorize_item_request(
+    connection: &PgPool,
+    time: &MockableDateTime,
+    item_rights_versions: &ItemRightsVersions,
+    biscuit: &BiscuitInfo,
+    token: &Biscuit,
+    facts: Option<ItemFacts>,
+    request: &ItemRequest,
+) -> Result<(), HttpResponse> {
+    let user_id = UserId::from(biscuit.user_id);
+    let Ok(baked) = BakedItemRights::from_biscuit(token) else {
+        return Err(HttpResponse::InternalServerError().finish());
+    };
+    let Ok(fresh_item_rights) = item_rights_versions.fresh_apps(connection, &baked).await else {
+        return Err(HttpResponse::InternalServerError().finish());
+    };
+    let facts = match (facts, request.item) {
+        (Some(facts), _) => facts,
+        (None, Some(item_id)) if baked.covers(&fresh_item_rights, item_id) => ItemFacts::default(),
+        (None, Some(item_id)) => match ItemFacts::load(connection, user_id, item_id).await {
+            Ok(facts) => facts,
+            Err(_) => return Err(HttpResponse::InternalServerError().finish()),
+        },
+        (None, None) => ItemFacts::default(),
+    };
+    let facts = ItemFacts {
+        fresh_item_rights,
+        ..facts
+    };
+    match authorize_item_operation(token, time.now_utc(), user_id, &facts, request) {
+        Ok(()) => Ok(()),
+        Err(ItemPolicyError::Denied) => Err(HttpResponse::Unauthorized()
+            .body("You are not allowed to do this operation on these items.")),
+        Err(ItemPolicyError::Datalog(_)) => Err(HttpResponse::InternalServerError().finish()),
+    }
+}
+
+/// Records the token making the change in the item ledger, along with its reason.
+fn item_change(
+    biscuit: &BiscuitInfo,
+    reason: &Option<String>,
+    time: &MockableDateTime,
+) -> Result<ItemChange, HttpResponse> {
+    if reason
+        .as_ref()
+        .is_some_and(|reason| reason.len() > REASON_MAX_LENGTH)
+    {
+        return Err(HttpResponse::BadRequest().body("reason is too long."));
+    }
+    Ok(ItemChange {
+        actor: UserId::from(biscuit.user_id),
+        app_id: match biscuit.role {
+            Role::User(app_id) | Role::AppService(app_id) => Some(AppId::from(app_id)),
+            Role::Admin => None,
+        },
+        reason: reason.clone(),
+        at: time.now_utc(),
+    })
+}
+
+/// Idempotency key of the request, from its header or its body, which should then be the same.
+fn idempotency_key(
+    req: &HttpRequest,
+    body_key: &Option<String>,
+) -> Result<Option<String>, HttpResponse> {
+    let header_key = match req.headers().get(IDEMPOTENCY_KEY_HEADER) {
+        Some(header) => match header.to_str() {
+            Ok(key) => Some(key.to_string()),
+            Err(_) => return Err(HttpResponse::BadRequest().body("invalid idempotency key.")),
+        },
+        None => None,
+    };
+    let key = match (header_key, body_key) {
+        (Some(header_key), Some(body_key)) if header_key != *body_key => {
+            return Err(HttpResponse::BadRequest()
+                .body("idempotency keys of the header and the body differ."))
+        }
+        (Some(key), _) => Some(key),
+        (None, body_key) => body_key.clone(),
+    };
+    if key
+        .as_ref()
+        .is_some_and(|key| key.is_empty() || key.len() > IDEMPOTENCY_KEY_MAX_LENGTH)
+    {
+        return Err(HttpResponse::BadRequest().body("invalid idempotency key."));
+    }
+    Ok(key)
+}
+
+/// Refusals are sent as an [`ItemAmountError`](shared::ItemAmountError), for clients to show.
+pub(super) fn amount_error_response(err: AmountError) -> HttpResponse {
+    match err {
+        AmountError::Refused(item_amount_error) => HttpResponse::Conflict().json(item_amount_error),
+        AmountError::IdempotencyKeyReused => HttpResponse::UnprocessableEntity()
+            .body("This idempotency key was already used for another request."),
+        AmountError::Database(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
 #[tracing::instrument(
     name = "Get item",
     skip_all,
     fields(item_id=%&*item_id)
 )]
-async fn get_item(connection: web::Data<PgPool>, item_id: web::Path<i32>) -> impl Responder {
-    if let Some(item_full) = ItemFull::get(ItemId(*item_id), &connection).await {
+async fn get_item(
+    connection: web::Data<PgPool>,
+    time: web::Data<MockableDateTime>,
+    item_rights_versions: web::Data<ItemRightsVersions>,
+    item_id: web::Path<i32>,
+    biscuit: ReqData<BiscuitInfo>,
+    token: ReqData<Biscuit>,
+) -> impl Responder {
+    let item_id = ItemId(*item_id);
+    let request = ItemRequest {
+        operation: ItemOperation::Read,
+        item: Some(item_id),
+        target_user: None,
+        amount: 0,
+    };
+    if let Err(response) = authorize_item_request(
+        &connection,
+        &time,
+        &item_rights_versions,
+        &biscuit,
+        &token,
+        None,
+        &request,
+    )
+    .await
+    {
+        return response;
+    }
+    if let Some(item_full) = ItemFull::get(item_id, &connection).await {
         HttpResponse::Ok().json(item_full)
     } else {
         HttpResponse::InternalServerError().finish()
@@ -56,8 +211,34 @@ async fn get_item(connection: web::Data<PgPool>, item_id: web::Path<i32>) -> imp
     fields(user_id=%&*user_id)
 )]
 /// For a given user, returns all its existing items.
-async fn get_user_items(connection: web::Data<PgPool>, user_id: web::Path<i32>) -> impl Responder {
+async fn get_user_items(
+    connection: web::Data<PgPool>,
+    time: web::Data<MockableDateTime>,
+    item_rights_versions: web::Data<ItemRightsVersions>,
+    user_id: web::Path<i32>,
+    biscuit: ReqData<BiscuitInfo>,
+    token: ReqData<Biscuit>,
+) -> impl Responder {
     let user_id = UserId::from(*user_id);
+    let request = ItemRequest {
+        operation: ItemOperation::Read,
+        item: None,
+        target_user: Some(user_id),
+        amount: 0,
+    };
+    if let Err(response) = authorize_item_request(
+        &connection,
+        &time,
+        &item_rights_versions,
+        &biscuit,
+        &token,
+        None,
+        &request,
+    )
+    .await
+    {
+        return response;
+    }
     if let Ok(res) = user_id.get_items(&connection).await {
         HttpResponse::Ok().json(res)
     } else {
@@ -72,10 +253,33 @@ async fn get_user_items(connection: web::Data<PgPool>, user_id: web::Path<i32>)
 )]
 async fn get_user_item(
     connection: web::Data<PgPool>,
+    time: web::Data<MockableDateTime>,
+    item_rights_versions: web::Data<ItemRightsVersions>,
     user_id_item_id: web::Path<(i32, i32)>,
+    biscuit: ReqData<BiscuitInfo>,
+    token: ReqData<Biscuit>,
 ) -> impl Responder {
     let user_id = UserId::from(user_id_item_id.0);
     let item_id = ItemId(user_id_item_id.1);
+    let request = ItemRequest {
+        operation: ItemOperation::Read,
+        item: Some(item_id),
+        target_user: Some(user_id),
+        amount: 0,
+    };
+    if let Err(response) = authorize_item_request(
+        &connection,
+        &time,
+        &item_rights_versions,
+        &biscuit,
+        &token,
+        None,
+        &request,
+    )
+    .await
+    {
+        return response;
+    }
     if let Ok(res) = ItemAmount::get(&connection, user_id, item_id).await {
         HttpResponse::Ok().json(res)
     } else {
@@ -85,10 +289,11 @@ async fn get_user_item(
 
 /// For a authenticated user, modify item.
 /// Attempts to modify an item.
-/// It does not check yet:
-/// - :construction: If the item is allowed to be modified by the app the user is authenticated on
-/// - :construction: If item is allowed to be modified by user
 ///
+/// Who can modify which items is decided by [`ITEM_POLICIES`](crate::item_policy::ITEM_POLICIES).
+///
+/// Requests with an [`IDEMPOTENCY_KEY_HEADER`] already applied are answered with their result
+/// instead of being applied again.
 #[tracing::instrument(
     name = "Modify item",
     skip_all,
@@ -96,51 +301,55 @@ async fn get_user_item(
 )]
 async fn modify_item(
     connection: web::Data<PgPool>,
+    time: web::Data<MockableDateTime>,
+    item_rights_versions: web::Data<ItemRightsVersions>,
     user_id_item_id: web::Path<(i32, i32)>,
     biscuit: ReqData<BiscuitInfo>,
+    token: ReqData<Biscuit>,
     user_item_modify: web::Json<UserItemModify>,
+    req: HttpRequest,
 ) -> impl Responder {
-    let user = biscuit.user_id;
+    let user = UserId::from(user_id_item_id.0);
     let item_id = ItemId(user_id_item_id.1);
-    match biscuit.role {
-        shared::Role::Admin => {
-            let authorized_apps = AppId::get_all_for_item(&connection, item_id).await.unwrap();
-            if !AppId::get_all_for_user(UserId::from(user), &connection)
-                .await
-                .unwrap()
-                .iter()
-                .any(|app| {
-                    authorized_apps
-                        .iter()
-                        .any(|authorized_app| authorized_app.app_id == app.app_id)
-                })
-            {
-                return HttpResponse::Unauthorized()
-                    .body("You're not admin of any app owner of this item.");
-            }
-        }
-        shared::Role::User(app_id) => {
-            if biscuit.user_id.0 != user_id_item_id.0 {
-                return HttpResponse::Unauthorized()
-                    .body("You are not allowed to modify other users' items (yet).");
-            }
-            let authorized_apps = AppId::get_all_for_item(&connection, item_id).await.unwrap();
-            if !authorized_apps
-                .iter()
-                .any(|authorized_app| authorized_app.app_id == AppId::from(app_id))
-            {
-                return HttpResponse::Unauthorized()
-                    .body("The app does not have rights to modify this item.");
-            }
-        }
+    let request = ItemRequest {
+        operation: ItemOperation::Modify,
+        item: Some(item_id),
+        target_user: Some(user),
+        amount: user_item_modify.amount,
+    };
+    if let Err(response) = authorize_item_request(
+        &connection,
+        &time,
+        &item_rights_versions,
+        &biscuit,
+        &token,
+        None,
+        &request,
+    )
+    .await
+    {
+        return response;
     }
-    if let Ok(new_amount) = item_id
-        .modify_amount(UserId::from(user), user_item_modify.amount, &connection)
+    let change = match item_change(&biscuit, &user_item_modify.reason, &time) {
+        Ok(change) => change,
+        Err(response) => return response,
+    };
+    let idempotency_key = match idempotency_key(&req, &user_item_modify.idempotency_key) {
+        Ok(idempotency_key) => idempotency_key,
+        Err(response) => return response,
+    };
+    match item_id
+        .modify_amount(
+            user,
+            user_item_modify.amount,
+            &change,
+            idempotency_key.as_deref(),
+            &connection,
+        )
         .await
     {
-        HttpResponse::Ok().json(new_amount)
-    } else {
-        HttpResponse::InternalServerError().finish()
+        Ok(new_amount) => HttpResponse::Ok().json(new_amount),
+        Err(err) => amount_error_response(err),
     }
 }
 
@@ -148,6 +357,7 @@ async fn modify_item(
 pub struct UserItemSend {
     pub amount: i32,
     pub user_to_send_to: UserId,
+    pub reason: Option<String>,
 }
 
 impl Display for UserItemSend {
@@ -157,10 +367,11 @@ impl Display for UserItemSend {
 }
 
 /// For a authenticated user, sends item to another
-/// It does not check yet:
-/// - :construction: If the item is allowed to be modified by the app the user is authenticated on
-/// - :construction: If item is allowed to be modified by user
 ///
+/// Who can send which items is decided by [`ITEM_POLICIES`](crate::item_policy::ITEM_POLICIES),
+/// only admins can send negative amounts.
+///
+/// The transfer is atomic, and refused if an amount would drop below zero.
 #[tracing::instrument(
     name = "Send item",
     skip_all,
@@ -168,67 +379,148 @@ impl Display for UserItemSend {
 )]
 async fn send_item(
     connection: web::Data<PgPool>,
+    time: web::Data<MockableDateTime>,
+    item_rights_versions: web::Data<ItemRightsVersions>,
     user_id_item_id: web::Path<(i32, i32)>,
     biscuit: ReqData<BiscuitInfo>,
+    token: ReqData<Biscuit>,
     user_item_send: web::Json<UserItemSend>,
 ) -> impl Responder {
-    let user = biscuit.user_id;
+    let user = UserId::from(user_id_item_id.0);
     let item_id = ItemId(user_id_item_id.1);
-    match biscuit.role {
-        shared::Role::Admin => {
-            let authorized_apps = AppId::get_all_for_item(&connection, item_id).await.unwrap();
-            if !AppId::get_all_for_user(UserId::from(user), &connection)
-                .await
-                .unwrap()
-                .iter()
-                .any(|app| {
-                    authorized_apps
-                        .iter()
-                        .any(|authorized_app| authorized_app.app_id == app.app_id)
-                })
-            {
-                return HttpResponse::Unauthorized()
-                    .body("You're not admin of any app owner of this item.");
-            }
-        }
-        shared::Role::User(app_id) => {
-            if user_item_send.amount <= 0 {
-                return HttpResponse::BadRequest().body("amount to send should be positive (> 0).");
-            }
-            if biscuit.user_id.0 != user_id_item_id.0 {
-                return HttpResponse::Unauthorized()
-                    .body("You are not allowed to modify other users' items (yet).");
-            }
-            let authorized_apps = AppId::get_all_for_item(&connection, item_id).await.unwrap();
-            if !authorized_apps
-                .iter()
-                .any(|authorized_app| authorized_app.app_id == AppId::from(app_id))
-            {
-                return HttpResponse::Unauthorized()
-                    .body("The app does not have rights to modify this item.");
-            }
-        }
+    if biscuit.role != Role::Admin && user_item_send.amount <= 0 {
+        return HttpResponse::BadRequest().body("amount to send should be positive (> 0).");
     }
-    // TODO: use transations!
-    if let Ok(new_amount) = item_id
-        .modify_amount(UserId::from(user), -user_item_send.amount, &connection)
+    let request = ItemRequest {
+        operation: ItemOperation::Send,
+        item: Some(item_id),
+        target_user: Some(user),
+        amount: -user_item_send.amount,
+    };
+    if let Err(response) = authorize_item_request(
+        &connection,
+        &time,
+        &item_rights_versions,
+        &biscuit,
+        &token,
+        None,
+        &request,
+    )
+    .await
+    {
+        return response;
+    }
+    let change = match item_change(&biscuit, &user_item_send.reason, &time) {
+        Ok(change) => change,
+        Err(response) => return response,
+    };
+    match item_id
+        .send_amount(
+            user,
+            user_item_send.user_to_send_to,
+            user_item_send.amount,
+            &change,
+            &connection,
+        )
         .await
     {
-        if (item_id
-            .modify_amount(
-                user_item_send.user_to_send_to,
-                user_item_send.amount,
-                &connection,
+        Ok(new_amount) => HttpResponse::Ok().json(new_amount),
+        Err(err) => amount_error_response(err),
+    }
+}
+
+/// Applies all the operations of the transaction, or none of them.
+///
+/// The operations on a same item and user are authorized together as a modification
+/// of their total amount, see [`modify_item`]: splitting an amount doesn't escape token limits.
+/// The first refused operation is sent as an [`ItemAmountError`](shared::ItemAmountError).
+#[tracing::instrument(
+    name = "Item transaction",
+    skip_all,
+    fields(biscuit=%&*biscuit, operations=item_transaction.operations.len())
+)]
+async fn item_transaction(
+    connection: web::Data<PgPool>,
+    time: web::Data<MockableDateTime>,
+    item_rights_versions: web::Data<ItemRightsVersions>,
+    biscuit: ReqData<BiscuitInfo>,
+    token: ReqData<Biscuit>,
+    item_transaction: web::Json<ItemTransaction>,
+    req: HttpRequest,
+) -> impl Responder {
+    if item_transaction.operations.is_empty()
+        || item_transaction.operations.len() > TRANSACTION_MAX_OPERATIONS
+    {
+        return HttpResponse::BadRequest().body(format!(
+            "a transaction should have between 1 and {} operations.",
+            TRANSACTION_MAX_OPERATIONS
+        ));
+    }
+    let operations: Vec<(ItemId, UserId, i32)> = item_transaction
+        .operations
+        .iter()
+        .map(|operation| {
+            (
+                ItemId(*operation.item_id),
+                UserId::from(operation.user_id),
+                operation.amount,
             )
-            .await)
-            .is_ok()
+        })
+        .collect();
+    // Totals in the order of their first operation, so the first refusal is deterministic.
+    let mut totals: Vec<(ItemId, UserId, i64)> = vec![];
+    for (item_id, user, amount) in &operations {
+        match totals
+            .iter_mut()
+            .find(|(total_item, total_user, _)| **total_item == **item_id && total_user == user)
         {
-            HttpResponse::Ok().json(new_amount)
-        } else {
-            HttpResponse::InternalServerError().finish()
+            Some((_, _, total)) => *total += *amount as i64,
+            None => totals.push((*item_id, *user, *amount as i64)),
         }
-    } else {
-        HttpResponse::InternalServerError().finish()
+    }
+    for (item_id, user, total) in totals {
+        let Ok(amount) = i32::try_from(total) else {
+            return HttpResponse::BadRequest()
+                .body("the total amount of an item in a transaction is too large.");
+        };
+        let request = ItemRequest {
+            operation: ItemOperation::Modify,
+            item: Some(item_id),
+            target_user: Some(user),
+            amount,
+        };
+        if let Err(response) = authorize_item_request(
+            &connection,
+            &time,
+            &item_rights_versions,
+            &biscuit,
+            &token,
+            None,
+            &request,
+        )
+        .await
+        {
+            return response;
+        }
+    }
+    let change = match item_change(&biscuit, &item_transaction.reason, &time) {
+        Ok(change) => change,
+        Err(response) => return response,
+    };
+    let idempotency_key = match idempotency_key(&req, &item_transaction.idempotency_key) {
+        Ok(idempotency_key) => idempotency_key,
+        Err(response) => return response,
+    };
+    match apply_transaction(
+        &operations,
+        &change,
+        idempotency_key.as_deref(),
+        &connection,
+    )
+    .await
+    {
+        Ok(new_amounts) => HttpResponse::Ok().json(new_amounts),
+        Err(err) => amount_error_response(err),
     }
 }
 
@@ -237,11 +529,199 @@ async fn send_item(
     skip_all,
     fields(app_id=%&*app_id)
 )]
-async fn get_app_items(connection: web::Data<PgPool>, app_id: web::Path<i32>) -> impl Responder {
+async fn get_app_items(
+    connection: web::Data<PgPool>,
+    time: web::Data<MockableDateTime>,
+    item_rights_versions: web::Data<ItemRightsVersions>,
+    app_id: web::Path<i32>,
+    biscuit: ReqData<BiscuitInfo>,
+    token: ReqData<Biscuit>,
+) -> impl Responder {
     let app_id = AppId::from(*app_id);
+    let request = ItemRequest {
+        operation: ItemOperation::Read,
+        item: None,
+        target_user: None,
+        amount: 0,
+    };
+    if let Err(response) = authorize_item_request(
+        &connection,
+        &time,
+        &item_rights_versions,
+        &biscuit,
+        &token,
+        Some(ItemFacts::for_app(app_id)),
+        &request,
+    )
+    .await
+    {
+        return response;
+    }
     if let Ok(res) = ItemWithName::get_for_app(&connection, app_id).await {
         HttpResponse::Ok().json(res)
     } else {
         HttpResponse::InternalServerError().finish()
     }
 }
+
+#[derive(Deserialize)]
+pub struct HistoryQuery {
+    /// Id of the first entry not to return, see [`LedgerPage::next_before`].
+    pub before: Option<i64>,
+    pub limit: Option<i64>,
+}
+
+impl HistoryQuery {
+    fn limit(&self) -> i64 {
+        self.limit
+            .unwrap_or(LEDGER_PAGE_MAX_SIZE)
+            .clamp(1, LEDGER_PAGE_MAX_SIZE)
+    }
+}
+
+fn ledger_page(entries: Vec<LedgerEntry>, limit: i64) -> LedgerPage {
+    let next_before = if entries.len() as i64 >= limit {
+        entries.last().map(|entry| entry.id)
+    } else {
+        None
+    };
+    LedgerPage {
+        entries: entries.into_iter().map(Into::into).collect(),
+        next_before,
+    }
+}
+
+#[tracing::instrument(
+    name = "Get user history",
+    skip_all,
+    fields(user_id=%&*user_id)
+)]
+/// Pages through the item ledger entries of a user.
+///
+/// Users see their whole history, app services and admins only see the entries
+/// of the items of their apps.
+async fn get_user_history(
+    connection: web::Data<PgPool>,
+    time: web::Data<MockableDateTime>,
+    item_rights_versions: web::Data<ItemRightsVersions>,
+    user_id: web::Path<i32>,
+    query: web::Query<HistoryQuery>,
+    biscuit: ReqData<BiscuitInfo>,
+    token: ReqData<Biscuit>,
+) -> impl Responder {
+    let user_id = UserId::from(*user_id);
+    let request = ItemRequest {
+        operation: ItemOperation::Read,
+        item: None,
+        target_user: Some(user_id),
+        amount: 0,
+    };
+    if let Err(response) = authorize_item_request(
+        &connection,
+        &time,
+        &item_rights_versions,
+        &biscuit,
+        &token,
+        None,
+        &request,
+    )
+    .await
+    {
+        return response;
+    }
+    let app_ids = match biscuit.role {
+        _ if biscuit.user_id == user_id.0 => None,
+        Role::AppService(app_id) => Some(vec![AppId::from(app_id)]),
+        Role::Admin => match AppId::get_all_administrable_for_user(
+            UserId::from(biscuit.user_id),
+            biscuit.two_factor,
+            &connection,
+        )
+        .await
+        {
+            Ok(apps) => Some(apps.into_iter().map(|app| app.app_id).collect()),
+            Err(_) => return HttpResponse::InternalServerError().finish(),
+        },
+        Role::User(_) => {
+            return HttpResponse::Forbidden().body("You can only see your own history.")
+        }
+    };
+    let limit = query.limit();
+    match LedgerEntry::get_for_user(
+        &connection,
+        user_id,
+        app_ids.as_deref(),
+        query.before,
+        limit,
+    )
+    .await
+    {
+        Ok(entries) => HttpResponse::Ok().json(ledger_page(entries, limit)),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+#[tracing::instrument(
+    name = "Get item history",
+    skip_all,
+    fields(item_id=%&*item_id)
+)]
+/// Pages through the item ledger entries of an item.
+///
+/// App services and admins of its app see the entries of all users, users only see theirs.
+async fn get_item_history(
+    connection: web::Data<PgPool>,
+    time: web::Data<MockableDateTime>,
+    item_rights_versions: web::Data<ItemRightsVersions>,
+    item_id: web::Path<i32>,
+    query: web::Query<HistoryQuery>,
+    biscuit: ReqData<BiscuitInfo>,
+    token: ReqData<Biscuit>,
+) -> impl Responder {
+    let item_id = ItemId(*item_id);
+    let request = ItemRequest {
+        operation: ItemOperation::Read,
+        item: Some(item_id),
+        target_user: None,
+        amount: 0,
+    };
+    if let Err(response) = authorize_item_request(
+        &connection,
+        &time,
+        &item_rights_versions,
+        &biscuit,
+        &token,
+        None,
+        &request,
+    )
+    .await
+    {
+        return response;
+    }
+    let Some(item) = ItemFull::get(item_id, &connection).await else {
+        return HttpResponse::NotFound().body("item not found");
+    };
+    let user_id = match biscuit.role {
+        Role::User(_) => Some(UserId::from(biscuit.user_id)),
+        Role::AppService(app_id) if AppId::from(app_id) == item.app_id => None,
+        Role::AppService(_) => {
+            return HttpResponse::Forbidden().body("This item is not an item of your app.")
+        }
+        Role::Admin => match AppId::get_all_administrable_for_user(
+            UserId::from(biscuit.user_id),
+            biscuit.two_factor,
+            &connection,
+        )
+        .await
+        {
+            Ok(apps) if apps.iter().any(|app| app.app_id == item.app_id) => None,
+            Ok(_) => return HttpResponse::Forbidden().body("This item is not one of your apps."),
+            Err(_) => return HttpResponse::InternalServerError().finish(),
+        },
+    };
+    let limit = query.limit();
+    match LedgerEntry::get_for_item(&connection, item_id, user_id, query.before, limit).await {
+        Ok(entries) => HttpResponse::Ok().json(ledger_page(entries, limit)),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
@@ -2,13 +2,60 @@ use actix_web::{dev::HttpServiceFactory, web, HttpResponse, Responder};
 use serde::Serialize;
 use sqlx::PgPool;
 
-use crate::models::user::UserId;
-use shared::BiscuitInfo;
+use super::identity;
+use crate::{
+    biscuit::RootKeys,
+    models::{
+        email_password,
+        refresh_token::{RefreshToken, RefreshTokenFamily, SESSION_LABEL_MAX_LENGTH},
+        user::UserId,
+    },
+    revocation::{parse_revocable, RevocationList},
+    routes::authentication::email_password::MIN_PASSWORD_LENGTH,
+    time::MockableDateTime,
+};
+use bcrypt::{hash, verify, DEFAULT_COST};
+use shared::{
+    BiscuitInfo, ChangePasswordData, CurrentSessionData, RefreshTokenFamilyId, RevokeTokenData,
+    Session, SessionLabelData,
+};
 
 pub(crate) fn config() -> impl HttpServiceFactory {
     web::scope("/user")
+        // Registered before `/{user_id}`, which would match them otherwise.
+        .route("/sessions", web::get().to(get_sessions))
+        .route(
+            "/sessions/revoke_others",
+            web::post().to(revoke_other_sessions),
+        )
+        .route("/sessions/{session_id}", web::delete().to(revoke_session))
+        .route(
+            "/sessions/{session_id}/label",
+            web::post().to(set_session_label),
+        )
+        .route("/identities", web::get().to(identity::get_login_methods))
+        .route(
+            "/identities/email_password",
+            web::post().to(identity::attach_email_password_method),
+        )
+        .route(
+            "/identities/email_password",
+            web::delete().to(identity::detach_email_password_method),
+        )
+        .route(
+            "/identities/oauth/{provider}",
+            web::post().to(identity::attach_oauth_method),
+        )
+        .route(
+            "/identities/oauth/{provider}/{external_id}",
+            web::delete().to(identity::detach_oauth_method),
+        )
+        .route("/merge", web::post().to(identity::merge_user))
+        .route("/revoke_token", web::post().to(revoke_token))
+        .route("/revoke_tokens", web::post().to(revoke_tokens))
         .route("/{user_id}", web::get().to(get_user))
         .route("", web::delete().to(delete_user))
+        .route("/password", web::post().to(change_password))
 }
 #[derive(Serialize)]
 struct Identity<'a> {
@@ -32,11 +79,196 @@ async fn get_user(connection: web::Data<PgPool>, user_id: web::Path<i32>) -> imp
 #[tracing::instrument(name = "Delete user", skip_all)]
 async fn delete_user(
     connection: web::Data<PgPool>,
+    revocation_list: web::Data<RevocationList>,
     account: web::ReqData<BiscuitInfo>,
 ) -> impl Responder {
-    let user_id = account.user_id.clone();
-    match UserId::from(user_id).delete(&connection).await {
+    let user_id = UserId::from(account.user_id.clone());
+    if revocation_list
+        .revoke_all_for_user(&connection, user_id)
+        .await
+        .is_err()
+    {
+        return HttpResponse::InternalServerError().finish();
+    }
+    match user_id.delete(&connection).await {
         Ok(_) => HttpResponse::Ok().finish(),
         _ => HttpResponse::Forbidden().body("yo"),
     }
 }
+
+#[tracing::instrument(name = "Change password", skip_all)]
+async fn change_password(
+    connection: web::Data<PgPool>,
+    account: web::ReqData<BiscuitInfo>,
+    req_data: web::Json<ChangePasswordData>,
+) -> impl Responder {
+    if req_data.new_password.len() < MIN_PASSWORD_LENGTH {
+        return HttpResponse::BadRequest().body(format!(
+            "Password should be at least {MIN_PASSWORD_LENGTH} characters long."
+        ));
+    }
+    let Ok(email_password) =
+        email_password::find_by_user(&connection, UserId::from(account.user_id.clone())).await
+    else {
+        return HttpResponse::NotFound().body("No email/password authentication for this user.");
+    };
+    let Ok(true) = verify(&req_data.old_password, &email_password.password_hash) else {
+        return HttpResponse::Unauthorized().finish();
+    };
+    let Ok(password_hashed) = hash(&req_data.new_password, DEFAULT_COST) else {
+        return HttpResponse::InternalServerError().finish();
+    };
+    match email_password::set_password_hash(&connection, email_password.id, &password_hashed).await
+    {
+        Ok(_) => HttpResponse::Ok().finish(),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+#[tracing::instrument(name = "Get sessions", skip_all)]
+async fn get_sessions(
+    connection: web::Data<PgPool>,
+    time: web::Data<MockableDateTime>,
+    account: web::ReqData<BiscuitInfo>,
+) -> impl Responder {
+    match RefreshTokenFamily::get_all_active_for_user(
+        &connection,
+        UserId::from(account.user_id),
+        time.now_utc(),
+    )
+    .await
+    {
+        Ok(families) => {
+            HttpResponse::Ok().json(families.into_iter().map(Session::from).collect::<Vec<_>>())
+        }
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+#[tracing::instrument(name = "Set session label", skip_all)]
+async fn set_session_label(
+    connection: web::Data<PgPool>,
+    account: web::ReqData<BiscuitInfo>,
+    session_id: web::Path<i32>,
+    req_data: web::Json<SessionLabelData>,
+) -> impl Responder {
+    if req_data
+        .label
+        .as_ref()
+        .is_some_and(|label| label.chars().count() > SESSION_LABEL_MAX_LENGTH)
+    {
+        return HttpResponse::BadRequest().body(format!(
+            "Label should be at most {SESSION_LABEL_MAX_LENGTH} characters long."
+        ));
+    }
+    match RefreshTokenFamily::set_label(
+        &connection,
+        UserId::from(account.user_id),
+        RefreshTokenFamilyId(*session_id),
+        req_data.label.as_deref(),
+    )
+    .await
+    {
+        Ok(true) => HttpResponse::Ok().finish(),
+        Ok(false) => HttpResponse::NotFound().finish(),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+/// The authentication tokens issued in the session are revoked with it.
+#[tracing::instrument(name = "Revoke session", skip_all)]
+async fn revoke_session(
+    connection: web::Data<PgPool>,
+    revocation_list: web::Data<RevocationList>,
+    account: web::ReqData<BiscuitInfo>,
+    session_id: web::Path<i32>,
+) -> impl Responder {
+    let family_id = RefreshTokenFamilyId(*session_id);
+    match RefreshTokenFamily::revoke_for_user(&connection, UserId::from(account.user_id), family_id)
+        .await
+    {
+        Ok(true) => {}
+        Ok(false) => return HttpResponse::NotFound().finish(),
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    }
+    match revocation_list
+        .revoke_all_for_session(&connection, family_id)
+        .await
+    {
+        Ok(_) => HttpResponse::Ok().finish(),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+/// The current session is identified by its refresh token.
+///
+/// The authentication tokens issued in the other sessions are revoked with them.
+#[tracing::instrument(name = "Revoke other sessions", skip_all)]
+async fn revoke_other_sessions(
+    connection: web::Data<PgPool>,
+    revocation_list: web::Data<RevocationList>,
+    account: web::ReqData<BiscuitInfo>,
+    req_data: web::Json<CurrentSessionData>,
+) -> impl Responder {
+    let user_id = UserId::from(account.user_id);
+    let Ok(refresh_token) = RefreshToken::get(&connection, &req_data.refresh_token, user_id).await
+    else {
+        return HttpResponse::BadRequest().finish();
+    };
+    if RefreshTokenFamily::revoke_all_except(&connection, user_id, refresh_token.family_id)
+        .await
+        .is_err()
+    {
+        return HttpResponse::InternalServerError().finish();
+    }
+    match revocation_list
+        .revoke_all_for_other_sessions(&connection, user_id, refresh_token.family_id)
+        .await
+    {
+        Ok(_) => HttpResponse::Ok().finish(),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+/// Revokes an authentication token of the current user, and the tokens attenuated from it.
+#[tracing::instrument(name = "Revoke token", skip_all)]
+async fn revoke_token(
+    connection: web::Data<PgPool>,
+    root: web::Data<RootKeys>,
+    time: web::Data<MockableDateTime>,
+    revocation_list: web::Data<RevocationList>,
+    account: web::ReqData<BiscuitInfo>,
+    req_data: web::Json<RevokeTokenData>,
+) -> impl Responder {
+    let Some((token, token_info)) = parse_revocable(&root, &time, &req_data.token) else {
+        return HttpResponse::BadRequest().body("Invalid authentication token.");
+    };
+    if token_info.user_id != account.user_id {
+        return HttpResponse::Forbidden().body("This token belongs to another user.");
+    }
+    match revocation_list
+        .revoke(&connection, &token, &token_info)
+        .await
+    {
+        Ok(_) => HttpResponse::Ok().finish(),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+/// Revokes all authentication tokens of the current user, including the one of this request.
+///
+/// Sessions are not revoked: refreshing them gives new tokens.
+#[tracing::instrument(name = "Revoke all tokens", skip_all)]
+async fn revoke_tokens(
+    connection: web::Data<PgPool>,
+    revocation_list: web::Data<RevocationList>,
+    account: web::ReqData<BiscuitInfo>,
+) -> impl Responder {
+    match revocation_list
+        .revoke_all_for_user(&connection, UserId::from(account.user_id))
+        .await
+    {
+        Ok(_) => HttpResponse::Ok().finish(),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
@@ -1,9 +1,14 @@
 use actix_web::{dev::HttpServiceFactory, web, HttpResponse, Responder};
+use actix_web_httpauth::middleware::HttpAuthentication;
 
 use shared::BiscuitInfo;
 
+use crate::auth_user::validator_any_role;
+
 pub(crate) fn config() -> impl HttpServiceFactory {
-    web::resource("/whoami").route(web::get().to(whoami))
+    web::resource("/whoami")
+        .wrap(HttpAuthentication::bearer(validator_any_role))
+        .route(web::get().to(whoami))
 }
 
 #[tracing::instrument(
@@ -1,18 +1,27 @@
 use actix_web::{dev::HttpServiceFactory, web};
-use biscuit_auth::KeyPair;
 
-use crate::time::MockableDateTime;
+use crate::{biscuit::RootKeys, time::MockableDateTime};
 
+pub mod app_credentials;
 pub mod auth;
 pub mod email_password;
+pub mod guest;
 pub mod health_check;
+pub mod oauth;
+pub mod public_keys;
+pub mod two_factor;
 
 pub fn config(
-    kp: web::Data<KeyPair>,
+    kp: web::Data<RootKeys>,
     time: web::Data<MockableDateTime>,
 ) -> impl HttpServiceFactory {
     web::scope("/authentication")
         .service(auth::config(kp.clone(), time.clone()))
-        .service(email_password::config(kp, time))
+        .service(app_credentials::config(kp.clone(), time.clone()))
+        .service(email_password::config(kp.clone(), time.clone()))
+        .service(guest::config(kp.clone(), time.clone()))
+        .service(oauth::config(kp.clone(), time.clone()))
+        .service(two_factor::config(kp.clone(), time))
+        .service(public_keys::config(kp))
         .service(health_check::config())
 }
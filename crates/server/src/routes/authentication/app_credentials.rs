@@ -0,0 +1,48 @@
+use actix_web::{dev::HttpServiceFactory, web, HttpResponse};
+use bcrypt::verify;
+use shared::LoginAppCredentialsData;
+use sqlx::PgPool;
+
+use crate::{biscuit::RootKeys, models::app_credentials::AppCredentials, time::MockableDateTime};
+
+use super::auth::create_new_app_service_authentication_token;
+
+pub fn config(
+    kp: web::Data<RootKeys>,
+    time: web::Data<MockableDateTime>,
+) -> impl HttpServiceFactory {
+    web::scope("/app_credentials")
+        .app_data(time)
+        .app_data(kp)
+        .route("login", web::post().to(login_app_credentials))
+}
+
+#[tracing::instrument(
+    name = "login_app_credentials",
+    skip_all,
+    fields(credentials_id=%req_data.credentials_id.0)
+)]
+async fn login_app_credentials(
+    req_data: web::Json<LoginAppCredentialsData>,
+    connection: web::Data<PgPool>,
+    root: web::Data<RootKeys>,
+    time: web::Data<MockableDateTime>,
+) -> HttpResponse {
+    let Ok(credentials) = AppCredentials::get(&connection, req_data.credentials_id).await else {
+        return HttpResponse::Unauthorized().finish();
+    };
+    if credentials.revoked {
+        return HttpResponse::Unauthorized().finish();
+    }
+    let Ok(true) = verify(&req_data.secret, &credentials.secret_hash) else {
+        return HttpResponse::Unauthorized().finish();
+    };
+    create_new_app_service_authentication_token(
+        connection,
+        root,
+        time,
+        credentials.service_user,
+        credentials.app_id,
+    )
+    .await
+}
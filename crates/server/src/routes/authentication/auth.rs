@@ -5,25 +5,42 @@ use actix_web_httpauth::{
     extractors::{basic::Config, bearer::BearerAuth, AuthenticationError},
     middleware::HttpAuthentication,
 };
-use biscuit_auth::{Biscuit, KeyPair};
 use rand::{distributions::Alphanumeric, Rng};
 use serde::Deserialize;
-use shared::{AuthenticationResponse, RefreshToken, Role};
+use shared::{
+    AppServiceAuthenticationResponse, AuthenticationResponse, RefreshToken, Role,
+    TwoFactorChallenge,
+};
 use sqlx::PgPool;
 use time::Duration;
 
 use crate::{
     auth_user::{decode_without_authorization, validator, validator_no_check},
-    biscuit::{AUTHENTICATION_TOKEN_TTL, REFRESH_TOKEN_TTL},
-    models::{self, app::AppId},
+    biscuit::{
+        RootKeys, APP_SERVICE_TOKEN_TTL, AUTHENTICATION_TOKEN_TTL, REFRESH_TOKEN_TTL,
+        TWO_FACTOR_CHALLENGE_TTL,
+    },
+    item_rights::BakedItemRights,
+    models::{
+        self,
+        app::AppId,
+        email_password,
+        refresh_token::{RefreshTokenFamily, RotationError},
+        two_factor::{challenge, TwoFactor},
+    },
+    revocation::{record_issued, RevocationList},
     time::MockableDateTime,
 };
-use shared::RefreshTokenString;
+use shared::{CurrentSessionData, RefreshError, RefreshTokenFamilyId, RefreshTokenString};
 
 use crate::models::user::UserId;
 
+/// A refresh token presented again within this many seconds after its rotation
+/// is considered a concurrent refresh rather than a reuse.
+pub const CONCURRENT_ROTATION_LEEWAY: i64 = 10;
+
 pub fn config(
-    kp: web::Data<KeyPair>,
+    kp: web::Data<RootKeys>,
     time: web::Data<MockableDateTime>,
 ) -> impl HttpServiceFactory {
     web::scope("/auth")
@@ -31,6 +48,7 @@ pub fn config(
         .app_data(kp)
         .wrap(HttpAuthentication::bearer(validator_no_check))
         .route("refresh", web::post().to(refresh_authentication_token))
+        .route("logout", web::post().to(logout))
 }
 
 #[derive(Debug, Deserialize, Clone)]
@@ -53,75 +71,346 @@ pub(super) async fn refresh_authentication_token(
     bearer_token: BearerAuth,
     req_data: web::Json<RefreshAuthenticationTokenData>,
     connection: web::Data<PgPool>,
-    root: web::Data<KeyPair>,
+    root: web::Data<RootKeys>,
     time: web::Data<MockableDateTime>,
+    revocation_list: web::Data<RevocationList>,
 ) -> HttpResponse {
     // TODO: decode bearer token
-    let Some(biscuit_info) = Biscuit::from_base64(bearer_token.token(), |_| root.public())
+    let Some(biscuit_info) = root
+        .parse(bearer_token.token())
         .ok()
         .and_then(|biscuit| decode_without_authorization(&biscuit, &time))
     else {
         return HttpResponse::InternalServerError().finish();
     };
-    let Ok(refresh_token) = models::refresh_token::RefreshToken::get(&connection, &req_data.refresh_token, UserId::from(biscuit_info.user_id)).await else {
-        return HttpResponse::BadRequest().finish();
+    // Sessions of a merged user continue as the user it was merged into.
+    let Ok(user_id) = UserId::from(biscuit_info.user_id)
+        .resolve_merged(&connection)
+        .await
+    else {
+        return HttpResponse::InternalServerError().finish();
     };
-    if refresh_token.revoked {
-        // token reuse? Is that a malicious usage?
-        return HttpResponse::BadRequest().finish();
-    }
-    if dbg!(refresh_token.expiration_date) < dbg!(time.now_utc()) {
-        return HttpResponse::Forbidden().finish();
-    }
-    // TODO: #19 refresh token should be returned and revoked in a same DB request (or transaction).
-    let Ok(_) = models::refresh_token::RefreshToken::revoke(&connection, refresh_token.id).await else {
-        return HttpResponse::Forbidden().finish();
+    let time_now = time.now_utc();
+    let rotated = models::refresh_token::RefreshToken::rotate(
+        &connection,
+        &req_data.refresh_token,
+        user_id,
+        new_refresh_token_string(),
+        time_now + Duration::seconds(REFRESH_TOKEN_TTL),
+        time_now,
+    )
+    .await;
+    let refresh_token = match rotated {
+        Ok(refresh_token) => refresh_token,
+        Err(RotationError::NotFound) => return HttpResponse::BadRequest().finish(),
+        Err(RotationError::Expired) => {
+            return HttpResponse::Forbidden().json(RefreshError::Expired)
+        }
+        Err(RotationError::Revoked(refresh_token)) if refresh_token.family_revoked => {
+            return HttpResponse::Unauthorized().json(RefreshError::Revoked)
+        }
+        Err(RotationError::Revoked(refresh_token))
+            if refresh_token.rotated_at.map_or(false, |rotated_at| {
+                time_now - rotated_at <= Duration::seconds(CONCURRENT_ROTATION_LEEWAY)
+            }) =>
+        {
+            // Most likely the same client sending concurrent refreshes,
+            // it should use the token received by the winning request.
+            return HttpResponse::Conflict().json(RefreshError::ConcurrentRotation);
+        }
+        Err(RotationError::Revoked(refresh_token)) => {
+            // This token was already rotated: either the legitimate client or an attacker
+            // holds a stolen copy, we can't know which, so the whole family is revoked.
+            tracing::warn!(
+                security_event = "refresh_token_reuse",
+                user_id = *refresh_token.user_id,
+                family_id = *refresh_token.family_id,
+                "Refresh token reuse detected, revoking its family."
+            );
+            if end_session(&connection, &revocation_list, refresh_token.family_id)
+                .await
+                .is_err()
+            {
+                return HttpResponse::InternalServerError().finish();
+            }
+            return HttpResponse::Unauthorized().json(RefreshError::Reused);
+        }
+        Err(RotationError::Database(_)) => return HttpResponse::InternalServerError().finish(),
     };
+    if let Some(app_id) = refresh_token.app_id {
+        if let Err(response) = check_verified_email(&connection, user_id, app_id).await {
+            // The rotated token is not sent: the session is over.
+            if end_session(&connection, &revocation_list, refresh_token.family_id)
+                .await
+                .is_err()
+            {
+                return HttpResponse::InternalServerError().finish();
+            }
+            return response;
+        }
+    }
 
-    create_new_authentication_token(
-        connection,
-        root,
-        time,
-        UserId::from(biscuit_info.user_id),
-        biscuit_info.role.to_option().map(AppId::from),
+    // The role and two-factor authentication come from the session,
+    // not from the authentication token sent with it.
+    match new_authentication_response(
+        &connection,
+        &root,
+        &time,
+        user_id,
+        refresh_token.app_id,
+        refresh_token.two_factor,
+        refresh_token,
     )
     .await
+    {
+        Ok(authentication) => HttpResponse::Ok().json(authentication),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+/// Revokes the session of the refresh token, and the authentication tokens issued in it.
+///
+/// Like refresh, this works with an expired authentication token.
+#[tracing::instrument(name = "logout", skip_all)]
+pub(super) async fn logout(
+    bearer_token: BearerAuth,
+    req_data: web::Json<CurrentSessionData>,
+    connection: web::Data<PgPool>,
+    root: web::Data<RootKeys>,
+    time: web::Data<MockableDateTime>,
+    revocation_list: web::Data<RevocationList>,
+) -> HttpResponse {
+    let Some(biscuit_info) = root
+        .parse(bearer_token.token())
+        .ok()
+        .and_then(|biscuit| decode_without_authorization(&biscuit, &time))
+    else {
+        return HttpResponse::Unauthorized().finish();
+    };
+    let Ok(user_id) = UserId::from(biscuit_info.user_id)
+        .resolve_merged(&connection)
+        .await
+    else {
+        return HttpResponse::InternalServerError().finish();
+    };
+    let Ok(refresh_token) =
+        models::refresh_token::RefreshToken::get(&connection, &req_data.refresh_token, user_id)
+            .await
+    else {
+        return HttpResponse::BadRequest().finish();
+    };
+    match end_session(&connection, &revocation_list, refresh_token.family_id).await {
+        Ok(_) => HttpResponse::Ok().finish(),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+/// Revokes the session, and the authentication biscuits issued in it.
+async fn end_session(
+    connection: &PgPool,
+    revocation_list: &RevocationList,
+    family_id: RefreshTokenFamilyId,
+) -> Result<(), sqlx::Error> {
+    RefreshTokenFamily::revoke(connection, family_id).await?;
+    revocation_list
+        .revoke_all_for_session(connection, family_id)
+        .await
+}
+
+/// Apps requiring a verified email refuse the sessions of users without one,
+/// checked on login and on each refresh.
+async fn check_verified_email(
+    connection: &PgPool,
+    user_id: UserId,
+    app_id: AppId,
+) -> Result<(), HttpResponse> {
+    let Ok(policy) = app_id.get_policy(connection).await else {
+        return Err(HttpResponse::InternalServerError().finish());
+    };
+    if !policy.require_verified_email {
+        return Ok(());
+    }
+    match email_password::has_verified_email(connection, user_id).await {
+        Ok(true) => Ok(()),
+        Ok(false) => Err(HttpResponse::Forbidden().body("This app requires a verified email.")),
+        Err(_) => Err(HttpResponse::InternalServerError().finish()),
+    }
 }
 
+/// Authenticates a login, starting a new refresh token family.
+///
+/// Admin logins of users with two-factor authentication are refused with a [`TwoFactorChallenge`],
+/// to finish through [`super::two_factor`].
 pub(super) async fn create_new_authentication_token(
     connection: web::Data<PgPool>,
-    root: web::Data<KeyPair>,
+    root: web::Data<RootKeys>,
     time: web::Data<MockableDateTime>,
     user_id: UserId,
     as_app_user: Option<AppId>,
 ) -> HttpResponse {
-    let time_now = time.now_utc();
-    let auth_expiration_date = time_now + Duration::seconds(AUTHENTICATION_TOKEN_TTL);
-    let biscuit = match as_app_user {
-        Some(app_id) => user_id.create_biscuit(&root, Role::User(app_id.0), auth_expiration_date),
-        None => user_id.create_biscuit(&root, Role::Admin, auth_expiration_date),
-    };
-    let refresh_token: String = rand::thread_rng()
+    if let Some(app_id) = as_app_user {
+        if let Err(response) = check_verified_email(&connection, user_id, app_id).await {
+            return response;
+        }
+    } else {
+        match TwoFactor::get(&connection, user_id).await {
+            Ok(Some(two_factor)) if two_factor.enabled => {
+                return two_factor_challenge_response(&connection, &time, user_id).await;
+            }
+            Ok(_) => {}
+            Err(_) => return HttpResponse::InternalServerError().finish(),
+        }
+    }
+    issue_authentication_token(connection, root, time, user_id, as_app_user, false).await
+}
+
+async fn two_factor_challenge_response(
+    connection: &PgPool,
+    time: &MockableDateTime,
+    user_id: UserId,
+) -> HttpResponse {
+    let challenge_token: String = rand::thread_rng()
         .sample_iter(&Alphanumeric)
-        .take(255)
+        .take(64)
         .map(char::from)
         .collect();
-    let Ok(refresh_token) = models::refresh_token::RefreshToken::create(
-    connection.as_ref(),
-        RefreshTokenString(refresh_token),
+    let expiration_date = time.now_utc() + Duration::seconds(TWO_FACTOR_CHALLENGE_TTL);
+    if challenge::create(connection, &challenge_token, user_id, expiration_date)
+        .await
+        .is_err()
+    {
+        return HttpResponse::InternalServerError().finish();
+    }
+    HttpResponse::Unauthorized().json(TwoFactorChallenge {
+        challenge_token,
+        expiration_date_unix_timestamp: expiration_date.unix_timestamp(),
+    })
+}
+
+/// Starts a new refresh token family, without checking two-factor authentication.
+pub(super) async fn issue_authentication_token(
+    connection: web::Data<PgPool>,
+    root: web::Data<RootKeys>,
+    time: web::Data<MockableDateTime>,
+    user_id: UserId,
+    as_app_user: Option<AppId>,
+    two_factor: bool,
+) -> HttpResponse {
+    match start_session(&connection, &root, &time, user_id, as_app_user, two_factor).await {
+        Ok(authentication) => HttpResponse::Ok().json(authentication),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+/// Creates a new refresh token family and its first tokens.
+pub(super) async fn start_session(
+    connection: &PgPool,
+    root: &RootKeys,
+    time: &MockableDateTime,
+    user_id: UserId,
+    as_app_user: Option<AppId>,
+    two_factor: bool,
+) -> Result<AuthenticationResponse, sqlx::Error> {
+    let time_now = time.now_utc();
+    let family_id =
+        RefreshTokenFamily::create(connection, user_id, as_app_user, two_factor, time_now).await?;
+    let refresh_token = models::refresh_token::RefreshToken::create(
+        connection,
+        new_refresh_token_string(),
         user_id,
+        family_id,
         time_now + Duration::seconds(REFRESH_TOKEN_TTL),
-        time_now)
-     .await else {
-        return HttpResponse::InternalServerError().finish();
+        time_now,
+    )
+    .await?;
+    new_authentication_response(
+        connection,
+        root,
+        time,
+        user_id,
+        as_app_user,
+        two_factor,
+        refresh_token,
+    )
+    .await
+}
+
+fn new_refresh_token_string() -> RefreshTokenString {
+    RefreshTokenString(
+        rand::thread_rng()
+            .sample_iter(&Alphanumeric)
+            .take(255)
+            .map(char::from)
+            .collect(),
+    )
+}
+
+/// The biscuit is recorded, to be revoked with the others of the user if needed.
+async fn new_authentication_response(
+    connection: &PgPool,
+    root: &RootKeys,
+    time: &MockableDateTime,
+    user_id: UserId,
+    as_app_user: Option<AppId>,
+    two_factor: bool,
+    refresh_token: models::refresh_token::RefreshToken,
+) -> Result<AuthenticationResponse, sqlx::Error> {
+    let auth_expiration_date = time.now_utc() + Duration::seconds(AUTHENTICATION_TOKEN_TTL);
+    let (role, two_factor) = match as_app_user {
+        Some(app_id) => (Role::User(app_id.0), false),
+        None => (Role::Admin, two_factor),
     };
-    let authentication_token = AuthenticationResponse {
+    let item_rights = BakedItemRights::load(connection, user_id, role).await?;
+    let biscuit = user_id.create_biscuit(root, role, two_factor, item_rights, auth_expiration_date);
+    record_issued(
+        connection,
+        &biscuit,
+        user_id,
+        Some(refresh_token.family_id),
+        auth_expiration_date,
+    )
+    .await?;
+    Ok(AuthenticationResponse {
         auth_token: biscuit.to_base64().unwrap(),
         refresh_token: RefreshToken {
             refresh_token: refresh_token.refresh_token,
             expiration_date_unix_timestamp: refresh_token.expiration_date.unix_timestamp(),
         },
         expiration_date_unix_timestamp: auth_expiration_date.unix_timestamp(),
+    })
+}
+
+/// App services don't get a refresh token, they should log in again with their credentials.
+///
+/// The biscuit is issued for the service user of the credentials, not for their creator.
+pub(super) async fn create_new_app_service_authentication_token(
+    connection: web::Data<PgPool>,
+    root: web::Data<RootKeys>,
+    time: web::Data<MockableDateTime>,
+    service_user: UserId,
+    app_id: AppId,
+) -> HttpResponse {
+    let auth_expiration_date = time.now_utc() + Duration::seconds(APP_SERVICE_TOKEN_TTL);
+    let role = Role::AppService(app_id.0);
+    let Ok(item_rights) = BakedItemRights::load(&connection, service_user, role).await else {
+        return HttpResponse::InternalServerError().finish();
     };
-    HttpResponse::Ok().json(authentication_token)
+    let biscuit =
+        service_user.create_biscuit(&root, role, false, item_rights, auth_expiration_date);
+    if record_issued(
+        &connection,
+        &biscuit,
+        service_user,
+        None,
+        auth_expiration_date,
+    )
+    .await
+    .is_err()
+    {
+        return HttpResponse::InternalServerError().finish();
+    }
+    HttpResponse::Ok().json(AppServiceAuthenticationResponse {
+        auth_token: biscuit.to_base64().unwrap(),
+        expiration_date_unix_timestamp: auth_expiration_date.unix_timestamp(),
+    })
 }
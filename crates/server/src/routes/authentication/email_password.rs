@@ -1,17 +1,24 @@
-use actix_web::{dev::HttpServiceFactory, web, HttpResponse, Responder};
-use biscuit_auth::KeyPair;
-use lettre::{transport::smtp::authentication::Credentials, Message, SmtpTransport, Transport};
-use rand::Rng;
-use secrecy::{ExposeSecret, Secret};
-use serde::{Deserialize, Serialize};
+use actix_web::{dev::HttpServiceFactory, web, HttpRequest, HttpResponse, Responder};
+use once_cell::sync::Lazy;
+use rand::{distributions::Alphanumeric, Rng};
+use serde::Deserialize;
+use shared::{ForgotPasswordData, ResetPasswordData, VerifyEmailData};
 use sqlx::PgPool;
+use time::Duration;
+use tracing::Instrument;
 
 use crate::{
+    biscuit::RootKeys,
+    configuration::Settings,
+    email::{send_email, Mailer},
     models::{
         app::AppId,
-        email_password::{create, exist, find},
+        email_password::{self, create, exist, find, set_verification_code},
+        guest::Guest,
+        password_reset_token,
     },
     random_names::random_name,
+    rate_limit::RateLimiter,
     time::MockableDateTime,
 };
 use bcrypt::{hash, verify, DEFAULT_COST};
@@ -21,16 +28,35 @@ use crate::models::user::UserId;
 use super::auth::create_new_authentication_token;
 
 pub fn config(
-    kp: web::Data<KeyPair>,
+    kp: web::Data<RootKeys>,
     time: web::Data<MockableDateTime>,
 ) -> impl HttpServiceFactory {
+    Lazy::force(&DUMMY_PASSWORD_HASH);
     web::scope("/email_password")
         .app_data(time)
         .app_data(kp)
         .route("create", web::post().to(create_email_password))
         .route("login", web::post().to(login_email_password))
+        .route("verify", web::get().to(verify_email_password))
+        .route(
+            "resend_verification",
+            web::post().to(resend_verification_email_password),
+        )
+        .route(
+            "forgot_password",
+            web::post().to(forgot_password_email_password),
+        )
+        .route(
+            "reset_password",
+            web::post().to(reset_password_email_password),
+        )
 }
 
+pub(crate) const CHECK_INBOX_MESSAGE: &str = "Check your inbox to finish signing up.";
+
+/// Time for a user to click on the verification link.
+pub const EMAIL_VERIFICATION_TTL: i64 = 24 * 3600;
+
 #[derive(Debug, Deserialize, Clone)]
 pub struct CreateEmailPasswordData {
     pub email: String,
@@ -41,20 +67,12 @@ impl std::fmt::Display for CreateEmailPasswordData {
     }
 }
 
-fn expose_secret_string<S>(data: &Secret<String>, ser: S) -> Result<S::Ok, S::Error>
-where
-    S: serde::Serializer,
-{
-    ser.serialize_str(data.expose_secret())
-}
-
-#[derive(Debug, Serialize, Clone)]
-pub struct CreatedUserEmailPasswordData {
-    pub id: UserId,
-    #[serde(serialize_with = "expose_secret_string")]
-    pub password: Secret<String>,
-}
+/// Verified when a login email is unknown, so it takes as long as a bad password.
+static DUMMY_PASSWORD_HASH: Lazy<String> =
+    Lazy::new(|| hash("dummy password", DEFAULT_COST).expect("could not hash dummy password"));
 
+/// Answers the same for new and existing emails, to avoid giving information about an account existence:
+/// the password is only sent by email, and existing users are told about the attempt instead.
 #[tracing::instrument(
     name = "create_email_password",
     skip_all,
@@ -62,56 +80,57 @@ pub struct CreatedUserEmailPasswordData {
 )]
 async fn create_email_password(
     connection: web::Data<PgPool>,
+    config: web::Data<Settings>,
+    time: web::Data<MockableDateTime>,
+    mailer: web::Data<dyn Mailer>,
     req_data: web::Json<CreateEmailPasswordData>,
 ) -> impl Responder {
-    use rand::Rng;
-    if exist(connection.as_ref(), &req_data.email).await {
-        // User should use login with email password.
-        return HttpResponse::ExpectationFailed().finish();
-    }
-    const CHARSET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ\
-                            abcdefghijklmnopqrstuvwxyz\
-                            0123456789)(*&^%$#@!~";
-    const PASSWORD_LEN: usize = 16;
-    let mut rng = rand::thread_rng();
-
-    let password: String = (0..PASSWORD_LEN)
-        .map(|_| {
-            let idx = rng.gen_range(0..CHARSET.len());
-            CHARSET[idx] as char
-        })
-        .collect();
+    let password = generate_password();
+    // Hashed even for existing emails, so both take as long.
     let Ok(password_hashed) = hash(&password, DEFAULT_COST) else {
         return HttpResponse::InternalServerError().finish();
     };
+    if exist(connection.as_ref(), &req_data.email).await {
+        return existing_account_response(&mailer, &req_data.email).await;
+    }
 
-    let user = UserId::create(&connection, &random_name()).await.unwrap();
-    assert!(create(&connection, &req_data.email, &password_hashed, user).await);
-
-    let email = Message::builder()
-        .from(dotenvy::var("BACKPACK_EMAIL").unwrap().parse().unwrap())
-        .reply_to(dotenvy::var("BACKPACK_EMAIL").unwrap().parse().unwrap())
-        .to(req_data.email.parse().unwrap())
-        .subject("Welcome to Backpack")
-        .body(format!(
-            "Hi,\nWelcome to Backpack, your password is {password}.",
-        ))
-        .unwrap();
-    let creds = Credentials::new(
-        dotenvy::var("BACKPACK_EMAIL").unwrap(),
-        dotenvy::var("BACKPACK_EMAIL_PASSWORD").unwrap(),
-    );
-
-    // Open a remote connection to mail service
-    let mailer = SmtpTransport::relay("smtp.zoho.com")
-        .unwrap()
-        .credentials(creds)
-        .build();
+    let user = match email_password::create_with_user(
+        &connection,
+        &random_name(),
+        &req_data.email,
+        &password_hashed,
+    )
+    .await
+    {
+        Ok(Some(user)) => user,
+        // Signed up concurrently with the same email.
+        Ok(None) => return existing_account_response(&mailer, &req_data.email).await,
+        Err(err) => {
+            tracing::error!("could not create user: {err}");
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
 
-    // Send the email
-    match mailer.send(&email) {
-        Ok(_) => {}
-        Err(e) => panic!("Could not send email: {e:?}"),
+    let Some(verification_link) =
+        new_verification_link(&connection, &config, &time, &req_data.email).await
+    else {
+        return HttpResponse::InternalServerError().finish();
+    };
+    if let Err(err) = send_email(
+        &mailer,
+        &req_data.email,
+        "Welcome to Backpack",
+        format!(
+            "Hi,\nWelcome to Backpack, your password is {password}.\n\
+            Please verify your email by visiting {verification_link}",
+        ),
+    )
+    .await
+    {
+        tracing::error!("could not send signup email: {err}");
+        // The password is only sent by email, the user should be able to sign up again.
+        let _ = user.delete(&connection).await;
+        return HttpResponse::InternalServerError().body("Could not send email.");
     }
 
     // We should not create biscuit here, because we need to verify the email first.
@@ -126,11 +145,95 @@ async fn create_email_password(
     // When the user clicks on a link, he is redirected to login,
     // gets an authentication token and can use services until its expiration.
     // then later we can flag the user as verified ? :shrug:
+    // -> Users are flagged as verified when visiting the link sent along their password,
+    // apps can then refuse unverified users through their `AppPolicy`.
+
+    HttpResponse::Accepted().body(CHECK_INBOX_MESSAGE)
+}
+
+/// Passwords are generated on signup and sent by email, users can change them later.
+fn generate_password() -> String {
+    const CHARSET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ\
+                            abcdefghijklmnopqrstuvwxyz\
+                            0123456789)(*&^%$#@!~";
+    const PASSWORD_LEN: usize = 16;
+    let mut rng = rand::thread_rng();
 
-    HttpResponse::Created().json(CreatedUserEmailPasswordData {
-        id: user,
-        password: Secret::new(password),
-    })
+    (0..PASSWORD_LEN)
+        .map(|_| {
+            let idx = rng.gen_range(0..CHARSET.len());
+            CHARSET[idx] as char
+        })
+        .collect()
+}
+
+/// Tells the owner of an existing account about the attempt,
+/// answering as if the email was new.
+async fn existing_account_response(mailer: &web::Data<dyn Mailer>, email: &str) -> HttpResponse {
+    if let Err(err) = send_email(
+        mailer,
+        email,
+        "Welcome back to Backpack",
+        "Hi,\nSomeone tried to sign up with this email, but you already have a Backpack account.\n\
+        If it was you, log in with your password, or reset it if you forgot it."
+            .to_string(),
+    )
+    .await
+    {
+        tracing::error!("could not send existing account email: {err}");
+        return HttpResponse::InternalServerError().body("Could not send email.");
+    }
+    HttpResponse::Accepted().body(CHECK_INBOX_MESSAGE)
+}
+
+/// Attaches an email/password to an existing user, the password is sent by email.
+///
+/// Returns `false` if the email is already used: its owner is told about the attempt instead,
+/// callers should answer the same in both cases.
+pub(crate) async fn attach_email_password(
+    connection: &PgPool,
+    config: &Settings,
+    time: &MockableDateTime,
+    mailer: &web::Data<dyn Mailer>,
+    user_id: UserId,
+    email: &str,
+) -> Result<bool, HttpResponse> {
+    let password = generate_password();
+    let Ok(password_hashed) = hash(&password, DEFAULT_COST) else {
+        return Err(HttpResponse::InternalServerError().finish());
+    };
+    if exist(connection, email).await {
+        let response = existing_account_response(mailer, email).await;
+        return if response.status().is_success() {
+            Ok(false)
+        } else {
+            Err(response)
+        };
+    }
+    if !create(connection, email, &password_hashed, user_id).await {
+        return Err(HttpResponse::InternalServerError().finish());
+    }
+    let Some(verification_link) = new_verification_link(connection, config, time, email).await
+    else {
+        return Err(HttpResponse::InternalServerError().finish());
+    };
+    if let Err(err) = send_email(
+        mailer,
+        email,
+        "Welcome to Backpack",
+        format!(
+            "Hi,\nThis email is now attached to your Backpack account, your password is {password}.\n\
+            Please verify your email by visiting {verification_link}",
+        ),
+    )
+    .await
+    {
+        tracing::error!("could not send attached email: {err}");
+        // The password is only sent by email, the user should be able to try again.
+        let _ = email_password::delete(connection, email).await;
+        return Err(HttpResponse::InternalServerError().body("Could not send email."));
+    }
+    Ok(true)
 }
 
 #[derive(Debug, Deserialize, Clone)]
@@ -144,45 +247,264 @@ pub struct LoginEmailPasswordData {
 async fn login_email_password(
     req_data: web::Json<LoginEmailPasswordData>,
     connection: web::Data<PgPool>,
-    root: web::Data<KeyPair>,
+    root: web::Data<RootKeys>,
     time: web::Data<MockableDateTime>,
+    rate_limiter: web::Data<RateLimiter>,
+    req: HttpRequest,
 ) -> HttpResponse {
-    let Ok((_email_password_id, password_hash_existing, user_id)) =
-        find(connection.as_ref(), &req_data.email).await else {
-            // We do not return not found, to avoid giving information about an account existence.
-            return dbg!(HttpResponse::Unauthorized().finish());
-        };
-    let Ok(true) =
-        verify(&req_data.password_plain, &password_hash_existing)
-        else {
+    let ip = rate_limiter.client_ip(&req);
+    if let Err(retry_after) = rate_limiter.check_login_lockout(&req_data.email, ip) {
+        return retry_after.response();
+    }
+    let Ok(email_password) = find(connection.as_ref(), &req_data.email).await else {
+        // We do not return not found, to avoid giving information about an account existence,
+        // for the same reason unknown emails are also locked out, and take as long to be refused.
+        let _ = verify(&req_data.password_plain, &DUMMY_PASSWORD_HASH);
+        rate_limiter.record_login_failure(&req_data.email, ip);
+        return dbg!(HttpResponse::Unauthorized().finish());
+    };
+    let Ok(true) = verify(&req_data.password_plain, &email_password.password_hash) else {
+        rate_limiter.record_login_failure(&req_data.email, ip);
         return dbg!(HttpResponse::Unauthorized().finish());
     };
-    // TODO: set email password as verified ? (or create another route to do that, it would probably be better.)
-    create_new_authentication_token(connection, root, time, user_id, req_data.as_app_user).await
+    rate_limiter.record_login_success(&req_data.email, ip);
+    // A guest who attached this email received its password, they don't need their device secret.
+    if Guest::upgrade(&connection, email_password.user_id)
+        .await
+        .is_err()
+    {
+        return HttpResponse::InternalServerError().finish();
+    }
+    create_new_authentication_token(
+        connection,
+        root,
+        time,
+        email_password.user_id,
+        req_data.as_app_user,
+    )
+    .await
 }
 
-#[derive(Debug, Deserialize, Clone)]
-pub struct RequestPasswordChangeData {
-    pub email: String,
+/// Time for a user to use the reset token received by email.
+pub const PASSWORD_RESET_TTL: i64 = 3600;
+/// Applies to passwords chosen by users, generated ones are longer.
+pub const MIN_PASSWORD_LENGTH: usize = 8;
+
+/// Sends a single-use reset token by email, after answering.
+///
+/// The answer waits neither for the account lookup nor for the email,
+/// so it is the same and as fast for unknown emails, to avoid giving information about an account existence.
+#[tracing::instrument(name = "forgot_password_email_password", skip_all)]
+async fn forgot_password_email_password(
+    req_data: web::Json<ForgotPasswordData>,
+    connection: web::Data<PgPool>,
+    time: web::Data<MockableDateTime>,
+    mailer: web::Data<dyn Mailer>,
+) -> HttpResponse {
+    actix_web::rt::spawn(
+        send_password_reset_token(connection, time, mailer, req_data.into_inner().email)
+            .instrument(tracing::Span::current()),
+    );
+    HttpResponse::Ok().finish()
 }
 
-#[tracing::instrument(name = "request_password_change_email_password", skip_all)]
-async fn request_password_change_email_password(
-    req_data: web::Json<LoginEmailPasswordData>,
+async fn send_password_reset_token(
+    connection: web::Data<PgPool>,
+    time: web::Data<MockableDateTime>,
+    mailer: web::Data<dyn Mailer>,
+    email: String,
+) {
+    let email_password = match find(&connection, &email).await {
+        Ok(email_password) => email_password,
+        Err(sqlx::Error::RowNotFound) => return,
+        Err(err) => {
+            tracing::error!("could not find email for password reset: {err}");
+            return;
+        }
+    };
+    let reset_token: String = rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(32)
+        .map(char::from)
+        .collect();
+    let now = time.now_utc();
+    if let Err(err) = password_reset_token::create(
+        &connection,
+        email_password.id,
+        &reset_token,
+        now + Duration::seconds(PASSWORD_RESET_TTL),
+        now,
+    )
+    .await
+    {
+        tracing::error!("could not store password reset token: {err}");
+        return;
+    }
+    if let Err(err) = send_email(
+        &mailer,
+        &email,
+        "Reset your password",
+        format!(
+            "Hi,\nA password reset was requested for your Backpack account.\n\
+            Your reset token is {reset_token}, it can be used once within the next hour.\n\
+            If you did not request it, you can ignore this email.",
+        ),
+    )
+    .await
+    {
+        tracing::error!("could not send password reset email: {err}");
+    }
+}
+
+/// Sets a new password from a reset token, all existing sessions of the user are revoked.
+#[tracing::instrument(name = "reset_password_email_password", skip_all)]
+async fn reset_password_email_password(
+    req_data: web::Json<ResetPasswordData>,
     connection: web::Data<PgPool>,
-    root: web::Data<KeyPair>,
     time: web::Data<MockableDateTime>,
 ) -> HttpResponse {
-    let Ok((_email_password_id, password_hash_existing, user_id)) =
-        find(connection.as_ref(), &req_data.email).await else {
-            // We do not return not found, to avoid giving information about an account existence.
-            return dbg!(HttpResponse::Unauthorized().finish());
-        };
-    let Ok(true) =
-        verify(&req_data.password_plain, &password_hash_existing)
-        else {
-        return dbg!(HttpResponse::Unauthorized().finish());
+    if req_data.new_password.len() < MIN_PASSWORD_LENGTH {
+        return HttpResponse::BadRequest().body(format!(
+            "Password should be at least {MIN_PASSWORD_LENGTH} characters long."
+        ));
+    }
+    let Ok(password_hashed) = hash(&req_data.new_password, DEFAULT_COST) else {
+        return HttpResponse::InternalServerError().finish();
+    };
+    match password_reset_token::reset_password(
+        &connection,
+        &req_data.reset_token,
+        &password_hashed,
+        time.now_utc(),
+    )
+    .await
+    {
+        Ok(_) => HttpResponse::Ok().finish(),
+        Err(sqlx::Error::RowNotFound) => {
+            HttpResponse::BadRequest().body("Invalid or expired reset token.")
+        }
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+/// Generates a new verification code for the email, and returns a link to verify it.
+async fn new_verification_link(
+    connection: &PgPool,
+    config: &Settings,
+    time: &MockableDateTime,
+    email: &str,
+) -> Option<String> {
+    let code: String = rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(32)
+        .map(char::from)
+        .collect();
+    set_verification_code(
+        connection,
+        email,
+        &code,
+        time.now_utc() + Duration::seconds(EMAIL_VERIFICATION_TTL),
+    )
+    .await
+    .ok()?;
+    reqwest::Url::parse_with_params(
+        &format!(
+            "{}/api/v1/authentication/email_password/verify",
+            config.public_url
+        ),
+        &[("email", email), ("code", &code)],
+    )
+    .ok()
+    .map(String::from)
+}
+
+/// Meant to be reached from the link sent by email.
+///
+/// A guest who attached this email is upgraded, see [`Guest::upgrade`].
+#[tracing::instrument(
+    name = "verify_email_password",
+    skip_all,
+    fields(email=%req_data.email)
+)]
+async fn verify_email_password(
+    req_data: web::Query<VerifyEmailData>,
+    connection: web::Data<PgPool>,
+    time: web::Data<MockableDateTime>,
+) -> HttpResponse {
+    let verified =
+        email_password::verify(&connection, &req_data.email, &req_data.code, time.now_utc()).await;
+    match verified {
+        Ok(user_id) => match Guest::upgrade(&connection, user_id).await {
+            Ok(_) => HttpResponse::Ok().body("Your email is verified."),
+            Err(_) => HttpResponse::InternalServerError().finish(),
+        },
+        Err(sqlx::Error::RowNotFound) => {
+            HttpResponse::BadRequest().body("Invalid or expired verification code.")
+        }
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+/// Sends a new verification link by email, after answering.
+///
+/// Like forgot password, the answer is the same and as fast for unknown or verified emails,
+/// and when the email can't be sent, to avoid giving information about an account existence.
+#[tracing::instrument(
+    name = "resend_verification_email_password",
+    skip_all,
+    fields(req_data=%&*req_data)
+)]
+async fn resend_verification_email_password(
+    req_data: web::Json<CreateEmailPasswordData>,
+    connection: web::Data<PgPool>,
+    config: web::Data<Settings>,
+    time: web::Data<MockableDateTime>,
+    mailer: web::Data<dyn Mailer>,
+) -> HttpResponse {
+    actix_web::rt::spawn(
+        send_verification_link(
+            connection,
+            config,
+            time,
+            mailer,
+            req_data.into_inner().email,
+        )
+        .instrument(tracing::Span::current()),
+    );
+    HttpResponse::Ok().finish()
+}
+
+async fn send_verification_link(
+    connection: web::Data<PgPool>,
+    config: web::Data<Settings>,
+    time: web::Data<MockableDateTime>,
+    mailer: web::Data<dyn Mailer>,
+    email: String,
+) {
+    let email_password = match find(&connection, &email).await {
+        Ok(email_password) => email_password,
+        Err(sqlx::Error::RowNotFound) => return,
+        Err(err) => {
+            tracing::error!("could not find email for verification: {err}");
+            return;
+        }
     };
-    // TODO: set email password as verified ? (or create another route to do that, it would probably be better.)
-    create_new_authentication_token(connection, root, time, user_id, req_data.as_app_user).await
+    if email_password.is_verified {
+        return;
+    }
+    let Some(verification_link) = new_verification_link(&connection, &config, &time, &email).await
+    else {
+        tracing::error!("could not create verification link");
+        return;
+    };
+    if let Err(err) = send_email(
+        &mailer,
+        &email,
+        "Verify your email",
+        format!("Hi,\nPlease verify your email by visiting {verification_link}"),
+    )
+    .await
+    {
+        tracing::error!("could not send verification email: {err}");
+    }
 }
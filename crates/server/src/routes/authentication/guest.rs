@@ -0,0 +1,106 @@
+use actix_web::{dev::HttpServiceFactory, web, HttpResponse};
+use rand::{distributions::Alphanumeric, Rng};
+use shared::{CreateGuestData, GuestLoginData, GuestSignupResponse};
+use sqlx::PgPool;
+
+use crate::{
+    biscuit::RootKeys,
+    models::{
+        app::AppId,
+        guest::{hash_device_secret, Guest},
+    },
+    random_names::random_name,
+    time::MockableDateTime,
+};
+
+use super::auth::{create_new_authentication_token, start_session};
+
+pub fn config(
+    kp: web::Data<RootKeys>,
+    time: web::Data<MockableDateTime>,
+) -> impl HttpServiceFactory {
+    web::scope("/guest")
+        .app_data(time)
+        .app_data(kp)
+        .route("create", web::post().to(create_guest))
+        .route("login", web::post().to(login_guest))
+}
+
+/// Apps requiring a verified email don't accept guests, who have no email.
+async fn check_app_accepts_guests(connection: &PgPool, app_id: AppId) -> Result<(), HttpResponse> {
+    match app_id.get_policy(connection).await {
+        Ok(policy) if policy.require_verified_email => {
+            Err(HttpResponse::Forbidden().body("This app requires a verified email."))
+        }
+        Ok(_) => Ok(()),
+        Err(sqlx::Error::RowNotFound) => Err(HttpResponse::NotFound().finish()),
+        Err(_) => Err(HttpResponse::InternalServerError().finish()),
+    }
+}
+
+/// Creates a user without email, logged in as a user of the app.
+///
+/// The returned device secret is not stored in plain text, it's the only way for the guest
+/// to log in again until they attach an email/password or an identity.
+#[tracing::instrument(name = "create_guest", skip_all, fields(app_id=%req_data.app_id.0))]
+async fn create_guest(
+    req_data: web::Json<CreateGuestData>,
+    connection: web::Data<PgPool>,
+    root: web::Data<RootKeys>,
+    time: web::Data<MockableDateTime>,
+) -> HttpResponse {
+    let app_id = AppId::from(req_data.app_id);
+    if let Err(response) = check_app_accepts_guests(&connection, app_id).await {
+        return response;
+    }
+    let device_secret: String = rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(64)
+        .map(char::from)
+        .collect();
+    let Ok(guest) = Guest::create(
+        &connection,
+        &random_name(),
+        &hash_device_secret(&device_secret),
+        app_id,
+        time.now_utc(),
+    )
+    .await
+    else {
+        return HttpResponse::InternalServerError().finish();
+    };
+    match start_session(
+        &connection,
+        &root,
+        &time,
+        guest.user_id,
+        Some(app_id),
+        false,
+    )
+    .await
+    {
+        Ok(authentication) => HttpResponse::Created().json(GuestSignupResponse {
+            device_secret,
+            authentication,
+        }),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+#[tracing::instrument(name = "login_guest", skip_all)]
+async fn login_guest(
+    req_data: web::Json<GuestLoginData>,
+    connection: web::Data<PgPool>,
+    root: web::Data<RootKeys>,
+    time: web::Data<MockableDateTime>,
+) -> HttpResponse {
+    let guest = match Guest::find(&connection, &hash_device_secret(&req_data.device_secret)).await {
+        Ok(guest) => guest,
+        Err(sqlx::Error::RowNotFound) => return HttpResponse::Unauthorized().finish(),
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+    if let Err(response) = check_app_accepts_guests(&connection, guest.app_id).await {
+        return response;
+    }
+    create_new_authentication_token(connection, root, time, guest.user_id, Some(guest.app_id)).await
+}
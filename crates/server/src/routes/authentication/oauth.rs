@@ -0,0 +1,159 @@
+use actix_web::{
+    cookie::{time::Duration, Cookie, SameSite},
+    dev::HttpServiceFactory,
+    http::header,
+    web, HttpRequest, HttpResponse,
+};
+use rand::{distributions::Alphanumeric, Rng};
+use serde::Deserialize;
+use sqlx::PgPool;
+
+use crate::{
+    biscuit::RootKeys,
+    configuration::Settings,
+    identity_provider::IdentityProviders,
+    models::{app::AppId, user::UserId},
+    random_names::random_name,
+    time::MockableDateTime,
+};
+
+use super::auth::create_new_authentication_token;
+
+pub fn config(
+    kp: web::Data<RootKeys>,
+    time: web::Data<MockableDateTime>,
+) -> impl HttpServiceFactory {
+    web::scope("/oauth/{provider}")
+        .app_data(time)
+        .app_data(kp)
+        .route("authorize", web::get().to(authorize))
+        .route("callback", web::get().to(oauth_callback))
+}
+
+/// Cookie binding the `state` sent to the identity provider to the client who started the login.
+pub const OAUTH_STATE_COOKIE: &str = "oauth_state";
+
+const OAUTH_STATE_COOKIE_PATH: &str = "/api/v1/authentication/oauth";
+
+/// Time to log in on the identity provider.
+const OAUTH_STATE_TTL_MINUTES: i64 = 10;
+
+#[derive(Debug, Deserialize)]
+pub struct OauthCallbackData {
+    code: String,
+    state: String,
+    as_app_user: Option<AppId>,
+}
+
+fn state_cookie(state: String, secure: bool) -> Cookie<'static> {
+    Cookie::build(OAUTH_STATE_COOKIE, state)
+        .path(OAUTH_STATE_COOKIE_PATH)
+        .http_only(true)
+        .secure(secure)
+        .same_site(SameSite::Lax)
+        .max_age(Duration::minutes(OAUTH_STATE_TTL_MINUTES))
+        .finish()
+}
+
+/// Redirects to the identity provider, which will then redirect to the callback.
+///
+/// The `state` given to the provider is also set in a cookie, the callback refuses it
+/// if they don't match: a login can't be finished by someone else than who started it.
+#[tracing::instrument(name = "oauth_authorize", skip_all, fields(provider=%&*provider))]
+async fn authorize(
+    provider: web::Path<String>,
+    identity_providers: web::Data<IdentityProviders>,
+    config: web::Data<Settings>,
+) -> HttpResponse {
+    let Some(provider) = identity_providers.get(&provider) else {
+        return HttpResponse::NotFound().finish();
+    };
+    let state: String = rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(32)
+        .map(char::from)
+        .collect();
+    HttpResponse::Found()
+        .insert_header((header::LOCATION, provider.authorize_url(&state)))
+        .cookie(state_cookie(state, config.production))
+        .finish()
+}
+
+/// Finds or creates the user linked to the identity, then authenticates them
+/// the same way as `/email_password/login`.
+///
+/// The `state` should match the [`OAUTH_STATE_COOKIE`] set by `/authorize`.
+#[tracing::instrument(name = "oauth_callback", skip_all, fields(provider=%&*provider))]
+async fn oauth_callback(
+    provider: web::Path<String>,
+    req_data: web::Query<OauthCallbackData>,
+    req: HttpRequest,
+    identity_providers: web::Data<IdentityProviders>,
+    connection: web::Data<PgPool>,
+    root: web::Data<RootKeys>,
+    time: web::Data<MockableDateTime>,
+) -> HttpResponse {
+    let Some(provider) = identity_providers.get(&provider) else {
+        return HttpResponse::NotFound().finish();
+    };
+    match req.cookie(OAUTH_STATE_COOKIE) {
+        Some(cookie) if !req_data.state.is_empty() && cookie.value() == req_data.state => {}
+        _ => return HttpResponse::Unauthorized().body("Invalid OAuth state."),
+    }
+    let identity = match provider.fetch_identity(&req_data.code).await {
+        Ok(identity) => identity,
+        Err(err) => {
+            tracing::warn!("identity provider error: {err}");
+            return HttpResponse::Unauthorized().finish();
+        }
+    };
+    let Ok(user_id) = identity
+        .get_or_create_user(&connection, &random_name())
+        .await
+    else {
+        return HttpResponse::InternalServerError().finish();
+    };
+    let mut response =
+        create_new_authentication_token(connection, root, time, user_id, req_data.as_app_user)
+            .await;
+    // The state is only used once.
+    let mut removal = Cookie::named(OAUTH_STATE_COOKIE);
+    removal.set_path(OAUTH_STATE_COOKIE_PATH);
+    let _ = response.add_removal_cookie(&removal);
+    response
+}
+
+/// Attaches the identity given by the provider to an existing user,
+/// refused if it's already linked to another user.
+pub(crate) async fn attach_identity(
+    connection: &PgPool,
+    identity_providers: &IdentityProviders,
+    provider: &str,
+    code: &str,
+    user_id: UserId,
+) -> Result<(), HttpResponse> {
+    let Some(provider) = identity_providers.get(provider) else {
+        return Err(HttpResponse::NotFound().finish());
+    };
+    let identity = match provider.fetch_identity(code).await {
+        Ok(identity) => identity,
+        Err(err) => {
+            tracing::warn!("identity provider error: {err}");
+            return Err(HttpResponse::Unauthorized().finish());
+        }
+    };
+    match identity.get_user(connection).await {
+        Ok(Some(linked_user)) if linked_user == user_id => return Ok(()),
+        Ok(Some(_)) => {
+            return Err(
+                HttpResponse::Conflict().body("This identity is already linked to another user.")
+            )
+        }
+        Ok(None) => {}
+        Err(_) => return Err(HttpResponse::InternalServerError().finish()),
+    }
+    if identity.create(connection, user_id).await.is_err() {
+        return Err(HttpResponse::InternalServerError().finish());
+    }
+    Ok(())
+}
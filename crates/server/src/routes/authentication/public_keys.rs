@@ -0,0 +1,26 @@
+use actix_web::{dev::HttpServiceFactory, web, HttpResponse, Responder};
+use base64::{engine::general_purpose, Engine as _};
+use shared::{PublicKeysResponse, RootPublicKey};
+
+use crate::biscuit::RootKeys;
+
+/// Lets clients verify and read biscuits without asking the server.
+pub fn config(kp: web::Data<RootKeys>) -> impl HttpServiceFactory {
+    web::resource("/public_keys")
+        .app_data(kp)
+        .route(web::get().to(public_keys))
+}
+
+async fn public_keys(root: web::Data<RootKeys>) -> impl Responder {
+    HttpResponse::Ok().json(PublicKeysResponse {
+        public_keys: root
+            .public_keys()
+            .0
+            .iter()
+            .map(|(id, public_key)| RootPublicKey {
+                id: *id,
+                public_key: general_purpose::STANDARD.encode(public_key.to_bytes()),
+            })
+            .collect(),
+    })
+}
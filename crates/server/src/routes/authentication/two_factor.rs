@@ -0,0 +1,67 @@
+use actix_web::{dev::HttpServiceFactory, web, HttpResponse};
+use shared::TwoFactorLoginData;
+use sqlx::PgPool;
+
+use crate::{
+    biscuit::RootKeys,
+    models::two_factor::{challenge, TwoFactor},
+    time::MockableDateTime,
+};
+
+use super::auth::issue_authentication_token;
+
+pub fn config(
+    kp: web::Data<RootKeys>,
+    time: web::Data<MockableDateTime>,
+) -> impl HttpServiceFactory {
+    web::scope("/two_factor")
+        .app_data(time)
+        .app_data(kp)
+        .route("login", web::post().to(login_two_factor))
+}
+
+/// Second step of an admin login, with the challenge received after a correct password.
+///
+/// A challenge is refused after a few wrong codes, the password is needed for a new one.
+#[tracing::instrument(name = "login_two_factor", skip_all)]
+async fn login_two_factor(
+    req_data: web::Json<TwoFactorLoginData>,
+    connection: web::Data<PgPool>,
+    root: web::Data<RootKeys>,
+    time: web::Data<MockableDateTime>,
+) -> HttpResponse {
+    let now = time.now_utc();
+    let user_id = match challenge::find(&connection, &req_data.challenge_token, now).await {
+        Ok(user_id) => user_id,
+        Err(sqlx::Error::RowNotFound) => return HttpResponse::Unauthorized().finish(),
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+    let two_factor = match TwoFactor::get(&connection, user_id).await {
+        Ok(Some(two_factor)) if two_factor.enabled => two_factor,
+        // Disabled since the challenge was given.
+        Ok(_) => return HttpResponse::Unauthorized().finish(),
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+    match two_factor
+        .verify_code(&connection, &req_data.code, now)
+        .await
+    {
+        Ok(true) => {}
+        Ok(false) => {
+            if challenge::record_failed_attempt(&connection, &req_data.challenge_token)
+                .await
+                .is_err()
+            {
+                return HttpResponse::InternalServerError().finish();
+            }
+            return HttpResponse::Unauthorized().finish();
+        }
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    }
+    match challenge::consume(&connection, &req_data.challenge_token).await {
+        Ok(true) => {}
+        Ok(false) => return HttpResponse::Unauthorized().finish(),
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    }
+    issue_authentication_token(connection, root, time, user_id, None, true).await
+}
@@ -0,0 +1,110 @@
+//! Time-based one-time passwords ([RFC 6238](https://www.rfc-editor.org/rfc/rfc6238)),
+//! with the defaults of authenticator apps: HMAC-SHA1, 6 digits, 30 seconds steps.
+
+use data_encoding::{BASE32_NOPAD, HEXLOWER};
+use hmac::{Hmac, Mac};
+use rand::{Rng, RngCore};
+use reqwest::Url;
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+use subtle::ConstantTimeEq;
+
+pub const ISSUER: &str = "Backpack";
+pub const STEP_SECONDS: i64 = 30;
+pub const DIGITS: u32 = 6;
+/// Codes of this many steps before and after the current one are accepted, for clock drift.
+pub const ALLOWED_DRIFT_STEPS: i64 = 1;
+pub const RECOVERY_CODES_COUNT: usize = 10;
+
+const SECRET_LENGTH: usize = 20;
+/// Without characters easily mistaken for one another.
+const RECOVERY_CODE_CHARSET: &[u8] = b"ABCDEFGHJKLMNPQRSTUVWXYZ23456789";
+const RECOVERY_CODE_GROUP_LENGTH: usize = 5;
+
+/// Returns a new secret, base32 encoded.
+pub fn generate_secret() -> String {
+    let mut secret = [0u8; SECRET_LENGTH];
+    rand::thread_rng().fill_bytes(&mut secret);
+    BASE32_NOPAD.encode(&secret)
+}
+
+pub fn step_at(unix_timestamp: i64) -> i64 {
+    unix_timestamp.div_euclid(STEP_SECONDS)
+}
+
+/// Code of the given time step, `None` if the secret is not valid base32.
+pub fn code_at_step(secret: &str, step: i64) -> Option<String> {
+    let key = BASE32_NOPAD.decode(secret.as_bytes()).ok()?;
+    let mut mac = Hmac::<Sha1>::new_from_slice(&key).ok()?;
+    mac.update(&step.to_be_bytes());
+    let hash = mac.finalize().into_bytes();
+    let offset = (hash[hash.len() - 1] & 0x0f) as usize;
+    let truncated = u32::from_be_bytes([
+        hash[offset] & 0x7f,
+        hash[offset + 1],
+        hash[offset + 2],
+        hash[offset + 3],
+    ]);
+    Some(format!(
+        "{:0width$}",
+        truncated % 10u32.pow(DIGITS),
+        width = DIGITS as usize
+    ))
+}
+
+/// Returns the time step of the code if it's valid around the given time.
+///
+/// Callers should refuse a step already used, so a code can't be replayed.
+pub fn verify(secret: &str, code: &str, unix_timestamp: i64) -> Option<i64> {
+    let code = code.trim();
+    let current_step = step_at(unix_timestamp);
+    (current_step - ALLOWED_DRIFT_STEPS..=current_step + ALLOWED_DRIFT_STEPS).find(|step| {
+        code_at_step(secret, *step).map_or(false, |expected| {
+            bool::from(expected.as_bytes().ct_eq(code.as_bytes()))
+        })
+    })
+}
+
+/// `otpauth://` uri to configure an authenticator app, usually shown as a QR code.
+pub fn provisioning_uri(secret: &str, account_name: &str) -> String {
+    let mut uri = Url::parse("otpauth://totp/").expect("static url should be valid");
+    uri.set_path(&format!("{ISSUER}:{account_name}"));
+    uri.query_pairs_mut()
+        .append_pair("secret", secret)
+        .append_pair("issuer", ISSUER)
+        .append_pair("algorithm", "SHA1")
+        .append_pair("digits", &DIGITS.to_string())
+        .append_pair("period", &STEP_SECONDS.to_string());
+    uri.to_string()
+}
+
+/// Recovery codes are shown to the user once, only their hash is stored.
+pub fn generate_recovery_codes() -> Vec<String> {
+    let mut rng = rand::thread_rng();
+    (0..RECOVERY_CODES_COUNT)
+        .map(|_| {
+            let mut code = String::new();
+            for i in 0..RECOVERY_CODE_GROUP_LENGTH * 2 {
+                if i == RECOVERY_CODE_GROUP_LENGTH {
+                    code.push('-');
+                }
+                code.push(
+                    RECOVERY_CODE_CHARSET[rng.gen_range(0..RECOVERY_CODE_CHARSET.len())] as char,
+                );
+            }
+            code
+        })
+        .collect()
+}
+
+/// Recovery codes are random enough for a fast hash, which allows looking them up by hash.
+///
+/// Dashes, spaces and case are ignored.
+pub fn hash_recovery_code(code: &str) -> String {
+    let normalized: String = code
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_uppercase())
+        .collect();
+    HEXLOWER.encode(&Sha256::digest(normalized.as_bytes()))
+}
@@ -0,0 +1,141 @@
+mod helper;
+#[cfg(test)]
+mod tests {
+
+    use backpack_client::{
+        shared::{CreateEmailPasswordData, LoginEmailPasswordData},
+        RequestError,
+    };
+    use backpack_server::configuration::{MailerSettings, OutboxSettings};
+
+    use crate::helper::{spawn_app, TestUser};
+
+    #[tokio::test]
+    async fn signup_does_not_reveal_existing_emails() {
+        // Arrange
+        let mut app = spawn_app().await;
+        let user = TestUser::generate(&app)
+            .await
+            .expect("error when generating test user");
+
+        // Act
+        app.api_client
+            .signup(&CreateEmailPasswordData {
+                email: user.email.clone(),
+            })
+            .await
+            .expect("Existing emails should not be distinguishable.");
+
+        // Assert
+        assert!(app
+            .sent_emails()
+            .contains("you already have a Backpack account"));
+        assert_eq!(app.sent_password(&user.email), Some(user.password.clone()));
+        user.login(&mut app.api_client, None)
+            .await
+            .expect("The existing password should still work.");
+    }
+
+    #[tokio::test]
+    async fn concurrent_signups_with_the_same_email_create_one_account() {
+        // Arrange
+        let app = spawn_app().await;
+        let data = CreateEmailPasswordData {
+            email: "twice@example.com".to_string(),
+        };
+
+        // Act
+        let (first, second) =
+            tokio::join!(app.api_client.signup(&data), app.api_client.signup(&data));
+
+        // Assert
+        first.expect("Concurrent signups should both be accepted.");
+        second.expect("Concurrent signups should both be accepted.");
+        let (users,): (i64,) =
+            sqlx::query_as("SELECT COUNT(*) FROM users_email_password WHERE email = $1")
+                .bind(&data.email)
+                .fetch_one(&app.db_pool)
+                .await
+                .expect("failed to count accounts");
+        assert_eq!(users, 1);
+    }
+
+    #[tokio::test]
+    async fn login_does_not_reveal_unknown_emails() {
+        // Arrange
+        let app = spawn_app().await;
+        let user = TestUser::generate(&app)
+            .await
+            .expect("error when generating test user");
+
+        // Act
+        let bad_password = app
+            .api_client
+            .login(&LoginEmailPasswordData {
+                email: user.email.clone(),
+                password_plain: "wrong password".to_string(),
+                as_app_user: None,
+            })
+            .await;
+        let unknown_email = app
+            .api_client
+            .login(&LoginEmailPasswordData {
+                email: "unknown@example.com".to_string(),
+                password_plain: "wrong password".to_string(),
+                as_app_user: None,
+            })
+            .await;
+
+        // Assert
+        match (bad_password, unknown_email) {
+            (
+                Err(RequestError::StatusError {
+                    status: bad_password_status,
+                    bytes: bad_password_bytes,
+                }),
+                Err(RequestError::StatusError {
+                    status: unknown_email_status,
+                    bytes: unknown_email_bytes,
+                }),
+            ) => {
+                assert_eq!(bad_password_status, unknown_email_status);
+                assert_eq!(bad_password_bytes, unknown_email_bytes);
+            }
+            _ => panic!("Both logins should be refused the same way."),
+        }
+    }
+
+    #[tokio::test]
+    async fn resend_verification_does_not_reveal_unverified_emails() {
+        // Arrange
+        let app = spawn_app().await;
+        let user = TestUser::generate(&app)
+            .await
+            .expect("error when generating test user");
+        // Emails can't be sent anymore once the outbox is a directory.
+        let MailerSettings::Outbox(OutboxSettings { path: Some(outbox) }) = &app.settings.mailer
+        else {
+            panic!("test app should use a file outbox");
+        };
+        std::fs::remove_file(outbox).expect("failed to remove the outbox");
+        std::fs::create_dir(outbox).expect("failed to replace the outbox");
+
+        // Act
+        let unverified = app
+            .api_client
+            .resend_verification(&CreateEmailPasswordData {
+                email: user.email.clone(),
+            })
+            .await;
+        let unknown = app
+            .api_client
+            .resend_verification(&CreateEmailPasswordData {
+                email: "unknown@example.com".to_string(),
+            })
+            .await;
+
+        // Assert
+        unverified.expect("Failing to send the email should not be revealed.");
+        unknown.expect("Unknown emails should be answered as unverified ones.");
+    }
+}
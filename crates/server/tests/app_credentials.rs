@@ -0,0 +1,172 @@
+mod helper;
+#[cfg(test)]
+mod tests {
+
+    use backpack_client::{
+        shared::{LoginAppCredentialsData, Role},
+        ModifyItemOptions,
+    };
+
+    use crate::helper::{spawn_app, TestUser};
+
+    #[tokio::test]
+    async fn app_service_modifies_player_item() {
+        // Arrange
+        let mut app = spawn_app().await;
+        let admin = TestUser::generate(&app)
+            .await
+            .expect("error when generating test user");
+        let admin_auth = admin
+            .login(&mut app.api_client, None)
+            .await
+            .expect("login failed");
+        let app_id = app
+            .api_client
+            .create_app(&admin_auth.raw_biscuit, "secure app")
+            .await
+            .expect("app creation failed");
+        let item_id = app
+            .api_client
+            .create_item(&admin_auth.raw_biscuit, app_id, "gold")
+            .await
+            .expect("item creation failed");
+        let credentials = app
+            .api_client
+            .create_app_credentials(&admin_auth.raw_biscuit, app_id)
+            .await
+            .expect("credentials creation failed");
+        let player = TestUser::generate(&app)
+            .await
+            .expect("error when generating test user");
+        let player_auth = player
+            .login(&mut app.api_client, Some(app_id))
+            .await
+            .expect("login failed");
+
+        // Act
+        let app_service_auth = app
+            .api_client
+            .login_app_credentials(&LoginAppCredentialsData {
+                credentials_id: credentials.id,
+                secret: credentials.secret.clone(),
+            })
+            .await
+            .expect("app service login failed");
+        let new_amount = app
+            .api_client
+            .modify_item(
+                &app_service_auth.raw_biscuit,
+                item_id,
+                3,
+                player_auth.biscuit_info.user_id,
+                ModifyItemOptions::default(),
+            )
+            .await
+            .expect("app service should be able to modify player's item");
+
+        // Assert
+        assert_eq!(app_service_auth.biscuit_info.role, Role::AppService(app_id));
+        assert_eq!(new_amount, 3);
+        app.api_client
+            .login_app_credentials(&LoginAppCredentialsData {
+                credentials_id: credentials.id,
+                secret: "wrong secret".to_string(),
+            })
+            .await
+            .expect_err("wrong secret should not authenticate.");
+    }
+
+    #[tokio::test]
+    async fn app_service_does_not_act_as_its_creator() {
+        // Arrange
+        let mut app = spawn_app().await;
+        let admin = TestUser::generate(&app)
+            .await
+            .expect("error when generating test user");
+        let admin_auth = admin
+            .login(&mut app.api_client, None)
+            .await
+            .expect("login failed");
+        let app_id = app
+            .api_client
+            .create_app(&admin_auth.raw_biscuit, "secure app")
+            .await
+            .expect("app creation failed");
+        let credentials = app
+            .api_client
+            .create_app_credentials(&admin_auth.raw_biscuit, app_id)
+            .await
+            .expect("credentials creation failed");
+
+        // Act
+        let app_service_auth = app
+            .api_client
+            .login_app_credentials(&LoginAppCredentialsData {
+                credentials_id: credentials.id,
+                secret: credentials.secret.clone(),
+            })
+            .await
+            .expect("app service login failed");
+
+        // Assert
+        assert_ne!(
+            app_service_auth.biscuit_info.user_id,
+            admin_auth.biscuit_info.user_id
+        );
+        app.api_client
+            .get_sessions(&app_service_auth.raw_biscuit)
+            .await
+            .expect_err("app services should not access user routes.");
+        app.api_client
+            .whoami(&app_service_auth.raw_biscuit)
+            .await
+            .expect("app services should be able to call whoami.");
+    }
+
+    #[tokio::test]
+    async fn revoked_credentials_revoke_their_tokens() {
+        // Arrange
+        let mut app = spawn_app().await;
+        let admin_auth = TestUser::generate(&app)
+            .await
+            .expect("error when generating test user")
+            .login(&mut app.api_client, None)
+            .await
+            .expect("login failed");
+        let app_id = app
+            .api_client
+            .create_app(&admin_auth.raw_biscuit, "secure app")
+            .await
+            .expect("app creation failed");
+        let credentials = app
+            .api_client
+            .create_app_credentials(&admin_auth.raw_biscuit, app_id)
+            .await
+            .expect("credentials creation failed");
+        let login_data = LoginAppCredentialsData {
+            credentials_id: credentials.id,
+            secret: credentials.secret.clone(),
+        };
+        let app_service_auth = app
+            .api_client
+            .login_app_credentials(&login_data)
+            .await
+            .expect("app service login failed");
+
+        // Act
+        app.api_client
+            .revoke_app_credentials(&admin_auth.raw_biscuit, &credentials)
+            .await
+            .expect("revoking credentials failed");
+
+        // Assert
+        app.api_client
+            .whoami(&app_service_auth.raw_biscuit)
+            .await
+            .expect_err("tokens of revoked credentials should be refused.");
+        app.api_client
+            .login_app_credentials(&login_data)
+            .await
+            .expect_err("revoked credentials should not log in.");
+    }
+}
@@ -0,0 +1,210 @@
+mod helper;
+#[cfg(test)]
+mod tests {
+
+    use backpack_client::{
+        shared::{biscuit::Attenuation, ItemTransaction, ItemTransactionOperation},
+        ModifyItemOptions,
+    };
+
+    use crate::helper::{spawn_app, TestUser};
+
+    #[tokio::test]
+    async fn attenuated_token_is_restricted_to_its_checks() {
+        // Arrange
+        let mut app = spawn_app().await;
+        let user = TestUser::generate(&app)
+            .await
+            .expect("error when generating test user");
+        let admin_auth = user
+            .login(&mut app.api_client, None)
+            .await
+            .expect("login failed");
+        let app_id = app
+            .api_client
+            .create_app(&admin_auth.raw_biscuit, "mini-game")
+            .await
+            .expect("app creation failed");
+        let item_id = app
+            .api_client
+            .create_item(&admin_auth.raw_biscuit, app_id, "coins")
+            .await
+            .expect("item creation failed");
+        let other_item_id = app
+            .api_client
+            .create_item(&admin_auth.raw_biscuit, app_id, "gems")
+            .await
+            .expect("item creation failed");
+        let auth = user
+            .login(&mut app.api_client, Some(app_id))
+            .await
+            .expect("login failed");
+        let user_id = auth.biscuit_info.user_id;
+        for item in [item_id, other_item_id] {
+            app.api_client
+                .modify_item(
+                    &auth.raw_biscuit,
+                    item,
+                    10,
+                    user_id,
+                    ModifyItemOptions::default(),
+                )
+                .await
+                .expect("modifying item failed");
+        }
+
+        // Act
+        let attenuated = app
+            .api_client
+            .attenuate(
+                &auth.raw_biscuit,
+                &Attenuation {
+                    items: vec![item_id],
+                    only_decrement: true,
+                    max_amount: Some(3),
+                    ..Default::default()
+                },
+            )
+            .await
+            .expect("attenuation failed");
+
+        // Assert
+        let new_amount = app
+            .api_client
+            .modify_item(
+                &attenuated,
+                item_id,
+                -3,
+                user_id,
+                ModifyItemOptions::default(),
+            )
+            .await
+            .expect("decrementing within the attenuation should be allowed");
+        assert_eq!(new_amount, 7);
+        app.api_client
+            .modify_item(
+                &attenuated,
+                item_id,
+                1,
+                user_id,
+                ModifyItemOptions::default(),
+            )
+            .await
+            .expect_err("attenuated token should only decrement.");
+        app.api_client
+            .modify_item(
+                &attenuated,
+                item_id,
+                -4,
+                user_id,
+                ModifyItemOptions::default(),
+            )
+            .await
+            .expect_err("attenuated token should not exceed its max amount.");
+        app.api_client
+            .modify_item(
+                &attenuated,
+                other_item_id,
+                -1,
+                user_id,
+                ModifyItemOptions::default(),
+            )
+            .await
+            .expect_err("attenuated token should only modify its items.");
+        app.api_client
+            .whoami(&attenuated)
+            .await
+            .expect_err("attenuated token should only be accepted on item routes.");
+        app.api_client
+            .modify_item(
+                &auth.raw_biscuit,
+                other_item_id,
+                1,
+                user_id,
+                ModifyItemOptions::default(),
+            )
+            .await
+            .expect("the original token should keep its rights");
+    }
+
+    #[tokio::test]
+    async fn attenuation_limits_the_total_of_a_transaction() {
+        // Arrange
+        let mut app = spawn_app().await;
+        let user = TestUser::generate(&app)
+            .await
+            .expect("error when generating test user");
+        let admin_auth = user
+            .login(&mut app.api_client, None)
+            .await
+            .expect("login failed");
+        let app_id = app
+            .api_client
+            .create_app(&admin_auth.raw_biscuit, "mini-game")
+            .await
+            .expect("app creation failed");
+        let item_id = app
+            .api_client
+            .create_item(&admin_auth.raw_biscuit, app_id, "coins")
+            .await
+            .expect("item creation failed");
+        let auth = user
+            .login(&mut app.api_client, Some(app_id))
+            .await
+            .expect("login failed");
+        let user_id = auth.biscuit_info.user_id;
+        app.api_client
+            .modify_item(
+                &auth.raw_biscuit,
+                item_id,
+                10,
+                user_id,
+                ModifyItemOptions::default(),
+            )
+            .await
+            .expect("modifying item failed");
+        let attenuated = app
+            .api_client
+            .attenuate(
+                &auth.raw_biscuit,
+                &Attenuation {
+                    items: vec![item_id],
+                    only_decrement: true,
+                    max_amount: Some(3),
+                    ..Default::default()
+                },
+            )
+            .await
+            .expect("attenuation failed");
+        let spend = |amounts: &[i32]| ItemTransaction {
+            operations: amounts
+                .iter()
+                .map(|&amount| ItemTransactionOperation {
+                    item_id,
+                    user_id,
+                    amount,
+                })
+                .collect(),
+            reason: None,
+            idempotency_key: None,
+        };
+
+        // Act
+        let split = app
+            .api_client
+            .apply_item_transaction(&attenuated, &spend(&[-2, -2]))
+            .await;
+        let within = app
+            .api_client
+            .apply_item_transaction(&attenuated, &spend(&[-2, -1]))
+            .await
+            .expect("a total within the attenuation should be allowed");
+
+        // Assert
+        assert!(
+            split.is_err(),
+            "split operations should not exceed the max amount together."
+        );
+        assert_eq!(within, vec![8, 7]);
+    }
+}
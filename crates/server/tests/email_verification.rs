@@ -0,0 +1,160 @@
+mod helper;
+#[cfg(test)]
+mod tests {
+
+    use backpack_client::shared::{AppPolicy, VerifyEmailData};
+    use backpack_server::{
+        configuration::{MailerSettings, OutboxSettings},
+        email::{mailer_from_settings, MailerError},
+    };
+
+    use crate::helper::{spawn_app, TestUser};
+
+    #[tokio::test]
+    async fn app_requiring_verified_email_refuses_unverified_users() {
+        // Arrange
+        let mut app = spawn_app().await;
+        let admin = TestUser::generate(&app)
+            .await
+            .expect("error when generating test user");
+        let admin_auth = admin
+            .login(&mut app.api_client, None)
+            .await
+            .expect("login failed");
+        let app_id = app
+            .api_client
+            .create_app(&admin_auth.raw_biscuit, "strict app")
+            .await
+            .expect("app creation failed");
+        app.api_client
+            .set_app_policy(
+                &admin_auth.raw_biscuit,
+                app_id,
+                &AppPolicy {
+                    require_verified_email: true,
+                    ..Default::default()
+                },
+            )
+            .await
+            .expect("setting policy failed");
+        let player = TestUser::generate(&app)
+            .await
+            .expect("error when generating test user");
+
+        // Act
+        player
+            .login(&mut app.api_client, Some(app_id))
+            .await
+            .expect_err("unverified user should not log in as app user.");
+        app.api_client
+            .verify_email(&VerifyEmailData {
+                email: player.email.clone(),
+                code: app
+                    .sent_verification_code(&player.email)
+                    .expect("a verification link should have been sent"),
+            })
+            .await
+            .expect("email verification failed");
+
+        // Assert
+        player
+            .login(&mut app.api_client, Some(app_id))
+            .await
+            .expect("verified user should log in as app user.");
+    }
+
+    #[tokio::test]
+    async fn sessions_of_unverified_users_are_not_refreshed_once_required() {
+        // Arrange
+        let mut app = spawn_app().await;
+        let admin = TestUser::generate(&app)
+            .await
+            .expect("error when generating test user");
+        let admin_auth = admin
+            .login(&mut app.api_client, None)
+            .await
+            .expect("login failed");
+        let app_id = app
+            .api_client
+            .create_app(&admin_auth.raw_biscuit, "soon strict app")
+            .await
+            .expect("app creation failed");
+        let player = TestUser::generate(&app)
+            .await
+            .expect("error when generating test user");
+        let player_auth = player
+            .login(&mut app.api_client, Some(app_id))
+            .await
+            .expect("login failed");
+
+        // Act
+        app.api_client
+            .set_app_policy(
+                &admin_auth.raw_biscuit,
+                app_id,
+                &AppPolicy {
+                    require_verified_email: true,
+                    ..Default::default()
+                },
+            )
+            .await
+            .expect("setting policy failed");
+
+        // Assert
+        app.api_client
+            .refresh(&player_auth.raw_biscuit, &player_auth.refresh_token)
+            .await
+            .expect_err("sessions of unverified users should not be refreshed.");
+    }
+
+    #[tokio::test]
+    async fn signup_emails_password_and_verification_link() {
+        // Arrange
+        let app = spawn_app().await;
+
+        // Act
+        let user = TestUser::generate(&app)
+            .await
+            .expect("error when generating test user");
+
+        // Assert
+        let emails = app.sent_emails();
+        assert!(emails.contains(&format!("To: {}", user.email)));
+        assert!(emails.contains(&user.password));
+        assert!(emails.contains("/api/v1/authentication/email_password/verify?"));
+    }
+
+    #[tokio::test]
+    async fn verification_codes_are_stored_hashed() {
+        // Arrange
+        let app = spawn_app().await;
+
+        // Act
+        let user = TestUser::generate(&app)
+            .await
+            .expect("error when generating test user");
+        let code = app
+            .sent_verification_code(&user.email)
+            .expect("a verification link should have been sent");
+
+        // Assert
+        let (stored,): (Option<String>,) = sqlx::query_as(
+            "SELECT verification_code_hash FROM users_email_password WHERE email = $1",
+        )
+        .bind(&user.email)
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("failed to fetch verification code");
+        assert_ne!(stored, Some(code));
+    }
+
+    #[test]
+    fn production_refuses_outbox_mailer() {
+        let outbox = MailerSettings::Outbox(OutboxSettings { path: None });
+        assert!(matches!(
+            mailer_from_settings(&outbox, true),
+            Err(MailerError::OutboxInProduction)
+        ));
+        assert!(mailer_from_settings(&outbox, false).is_ok());
+    }
+}
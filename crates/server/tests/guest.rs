@@ -0,0 +1,295 @@
+mod helper;
+#[cfg(test)]
+mod tests {
+
+    use backpack_client::{
+        shared::{
+            AppId, AppPolicy, CreateEmailPasswordData, CreateGuestData, GuestLoginData, ItemId,
+            LoginEmailPasswordData, Role, VerifyEmailData,
+        },
+        ModifyItemOptions,
+    };
+    use uuid::Uuid;
+
+    use crate::helper::{spawn_app, TestApp, TestUser};
+
+    /// Creates an app with an item, returns the app, the item and the admin biscuit.
+    async fn create_app_with_item(app: &mut TestApp) -> (AppId, ItemId, Vec<u8>) {
+        let admin = TestUser::generate(app)
+            .await
+            .expect("error when generating test user");
+        let admin_auth = admin
+            .login(&mut app.api_client, None)
+            .await
+            .expect("login failed");
+        let app_id = app
+            .api_client
+            .create_app(&admin_auth.raw_biscuit, "guest friendly app")
+            .await
+            .expect("app creation failed");
+        let item_id = app
+            .api_client
+            .create_item(&admin_auth.raw_biscuit, app_id, "gold")
+            .await
+            .expect("item creation failed");
+        (app_id, item_id, admin_auth.raw_biscuit)
+    }
+
+    #[tokio::test]
+    async fn guest_logs_in_with_device_secret() {
+        // Arrange
+        let mut app = spawn_app().await;
+        let (app_id, item_id, _) = create_app_with_item(&mut app).await;
+
+        // Act
+        let signup = app
+            .api_client
+            .signup_guest(&CreateGuestData { app_id })
+            .await
+            .expect("guest signup failed");
+
+        // Assert
+        let guest_auth = signup.authentication_token;
+        assert_eq!(guest_auth.biscuit_info.role, Role::User(app_id));
+        let new_amount = app
+            .api_client
+            .modify_item(
+                &guest_auth.raw_biscuit,
+                item_id,
+                5,
+                guest_auth.biscuit_info.user_id,
+                ModifyItemOptions::default(),
+            )
+            .await
+            .expect("guests should earn items");
+        assert_eq!(new_amount, 5);
+        let login = app
+            .api_client
+            .login_guest(&GuestLoginData {
+                device_secret: signup.device_secret,
+            })
+            .await
+            .expect("guest login failed");
+        assert_eq!(login.biscuit_info.user_id, guest_auth.biscuit_info.user_id);
+        assert_eq!(login.biscuit_info.role, Role::User(app_id));
+        app.api_client
+            .login_guest(&GuestLoginData {
+                device_secret: "wrong secret".to_string(),
+            })
+            .await
+            .expect_err("wrong device secret should not authenticate.");
+        app.api_client
+            .signup_guest(&CreateGuestData {
+                app_id: AppId(app_id.0 + 1),
+            })
+            .await
+            .expect_err("guests should not sign up for unknown apps.");
+    }
+
+    #[tokio::test]
+    async fn apps_requiring_verified_email_refuse_guests() {
+        // Arrange
+        let mut app = spawn_app().await;
+        let (app_id, _, admin_biscuit) = create_app_with_item(&mut app).await;
+        let signup = app
+            .api_client
+            .signup_guest(&CreateGuestData { app_id })
+            .await
+            .expect("guest signup failed");
+
+        // Act
+        app.api_client
+            .set_app_policy(
+                &admin_biscuit,
+                app_id,
+                &AppPolicy {
+                    require_verified_email: true,
+                    ..Default::default()
+                },
+            )
+            .await
+            .expect("setting policy failed");
+
+        // Assert
+        app.api_client
+            .signup_guest(&CreateGuestData { app_id })
+            .await
+            .expect_err("guests have no verified email.");
+        app.api_client
+            .login_guest(&GuestLoginData {
+                device_secret: signup.device_secret,
+            })
+            .await
+            .expect_err("guests have no verified email.");
+    }
+
+    #[tokio::test]
+    async fn upgraded_guest_keeps_items() {
+        // Arrange
+        let mut app = spawn_app().await;
+        let (app_id, item_id, _) = create_app_with_item(&mut app).await;
+        let signup = app
+            .api_client
+            .signup_guest(&CreateGuestData { app_id })
+            .await
+            .expect("guest signup failed");
+        let guest_auth = signup.authentication_token;
+        app.api_client
+            .modify_item(
+                &guest_auth.raw_biscuit,
+                item_id,
+                5,
+                guest_auth.biscuit_info.user_id,
+                ModifyItemOptions::default(),
+            )
+            .await
+            .expect("guests should earn items");
+        let email = Uuid::new_v4().to_string() + "@example.com";
+
+        // Act
+        app.api_client
+            .upgrade_guest_email_password(
+                &guest_auth.raw_biscuit,
+                &CreateEmailPasswordData {
+                    email: email.clone(),
+                },
+            )
+            .await
+            .expect("guest upgrade failed");
+
+        // Assert
+        let password = app
+            .sent_password(&email)
+            .expect("upgrade email should contain a password");
+        let user_auth = app
+            .api_client
+            .login(&LoginEmailPasswordData {
+                email,
+                password_plain: password,
+                as_app_user: Some(app_id),
+            })
+            .await
+            .expect("login with the attached email failed");
+        assert_eq!(
+            user_auth.biscuit_info.user_id,
+            guest_auth.biscuit_info.user_id
+        );
+        let items = app
+            .api_client
+            .get_items(&user_auth.raw_biscuit, &user_auth.biscuit_info.user_id)
+            .await
+            .expect("getting items failed");
+        assert!(items
+            .iter()
+            .any(|item| item.item.id == item_id && item.amount == 5));
+        app.api_client
+            .login_guest(&GuestLoginData {
+                device_secret: signup.device_secret,
+            })
+            .await
+            .expect_err("device secret should not be valid after upgrade.");
+        app.api_client
+            .upgrade_guest_email_password(
+                &user_auth.raw_biscuit,
+                &CreateEmailPasswordData {
+                    email: Uuid::new_v4().to_string() + "@example.com",
+                },
+            )
+            .await
+            .expect_err("only guests can be upgraded.");
+    }
+
+    #[tokio::test]
+    async fn guest_upgrade_with_used_email_keeps_guest() {
+        // Arrange
+        let mut app = spawn_app().await;
+        let (app_id, _, _) = create_app_with_item(&mut app).await;
+        let existing_user = TestUser::generate(&app)
+            .await
+            .expect("error when generating test user");
+        let signup = app
+            .api_client
+            .signup_guest(&CreateGuestData { app_id })
+            .await
+            .expect("guest signup failed");
+
+        // Act
+        app.api_client
+            .upgrade_guest_email_password(
+                &signup.authentication_token.raw_biscuit,
+                &CreateEmailPasswordData {
+                    email: existing_user.email.clone(),
+                },
+            )
+            .await
+            .expect("used emails should be answered as new ones");
+
+        // Assert
+        let login = app
+            .api_client
+            .login_guest(&GuestLoginData {
+                device_secret: signup.device_secret,
+            })
+            .await
+            .expect("guest should still log in with their device secret");
+        assert_eq!(
+            login.biscuit_info.user_id,
+            signup.authentication_token.biscuit_info.user_id
+        );
+        existing_user
+            .login(&mut app.api_client, Some(app_id))
+            .await
+            .expect("existing user should be untouched");
+    }
+
+    #[tokio::test]
+    async fn guest_keeps_device_secret_until_email_is_verified() {
+        // Arrange
+        let mut app = spawn_app().await;
+        let (app_id, _, _) = create_app_with_item(&mut app).await;
+        let signup = app
+            .api_client
+            .signup_guest(&CreateGuestData { app_id })
+            .await
+            .expect("guest signup failed");
+        let guest_auth = signup.authentication_token;
+        let mistyped_email = Uuid::new_v4().to_string() + "@example.com";
+        let email = Uuid::new_v4().to_string() + "@example.com";
+
+        // Act
+        for email in [&mistyped_email, &email] {
+            app.api_client
+                .upgrade_guest_email_password(
+                    &guest_auth.raw_biscuit,
+                    &CreateEmailPasswordData {
+                        email: email.clone(),
+                    },
+                )
+                .await
+                .expect("guests should upgrade again until their email is verified");
+        }
+
+        // Assert
+        let login = app
+            .api_client
+            .login_guest(&GuestLoginData {
+                device_secret: signup.device_secret.clone(),
+            })
+            .await
+            .expect("device secret should be valid until the email is verified");
+        assert_eq!(login.biscuit_info.user_id, guest_auth.biscuit_info.user_id);
+        let code = app
+            .sent_verification_code(&email)
+            .expect("a verification link should have been sent");
+        app.api_client
+            .verify_email(&VerifyEmailData { email, code })
+            .await
+            .expect("email verification failed");
+        app.api_client
+            .login_guest(&GuestLoginData {
+                device_secret: signup.device_secret,
+            })
+            .await
+            .expect_err("device secret should not be valid once the email is verified.");
+    }
+}
@@ -1,11 +1,13 @@
 use std::net::TcpListener;
 
 use backpack_client::{
-    shared::{AppId, Role, UserId},
+    shared::{AppId, Role},
     BackpackClient, RequestError,
 };
 use backpack_server::{
-    configuration::{get_configuration, DatabaseSettings, Settings},
+    configuration::{
+        get_configuration, DatabaseSettings, MailerSettings, OutboxSettings, Settings,
+    },
     telemetry::{get_subscriber, init_subscriber},
 };
 use once_cell::sync::Lazy;
@@ -33,6 +35,11 @@ pub struct TestApp {
 }
 
 pub async fn spawn_app() -> TestApp {
+    spawn_app_with_settings(|_| {}).await
+}
+
+/// Spawns the app after customizing its settings, to point to mock services for example.
+pub async fn spawn_app_with_settings(configure: impl FnOnce(&mut Settings)) -> TestApp {
     Lazy::force(&TRACING);
     let listener = TcpListener::bind("127.0.0.1:0").expect("Failed to bind random port");
     let port = listener.local_addr().unwrap().port();
@@ -40,6 +47,15 @@ pub async fn spawn_app() -> TestApp {
     let mut settings = get_configuration();
     settings.database.database_name = format!("test-{}", Uuid::new_v4());
     settings.application_port = port;
+    settings.mailer = MailerSettings::Outbox(OutboxSettings {
+        path: Some(
+            std::env::temp_dir()
+                .join(format!("backpack-outbox-{}.txt", Uuid::new_v4()))
+                .to_string_lossy()
+                .to_string(),
+        ),
+    });
+    configure(&mut settings);
     let connection_pool = configure_database(&settings.database).await;
 
     let server = backpack_server::run(listener, connection_pool.clone(), settings.clone())
@@ -54,6 +70,41 @@ pub async fn spawn_app() -> TestApp {
     }
 }
 
+impl TestApp {
+    /// Emails written to the outbox by the app.
+    pub fn sent_emails(&self) -> String {
+        let MailerSettings::Outbox(OutboxSettings { path: Some(path) }) = &self.settings.mailer
+        else {
+            panic!("test app should use a file outbox");
+        };
+        std::fs::read_to_string(path).unwrap_or_default()
+    }
+    /// Password sent to this email on signup.
+    pub fn sent_password(&self, email: &str) -> Option<String> {
+        let emails = self.sent_emails();
+        let signup_email = emails.split("From: ").find(|sent| {
+            sent.contains(&format!("To: {email}\n")) && sent.contains("your password is ")
+        })?;
+        let (_, password) = signup_email.split_once("your password is ")?;
+        password
+            .split_once(".\n")
+            .map(|(password, _)| password.to_string())
+    }
+    /// Code of the last verification link sent to this email, only its hash is stored.
+    pub fn sent_verification_code(&self, email: &str) -> Option<String> {
+        let emails = self.sent_emails();
+        let verification_email = emails.split("From: ").rfind(|sent| {
+            sent.contains(&format!("To: {email}\n"))
+                && sent.contains("verify your email by visiting ")
+        })?;
+        let (_, link) = verification_email.split_once("verify your email by visiting ")?;
+        let link = reqwest::Url::parse(link.split_whitespace().next()?).ok()?;
+        link.query_pairs()
+            .find(|(name, _)| name == "code")
+            .map(|(_, code)| code.into_owned())
+    }
+}
+
 async fn configure_database(config: &DatabaseSettings) -> PgPool {
     // Create database
     let mut connection = PgConnection::connect_with(&config.without_db())
@@ -77,25 +128,24 @@ async fn configure_database(config: &DatabaseSettings) -> PgPool {
 }
 
 pub struct TestUser {
-    user_id: UserId,
     pub email: String,
     pub password: String,
 }
 
 impl TestUser {
-    pub async fn generate(client: &mut BackpackClient) -> Result<Self, RequestError> {
+    /// Signs up a new user, its password is read from the email it received.
+    pub async fn generate(app: &TestApp) -> Result<Self, RequestError> {
         let email = Uuid::new_v4().to_string() + "@example.com";
-        let created_data = client
+        app.api_client
             .signup(&backpack_client::shared::CreateEmailPasswordData {
                 email: email.clone(),
             })
             .await?;
+        let password = app
+            .sent_password(&email)
+            .expect("signup email should contain a password");
 
-        Ok(Self {
-            user_id: created_data.id,
-            email,
-            password: created_data.password,
-        })
+        Ok(Self { email, password })
     }
     pub async fn login(
         &self,
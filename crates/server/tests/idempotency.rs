@@ -0,0 +1,181 @@
+mod helper;
+#[cfg(test)]
+mod tests {
+
+    use backpack_client::{
+        shared::{ItemTransaction, ItemTransactionOperation},
+        ModifyItemOptions,
+    };
+
+    use crate::helper::{spawn_app, TestUser};
+
+    #[tokio::test]
+    async fn modify_item_with_idempotency_key_is_applied_once() {
+        // Arrange
+        let mut app = spawn_app().await;
+        let admin = TestUser::generate(&app)
+            .await
+            .expect("error when generating test user");
+        let admin_auth = admin
+            .login(&mut app.api_client, None)
+            .await
+            .expect("login failed");
+        let app_id = app
+            .api_client
+            .create_app(&admin_auth.raw_biscuit, "mini-game")
+            .await
+            .expect("app creation failed");
+        let item_id = app
+            .api_client
+            .create_item(&admin_auth.raw_biscuit, app_id, "coins")
+            .await
+            .expect("item creation failed");
+        let user = TestUser::generate(&app)
+            .await
+            .expect("error when generating test user");
+        let auth = user
+            .login(&mut app.api_client, Some(app_id))
+            .await
+            .expect("login failed");
+        let user_id = auth.biscuit_info.user_id;
+
+        // Act
+        let first = app
+            .api_client
+            .modify_item(
+                &auth.raw_biscuit,
+                item_id,
+                3,
+                user_id,
+                ModifyItemOptions {
+                    idempotency_key: Some("grant-1"),
+                    ..Default::default()
+                },
+            )
+            .await
+            .expect("modifying item failed");
+        let retry = app
+            .api_client
+            .modify_item(
+                &auth.raw_biscuit,
+                item_id,
+                3,
+                user_id,
+                ModifyItemOptions {
+                    idempotency_key: Some("grant-1"),
+                    ..Default::default()
+                },
+            )
+            .await
+            .expect("retrying the modification failed");
+        let reused = app
+            .api_client
+            .modify_item(
+                &auth.raw_biscuit,
+                item_id,
+                4,
+                user_id,
+                ModifyItemOptions {
+                    idempotency_key: Some("grant-1"),
+                    ..Default::default()
+                },
+            )
+            .await;
+
+        // Assert
+        assert_eq!(first, 3);
+        assert_eq!(retry, 3);
+        assert!(reused.is_err());
+        let items = app
+            .api_client
+            .get_items(&auth.raw_biscuit, &user_id)
+            .await
+            .expect("getting items failed");
+        assert_eq!(items[0].amount, 3);
+        let history = app
+            .api_client
+            .get_user_history(&auth.raw_biscuit, &user_id, None, None)
+            .await
+            .expect("getting history failed");
+        assert_eq!(history.entries.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn item_transaction_with_idempotency_key_is_applied_once() {
+        // Arrange
+        let mut app = spawn_app().await;
+        let admin = TestUser::generate(&app)
+            .await
+            .expect("error when generating test user");
+        let admin_auth = admin
+            .login(&mut app.api_client, None)
+            .await
+            .expect("login failed");
+        let app_id = app
+            .api_client
+            .create_app(&admin_auth.raw_biscuit, "mini-game")
+            .await
+            .expect("app creation failed");
+        let coins = app
+            .api_client
+            .create_item(&admin_auth.raw_biscuit, app_id, "coins")
+            .await
+            .expect("item creation failed");
+        let gems = app
+            .api_client
+            .create_item(&admin_auth.raw_biscuit, app_id, "gems")
+            .await
+            .expect("item creation failed");
+        let user = TestUser::generate(&app)
+            .await
+            .expect("error when generating test user");
+        let auth = user
+            .login(&mut app.api_client, Some(app_id))
+            .await
+            .expect("login failed");
+        let user_id = auth.biscuit_info.user_id;
+        let reward = |gems_amount| ItemTransaction {
+            operations: vec![
+                ItemTransactionOperation {
+                    item_id: coins,
+                    user_id,
+                    amount: 5,
+                },
+                ItemTransactionOperation {
+                    item_id: gems,
+                    user_id,
+                    amount: gems_amount,
+                },
+            ],
+            reason: Some("quest reward".to_string()),
+            idempotency_key: Some("reward-1".to_string()),
+        };
+
+        // Act
+        let first = app
+            .api_client
+            .apply_item_transaction(&auth.raw_biscuit, &reward(1))
+            .await
+            .expect("transaction failed");
+        let retry = app
+            .api_client
+            .apply_item_transaction(&auth.raw_biscuit, &reward(1))
+            .await
+            .expect("retrying the transaction failed");
+        let reused = app
+            .api_client
+            .apply_item_transaction(&auth.raw_biscuit, &reward(2))
+            .await;
+
+        // Assert
+        assert_eq!(first, vec![5, 1]);
+        assert_eq!(retry, vec![5, 1]);
+        assert!(reused.is_err());
+        let history = app
+            .api_client
+            .get_user_history(&auth.raw_biscuit, &user_id, None, None)
+            .await
+            .expect("getting history failed");
+        assert_eq!(history.entries.len(), 2);
+    }
+}
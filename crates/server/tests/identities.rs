@@ -0,0 +1,332 @@
+mod helper;
+#[cfg(test)]
+mod tests {
+
+    use backpack_client::{
+        shared::{
+            CreateEmailPasswordData, ItemAmountError, ItemBounds, LoginEmailPasswordData,
+            LoginMethod,
+        },
+        ModifyItemOptions, RequestError,
+    };
+    use uuid::Uuid;
+
+    use crate::helper::{spawn_app, TestUser};
+
+    #[tokio::test]
+    async fn attached_email_logs_in_same_user() {
+        // Arrange
+        let mut app = spawn_app().await;
+        let user = TestUser::generate(&app)
+            .await
+            .expect("error when generating test user");
+        let auth = user
+            .login(&mut app.api_client, None)
+            .await
+            .expect("login failed");
+        let email = Uuid::new_v4().to_string() + "@example.com";
+
+        // Act
+        app.api_client
+            .attach_email_password(
+                &auth.raw_biscuit,
+                &CreateEmailPasswordData {
+                    email: email.clone(),
+                },
+            )
+            .await
+            .expect("attaching email failed");
+
+        // Assert
+        let login_methods = app
+            .api_client
+            .get_login_methods(&auth.raw_biscuit)
+            .await
+            .expect("getting login methods failed");
+        assert_eq!(login_methods.len(), 2);
+        assert!(login_methods.contains(&LoginMethod::EmailPassword {
+            email: email.clone(),
+            is_verified: false,
+        }));
+        let password = app
+            .sent_password(&email)
+            .expect("attach email should contain a password");
+        let other_login = app
+            .api_client
+            .login(&LoginEmailPasswordData {
+                email,
+                password_plain: password,
+                as_app_user: None,
+            })
+            .await
+            .expect("login with the attached email failed");
+        assert_eq!(other_login.biscuit_info.user_id, auth.biscuit_info.user_id);
+    }
+
+    #[tokio::test]
+    async fn last_login_method_cannot_be_detached() {
+        // Arrange
+        let mut app = spawn_app().await;
+        let user = TestUser::generate(&app)
+            .await
+            .expect("error when generating test user");
+        let auth = user
+            .login(&mut app.api_client, None)
+            .await
+            .expect("login failed");
+        let email = Uuid::new_v4().to_string() + "@example.com";
+        app.api_client
+            .attach_email_password(
+                &auth.raw_biscuit,
+                &CreateEmailPasswordData {
+                    email: email.clone(),
+                },
+            )
+            .await
+            .expect("attaching email failed");
+
+        // Act
+        app.api_client
+            .detach_email_password(&auth.raw_biscuit, &user.email)
+            .await
+            .expect("detaching a login method failed");
+
+        // Assert
+        user.login(&mut app.api_client, None)
+            .await
+            .expect_err("detached email should not log in.");
+        app.api_client
+            .detach_email_password(&auth.raw_biscuit, &email)
+            .await
+            .expect_err("the last login method should not be detached.");
+        app.api_client
+            .detach_email_password(&auth.raw_biscuit, &user.email)
+            .await
+            .expect_err("an unknown login method should not be detached.");
+    }
+
+    #[tokio::test]
+    async fn merged_user_moves_into_current_user() {
+        // Arrange
+        let mut app = spawn_app().await;
+        let user = TestUser::generate(&app)
+            .await
+            .expect("error when generating test user");
+        let other_user = TestUser::generate(&app)
+            .await
+            .expect("error when generating test user");
+        let admin_auth = user
+            .login(&mut app.api_client, None)
+            .await
+            .expect("login failed");
+        let other_admin_auth = other_user
+            .login(&mut app.api_client, None)
+            .await
+            .expect("login failed");
+        let app_id = app
+            .api_client
+            .create_app(&admin_auth.raw_biscuit, "merged app")
+            .await
+            .expect("app creation failed");
+        let item_id = app
+            .api_client
+            .create_item(&admin_auth.raw_biscuit, app_id, "gold")
+            .await
+            .expect("item creation failed");
+        let other_app_id = app
+            .api_client
+            .create_app(&other_admin_auth.raw_biscuit, "other app")
+            .await
+            .expect("app creation failed");
+        let auth = user
+            .login(&mut app.api_client, Some(app_id))
+            .await
+            .expect("login failed");
+        let other_auth = other_user
+            .login(&mut app.api_client, Some(app_id))
+            .await
+            .expect("login failed");
+        for (auth, amount) in [(&auth, 3), (&other_auth, 4)] {
+            app.api_client
+                .modify_item(
+                    &auth.raw_biscuit,
+                    item_id,
+                    amount,
+                    auth.biscuit_info.user_id,
+                    ModifyItemOptions::default(),
+                )
+                .await
+                .expect("modifying item failed");
+        }
+
+        // Act
+        app.api_client
+            .merge_users(&auth.raw_biscuit, &other_auth.raw_biscuit)
+            .await
+            .expect("merging users failed");
+
+        // Assert
+        let items = app
+            .api_client
+            .get_items(&auth.raw_biscuit, &auth.biscuit_info.user_id)
+            .await
+            .expect("getting items failed");
+        assert!(items
+            .iter()
+            .any(|item| item.item.id == item_id && item.amount == 7));
+        let history = app
+            .api_client
+            .get_user_history(&auth.raw_biscuit, &auth.biscuit_info.user_id, None, None)
+            .await
+            .expect("getting history failed");
+        let merged_entry = &history.entries[0];
+        assert_eq!(merged_entry.item_id, item_id);
+        assert_eq!((merged_entry.delta, merged_entry.balance), (4, 7));
+        assert_eq!(
+            merged_entry.reason,
+            Some(format!("Merged user {}", other_auth.biscuit_info.user_id.0))
+        );
+        let other_login = other_user
+            .login(&mut app.api_client, None)
+            .await
+            .expect("merged login method should still log in");
+        assert_eq!(other_login.biscuit_info.user_id, auth.biscuit_info.user_id);
+        app.api_client
+            .create_item(&other_login.raw_biscuit, other_app_id, "silver")
+            .await
+            .expect("app admin rights should be merged");
+        let refreshed = app
+            .api_client
+            .refresh(&other_auth.raw_biscuit, &other_auth.refresh_token)
+            .await
+            .expect("sessions of the merged user should still refresh");
+        assert_eq!(refreshed.biscuit_info.user_id, auth.biscuit_info.user_id);
+        app.api_client
+            .merge_users(&auth.raw_biscuit, &auth.raw_biscuit)
+            .await
+            .expect_err("a user should not be merged into itself.");
+    }
+
+    #[tokio::test]
+    async fn merge_is_refused_over_item_maximum() {
+        // Arrange
+        let mut app = spawn_app().await;
+        let user = TestUser::generate(&app)
+            .await
+            .expect("error when generating test user");
+        let other_user = TestUser::generate(&app)
+            .await
+            .expect("error when generating test user");
+        let admin_auth = user
+            .login(&mut app.api_client, None)
+            .await
+            .expect("login failed");
+        let app_id = app
+            .api_client
+            .create_app(&admin_auth.raw_biscuit, "capped app")
+            .await
+            .expect("app creation failed");
+        let item_id = app
+            .api_client
+            .create_item(&admin_auth.raw_biscuit, app_id, "gold")
+            .await
+            .expect("item creation failed");
+        let auth = user
+            .login(&mut app.api_client, Some(app_id))
+            .await
+            .expect("login failed");
+        let other_auth = other_user
+            .login(&mut app.api_client, Some(app_id))
+            .await
+            .expect("login failed");
+        for (auth, amount) in [(&auth, 3), (&other_auth, 4)] {
+            app.api_client
+                .modify_item(
+                    &auth.raw_biscuit,
+                    item_id,
+                    amount,
+                    auth.biscuit_info.user_id,
+                    ModifyItemOptions::default(),
+                )
+                .await
+                .expect("modifying item failed");
+        }
+        app.api_client
+            .set_item_bounds(
+                &admin_auth.raw_biscuit,
+                item_id,
+                &ItemBounds {
+                    min_amount: 0,
+                    max_amount: Some(5),
+                    max_change: None,
+                },
+            )
+            .await
+            .expect("setting item bounds failed");
+
+        // Act
+        let merged = app
+            .api_client
+            .merge_users(&auth.raw_biscuit, &other_auth.raw_biscuit)
+            .await;
+
+        // Assert
+        assert!(matches!(
+            merged,
+            Err(RequestError::ItemAmountError(
+                ItemAmountError::MaximumExceeded { max_amount: 5, .. }
+            ))
+        ));
+        let other_items = app
+            .api_client
+            .get_items(&other_auth.raw_biscuit, &other_auth.biscuit_info.user_id)
+            .await
+            .expect("the other user should not be merged");
+        assert!(other_items
+            .iter()
+            .any(|item| item.item.id == item_id && item.amount == 4));
+    }
+
+    #[tokio::test]
+    async fn merge_is_refused_with_a_revoked_token() {
+        // Arrange
+        let mut app = spawn_app().await;
+        let auth = TestUser::generate(&app)
+            .await
+            .expect("error when generating test user")
+            .login(&mut app.api_client, None)
+            .await
+            .expect("login failed");
+        let other_user = TestUser::generate(&app)
+            .await
+            .expect("error when generating test user");
+        let other_auth = other_user
+            .login(&mut app.api_client, None)
+            .await
+            .expect("login failed");
+        app.api_client
+            .revoke_token(&other_auth.raw_biscuit, &other_auth.raw_biscuit)
+            .await
+            .expect("revoking token failed");
+
+        // Act
+        let merged = app
+            .api_client
+            .merge_users(&auth.raw_biscuit, &other_auth.raw_biscuit)
+            .await;
+
+        // Assert
+        assert!(matches!(
+            merged,
+            Err(RequestError::StatusError { status: 401, .. })
+        ));
+        let other_login = other_user
+            .login(&mut app.api_client, None)
+            .await
+            .expect("login failed");
+        assert_eq!(
+            other_login.biscuit_info.user_id, other_auth.biscuit_info.user_id,
+            "the other user should not be merged"
+        );
+    }
+}
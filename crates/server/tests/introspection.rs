@@ -0,0 +1,332 @@
+mod helper;
+#[cfg(test)]
+mod tests {
+
+    use backpack_client::shared::{
+        biscuit::Attenuation, ItemRights, LoginAppCredentialsData, Role,
+    };
+
+    use time::{Duration, OffsetDateTime};
+
+    use crate::helper::{spawn_app, TestUser};
+
+    #[tokio::test]
+    async fn app_service_introspects_player_token() {
+        // Arrange
+        let mut app = spawn_app().await;
+        let admin = TestUser::generate(&app)
+            .await
+            .expect("error when generating test user");
+        let admin_auth = admin
+            .login(&mut app.api_client, None)
+            .await
+            .expect("login failed");
+        let app_id = app
+            .api_client
+            .create_app(&admin_auth.raw_biscuit, "secure app")
+            .await
+            .expect("app creation failed");
+        let item_id = app
+            .api_client
+            .create_item(&admin_auth.raw_biscuit, app_id, "gold")
+            .await
+            .expect("item creation failed");
+        let credentials = app
+            .api_client
+            .create_app_credentials(&admin_auth.raw_biscuit, app_id)
+            .await
+            .expect("credentials creation failed");
+        let app_service_auth = app
+            .api_client
+            .login_app_credentials(&LoginAppCredentialsData {
+                credentials_id: credentials.id,
+                secret: credentials.secret.clone(),
+            })
+            .await
+            .expect("app service login failed");
+        let player = TestUser::generate(&app)
+            .await
+            .expect("error when generating test user");
+        let player_auth = player
+            .login(&mut app.api_client, Some(app_id))
+            .await
+            .expect("login failed");
+        let attenuated = app
+            .api_client
+            .attenuate(
+                &player_auth.raw_biscuit,
+                &Attenuation {
+                    only_decrement: true,
+                    ..Default::default()
+                },
+            )
+            .await
+            .expect("attenuation failed");
+
+        // Act
+        let introspection = app
+            .api_client
+            .introspect(&app_service_auth.raw_biscuit, &player_auth.raw_biscuit)
+            .await
+            .expect("introspection failed");
+        let attenuated_introspection = app
+            .api_client
+            .introspect(&app_service_auth.raw_biscuit, &attenuated)
+            .await
+            .expect("introspection failed");
+
+        // Assert
+        assert!(introspection.active);
+        let biscuit_info = introspection.biscuit_info.expect("token should be decoded");
+        assert_eq!(biscuit_info.user_id, player_auth.biscuit_info.user_id);
+        assert_eq!(biscuit_info.role, Role::User(app_id));
+        assert!(introspection.expires_in_seconds > 0);
+        assert_eq!(
+            introspection.item_rights,
+            vec![ItemRights {
+                item_id,
+                read: true,
+                increase: true,
+                decrease: true,
+                send: true,
+            }]
+        );
+        assert_eq!(
+            attenuated_introspection.item_rights,
+            vec![ItemRights {
+                item_id,
+                read: true,
+                increase: false,
+                decrease: true,
+                send: true,
+            }]
+        );
+    }
+
+    #[tokio::test]
+    async fn revoked_or_foreign_tokens_are_inactive() {
+        // Arrange
+        let mut app = spawn_app().await;
+        let user = TestUser::generate(&app)
+            .await
+            .expect("error when generating test user");
+        let auth = user
+            .login(&mut app.api_client, None)
+            .await
+            .expect("login failed");
+        let revoked_auth = user
+            .login(&mut app.api_client, None)
+            .await
+            .expect("login failed");
+        app.api_client
+            .revoke_token(&auth.raw_biscuit, &revoked_auth.raw_biscuit)
+            .await
+            .expect("revoking token failed");
+
+        // Act
+        let revoked = app
+            .api_client
+            .introspect(&auth.raw_biscuit, &revoked_auth.raw_biscuit)
+            .await
+            .expect("introspection failed");
+        let foreign = app
+            .api_client
+            .introspect(&auth.raw_biscuit, b"not a biscuit")
+            .await
+            .expect("introspection failed");
+
+        // Assert
+        assert!(!revoked.active);
+        assert!(revoked.biscuit_info.is_some());
+        assert!(revoked.item_rights.is_empty());
+        assert!(!foreign.active);
+        assert!(foreign.biscuit_info.is_none());
+    }
+
+    #[tokio::test]
+    async fn app_service_does_not_introspect_tokens_outside_its_app() {
+        // Arrange
+        let mut app = spawn_app().await;
+        let admin = TestUser::generate(&app)
+            .await
+            .expect("error when generating test user");
+        let admin_auth = admin
+            .login(&mut app.api_client, None)
+            .await
+            .expect("login failed");
+        let app_id = app
+            .api_client
+            .create_app(&admin_auth.raw_biscuit, "secure app")
+            .await
+            .expect("app creation failed");
+        let credentials = app
+            .api_client
+            .create_app_credentials(&admin_auth.raw_biscuit, app_id)
+            .await
+            .expect("credentials creation failed");
+        let app_service_auth = app
+            .api_client
+            .login_app_credentials(&LoginAppCredentialsData {
+                credentials_id: credentials.id,
+                secret: credentials.secret.clone(),
+            })
+            .await
+            .expect("app service login failed");
+
+        // Act
+        let introspection = app
+            .api_client
+            .introspect(&app_service_auth.raw_biscuit, &admin_auth.raw_biscuit)
+            .await;
+
+        // Assert
+        introspection.expect_err("Admin tokens are outside of the app of the service.");
+    }
+
+    #[tokio::test]
+    async fn tokens_past_their_attenuated_expiration_are_inactive() {
+        // Arrange
+        let mut app = spawn_app().await;
+        let user = TestUser::generate(&app)
+            .await
+            .expect("error when generating test user");
+        let admin_auth = user
+            .login(&mut app.api_client, None)
+            .await
+            .expect("login failed");
+        let app_id = app
+            .api_client
+            .create_app(&admin_auth.raw_biscuit, "expiring app")
+            .await
+            .expect("app creation failed");
+        app.api_client
+            .create_item(&admin_auth.raw_biscuit, app_id, "gold")
+            .await
+            .expect("item creation failed");
+        let auth = user
+            .login(&mut app.api_client, Some(app_id))
+            .await
+            .expect("login failed");
+        let expired = app
+            .api_client
+            .attenuate(
+                &auth.raw_biscuit,
+                &Attenuation {
+                    expiration_date_unix_timestamp: Some(
+                        (OffsetDateTime::now_utc() - Duration::minutes(1)).unix_timestamp() as u64,
+                    ),
+                    ..Default::default()
+                },
+            )
+            .await
+            .expect("attenuation failed");
+
+        // Act
+        let introspection = app
+            .api_client
+            .introspect(&auth.raw_biscuit, &expired)
+            .await
+            .expect("introspection failed");
+
+        // Assert
+        assert!(!introspection.active);
+        assert_eq!(introspection.expires_in_seconds, 0);
+        assert!(introspection.item_rights.is_empty());
+    }
+
+    #[tokio::test]
+    async fn tokens_expire_with_their_earliest_attenuation() {
+        // Arrange
+        let mut app = spawn_app().await;
+        let user = TestUser::generate(&app)
+            .await
+            .expect("error when generating test user");
+        let admin_auth = user
+            .login(&mut app.api_client, None)
+            .await
+            .expect("login failed");
+        let app_id = app
+            .api_client
+            .create_app(&admin_auth.raw_biscuit, "expiring app")
+            .await
+            .expect("app creation failed");
+        app.api_client
+            .create_item(&admin_auth.raw_biscuit, app_id, "gold")
+            .await
+            .expect("item creation failed");
+        let auth = user
+            .login(&mut app.api_client, Some(app_id))
+            .await
+            .expect("login failed");
+        let expiring_in = |minutes: i64| Attenuation {
+            expiration_date_unix_timestamp: Some(
+                (OffsetDateTime::now_utc() + Duration::minutes(minutes)).unix_timestamp() as u64,
+            ),
+            ..Default::default()
+        };
+        let attenuated = app
+            .api_client
+            .attenuate(&auth.raw_biscuit, &expiring_in(1))
+            .await
+            .expect("attenuation failed");
+        let attenuated = app
+            .api_client
+            .attenuate(&attenuated, &expiring_in(2))
+            .await
+            .expect("attenuation failed");
+
+        // Act
+        let introspection = app
+            .api_client
+            .introspect(&auth.raw_biscuit, &attenuated)
+            .await
+            .expect("introspection failed");
+
+        // Assert
+        assert!(introspection.active);
+        assert!((1..=60).contains(&introspection.expires_in_seconds));
+    }
+
+    #[tokio::test]
+    async fn admin_item_rights_are_those_on_their_own_items() {
+        // Arrange
+        let mut app = spawn_app().await;
+        let admin = TestUser::generate(&app)
+            .await
+            .expect("error when generating test user");
+        let admin_auth = admin
+            .login(&mut app.api_client, None)
+            .await
+            .expect("login failed");
+        let app_id = app
+            .api_client
+            .create_app(&admin_auth.raw_biscuit, "administered app")
+            .await
+            .expect("app creation failed");
+        let item_id = app
+            .api_client
+            .create_item(&admin_auth.raw_biscuit, app_id, "gold")
+            .await
+            .expect("item creation failed");
+
+        // Act
+        let introspection = app
+            .api_client
+            .introspect(&admin_auth.raw_biscuit, &admin_auth.raw_biscuit)
+            .await
+            .expect("introspection failed");
+
+        // Assert
+        assert!(introspection.active);
+        assert_eq!(
+            introspection.item_rights,
+            vec![ItemRights {
+                item_id,
+                read: true,
+                increase: true,
+                decrease: true,
+                send: true,
+            }]
+        );
+    }
+}
@@ -0,0 +1,160 @@
+mod helper;
+#[cfg(test)]
+mod tests {
+
+    use backpack_client::{
+        shared::{ItemAmountError, ItemBounds},
+        ModifyItemOptions, RequestError,
+    };
+
+    use crate::helper::{spawn_app, TestUser};
+
+    #[tokio::test]
+    async fn modify_item_respects_item_bounds() {
+        // Arrange
+        let mut app = spawn_app().await;
+        let admin = TestUser::generate(&app)
+            .await
+            .expect("error when generating test user");
+        let admin_auth = admin
+            .login(&mut app.api_client, None)
+            .await
+            .expect("login failed");
+        let app_id = app
+            .api_client
+            .create_app(&admin_auth.raw_biscuit, "mini-game")
+            .await
+            .expect("app creation failed");
+        let item_id = app
+            .api_client
+            .create_item(&admin_auth.raw_biscuit, app_id, "potions")
+            .await
+            .expect("item creation failed");
+        let user = TestUser::generate(&app)
+            .await
+            .expect("error when generating test user");
+        let user_auth = user
+            .login(&mut app.api_client, Some(app_id))
+            .await
+            .expect("login failed");
+        let user_id = user_auth.biscuit_info.user_id;
+
+        // Act
+        app.api_client
+            .set_item_bounds(
+                &admin_auth.raw_biscuit,
+                item_id,
+                &ItemBounds {
+                    min_amount: 0,
+                    max_amount: Some(10),
+                    max_change: Some(5),
+                },
+            )
+            .await
+            .expect("setting item bounds failed");
+        let too_large = app
+            .api_client
+            .modify_item(
+                &user_auth.raw_biscuit,
+                item_id,
+                6,
+                user_id,
+                ModifyItemOptions::default(),
+            )
+            .await;
+        app.api_client
+            .modify_item(
+                &user_auth.raw_biscuit,
+                item_id,
+                5,
+                user_id,
+                ModifyItemOptions::default(),
+            )
+            .await
+            .expect("modifying item failed");
+        app.api_client
+            .modify_item(
+                &user_auth.raw_biscuit,
+                item_id,
+                4,
+                user_id,
+                ModifyItemOptions::default(),
+            )
+            .await
+            .expect("modifying item failed");
+        let above_maximum = app
+            .api_client
+            .modify_item(
+                &user_auth.raw_biscuit,
+                item_id,
+                2,
+                user_id,
+                ModifyItemOptions::default(),
+            )
+            .await;
+
+        // Assert
+        assert!(matches!(
+            too_large,
+            Err(RequestError::ItemAmountError(
+                ItemAmountError::ChangeTooLarge { max_change: 5 }
+            ))
+        ));
+        assert!(matches!(
+            above_maximum,
+            Err(RequestError::ItemAmountError(
+                ItemAmountError::MaximumExceeded {
+                    balance: 9,
+                    max_amount: 10,
+                    ..
+                }
+            ))
+        ));
+        let items = app
+            .api_client
+            .get_items(&user_auth.raw_biscuit, &user_id)
+            .await
+            .expect("getting items failed");
+        assert_eq!(items[0].amount, 9);
+    }
+
+    #[tokio::test]
+    async fn set_item_bounds_refuses_invalid_bounds() {
+        // Arrange
+        let mut app = spawn_app().await;
+        let admin = TestUser::generate(&app)
+            .await
+            .expect("error when generating test user");
+        let admin_auth = admin
+            .login(&mut app.api_client, None)
+            .await
+            .expect("login failed");
+        let app_id = app
+            .api_client
+            .create_app(&admin_auth.raw_biscuit, "mini-game")
+            .await
+            .expect("app creation failed");
+        let item_id = app
+            .api_client
+            .create_item(&admin_auth.raw_biscuit, app_id, "potions")
+            .await
+            .expect("item creation failed");
+
+        // Act
+        let result = app
+            .api_client
+            .set_item_bounds(
+                &admin_auth.raw_biscuit,
+                item_id,
+                &ItemBounds {
+                    min_amount: 5,
+                    max_amount: Some(2),
+                    max_change: None,
+                },
+            )
+            .await;
+
+        // Assert
+        assert!(result.is_err());
+    }
+}
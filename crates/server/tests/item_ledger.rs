@@ -0,0 +1,118 @@
+mod helper;
+#[cfg(test)]
+mod tests {
+
+    use backpack_client::{ModifyItemOptions, SendItemOptions};
+
+    use crate::helper::{spawn_app, TestUser};
+
+    #[tokio::test]
+    async fn item_changes_are_recorded_in_history() {
+        // Arrange
+        let mut app = spawn_app().await;
+        let admin = TestUser::generate(&app)
+            .await
+            .expect("error when generating test user");
+        let admin_auth = admin
+            .login(&mut app.api_client, None)
+            .await
+            .expect("login failed");
+        let app_id = app
+            .api_client
+            .create_app(&admin_auth.raw_biscuit, "mini-game")
+            .await
+            .expect("app creation failed");
+        let item_id = app
+            .api_client
+            .create_item(&admin_auth.raw_biscuit, app_id, "coins")
+            .await
+            .expect("item creation failed");
+        let sender = TestUser::generate(&app)
+            .await
+            .expect("error when generating test user");
+        let sender_auth = sender
+            .login(&mut app.api_client, Some(app_id))
+            .await
+            .expect("login failed");
+        let sender_id = sender_auth.biscuit_info.user_id;
+        let receiver = TestUser::generate(&app)
+            .await
+            .expect("error when generating test user");
+        let receiver_auth = receiver
+            .login(&mut app.api_client, Some(app_id))
+            .await
+            .expect("login failed");
+        let receiver_id = receiver_auth.biscuit_info.user_id;
+
+        // Act
+        app.api_client
+            .modify_item(
+                &sender_auth.raw_biscuit,
+                item_id,
+                5,
+                sender_id,
+                ModifyItemOptions {
+                    reason: Some("quest reward"),
+                    ..Default::default()
+                },
+            )
+            .await
+            .expect("modifying item failed");
+        app.api_client
+            .send_item(
+                &sender_auth.raw_biscuit,
+                item_id,
+                2,
+                sender_id,
+                receiver_id,
+                SendItemOptions {
+                    reason: Some("gift"),
+                },
+            )
+            .await
+            .expect("sending item failed");
+
+        // Assert
+        let first_page = app
+            .api_client
+            .get_user_history(&sender_auth.raw_biscuit, &sender_id, None, Some(1))
+            .await
+            .expect("getting history failed");
+        assert_eq!(first_page.entries.len(), 1);
+        let sent = &first_page.entries[0];
+        assert_eq!(sent.delta, -2);
+        assert_eq!(sent.balance, 3);
+        assert_eq!(sent.reason.as_deref(), Some("gift"));
+        assert_eq!(sent.actor_user_id, sender_id);
+        assert_eq!(sent.app_id, Some(app_id));
+        let second_page = app
+            .api_client
+            .get_user_history(
+                &sender_auth.raw_biscuit,
+                &sender_id,
+                first_page.next_before,
+                Some(1),
+            )
+            .await
+            .expect("getting history failed");
+        assert_eq!(second_page.entries.len(), 1);
+        assert_eq!(second_page.entries[0].delta, 5);
+        assert_eq!(
+            second_page.entries[0].reason.as_deref(),
+            Some("quest reward")
+        );
+        let item_history = app
+            .api_client
+            .get_item_history(&admin_auth.raw_biscuit, item_id, None, None)
+            .await
+            .expect("getting history failed");
+        assert_eq!(item_history.entries.len(), 3);
+        assert_eq!(item_history.entries[0].user_id, receiver_id);
+        assert_eq!(item_history.entries[0].balance, 2);
+        assert_eq!(item_history.next_before, None);
+        app.api_client
+            .get_user_history(&receiver_auth.raw_biscuit, &sender_id, None, None)
+            .await
+            .expect_err("users should only see their own history.");
+    }
+}
@@ -0,0 +1,71 @@
+mod helper;
+#[cfg(test)]
+mod tests {
+
+    use backpack_client::ModifyItemOptions;
+
+    use crate::helper::{spawn_app, TestUser};
+
+    #[tokio::test]
+    async fn items_created_after_login_are_looked_up() {
+        // Arrange
+        let mut app = spawn_app().await;
+        let admin = TestUser::generate(&app)
+            .await
+            .expect("error when generating test user");
+        let admin_auth = admin
+            .login(&mut app.api_client, None)
+            .await
+            .expect("login failed");
+        let app_id = app
+            .api_client
+            .create_app(&admin_auth.raw_biscuit, "mini-game")
+            .await
+            .expect("app creation failed");
+        let baked_item_id = app
+            .api_client
+            .create_item(&admin_auth.raw_biscuit, app_id, "coins")
+            .await
+            .expect("item creation failed");
+        let player = TestUser::generate(&app)
+            .await
+            .expect("error when generating test user");
+        let player_auth = player
+            .login(&mut app.api_client, Some(app_id))
+            .await
+            .expect("login failed");
+        let new_item_id = app
+            .api_client
+            .create_item(&admin_auth.raw_biscuit, app_id, "gems")
+            .await
+            .expect("item creation failed");
+
+        // Act
+        let baked_amount = app
+            .api_client
+            .modify_item(
+                &player_auth.raw_biscuit,
+                baked_item_id,
+                2,
+                player_auth.biscuit_info.user_id,
+                ModifyItemOptions::default(),
+            )
+            .await
+            .expect("modifying an item baked in the biscuit failed");
+        let new_amount = app
+            .api_client
+            .modify_item(
+                &player_auth.raw_biscuit,
+                new_item_id,
+                3,
+                player_auth.biscuit_info.user_id,
+                ModifyItemOptions::default(),
+            )
+            .await
+            .expect("modifying an item created after login failed");
+
+        // Assert
+        assert_eq!(baked_amount, 2);
+        assert_eq!(new_amount, 3);
+    }
+}
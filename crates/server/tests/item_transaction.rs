@@ -0,0 +1,106 @@
+mod helper;
+#[cfg(test)]
+mod tests {
+
+    use backpack_client::{
+        shared::{ItemAmountError, ItemTransaction, ItemTransactionOperation},
+        ModifyItemOptions, RequestError,
+    };
+
+    use crate::helper::{spawn_app, TestUser};
+
+    #[tokio::test]
+    async fn item_transaction_is_all_or_nothing() {
+        // Arrange
+        let mut app = spawn_app().await;
+        let admin = TestUser::generate(&app)
+            .await
+            .expect("error when generating test user");
+        let admin_auth = admin
+            .login(&mut app.api_client, None)
+            .await
+            .expect("login failed");
+        let app_id = app
+            .api_client
+            .create_app(&admin_auth.raw_biscuit, "mini-game")
+            .await
+            .expect("app creation failed");
+        let coins = app
+            .api_client
+            .create_item(&admin_auth.raw_biscuit, app_id, "coins")
+            .await
+            .expect("item creation failed");
+        let sword = app
+            .api_client
+            .create_item(&admin_auth.raw_biscuit, app_id, "sword")
+            .await
+            .expect("item creation failed");
+        let user = TestUser::generate(&app)
+            .await
+            .expect("error when generating test user");
+        let auth = user
+            .login(&mut app.api_client, Some(app_id))
+            .await
+            .expect("login failed");
+        let user_id = auth.biscuit_info.user_id;
+        app.api_client
+            .modify_item(
+                &auth.raw_biscuit,
+                coins,
+                5,
+                user_id,
+                ModifyItemOptions::default(),
+            )
+            .await
+            .expect("modifying item failed");
+        let buy_sword = ItemTransaction {
+            operations: vec![
+                ItemTransactionOperation {
+                    item_id: coins,
+                    user_id,
+                    amount: -3,
+                },
+                ItemTransactionOperation {
+                    item_id: sword,
+                    user_id,
+                    amount: 1,
+                },
+            ],
+            reason: Some("buy sword".to_string()),
+            idempotency_key: None,
+        };
+
+        // Act
+        let new_amounts = app
+            .api_client
+            .apply_item_transaction(&auth.raw_biscuit, &buy_sword)
+            .await
+            .expect("transaction failed");
+        let refused = app
+            .api_client
+            .apply_item_transaction(&auth.raw_biscuit, &buy_sword)
+            .await;
+
+        // Assert
+        assert_eq!(new_amounts, vec![2, 1]);
+        assert!(matches!(
+            refused,
+            Err(RequestError::ItemAmountError(
+                ItemAmountError::InsufficientBalance { balance: 2, .. }
+            ))
+        ));
+        let items = app
+            .api_client
+            .get_items(&auth.raw_biscuit, &user_id)
+            .await
+            .expect("getting items failed");
+        let amount_of = |item_id| {
+            items
+                .iter()
+                .find(|item| item.item.id == item_id)
+                .map(|item| item.amount)
+        };
+        assert_eq!(amount_of(coins), Some(2));
+        assert_eq!(amount_of(sword), Some(1));
+    }
+}
@@ -0,0 +1,241 @@
+mod helper;
+#[cfg(test)]
+mod tests {
+
+    use std::net::TcpListener;
+
+    use actix_web::{web, App, HttpResponse, HttpServer};
+    use serde_json::json;
+
+    use backpack_client::shared::{CreateGuestData, GuestLoginData, LoginMethod};
+
+    use crate::helper::{spawn_app_with_settings, TestUser};
+
+    /// Mimics github oauth and api routes.
+    fn spawn_mock_github() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("Failed to bind random port");
+        let port = listener.local_addr().unwrap().port();
+        let server = HttpServer::new(|| {
+            App::new()
+                .route(
+                    "/login/oauth/access_token",
+                    web::post().to(|| async {
+                        HttpResponse::Ok().json(json!({ "access_token": "mock_access_token" }))
+                    }),
+                )
+                .route(
+                    "/user",
+                    web::get().to(|| async {
+                        HttpResponse::Ok().json(json!({ "id": 42, "login": "octocat" }))
+                    }),
+                )
+        })
+        .listen(listener)
+        .expect("Failed to bind address")
+        .run();
+        drop(tokio::spawn(server));
+        format!("http://127.0.0.1:{port}")
+    }
+
+    #[tokio::test]
+    async fn oauth_login_creates_then_finds_user() {
+        // Arrange
+        let mock_github_url = spawn_mock_github();
+        let app = spawn_app_with_settings(|settings| {
+            settings.github_admin_app.client_id = "mock_client_id".to_string();
+            settings.github_admin_app.oauth_url = mock_github_url.clone();
+            settings.github_admin_app.api_url = mock_github_url.clone();
+        })
+        .await;
+
+        // Act
+        let first_login = app
+            .api_client
+            .login_oauth("github", "mock_code", "mock_state", None)
+            .await
+            .expect("first oauth login failed");
+        let second_login = app
+            .api_client
+            .login_oauth("github", "mock_code", "mock_state", None)
+            .await
+            .expect("second oauth login failed");
+
+        // Assert
+        assert_eq!(
+            first_login.biscuit_info.user_id,
+            second_login.biscuit_info.user_id
+        );
+        app.api_client
+            .login_oauth("unknown_provider", "mock_code", "mock_state", None)
+            .await
+            .expect_err("unknown provider should not authenticate.");
+    }
+
+    #[tokio::test]
+    async fn guest_upgrades_with_oauth_identity() {
+        // Arrange
+        let mock_github_url = spawn_mock_github();
+        let mut app = spawn_app_with_settings(|settings| {
+            settings.github_admin_app.client_id = "mock_client_id".to_string();
+            settings.github_admin_app.oauth_url = mock_github_url.clone();
+            settings.github_admin_app.api_url = mock_github_url.clone();
+        })
+        .await;
+        let admin = TestUser::generate(&app)
+            .await
+            .expect("error when generating test user");
+        let admin_auth = admin
+            .login(&mut app.api_client, None)
+            .await
+            .expect("login failed");
+        let app_id = app
+            .api_client
+            .create_app(&admin_auth.raw_biscuit, "guest friendly app")
+            .await
+            .expect("app creation failed");
+        let signup = app
+            .api_client
+            .signup_guest(&CreateGuestData { app_id })
+            .await
+            .expect("guest signup failed");
+        let guest_auth = signup.authentication_token;
+
+        // Act
+        app.api_client
+            .upgrade_guest_oauth(&guest_auth.raw_biscuit, "github", "mock_code")
+            .await
+            .expect("guest upgrade failed");
+
+        // Assert
+        let oauth_login = app
+            .api_client
+            .login_oauth("github", "mock_code", "mock_state", Some(app_id))
+            .await
+            .expect("oauth login failed");
+        assert_eq!(
+            oauth_login.biscuit_info.user_id,
+            guest_auth.biscuit_info.user_id
+        );
+        app.api_client
+            .login_guest(&GuestLoginData {
+                device_secret: signup.device_secret,
+            })
+            .await
+            .expect_err("device secret should not be valid after upgrade.");
+        let other_guest = app
+            .api_client
+            .signup_guest(&CreateGuestData { app_id })
+            .await
+            .expect("guest signup failed");
+        app.api_client
+            .upgrade_guest_oauth(
+                &other_guest.authentication_token.raw_biscuit,
+                "github",
+                "mock_code",
+            )
+            .await
+            .expect_err("an identity should not be attached to two users.");
+    }
+
+    #[tokio::test]
+    async fn user_attaches_and_detaches_oauth_identity() {
+        // Arrange
+        let mock_github_url = spawn_mock_github();
+        let mut app = spawn_app_with_settings(|settings| {
+            settings.github_admin_app.client_id = "mock_client_id".to_string();
+            settings.github_admin_app.oauth_url = mock_github_url.clone();
+            settings.github_admin_app.api_url = mock_github_url.clone();
+        })
+        .await;
+        let user = TestUser::generate(&app)
+            .await
+            .expect("error when generating test user");
+        let auth = user
+            .login(&mut app.api_client, None)
+            .await
+            .expect("login failed");
+
+        // Act
+        app.api_client
+            .attach_oauth(&auth.raw_biscuit, "github", "mock_code")
+            .await
+            .expect("attaching identity failed");
+
+        // Assert
+        let login_methods = app
+            .api_client
+            .get_login_methods(&auth.raw_biscuit)
+            .await
+            .expect("getting login methods failed");
+        assert!(login_methods.contains(&LoginMethod::Oauth {
+            provider: "github".to_string(),
+            external_id: "42".to_string(),
+            login: "octocat".to_string(),
+        }));
+        let oauth_login = app
+            .api_client
+            .login_oauth("github", "mock_code", "mock_state", None)
+            .await
+            .expect("oauth login failed");
+        assert_eq!(oauth_login.biscuit_info.user_id, auth.biscuit_info.user_id);
+        app.api_client
+            .detach_oauth(&auth.raw_biscuit, "github", "42")
+            .await
+            .expect("detaching identity failed");
+        let login_methods = app
+            .api_client
+            .get_login_methods(&auth.raw_biscuit)
+            .await
+            .expect("getting login methods failed");
+        assert_eq!(login_methods.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn oauth_callback_refuses_state_of_another_client() {
+        // Arrange
+        let mock_github_url = spawn_mock_github();
+        let app = spawn_app_with_settings(|settings| {
+            settings.github_admin_app.client_id = "mock_client_id".to_string();
+            settings.github_admin_app.oauth_url = mock_github_url.clone();
+            settings.github_admin_app.api_url = mock_github_url.clone();
+        })
+        .await;
+        let client = reqwest::Client::builder()
+            .redirect(reqwest::redirect::Policy::none())
+            .build()
+            .unwrap();
+
+        // Act
+        let authorize = client
+            .get(format!(
+                "{}/authentication/oauth/github/authorize",
+                app.api_client.get_url()
+            ))
+            .send()
+            .await
+            .expect("authorize request failed");
+        let state_cookie = authorize
+            .cookies()
+            .find(|cookie| cookie.name() == "oauth_state")
+            .expect("authorize should set the state cookie")
+            .value()
+            .to_string();
+        let callback = client
+            .get(format!(
+                "{}/authentication/oauth/github/callback?code=mock_code&state=attacker_state",
+                app.api_client.get_url()
+            ))
+            .header("Cookie", format!("oauth_state={state_cookie}"))
+            .send()
+            .await
+            .expect("callback request failed");
+
+        // Assert
+        assert_eq!(authorize.status(), 302);
+        assert!(authorize.headers()["Location"]
+            .to_str()
+            .unwrap()
+            .ends_with(&format!("state={state_cookie}")));
+        assert_eq!(callback.status(), 401);
+    }
+}
@@ -0,0 +1,165 @@
+mod helper;
+#[cfg(test)]
+mod tests {
+
+    use backpack_client::shared::{ChangePasswordData, ForgotPasswordData, ResetPasswordData};
+
+    use crate::helper::{spawn_app, TestApp, TestUser};
+
+    /// Reset token sent to this email, waiting for it as it is sent after answering.
+    async fn sent_reset_token(app: &TestApp, email: &str) -> Option<String> {
+        for _ in 0..50 {
+            let emails = app.sent_emails();
+            let reset_email = emails.split("From: ").find(|sent| {
+                sent.contains(&format!("To: {email}\n")) && sent.contains("Your reset token is ")
+            });
+            if let Some((_, reset_token)) =
+                reset_email.and_then(|sent| sent.split_once("Your reset token is "))
+            {
+                return reset_token
+                    .split_once(',')
+                    .map(|(reset_token, _)| reset_token.to_string());
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        }
+        None
+    }
+
+    #[tokio::test]
+    async fn change_password() {
+        // Arrange
+        let mut app = spawn_app().await;
+        let mut user = TestUser::generate(&app)
+            .await
+            .expect("error when generating test user");
+        let auth_info = user
+            .login(&mut app.api_client, None)
+            .await
+            .expect("login failed");
+
+        // Act
+        app.api_client
+            .change_password(
+                &auth_info.raw_biscuit,
+                &ChangePasswordData {
+                    old_password: "wrong password".to_string(),
+                    new_password: "new password".to_string(),
+                },
+            )
+            .await
+            .expect_err("Password should not change without the old password.");
+        app.api_client
+            .change_password(
+                &auth_info.raw_biscuit,
+                &ChangePasswordData {
+                    old_password: user.password.clone(),
+                    new_password: "new password".to_string(),
+                },
+            )
+            .await
+            .expect("Password change failed.");
+
+        // Assert
+        user.login(&mut app.api_client, None)
+            .await
+            .expect_err("Old password should not work anymore.");
+        user.password = "new password".to_string();
+        user.login(&mut app.api_client, None)
+            .await
+            .expect("New password should work.");
+    }
+
+    #[tokio::test]
+    async fn reset_password_revokes_refresh_tokens() {
+        // Arrange
+        let mut app = spawn_app().await;
+        let mut user = TestUser::generate(&app)
+            .await
+            .expect("error when generating test user");
+        let auth_info = user
+            .login(&mut app.api_client, None)
+            .await
+            .expect("login failed");
+
+        // Act
+        app.api_client
+            .forgot_password(&ForgotPasswordData {
+                email: user.email.clone(),
+            })
+            .await
+            .expect("forgot password failed");
+        let reset_token = sent_reset_token(&app, &user.email)
+            .await
+            .expect("reset email should contain a reset token");
+        let reset_data = ResetPasswordData {
+            reset_token,
+            new_password: "new password".to_string(),
+        };
+        app.api_client
+            .reset_password(&reset_data)
+            .await
+            .expect("password reset failed");
+
+        // Assert
+        app.api_client
+            .reset_password(&reset_data)
+            .await
+            .expect_err("A reset token should only be used once.");
+        app.api_client
+            .refresh(&auth_info.raw_biscuit, &auth_info.refresh_token)
+            .await
+            .expect_err("Refresh tokens should be revoked after a password reset.");
+        user.password = "new password".to_string();
+        user.login(&mut app.api_client, None)
+            .await
+            .expect("New password should work.");
+    }
+
+    #[tokio::test]
+    async fn forgot_password_does_not_reveal_unknown_emails() {
+        let app = spawn_app().await;
+        app.api_client
+            .forgot_password(&ForgotPasswordData {
+                email: "unknown@example.com".to_string(),
+            })
+            .await
+            .expect("Unknown emails should not be distinguishable.");
+        assert!(sent_reset_token(&app, "unknown@example.com")
+            .await
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn reset_tokens_are_stored_hashed() {
+        // Arrange
+        let app = spawn_app().await;
+        let user = TestUser::generate(&app)
+            .await
+            .expect("error when generating test user");
+
+        // Act
+        app.api_client
+            .forgot_password(&ForgotPasswordData {
+                email: user.email.clone(),
+            })
+            .await
+            .expect("forgot password failed");
+        let reset_token = sent_reset_token(&app, &user.email)
+            .await
+            .expect("reset email should contain a reset token");
+
+        // Assert
+        let (stored,): (String,) = sqlx::query_as(
+            r#"
+            SELECT reset_token_hash FROM password_reset_tokens
+            JOIN users_email_password ON users_email_password.id = email_password_id
+            WHERE email = $1
+            "#,
+        )
+        .bind(&user.email)
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("failed to fetch reset token");
+        assert_ne!(stored, reset_token);
+    }
+}
@@ -0,0 +1,379 @@
+mod helper;
+#[cfg(test)]
+mod tests {
+
+    use backpack_client::{
+        shared::{ForgotPasswordData, LoginEmailPasswordData},
+        RequestError,
+    };
+    use backpack_server::configuration::{LoginLockoutSettings, RequestLimit};
+    use time::OffsetDateTime;
+
+    use crate::helper::{spawn_app_with_settings, TestUser};
+
+    #[tokio::test]
+    async fn requests_over_the_ip_limit_are_refused_until_the_period_ends() {
+        // Arrange
+        let app = spawn_app_with_settings(|settings| {
+            settings.rate_limit.per_ip = RequestLimit {
+                max_requests: 3,
+                period_seconds: 60,
+            };
+        })
+        .await;
+        for _ in 0..3 {
+            app.api_client
+                .get_public_keys()
+                .await
+                .expect("Requests under the limit should succeed.");
+        }
+
+        // Act
+        let limited = app.api_client.get_public_keys().await;
+
+        // Assert
+        assert!(matches!(
+            limited,
+            Err(RequestError::TooManyRequests {
+                retry_after_seconds: Some(1..=60)
+            })
+        ));
+        app.settings
+            .time
+            .clone()
+            .set_override(OffsetDateTime::now_utc().checked_add(time::Duration::seconds(61)));
+        app.api_client
+            .get_public_keys()
+            .await
+            .expect("Requests should succeed after the period.");
+    }
+
+    #[tokio::test]
+    async fn requests_over_the_email_limit_are_refused() {
+        // Arrange
+        let app = spawn_app_with_settings(|settings| {
+            settings.rate_limit.per_email = RequestLimit {
+                max_requests: 2,
+                period_seconds: 60,
+            };
+        })
+        .await;
+        let forgot_password = |email: &str| ForgotPasswordData {
+            email: email.to_string(),
+        };
+        for _ in 0..2 {
+            app.api_client
+                .forgot_password(&forgot_password("spammed@example.com"))
+                .await
+                .expect("Requests under the limit should succeed.");
+        }
+
+        // Act
+        let limited = app
+            .api_client
+            .forgot_password(&forgot_password("Spammed@example.com"))
+            .await;
+
+        // Assert
+        assert!(matches!(limited, Err(RequestError::TooManyRequests { .. })));
+        app.api_client
+            .forgot_password(&forgot_password("other@example.com"))
+            .await
+            .expect("Other emails should not be limited.");
+    }
+
+    #[tokio::test]
+    async fn repeated_bad_passwords_lock_out_logins_progressively() {
+        // Arrange
+        let mut app = spawn_app_with_settings(|settings| {
+            settings.rate_limit.login_lockout = LoginLockoutSettings {
+                failures_before_lockout: 3,
+                base_lockout_seconds: 30,
+                max_lockout_seconds: 3600,
+            };
+        })
+        .await;
+        let user = TestUser::generate(&app)
+            .await
+            .expect("error when generating test user");
+        let bad_login = LoginEmailPasswordData {
+            email: user.email.clone(),
+            password_plain: "wrong password".to_string(),
+            as_app_user: None,
+        };
+        let mut time = app.settings.time.clone();
+        let start = OffsetDateTime::now_utc();
+        time.set_override(Some(start));
+
+        // Act
+        for _ in 0..3 {
+            app.api_client
+                .login(&bad_login)
+                .await
+                .expect_err("Bad passwords should be refused.");
+        }
+
+        // Assert
+        assert!(matches!(
+            user.login(&mut app.api_client, None).await,
+            Err(RequestError::TooManyRequests {
+                retry_after_seconds: Some(1..=30)
+            })
+        ));
+        time.set_override(Some(start + time::Duration::seconds(31)));
+        app.api_client
+            .login(&bad_login)
+            .await
+            .expect_err("Bad passwords should be refused.");
+        assert!(matches!(
+            user.login(&mut app.api_client, None).await,
+            Err(RequestError::TooManyRequests {
+                retry_after_seconds: Some(31..=60)
+            })
+        ));
+        time.set_override(Some(start + time::Duration::seconds(31 + 61)));
+        user.login(&mut app.api_client, None)
+            .await
+            .expect("Login should succeed after the lockout.");
+    }
+
+    #[tokio::test]
+    async fn forged_forwarded_for_entries_do_not_escape_the_ip_limit() {
+        // Arrange
+        let app = spawn_app_with_settings(|settings| {
+            settings.rate_limit.behind_proxy = true;
+            settings.rate_limit.per_ip = RequestLimit {
+                max_requests: 2,
+                period_seconds: 60,
+            };
+        })
+        .await;
+        let client = reqwest::Client::new();
+        let url = format!("{}/authentication/public_keys", app.api_client.get_url());
+        // Each request claims another client ip, behind the same proxy hop.
+        let request = |forged: u8| {
+            client
+                .get(&url)
+                .header(
+                    "X-Forwarded-For",
+                    format!("203.0.113.{forged}, 198.51.100.7"),
+                )
+                .send()
+        };
+        for forged in 0..2 {
+            let response = request(forged).await.expect("request failed");
+            assert!(response.status().is_success());
+        }
+
+        // Act
+        let limited = request(2).await.expect("request failed");
+
+        // Assert
+        assert_eq!(limited.status(), reqwest::StatusCode::TOO_MANY_REQUESTS);
+    }
+
+    #[tokio::test]
+    async fn unauthenticated_requests_do_not_use_the_app_limit() {
+        // Arrange
+        let mut app = spawn_app_with_settings(|settings| {
+            settings.rate_limit.per_app = RequestLimit {
+                max_requests: 2,
+                period_seconds: 60,
+            };
+        })
+        .await;
+        let user = TestUser::generate(&app)
+            .await
+            .expect("error when generating test user");
+        let admin_auth = user
+            .login(&mut app.api_client, None)
+            .await
+            .expect("login failed");
+        let app_id = app
+            .api_client
+            .create_app(&admin_auth.raw_biscuit, "popular app")
+            .await
+            .expect("app creation failed");
+        let app_auth = user
+            .login(&mut app.api_client, Some(app_id))
+            .await
+            .expect("login failed");
+
+        // Act
+        for _ in 0..3 {
+            app.api_client
+                .login(&LoginEmailPasswordData {
+                    email: "nobody@example.com".to_string(),
+                    password_plain: "wrong password".to_string(),
+                    as_app_user: Some(app_id),
+                })
+                .await
+                .expect_err("Unknown users should be refused.");
+        }
+
+        // Assert
+        for _ in 0..2 {
+            app.api_client
+                .whoami(&app_auth.raw_biscuit)
+                .await
+                .expect("Requests of the app should not be limited by others.");
+        }
+    }
+
+    #[tokio::test]
+    async fn lockouts_do_not_lock_the_user_out_from_other_ips() {
+        // Arrange
+        let app = spawn_app_with_settings(|settings| {
+            settings.rate_limit.behind_proxy = true;
+            settings.rate_limit.login_lockout = LoginLockoutSettings {
+                failures_before_lockout: 3,
+                base_lockout_seconds: 30,
+                max_lockout_seconds: 3600,
+            };
+        })
+        .await;
+        let user = TestUser::generate(&app)
+            .await
+            .expect("error when generating test user");
+        let client = reqwest::Client::new();
+        let url = format!(
+            "{}/authentication/email_password/login",
+            app.api_client.get_url()
+        );
+        let login = |password: &str, ip: &str| {
+            client
+                .post(&url)
+                .header("X-Forwarded-For", ip)
+                .json(&LoginEmailPasswordData {
+                    email: user.email.clone(),
+                    password_plain: password.to_string(),
+                    as_app_user: None,
+                })
+                .send()
+        };
+
+        // Act
+        for _ in 0..3 {
+            let response = login("wrong password", "203.0.113.1")
+                .await
+                .expect("request failed");
+            assert_eq!(response.status(), reqwest::StatusCode::UNAUTHORIZED);
+        }
+
+        // Assert
+        let locked = login(&user.password, "203.0.113.1")
+            .await
+            .expect("request failed");
+        assert_eq!(locked.status(), reqwest::StatusCode::TOO_MANY_REQUESTS);
+        let other_ip = login(&user.password, "198.51.100.7")
+            .await
+            .expect("request failed");
+        assert!(other_ip.status().is_success());
+    }
+
+    #[tokio::test]
+    async fn email_requests_from_other_ips_do_not_block_logins() {
+        // Arrange
+        let app = spawn_app_with_settings(|settings| {
+            settings.rate_limit.behind_proxy = true;
+            settings.rate_limit.per_email = RequestLimit {
+                max_requests: 2,
+                period_seconds: 60,
+            };
+        })
+        .await;
+        let user = TestUser::generate(&app)
+            .await
+            .expect("error when generating test user");
+        let client = reqwest::Client::new();
+        let url = format!(
+            "{}/authentication/email_password/login",
+            app.api_client.get_url()
+        );
+        let login = |password: &str, ip: &str| {
+            client
+                .post(&url)
+                .header("X-Forwarded-For", ip)
+                .json(&LoginEmailPasswordData {
+                    email: user.email.clone(),
+                    password_plain: password.to_string(),
+                    as_app_user: None,
+                })
+                .send()
+        };
+
+        // Act
+        for _ in 0..2 {
+            login("wrong password", "203.0.113.1")
+                .await
+                .expect("request failed");
+        }
+        let limited = login("wrong password", "203.0.113.1")
+            .await
+            .expect("request failed");
+
+        // Assert
+        assert_eq!(limited.status(), reqwest::StatusCode::TOO_MANY_REQUESTS);
+        for _ in 0..3 {
+            let valid = login(&user.password, "198.51.100.7")
+                .await
+                .expect("request failed");
+            assert!(
+                valid.status().is_success(),
+                "Successful logins should not use up the email limit."
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn app_users_do_not_share_the_app_limit() {
+        // Arrange
+        let mut app = spawn_app_with_settings(|settings| {
+            settings.rate_limit.per_app = RequestLimit {
+                max_requests: 2,
+                period_seconds: 60,
+            };
+        })
+        .await;
+        let admin = TestUser::generate(&app)
+            .await
+            .expect("error when generating test user");
+        let admin_auth = admin
+            .login(&mut app.api_client, None)
+            .await
+            .expect("login failed");
+        let app_id = app
+            .api_client
+            .create_app(&admin_auth.raw_biscuit, "popular app")
+            .await
+            .expect("app creation failed");
+        let greedy = TestUser::generate(&app)
+            .await
+            .expect("error when generating test user")
+            .login(&mut app.api_client, Some(app_id))
+            .await
+            .expect("login failed");
+        let other = TestUser::generate(&app)
+            .await
+            .expect("error when generating test user")
+            .login(&mut app.api_client, Some(app_id))
+            .await
+            .expect("login failed");
+
+        // Act
+        for _ in 0..2 {
+            app.api_client
+                .whoami(&greedy.raw_biscuit)
+                .await
+                .expect("Requests within the limit should be accepted.");
+        }
+        let limited = app.api_client.whoami(&greedy.raw_biscuit).await;
+
+        // Assert
+        assert!(matches!(limited, Err(RequestError::TooManyRequests { .. })));
+        app.api_client
+            .whoami(&other.raw_biscuit)
+            .await
+            .expect("Other users of the app should not be limited.");
+    }
+}
@@ -0,0 +1,169 @@
+mod helper;
+#[cfg(test)]
+mod tests {
+
+    use backpack_server::{
+        biscuit::AUTHENTICATION_TOKEN_TTL, revocation::REVOCATION_LIST_RELOAD_INTERVAL,
+    };
+    use time::{Duration, OffsetDateTime};
+
+    use crate::helper::{spawn_app, TestUser};
+
+    #[tokio::test]
+    async fn revoked_token_is_refused() {
+        // Arrange
+        let mut app = spawn_app().await;
+        let user = TestUser::generate(&app)
+            .await
+            .expect("error when generating test user");
+        let auth = user
+            .login(&mut app.api_client, None)
+            .await
+            .expect("login failed");
+        let other_auth = user
+            .login(&mut app.api_client, None)
+            .await
+            .expect("login failed");
+
+        // Act
+        app.api_client
+            .revoke_token(&other_auth.raw_biscuit, &auth.raw_biscuit)
+            .await
+            .expect("revoking token failed");
+
+        // Assert
+        app.api_client
+            .whoami(&auth.raw_biscuit)
+            .await
+            .expect_err("revoked token should be refused.");
+        app.api_client
+            .whoami(&other_auth.raw_biscuit)
+            .await
+            .expect("other tokens should still be accepted");
+    }
+
+    #[tokio::test]
+    async fn revoking_all_tokens_refuses_them() {
+        // Arrange
+        let mut app = spawn_app().await;
+        let user = TestUser::generate(&app)
+            .await
+            .expect("error when generating test user");
+        let auth = user
+            .login(&mut app.api_client, None)
+            .await
+            .expect("login failed");
+        let other_auth = user
+            .login(&mut app.api_client, None)
+            .await
+            .expect("login failed");
+
+        // Act
+        app.api_client
+            .revoke_all_tokens(&auth.raw_biscuit)
+            .await
+            .expect("revoking tokens failed");
+
+        // Assert
+        for raw_biscuit in [&auth.raw_biscuit, &other_auth.raw_biscuit] {
+            app.api_client
+                .whoami(raw_biscuit)
+                .await
+                .expect_err("revoked token should be refused.");
+        }
+        let new_auth = user
+            .login(&mut app.api_client, None)
+            .await
+            .expect("login failed");
+        app.api_client
+            .whoami(&new_auth.raw_biscuit)
+            .await
+            .expect("new tokens should be accepted");
+    }
+
+    #[tokio::test]
+    async fn admin_revokes_token_of_app_user() {
+        // Arrange
+        let mut app = spawn_app().await;
+        let admin = TestUser::generate(&app)
+            .await
+            .expect("error when generating test user");
+        let admin_auth = admin
+            .login(&mut app.api_client, None)
+            .await
+            .expect("login failed");
+        let app_id = app
+            .api_client
+            .create_app(&admin_auth.raw_biscuit, "mini-game")
+            .await
+            .expect("app creation failed");
+        let user = TestUser::generate(&app)
+            .await
+            .expect("error when generating test user");
+        let user_auth = user
+            .login(&mut app.api_client, Some(app_id))
+            .await
+            .expect("login failed");
+        let other_admin = TestUser::generate(&app)
+            .await
+            .expect("error when generating test user");
+        let other_admin_auth = other_admin
+            .login(&mut app.api_client, None)
+            .await
+            .expect("login failed");
+
+        // Act
+        app.api_client
+            .admin_revoke_token(&other_admin_auth.raw_biscuit, &user_auth.raw_biscuit)
+            .await
+            .expect_err("only admins of the app should revoke its tokens.");
+        app.api_client
+            .admin_revoke_token(&admin_auth.raw_biscuit, &user_auth.raw_biscuit)
+            .await
+            .expect("revoking token failed");
+
+        // Assert
+        app.api_client
+            .whoami(&user_auth.raw_biscuit)
+            .await
+            .expect_err("revoked token should be refused.");
+    }
+
+    #[tokio::test]
+    async fn expired_tokens_are_deleted_on_reload() {
+        // Arrange
+        let mut app = spawn_app().await;
+        let user = TestUser::generate(&app)
+            .await
+            .expect("error when generating test user");
+        let mut time = app.settings.time.clone();
+        let start = OffsetDateTime::now_utc();
+        time.set_override(Some(start));
+        let expired_auth = user
+            .login(&mut app.api_client, None)
+            .await
+            .expect("login failed");
+
+        // Act
+        time.set_override(Some(
+            start + Duration::seconds(AUTHENTICATION_TOKEN_TTL + REVOCATION_LIST_RELOAD_INTERVAL),
+        ));
+        let auth = user
+            .login(&mut app.api_client, None)
+            .await
+            .expect("login failed");
+        app.api_client
+            .whoami(&auth.raw_biscuit)
+            .await
+            .expect("unexpired tokens should be accepted");
+
+        // Assert
+        let (issued,): (i64,) =
+            sqlx::query_as("SELECT COUNT(*) FROM issued_biscuits WHERE user_id = $1")
+                .bind(expired_auth.biscuit_info.user_id.0)
+                .fetch_one(&app.db_pool)
+                .await
+                .expect("failed to count issued biscuits");
+        assert_eq!(issued, 1, "only the unexpired token should be kept");
+    }
+}
@@ -0,0 +1,111 @@
+mod helper;
+#[cfg(test)]
+mod tests {
+
+    use backpack_server::configuration::{
+        get_configuration, RetiredRootKeySettings, RootKeySettings, RootKeysError,
+    };
+    use base64::{engine::general_purpose, Engine as _};
+    use biscuit_auth::KeyPair;
+    use secrecy::Secret;
+
+    use crate::helper::{spawn_app_with_settings, TestUser};
+
+    fn root_key_settings(id: u32, key: &KeyPair) -> RootKeySettings {
+        RootKeySettings {
+            id,
+            private_key: Secret::new(general_purpose::STANDARD.encode(key.private().to_bytes())),
+        }
+    }
+
+    #[tokio::test]
+    async fn root_key_rotation_keeps_issued_biscuits_valid() {
+        // Arrange
+        let (old_key, new_key) = (KeyPair::new(), KeyPair::new());
+        let mut old_app = spawn_app_with_settings(|settings| {
+            settings.root_keys = vec![root_key_settings(0, &old_key)];
+        })
+        .await;
+        let user = TestUser::generate(&old_app)
+            .await
+            .expect("error when generating test user");
+        let old_auth = user
+            .login(&mut old_app.api_client, None)
+            .await
+            .expect("login failed");
+
+        // Act
+        let mut rotated_app = spawn_app_with_settings(|settings| {
+            settings.root_keys = vec![
+                root_key_settings(0, &old_key),
+                root_key_settings(1, &new_key),
+            ];
+        })
+        .await;
+        let retired_app = spawn_app_with_settings(|settings| {
+            settings.root_keys = vec![root_key_settings(1, &new_key)];
+            settings.retired_root_public_keys = vec![RetiredRootKeySettings {
+                id: 0,
+                public_key: general_purpose::STANDARD.encode(old_key.public().to_bytes()),
+            }];
+        })
+        .await;
+        let forgotten_app = spawn_app_with_settings(|settings| {
+            settings.root_keys = vec![root_key_settings(1, &new_key)];
+        })
+        .await;
+
+        // Assert
+        rotated_app
+            .api_client
+            .whoami(&old_auth.raw_biscuit)
+            .await
+            .expect("Biscuits signed by a previous key should be accepted.");
+        retired_app
+            .api_client
+            .whoami(&old_auth.raw_biscuit)
+            .await
+            .expect("Biscuits signed by a retired key should be accepted.");
+        forgotten_app
+            .api_client
+            .whoami(&old_auth.raw_biscuit)
+            .await
+            .expect_err("Biscuits signed by an unknown key should be refused.");
+        let user = TestUser::generate(&rotated_app)
+            .await
+            .expect("error when generating test user");
+        let new_auth = user
+            .login(&mut rotated_app.api_client, None)
+            .await
+            .expect("Biscuits signed by the newest key should be decoded by the client.");
+        forgotten_app
+            .api_client
+            .whoami(&new_auth.raw_biscuit)
+            .await
+            .expect("New biscuits should be signed by the newest key.");
+    }
+
+    #[test]
+    fn production_requires_valid_root_keys() {
+        let mut settings = get_configuration();
+        settings.production = true;
+        settings.root_keys = vec![];
+        settings.retired_root_public_keys = vec![];
+        assert!(matches!(
+            settings.get_root_keys(),
+            Err(RootKeysError::Missing)
+        ));
+
+        settings.production = false;
+        settings.get_root_keys().expect("a key should be generated");
+
+        settings.root_keys = vec![RootKeySettings {
+            id: 3,
+            private_key: Secret::new("not a key".to_string()),
+        }];
+        assert!(matches!(
+            settings.get_root_keys(),
+            Err(RootKeysError::InvalidPrivateKey(3))
+        ));
+    }
+}
@@ -0,0 +1,115 @@
+mod helper;
+#[cfg(test)]
+mod tests {
+
+    use backpack_client::{
+        shared::ItemAmountError, ModifyItemOptions, RequestError, SendItemOptions,
+    };
+
+    use crate::helper::{spawn_app, TestUser};
+
+    #[tokio::test]
+    async fn send_item_refuses_insufficient_balance() {
+        // Arrange
+        let mut app = spawn_app().await;
+        let admin = TestUser::generate(&app)
+            .await
+            .expect("error when generating test user");
+        let admin_auth = admin
+            .login(&mut app.api_client, None)
+            .await
+            .expect("login failed");
+        let app_id = app
+            .api_client
+            .create_app(&admin_auth.raw_biscuit, "mini-game")
+            .await
+            .expect("app creation failed");
+        let item_id = app
+            .api_client
+            .create_item(&admin_auth.raw_biscuit, app_id, "coins")
+            .await
+            .expect("item creation failed");
+        let sender = TestUser::generate(&app)
+            .await
+            .expect("error when generating test user");
+        let sender_auth = sender
+            .login(&mut app.api_client, Some(app_id))
+            .await
+            .expect("login failed");
+        let sender_id = sender_auth.biscuit_info.user_id;
+        let receiver = TestUser::generate(&app)
+            .await
+            .expect("error when generating test user");
+        let receiver_auth = receiver
+            .login(&mut app.api_client, Some(app_id))
+            .await
+            .expect("login failed");
+        let receiver_id = receiver_auth.biscuit_info.user_id;
+        app.api_client
+            .modify_item(
+                &sender_auth.raw_biscuit,
+                item_id,
+                5,
+                sender_id,
+                ModifyItemOptions::default(),
+            )
+            .await
+            .expect("modifying item failed");
+
+        // Act
+        let sender_amount = app
+            .api_client
+            .send_item(
+                &sender_auth.raw_biscuit,
+                item_id,
+                3,
+                sender_id,
+                receiver_id,
+                SendItemOptions::default(),
+            )
+            .await
+            .expect("sending item failed");
+        let refused = app
+            .api_client
+            .send_item(
+                &sender_auth.raw_biscuit,
+                item_id,
+                3,
+                sender_id,
+                receiver_id,
+                SendItemOptions::default(),
+            )
+            .await;
+
+        // Assert
+        assert_eq!(sender_amount, 2);
+        assert!(matches!(
+            refused,
+            Err(RequestError::ItemAmountError(
+                ItemAmountError::InsufficientBalance { balance: 2, .. }
+            ))
+        ));
+        let receiver_items = app
+            .api_client
+            .get_items(&receiver_auth.raw_biscuit, &receiver_id)
+            .await
+            .expect("getting items failed");
+        assert_eq!(receiver_items[0].amount, 3);
+        let sender_items = app
+            .api_client
+            .get_items(&sender_auth.raw_biscuit, &sender_id)
+            .await
+            .expect("getting items failed");
+        assert_eq!(sender_items[0].amount, 2);
+        app.api_client
+            .modify_item(
+                &sender_auth.raw_biscuit,
+                item_id,
+                -3,
+                sender_id,
+                ModifyItemOptions::default(),
+            )
+            .await
+            .expect_err("amounts should not drop below zero.");
+    }
+}
@@ -0,0 +1,239 @@
+mod helper;
+#[cfg(test)]
+mod tests {
+
+    use backpack_client::shared::Role;
+
+    use crate::helper::{spawn_app, TestUser};
+
+    #[tokio::test]
+    async fn list_label_and_revoke_sessions() {
+        // Arrange
+        let mut app = spawn_app().await;
+        let user = TestUser::generate(&app)
+            .await
+            .expect("error when generating test user");
+        let desktop = user
+            .login(&mut app.api_client, None)
+            .await
+            .expect("login failed");
+        let laptop = user
+            .login(&mut app.api_client, None)
+            .await
+            .expect("login failed");
+        let phone = user
+            .login(&mut app.api_client, None)
+            .await
+            .expect("login failed");
+
+        // Act
+        let sessions = app
+            .api_client
+            .get_sessions(&desktop.raw_biscuit)
+            .await
+            .expect("listing sessions failed");
+        assert_eq!(sessions.len(), 3);
+        app.api_client
+            .set_session_label(
+                &desktop.raw_biscuit,
+                sessions[0].id,
+                Some("desktop".to_string()),
+            )
+            .await
+            .expect("labelling session failed");
+        app.api_client
+            .revoke_session(&desktop.raw_biscuit, sessions[2].id)
+            .await
+            .expect("revoking session failed");
+
+        // Assert
+        let sessions = app
+            .api_client
+            .get_sessions(&desktop.raw_biscuit)
+            .await
+            .expect("listing sessions failed");
+        assert_eq!(sessions.len(), 2);
+        assert_eq!(sessions[0].label.as_deref(), Some("desktop"));
+        app.api_client
+            .refresh(&phone.raw_biscuit, &phone.refresh_token)
+            .await
+            .expect_err("Revoked session should not be refreshed.");
+        app.api_client
+            .refresh(&laptop.raw_biscuit, &laptop.refresh_token)
+            .await
+            .expect("Other sessions should still be refreshed.");
+    }
+
+    #[tokio::test]
+    async fn revoke_other_sessions_and_logout() {
+        // Arrange
+        let mut app = spawn_app().await;
+        let user = TestUser::generate(&app)
+            .await
+            .expect("error when generating test user");
+        let current = user
+            .login(&mut app.api_client, None)
+            .await
+            .expect("login failed");
+        let other = user
+            .login(&mut app.api_client, None)
+            .await
+            .expect("login failed");
+
+        // Act
+        app.api_client
+            .revoke_other_sessions(&current.raw_biscuit, &current.refresh_token)
+            .await
+            .expect("revoking other sessions failed");
+
+        // Assert
+        app.api_client
+            .refresh(&other.raw_biscuit, &other.refresh_token)
+            .await
+            .expect_err("Other sessions should be revoked.");
+        let current = app
+            .api_client
+            .refresh(&current.raw_biscuit, &current.refresh_token)
+            .await
+            .expect("Current session should still be refreshed.");
+        app.api_client
+            .logout(&current.raw_biscuit, &current.refresh_token)
+            .await
+            .expect("logout failed");
+        app.api_client
+            .refresh(&current.raw_biscuit, &current.refresh_token)
+            .await
+            .expect_err("Logged out session should not be refreshed.");
+        app.api_client
+            .whoami(&current.raw_biscuit)
+            .await
+            .expect_err("Tokens of the logged out session should be revoked.");
+        let new_session = user
+            .login(&mut app.api_client, None)
+            .await
+            .expect("login failed");
+        let sessions = app
+            .api_client
+            .get_sessions(&new_session.raw_biscuit)
+            .await
+            .expect("listing sessions failed");
+        assert_eq!(sessions.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn revoked_sessions_revoke_their_tokens() {
+        // Arrange
+        let mut app = spawn_app().await;
+        let user = TestUser::generate(&app)
+            .await
+            .expect("error when generating test user");
+        let current = user
+            .login(&mut app.api_client, None)
+            .await
+            .expect("login failed");
+        let revoked = user
+            .login(&mut app.api_client, None)
+            .await
+            .expect("login failed");
+        let other = user
+            .login(&mut app.api_client, None)
+            .await
+            .expect("login failed");
+        let sessions = app
+            .api_client
+            .get_sessions(&current.raw_biscuit)
+            .await
+            .expect("listing sessions failed");
+
+        // Act
+        app.api_client
+            .revoke_session(&current.raw_biscuit, sessions[1].id)
+            .await
+            .expect("revoking session failed");
+        app.api_client
+            .whoami(&other.raw_biscuit)
+            .await
+            .expect("Tokens of other sessions should still be valid.");
+        app.api_client
+            .revoke_other_sessions(&current.raw_biscuit, &current.refresh_token)
+            .await
+            .expect("revoking other sessions failed");
+
+        // Assert
+        app.api_client
+            .whoami(&revoked.raw_biscuit)
+            .await
+            .expect_err("Tokens of a revoked session should be revoked.");
+        app.api_client
+            .whoami(&other.raw_biscuit)
+            .await
+            .expect_err("Tokens of other sessions should be revoked.");
+        app.api_client
+            .whoami(&current.raw_biscuit)
+            .await
+            .expect("Tokens of the current session should still be valid.");
+    }
+
+    #[tokio::test]
+    async fn refreshed_role_comes_from_the_session() {
+        // Arrange
+        let mut app = spawn_app().await;
+        let user = TestUser::generate(&app)
+            .await
+            .expect("error when generating test user");
+        let admin_auth = user
+            .login(&mut app.api_client, None)
+            .await
+            .expect("login failed");
+        let app_id = app
+            .api_client
+            .create_app(&admin_auth.raw_biscuit, "game")
+            .await
+            .expect("app creation failed");
+        let app_auth = user
+            .login(&mut app.api_client, Some(app_id))
+            .await
+            .expect("login failed");
+
+        // Act
+        let refreshed = app
+            .api_client
+            .refresh(&admin_auth.raw_biscuit, &app_auth.refresh_token)
+            .await
+            .expect("refresh failed");
+
+        // Assert
+        assert_eq!(refreshed.biscuit_info.role, Role::User(app_id));
+    }
+
+    #[tokio::test]
+    async fn too_long_session_labels_are_refused() {
+        // Arrange
+        let mut app = spawn_app().await;
+        let user = TestUser::generate(&app)
+            .await
+            .expect("error when generating test user");
+        let auth = user
+            .login(&mut app.api_client, None)
+            .await
+            .expect("login failed");
+        let sessions = app
+            .api_client
+            .get_sessions(&auth.raw_biscuit)
+            .await
+            .expect("listing sessions failed");
+
+        // Act
+        let labelled = app
+            .api_client
+            .set_session_label(&auth.raw_biscuit, sessions[0].id, Some("a".repeat(256)))
+            .await;
+
+        // Assert
+        labelled.expect_err("A label longer than the column should be refused.");
+        app.api_client
+            .set_session_label(&auth.raw_biscuit, sessions[0].id, Some("é".repeat(255)))
+            .await
+            .expect("A label of 255 characters should be accepted.");
+    }
+}
@@ -0,0 +1,366 @@
+mod helper;
+#[cfg(test)]
+mod tests {
+
+    use backpack_client::{
+        shared::{AppPolicy, TwoFactorChallenge, TwoFactorLoginData},
+        RequestError,
+    };
+    use backpack_server::{
+        models::two_factor::MAX_CHALLENGE_FAILED_ATTEMPTS,
+        totp::{self, RECOVERY_CODES_COUNT},
+    };
+    use time::{Duration, OffsetDateTime};
+
+    use crate::helper::{spawn_app, TestApp, TestUser};
+
+    fn code_at(secret: &str, time: OffsetDateTime) -> String {
+        totp::code_at_step(secret, totp::step_at(time.unix_timestamp()))
+            .expect("secret should be base32")
+    }
+
+    async fn two_factor_challenge(app: &mut TestApp, user: &TestUser) -> TwoFactorChallenge {
+        match user.login(&mut app.api_client, None).await {
+            Err(RequestError::TwoFactorRequired(challenge)) => challenge,
+            _ => panic!("Admin login should require a two-factor code."),
+        }
+    }
+
+    /// Enrolls and confirms two-factor authentication at the current time, returns the secret
+    /// and recovery codes.
+    async fn enable_two_factor(
+        app: &mut TestApp,
+        user: &TestUser,
+        now: OffsetDateTime,
+    ) -> (String, Vec<String>) {
+        let admin_auth = user
+            .login(&mut app.api_client, None)
+            .await
+            .expect("login failed");
+        let enrollment = app
+            .api_client
+            .enroll_two_factor(&admin_auth.raw_biscuit)
+            .await
+            .expect("enrolment failed");
+        app.api_client
+            .confirm_two_factor(&admin_auth.raw_biscuit, &code_at(&enrollment.secret, now))
+            .await
+            .expect("confirmation failed");
+        (enrollment.secret, enrollment.recovery_codes)
+    }
+
+    #[tokio::test]
+    async fn admin_login_requires_a_code_once_confirmed() {
+        // Arrange
+        let mut app = spawn_app().await;
+        let mut time = app.settings.time.clone();
+        let start = OffsetDateTime::now_utc();
+        time.set_override(Some(start));
+        let user = TestUser::generate(&app)
+            .await
+            .expect("error when generating test user");
+        let admin_auth = user
+            .login(&mut app.api_client, None)
+            .await
+            .expect("login failed");
+        let enrollment = app
+            .api_client
+            .enroll_two_factor(&admin_auth.raw_biscuit)
+            .await
+            .expect("enrolment failed");
+        assert!(enrollment
+            .provisioning_uri
+            .starts_with("otpauth://totp/Backpack:"));
+        assert_eq!(enrollment.recovery_codes.len(), RECOVERY_CODES_COUNT);
+        user.login(&mut app.api_client, None)
+            .await
+            .expect("Two-factor should not be required before its confirmation.");
+        app.api_client
+            .confirm_two_factor(&admin_auth.raw_biscuit, "not a code")
+            .await
+            .expect_err("Wrong codes should not confirm two-factor authentication.");
+        app.api_client
+            .confirm_two_factor(&admin_auth.raw_biscuit, &code_at(&enrollment.secret, start))
+            .await
+            .expect("confirmation failed");
+
+        // Act
+        let later = start + Duration::seconds(totp::STEP_SECONDS);
+        time.set_override(Some(later));
+        let challenge = two_factor_challenge(&mut app, &user).await;
+        app.api_client
+            .login_two_factor(&TwoFactorLoginData {
+                challenge_token: challenge.challenge_token.clone(),
+                code: "not a code".to_string(),
+            })
+            .await
+            .expect_err("Wrong codes should be refused.");
+        let two_factor_auth = app
+            .api_client
+            .login_two_factor(&TwoFactorLoginData {
+                challenge_token: challenge.challenge_token.clone(),
+                code: code_at(&enrollment.secret, later),
+            })
+            .await
+            .expect("Two-factor login failed.");
+
+        // Assert
+        assert!(two_factor_auth.biscuit_info.two_factor);
+        app.api_client
+            .login_two_factor(&TwoFactorLoginData {
+                challenge_token: challenge.challenge_token,
+                code: code_at(&enrollment.secret, later),
+            })
+            .await
+            .expect_err("Challenges should be single-use.");
+        let new_challenge = two_factor_challenge(&mut app, &user).await;
+        app.api_client
+            .login_two_factor(&TwoFactorLoginData {
+                challenge_token: new_challenge.challenge_token,
+                code: code_at(&enrollment.secret, later),
+            })
+            .await
+            .expect_err("Codes should not be replayed.");
+        let app_id = app
+            .api_client
+            .create_app(&two_factor_auth.raw_biscuit, "test app")
+            .await
+            .expect("app creation failed");
+        user.login(&mut app.api_client, Some(app_id))
+            .await
+            .expect("Logins as app user should not require two-factor authentication.");
+    }
+
+    #[tokio::test]
+    async fn recovery_codes_are_single_use() {
+        // Arrange
+        let mut app = spawn_app().await;
+        let start = OffsetDateTime::now_utc();
+        app.settings.time.clone().set_override(Some(start));
+        let user = TestUser::generate(&app)
+            .await
+            .expect("error when generating test user");
+        let (_, recovery_codes) = enable_two_factor(&mut app, &user, start).await;
+        let first_challenge = two_factor_challenge(&mut app, &user).await;
+
+        // Act
+        let auth = app
+            .api_client
+            .login_two_factor(&TwoFactorLoginData {
+                challenge_token: first_challenge.challenge_token,
+                code: recovery_codes[0].to_lowercase(),
+            })
+            .await
+            .expect("Recovery codes should be accepted, whatever their case.");
+
+        // Assert
+        assert!(auth.biscuit_info.two_factor);
+        let second_challenge = two_factor_challenge(&mut app, &user).await;
+        app.api_client
+            .login_two_factor(&TwoFactorLoginData {
+                challenge_token: second_challenge.challenge_token.clone(),
+                code: recovery_codes[0].clone(),
+            })
+            .await
+            .expect_err("Used recovery codes should be refused.");
+        app.api_client
+            .login_two_factor(&TwoFactorLoginData {
+                challenge_token: second_challenge.challenge_token,
+                code: recovery_codes[1].clone(),
+            })
+            .await
+            .expect("Other recovery codes should still be accepted.");
+    }
+
+    #[tokio::test]
+    async fn challenges_are_refused_after_too_many_wrong_codes() {
+        // Arrange
+        let mut app = spawn_app().await;
+        let start = OffsetDateTime::now_utc();
+        let mut time = app.settings.time.clone();
+        time.set_override(Some(start));
+        let user = TestUser::generate(&app)
+            .await
+            .expect("error when generating test user");
+        let (secret, _) = enable_two_factor(&mut app, &user, start).await;
+        let later = start + Duration::seconds(totp::STEP_SECONDS);
+        time.set_override(Some(later));
+        let challenge = two_factor_challenge(&mut app, &user).await;
+
+        // Act
+        for _ in 0..MAX_CHALLENGE_FAILED_ATTEMPTS {
+            app.api_client
+                .login_two_factor(&TwoFactorLoginData {
+                    challenge_token: challenge.challenge_token.clone(),
+                    code: "not a code".to_string(),
+                })
+                .await
+                .expect_err("Wrong codes should be refused.");
+        }
+
+        // Assert
+        app.api_client
+            .login_two_factor(&TwoFactorLoginData {
+                challenge_token: challenge.challenge_token,
+                code: code_at(&secret, later),
+            })
+            .await
+            .expect_err("The challenge should be refused after too many wrong codes.");
+    }
+
+    #[tokio::test]
+    async fn apps_can_require_two_factor_from_their_admins() {
+        // Arrange
+        let mut app = spawn_app().await;
+        let start = OffsetDateTime::now_utc();
+        let mut time = app.settings.time.clone();
+        time.set_override(Some(start));
+        let user = TestUser::generate(&app)
+            .await
+            .expect("error when generating test user");
+        let password_auth = user
+            .login(&mut app.api_client, None)
+            .await
+            .expect("login failed");
+        let app_id = app
+            .api_client
+            .create_app(&password_auth.raw_biscuit, "strict app")
+            .await
+            .expect("app creation failed");
+        let (secret, _) = enable_two_factor(&mut app, &user, start).await;
+        let later = start + Duration::seconds(totp::STEP_SECONDS);
+        time.set_override(Some(later));
+        // Refreshing doesn't upgrade a login without two-factor authentication.
+        let password_auth = app
+            .api_client
+            .refresh(&password_auth.raw_biscuit, &password_auth.refresh_token)
+            .await
+            .expect("refresh failed");
+        assert!(!password_auth.biscuit_info.two_factor);
+        let challenge = two_factor_challenge(&mut app, &user).await;
+        let two_factor_auth = app
+            .api_client
+            .login_two_factor(&TwoFactorLoginData {
+                challenge_token: challenge.challenge_token,
+                code: code_at(&secret, later),
+            })
+            .await
+            .expect("Two-factor login failed.");
+        let policy = AppPolicy {
+            require_admin_two_factor: true,
+            ..Default::default()
+        };
+        app.api_client
+            .set_app_policy(&password_auth.raw_biscuit, app_id, &policy)
+            .await
+            .expect_err("Requiring two-factor should need a two-factor login.");
+
+        // Act
+        app.api_client
+            .set_app_policy(&two_factor_auth.raw_biscuit, app_id, &policy)
+            .await
+            .expect("setting policy failed");
+
+        // Assert
+        app.api_client
+            .create_app_credentials(&password_auth.raw_biscuit, app_id)
+            .await
+            .expect_err("Admins without two-factor should be refused.");
+        app.api_client
+            .create_app_credentials(&two_factor_auth.raw_biscuit, app_id)
+            .await
+            .expect("Admins with two-factor should be accepted.");
+    }
+
+    #[tokio::test]
+    async fn refreshed_two_factor_comes_from_the_session() {
+        // Arrange
+        let mut app = spawn_app().await;
+        let start = OffsetDateTime::now_utc();
+        let mut time = app.settings.time.clone();
+        time.set_override(Some(start));
+        let user = TestUser::generate(&app)
+            .await
+            .expect("error when generating test user");
+        let password_auth = user
+            .login(&mut app.api_client, None)
+            .await
+            .expect("login failed");
+        let (secret, _) = enable_two_factor(&mut app, &user, start).await;
+        let later = start + Duration::seconds(totp::STEP_SECONDS);
+        time.set_override(Some(later));
+        let challenge = two_factor_challenge(&mut app, &user).await;
+        let two_factor_auth = app
+            .api_client
+            .login_two_factor(&TwoFactorLoginData {
+                challenge_token: challenge.challenge_token,
+                code: code_at(&secret, later),
+            })
+            .await
+            .expect("Two-factor login failed.");
+
+        // Act
+        let refreshed_password_session = app
+            .api_client
+            .refresh(&two_factor_auth.raw_biscuit, &password_auth.refresh_token)
+            .await
+            .expect("refresh failed");
+        let refreshed_two_factor_session = app
+            .api_client
+            .refresh(&password_auth.raw_biscuit, &two_factor_auth.refresh_token)
+            .await
+            .expect("refresh failed");
+
+        // Assert
+        assert!(!refreshed_password_session.biscuit_info.two_factor);
+        assert!(refreshed_two_factor_session.biscuit_info.two_factor);
+    }
+
+    #[tokio::test]
+    async fn disabling_two_factor_needs_a_two_factor_login() {
+        // Arrange
+        let mut app = spawn_app().await;
+        let start = OffsetDateTime::now_utc();
+        let mut time = app.settings.time.clone();
+        time.set_override(Some(start));
+        let user = TestUser::generate(&app)
+            .await
+            .expect("error when generating test user");
+        let password_auth = user
+            .login(&mut app.api_client, None)
+            .await
+            .expect("login failed");
+        let (secret, recovery_codes) = enable_two_factor(&mut app, &user, start).await;
+        let later = start + Duration::seconds(totp::STEP_SECONDS);
+        time.set_override(Some(later));
+        let challenge = two_factor_challenge(&mut app, &user).await;
+        let two_factor_auth = app
+            .api_client
+            .login_two_factor(&TwoFactorLoginData {
+                challenge_token: challenge.challenge_token,
+                code: code_at(&secret, later),
+            })
+            .await
+            .expect("Two-factor login failed.");
+        let password_auth = app
+            .api_client
+            .refresh(&password_auth.raw_biscuit, &password_auth.refresh_token)
+            .await
+            .expect("refresh failed");
+        app.api_client
+            .disable_two_factor(&password_auth.raw_biscuit, &recovery_codes[0])
+            .await
+            .expect_err("Disabling should need a two-factor login.");
+
+        // Act
+        app.api_client
+            .disable_two_factor(&two_factor_auth.raw_biscuit, &recovery_codes[0])
+            .await
+            .expect("disabling failed");
+
+        // Assert
+        user.login(&mut app.api_client, None)
+            .await
+            .expect("Two-factor should not be required anymore.");
+    }
+}
@@ -2,8 +2,10 @@ mod helper;
 #[cfg(test)]
 mod tests {
 
-    use backpack_client::shared::AppId;
-    use backpack_server::biscuit::AUTHENTICATION_TOKEN_TTL;
+    use backpack_client::{shared::RefreshError, RequestError};
+    use backpack_server::{
+        biscuit::AUTHENTICATION_TOKEN_TTL, routes::authentication::auth::CONCURRENT_ROTATION_LEEWAY,
+    };
     use time::OffsetDateTime;
 
     use crate::helper::{spawn_app, TestUser};
@@ -14,7 +16,7 @@ mod tests {
         let mut app = spawn_app().await;
 
         // Act
-        let user = TestUser::generate(&mut app.api_client)
+        let user = TestUser::generate(&app)
             .await
             .expect("error when generating test user");
         let auth_info = user
@@ -23,12 +25,63 @@ mod tests {
             .expect("login failed");
     }
 
+    #[tokio::test]
+    async fn biscuit_decoded_with_public_key_matches_whoami() {
+        // Arrange
+        let mut app = spawn_app().await;
+        let user = TestUser::generate(&app)
+            .await
+            .expect("error when generating test user");
+        let auth_info = user
+            .login(&mut app.api_client, None)
+            .await
+            .expect("login failed");
+
+        // Act
+        let decoded = app
+            .api_client
+            .decode_biscuit(&auth_info.raw_biscuit)
+            .await
+            .expect("decoding biscuit failed");
+        let whoami = app
+            .api_client
+            .whoami(&auth_info.raw_biscuit)
+            .await
+            .expect("whoami failed");
+
+        // Assert
+        assert_eq!(decoded.user_id, whoami.user_id);
+        assert_eq!(decoded.role, whoami.role);
+        assert_eq!(
+            decoded.expiration_date_unix_timestamp,
+            whoami.expiration_date_unix_timestamp
+        );
+        let mut tampered = auth_info.raw_biscuit.clone();
+        let middle = tampered.len() / 2;
+        tampered[middle] = if tampered[middle] == b'A' { b'B' } else { b'A' };
+        app.api_client
+            .decode_biscuit(&tampered)
+            .await
+            .expect_err("tampered biscuit should not be decoded");
+    }
+
     #[tokio::test]
     async fn authentication_and_refresh_token() {
         // Arrange
         let mut app = spawn_app().await;
 
-        let app_id = AppId(0);
+        let user = TestUser::generate(&app)
+            .await
+            .expect("error when generating test user");
+        let admin_auth = user
+            .login(&mut app.api_client, None)
+            .await
+            .expect("login failed");
+        let app_id = app
+            .api_client
+            .create_app(&admin_auth.raw_biscuit, "test app")
+            .await
+            .expect("app creation failed");
 
         let mut time = app.settings.time.clone();
 
@@ -37,9 +90,6 @@ mod tests {
             OffsetDateTime::now_utc()
                 .checked_sub(time::Duration::seconds(AUTHENTICATION_TOKEN_TTL + 200)),
         );
-        let user = TestUser::generate(&mut app.api_client)
-            .await
-            .expect("error when generating test user");
 
         let auth_info = user
             .login(&mut app.api_client, Some(app_id))
@@ -68,4 +118,91 @@ mod tests {
             .await
             .expect_err("Old token should not be usable.");
     }
+
+    #[tokio::test]
+    async fn refresh_token_reuse_revokes_family() {
+        // Arrange
+        let mut app = spawn_app().await;
+        let user = TestUser::generate(&app)
+            .await
+            .expect("error when generating test user");
+        let stolen_auth_info = user
+            .login(&mut app.api_client, None)
+            .await
+            .expect("login failed");
+        let legit_auth_info = app
+            .api_client
+            .refresh(
+                &stolen_auth_info.raw_biscuit,
+                &stolen_auth_info.refresh_token,
+            )
+            .await
+            .expect("Token should have correctly be refreshed.");
+        let other_session = user
+            .login(&mut app.api_client, None)
+            .await
+            .expect("login failed");
+        app.settings.time.clone().set_override(
+            OffsetDateTime::now_utc()
+                .checked_add(time::Duration::seconds(CONCURRENT_ROTATION_LEEWAY + 60)),
+        );
+
+        // Act
+        let reuse = app
+            .api_client
+            .refresh(
+                &stolen_auth_info.raw_biscuit,
+                &stolen_auth_info.refresh_token,
+            )
+            .await;
+        assert!(matches!(
+            reuse,
+            Err(RequestError::RefreshError(RefreshError::Reused))
+        ));
+
+        // Assert
+        app.api_client
+            .refresh(&legit_auth_info.raw_biscuit, &legit_auth_info.refresh_token)
+            .await
+            .expect_err("The whole family should be revoked after a reuse.");
+        app.api_client
+            .refresh(&other_session.raw_biscuit, &other_session.refresh_token)
+            .await
+            .expect("Other logins should not be affected.");
+    }
+
+    #[tokio::test]
+    async fn concurrent_refreshes_rotate_only_once() {
+        // Arrange
+        let mut app = spawn_app().await;
+        let user = TestUser::generate(&app)
+            .await
+            .expect("error when generating test user");
+        let auth_info = user
+            .login(&mut app.api_client, None)
+            .await
+            .expect("login failed");
+
+        // Act
+        let (first, second) = tokio::join!(
+            app.api_client
+                .refresh(&auth_info.raw_biscuit, &auth_info.refresh_token),
+            app.api_client
+                .refresh(&auth_info.raw_biscuit, &auth_info.refresh_token),
+        );
+
+        // Assert
+        let (winner, loser) = match (first, second) {
+            (Ok(winner), loser) | (loser, Ok(winner)) => (winner, loser),
+            _ => panic!("One refresh should have succeeded."),
+        };
+        assert!(matches!(
+            loser,
+            Err(RequestError::RefreshError(RefreshError::ConcurrentRotation))
+        ));
+        app.api_client
+            .refresh(&winner.raw_biscuit, &winner.refresh_token)
+            .await
+            .expect("The losing refresh should not revoke the winner's token.");
+    }
 }
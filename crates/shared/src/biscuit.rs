@@ -0,0 +1,183 @@
+//! Reads [`BiscuitInfo`] from backpack's biscuits.
+//!
+//! Clients can verify biscuits offline with the server public keys, from [`PublicKeysResponse`](crate::PublicKeysResponse).
+
+use std::collections::HashMap;
+
+pub use biscuit_auth::{error, Authorizer, Biscuit, PublicKey, RootKeyProvider};
+
+use biscuit_auth::builder::Term;
+
+use crate::{AppId, BiscuitInfo, ItemId, Role, UserId};
+
+/// Root public keys by id, biscuits carry the id of the root key which signed them.
+///
+/// Biscuits without a key id were issued before root key rotation, by the key `0`.
+#[derive(Debug, Clone, Default)]
+pub struct PublicKeys(pub HashMap<u32, PublicKey>);
+
+impl RootKeyProvider for &PublicKeys {
+    fn choose(&self, key_id: Option<u32>) -> Result<PublicKey, error::Format> {
+        self.0
+            .get(&key_id.unwrap_or(0))
+            .copied()
+            .ok_or(error::Format::UnknownPublicKey)
+    }
+}
+
+fn parse_role(authorizer: &mut Authorizer) -> Result<Role, String> {
+    let admin: Option<Vec<(bool,)>> = authorizer
+        .query("data($is_admin) <- is_admin($is_admin)")
+        .ok();
+    match admin {
+        Some(res) if !res.is_empty() && res[0].0 => Ok(Role::Admin),
+        _ => {
+            let app_service_id: Vec<(String,)> = authorizer
+                .query("data($app_id) <- app_service_id($app_id)")
+                .map_err(|_| "query app_service_id error")?;
+            if let Some(app_service_id) = app_service_id.get(0) {
+                return Ok(Role::AppService(AppId(
+                    app_service_id
+                        .0
+                        .as_str()
+                        .parse::<i32>()
+                        .map_err(|_| "parse error")?,
+                )));
+            }
+            let app_id: Vec<(String,)> = authorizer
+                .query("data($app_id) <- user_app_id($app_id)")
+                .map_err(|_| "query app_id error")?;
+            Ok(Role::User(AppId(
+                app_id
+                    .get(0)
+                    .ok_or("get(0) error")?
+                    .0
+                    .as_str()
+                    .parse::<i32>()
+                    .map_err(|_| "parse error")?,
+            )))
+        }
+    }
+}
+
+pub fn parse_user_id(authorizer: &mut Authorizer) -> Result<UserId, String> {
+    let res: Vec<(String,)> = authorizer
+        .query("data($id) <- user($id)")
+        .map_err(|_| "query error")?;
+    Ok(UserId(
+        res.get(0)
+            .ok_or("get(0) error")?
+            .0
+            .as_str()
+            .parse::<i32>()
+            .map_err(|_| "parse error")?,
+    ))
+}
+
+fn parse_expiration_date(authorizer: &mut Authorizer) -> Result<i64, String> {
+    let res: Vec<(i64,)> = authorizer
+        .query("data($unix_timestamp) <- expiration_date($unix_timestamp)")
+        .map_err(|err| err.to_string())?;
+    Ok(res.get(0).ok_or("get(0) error")?.0)
+}
+
+fn parse_two_factor(authorizer: &mut Authorizer) -> Result<bool, String> {
+    let res: Vec<(bool,)> = authorizer
+        .query("data($two_factor) <- two_factor($two_factor)")
+        .map_err(|err| err.to_string())?;
+    Ok(res.get(0).map_or(false, |res| res.0))
+}
+
+/// Reads the facts of the authority block, signed by the server: call it before
+/// [`Authorizer::authorize`], which loads the facts of attenuated blocks,
+/// or anyone attenuating a biscuit could change its user or role.
+pub fn parse_biscuit_info(authorizer: &mut Authorizer) -> Result<BiscuitInfo, String> {
+    Ok(BiscuitInfo {
+        expiration_date_unix_timestamp: parse_expiration_date(authorizer)?,
+        user_id: parse_user_id(authorizer)?,
+        role: parse_role(authorizer)?,
+        two_factor: parse_two_factor(authorizer)?,
+    })
+}
+
+/// Verifies the biscuit signature then reads its information, without checking its expiration.
+pub fn verify_biscuit_info(token: &[u8], public_keys: &PublicKeys) -> Result<BiscuitInfo, String> {
+    let biscuit = Biscuit::from_base64(token, public_keys).map_err(|err| err.to_string())?;
+    let mut authorizer = biscuit.authorizer().map_err(|err| err.to_string())?;
+    authorizer.allow().map_err(|err| err.to_string())?;
+    parse_biscuit_info(&mut authorizer)
+}
+
+/// Restrictions added to a biscuit by [`attenuate`].
+///
+/// Attenuated biscuits are only accepted on item routes, the server evaluates these checks
+/// with facts about each request: `operation`, `item`, `item_app` and `amount`.
+#[derive(Debug, Clone, Default)]
+pub struct Attenuation {
+    /// Only these items, any item if empty.
+    pub items: Vec<ItemId>,
+    /// Only items of this app.
+    pub app: Option<AppId>,
+    /// Only decrease amounts: negative modifications and sends.
+    pub only_decrement: bool,
+    /// Maximum change of an amount, in absolute value.
+    pub max_amount: Option<i32>,
+    /// Refused after this date, the biscuit own expiration still applies.
+    pub expiration_date_unix_timestamp: Option<u64>,
+}
+
+impl Attenuation {
+    fn checks(&self) -> Vec<String> {
+        let mut checks = vec!["check if operation($operation)".to_string()];
+        if !self.items.is_empty() {
+            let items = self
+                .items
+                .iter()
+                .map(|item_id| format!("item({})", **item_id))
+                .collect::<Vec<_>>()
+                .join(" or ");
+            checks.push(format!("check if {items}"));
+        }
+        if let Some(app_id) = self.app {
+            checks.push(format!(r#"check if item_app("{}")"#, *app_id));
+        }
+        if self.only_decrement {
+            checks
+                .push(r#"check if operation("read") or amount($amount), $amount < 0"#.to_string());
+        }
+        if let Some(max_amount) = self.max_amount {
+            checks.push(format!(
+                r#"check if operation("read") or amount($amount), $amount >= {}, $amount <= {max_amount}"#,
+                -max_amount
+            ));
+        }
+        if let Some(expiration_date) = self.expiration_date_unix_timestamp {
+            checks.push(format!(
+                "check if time($time), $time < {}",
+                Term::Date(expiration_date)
+            ));
+        }
+        checks
+    }
+}
+
+/// Appends a block restricting the biscuit, offline: the attenuated biscuit can be handed
+/// to a game server or a mini-game without giving all the rights of the original one.
+pub fn attenuate(
+    token: &[u8],
+    public_keys: &PublicKeys,
+    attenuation: &Attenuation,
+) -> Result<Vec<u8>, String> {
+    let biscuit = Biscuit::from_base64(token, public_keys).map_err(|err| err.to_string())?;
+    let mut block = biscuit.create_block();
+    for check in attenuation.checks() {
+        block
+            .add_check(check.as_str())
+            .map_err(|err| err.to_string())?;
+    }
+    let attenuated = biscuit.append(block).map_err(|err| err.to_string())?;
+    attenuated
+        .to_base64()
+        .map(String::into_bytes)
+        .map_err(|err| err.to_string())
+}
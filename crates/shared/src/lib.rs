@@ -2,6 +2,9 @@ use std::fmt::Display;
 
 use serde::{Deserialize, Serialize};
 
+#[cfg(feature = "biscuit")]
+pub mod biscuit;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AuthenticationToken {
     pub refresh_token: RefreshToken,
@@ -55,6 +58,17 @@ impl std::ops::Deref for RefreshTokenId {
     }
 }
 
+/// Refresh tokens rotated from a same login share their family.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+pub struct RefreshTokenFamilyId(pub i32);
+impl std::ops::Deref for RefreshTokenFamilyId {
+    type Target = i32;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
 #[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
 pub struct RefreshTokenString(pub String);
 
@@ -84,6 +98,18 @@ impl RefreshToken {
     }
 }
 
+/// Machine-readable reason for a refused refresh, sent as the response body.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+#[serde(tag = "error", rename_all = "snake_case")]
+pub enum RefreshError {
+    /// Another request rotated this refresh token at the same time, only one of them gets a new token.
+    ConcurrentRotation,
+    /// This refresh token was already used, all tokens from the same login are now revoked.
+    Reused,
+    Revoked,
+    Expired,
+}
+
 #[derive(PartialEq, Eq, Debug, Clone, Copy, Serialize, Deserialize)]
 pub struct AppId(pub i32);
 
@@ -101,12 +127,26 @@ pub struct App {
     pub name: String,
 }
 
+/// Rules an app admin can set for the users of their app.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct AppPolicy {
+    /// Users with an unverified email cannot log in as a user of this app.
+    pub require_verified_email: bool,
+    /// Co-admins can only administer this app when logged in with two-factor authentication.
+    #[serde(default)]
+    pub require_admin_two_factor: bool,
+}
+
 #[derive(Clone, PartialEq, Eq, Serialize, Deserialize, Copy, Debug)]
 pub enum Role {
     /// Connected as an admin, still, the user should be admin for the apps to be able to modify admin data.
     Admin,
     /// Connected as a user of a specific app.
     User(AppId),
+    /// Connected as a server of a specific app, through its app credentials.
+    ///
+    /// It can modify items of any user, as long as the app has rights on these items.
+    AppService(AppId),
 }
 impl Display for Role {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -118,7 +158,7 @@ impl Role {
     pub fn to_option(&self) -> Option<AppId> {
         match self {
             Role::User(app_id) => Some(*app_id),
-            Role::Admin => None,
+            Role::Admin | Role::AppService(_) => None,
         }
     }
 }
@@ -128,6 +168,9 @@ pub struct BiscuitInfo {
     pub expiration_date_unix_timestamp: i64,
     pub user_id: UserId,
     pub role: Role,
+    /// Logged in as admin with two-factor authentication.
+    #[serde(default)]
+    pub two_factor: bool,
 }
 
 impl Display for BiscuitInfo {
@@ -157,6 +200,38 @@ pub struct ItemAmount {
     pub amount: i32,
 }
 
+/// Bounds of the amount of an item for each user, set by admins of its app.
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+pub struct ItemBounds {
+    /// Never below zero.
+    pub min_amount: i32,
+    /// No maximum when `None`.
+    pub max_amount: Option<i32>,
+    /// Maximum change of an amount in a single request, in both directions.
+    pub max_change: Option<i32>,
+}
+
+/// Machine-readable reason for a refused change of item amounts, sent as the response body.
+///
+/// Amounts already out of their bounds, after the bounds changed, can still move towards them.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+#[serde(tag = "error", rename_all = "snake_case")]
+pub enum ItemAmountError {
+    /// The amount would drop below the item minimum, zero by default,
+    /// `balance` is the current amount of the user.
+    InsufficientBalance { user_id: UserId, balance: i32 },
+    /// The amount would exceed the item maximum.
+    MaximumExceeded {
+        user_id: UserId,
+        balance: i32,
+        max_amount: i32,
+    },
+    /// The change is larger than the item allows in a single request.
+    ChangeTooLarge { max_change: i32 },
+    /// The amount would not fit in 32 bits.
+    Overflow { user_id: UserId },
+}
+
 // region: request parameters
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
@@ -164,11 +239,6 @@ pub struct CreateEmailPasswordData {
     pub email: String,
 }
 
-#[derive(Debug, Deserialize, Serialize, Clone)]
-pub struct CreatedUserEmailPasswordData {
-    pub id: UserId,
-    pub password: String,
-}
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct LoginEmailPasswordData {
     pub email: String,
@@ -176,7 +246,352 @@ pub struct LoginEmailPasswordData {
     pub as_app_user: Option<AppId>,
 }
 
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct VerifyEmailData {
+    pub email: String,
+    pub code: String,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct ChangePasswordData {
+    pub old_password: String,
+    pub new_password: String,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct ForgotPasswordData {
+    pub email: String,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct ResetPasswordData {
+    /// Token received by email after a [`ForgotPasswordData`] request.
+    pub reset_token: String,
+    pub new_password: String,
+}
+
 #[derive(Deserialize, Serialize)]
 pub struct UserItemModify {
     pub amount: i32,
+    /// Recorded in the item ledger, see [`LedgerEntry`].
+    pub reason: Option<String>,
+    /// Retries of a modification with the same key are not applied again,
+    /// the server answers with the result of the first one.
+    ///
+    /// Can also be sent as an `Idempotency-Key` header.
+    pub idempotency_key: Option<String>,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct UserItemSend {
+    pub amount: i32,
+    pub user_to_send_to: UserId,
+    /// Recorded in the item ledger, see [`LedgerEntry`].
+    pub reason: Option<String>,
+}
+
+/// A change of the amount of an item for a user, part of an [`ItemTransaction`].
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct ItemTransactionOperation {
+    pub item_id: ItemId,
+    pub user_id: UserId,
+    /// Negative to spend.
+    pub amount: i32,
+}
+
+/// Operations applied all together or not at all, such as spending an item to gain another.
+///
+/// The operations on a same item and user are allowed together,
+/// as a modification of their total amount would be.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct ItemTransaction {
+    pub operations: Vec<ItemTransactionOperation>,
+    /// Recorded in the item ledger for each operation, see [`LedgerEntry`].
+    pub reason: Option<String>,
+    /// Retries of a transaction with the same key are not applied again,
+    /// the server answers with the new amounts of the first one.
+    ///
+    /// Can also be sent as an `Idempotency-Key` header.
+    pub idempotency_key: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct LoginAppCredentialsData {
+    pub credentials_id: AppCredentialsId,
+    pub secret: String,
+}
+
+// endregion
+
+// region: app credentials
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+pub struct AppCredentialsId(pub i32);
+
+impl std::ops::Deref for AppCredentialsId {
+    type Target = i32;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+/// Returned only once, on creation: the secret is not stored in plain text.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct CreatedAppCredentials {
+    pub id: AppCredentialsId,
+    pub app_id: AppId,
+    pub secret: String,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct AppCredentials {
+    pub id: AppCredentialsId,
+    pub app_id: AppId,
+    pub created_by: UserId,
+    /// User of the app service authentication tokens, as found in item histories.
+    pub service_user_id: UserId,
+    /// unix timestamp (seconds since 1970)
+    pub created_at_unix_timestamp: i64,
+    pub revoked: bool,
+}
+
+/// Authentication for a game server: there is no refresh token,
+/// the server should log in again with its credentials when the token expires.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct AppServiceAuthenticationResponse {
+    /// unix timestamp (seconds since 1970)
+    pub expiration_date_unix_timestamp: i64,
+    pub auth_token: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AppServiceAuthenticationToken {
+    pub raw_biscuit: Vec<u8>,
+    pub biscuit_info: BiscuitInfo,
+}
+
+// endregion
+
+// region: sessions
+
+/// A login and the refresh tokens rotated from it.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct Session {
+    pub id: RefreshTokenFamilyId,
+    /// `None` for admin sessions.
+    pub app_id: Option<AppId>,
+    /// Set by the user, to recognize their devices.
+    pub label: Option<String>,
+    /// unix timestamp (seconds since 1970)
+    pub created_at_unix_timestamp: i64,
+    /// unix timestamp (seconds since 1970), when its current refresh token expires.
+    pub expiration_date_unix_timestamp: i64,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct SessionLabelData {
+    pub label: Option<String>,
+}
+
+/// Identifies the session of the caller, by proving it owns its refresh token.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct CurrentSessionData {
+    pub refresh_token: RefreshTokenString,
+}
+
+/// An authentication token to revoke, see [`BiscuitInfo`].
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct RevokeTokenData {
+    pub token: String,
+}
+
+// endregion
+
+// region: two-factor authentication
+
+/// Refusal of an admin login with a correct password, for a user with two-factor authentication:
+/// the login is finished by sending a code along the challenge token, through [`TwoFactorLoginData`].
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct TwoFactorChallenge {
+    pub challenge_token: String,
+    /// unix timestamp (seconds since 1970)
+    pub expiration_date_unix_timestamp: i64,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct TwoFactorLoginData {
+    pub challenge_token: String,
+    /// Code from the authenticator app, or one of the recovery codes.
+    pub code: String,
+}
+
+/// Returned only once, on enrolment: it's enabled after a first code is confirmed
+/// through [`TwoFactorCodeData`].
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct TwoFactorEnrollment {
+    /// Base32 TOTP secret, for authenticator apps which can't read the provisioning uri.
+    pub secret: String,
+    /// `otpauth://` uri, usually shown as a QR code.
+    pub provisioning_uri: String,
+    /// Single-use codes to log in when the authenticator app is lost.
+    pub recovery_codes: Vec<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct TwoFactorCodeData {
+    pub code: String,
+}
+
+// endregion
+
+// region: guests
+
+/// Signs up a guest for an app, without any email.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct CreateGuestData {
+    pub app_id: AppId,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct GuestLoginData {
+    pub device_secret: String,
+}
+
+/// The device secret is only returned on signup: it should be stored on the device
+/// to log in again, until the guest attaches an email/password or an identity.
+#[derive(Deserialize, Serialize, Clone)]
+pub struct GuestSignupResponse {
+    pub device_secret: String,
+    pub authentication: AuthenticationResponse,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct GuestSignup {
+    pub device_secret: String,
+    pub authentication_token: AuthenticationToken,
+}
+
+// endregion
+
+// region: login methods
+
+/// A way for a user to log in, users can have several of them.
+#[derive(Debug, PartialEq, Eq, Deserialize, Serialize, Clone)]
+#[serde(tag = "method", rename_all = "snake_case")]
+pub enum LoginMethod {
+    EmailPassword {
+        email: String,
+        is_verified: bool,
+    },
+    /// An identity from a third party identity provider.
+    Oauth {
+        provider: String,
+        external_id: String,
+        login: String,
+    },
+    /// Device secret of a guest, until they attach another login method.
+    Guest {
+        app_id: AppId,
+    },
+}
+
+/// Attaches an identity to the current user, with the code its identity provider gave on callback.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct OauthCodeData {
+    pub code: String,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct DetachEmailPasswordData {
+    pub email: String,
+}
+
+/// Merges the user of this authentication token into the current user.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct MergeUsersData {
+    pub other_auth_token: String,
+}
+
+// endregion
+
+// region: introspection
+
+/// An authentication token to introspect, received from a player by a game server.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct IntrospectTokenData {
+    pub token: String,
+}
+
+/// What an authentication token is and what it allows, without having to trust its holder.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct IntrospectionResponse {
+    /// Signed by the server, not expired, not revoked, and passing its checks:
+    /// attenuated tokens are active while they allow an operation on an item.
+    pub active: bool,
+    /// `None` if the token is not signed by the server.
+    pub biscuit_info: Option<BiscuitInfo>,
+    /// Until the earliest expiration of the token and of its attenuations, `0` once expired.
+    pub expires_in_seconds: i64,
+    /// Items of the apps of the token, empty if it is not active.
+    pub item_rights: Vec<ItemRights>,
+}
+
+/// Operations the token allows on an item: on the items of its user for user tokens,
+/// on the items of any user for app services and admins.
+#[derive(Debug, PartialEq, Eq, Deserialize, Serialize, Clone)]
+pub struct ItemRights {
+    pub item_id: ItemId,
+    pub read: bool,
+    pub increase: bool,
+    pub decrease: bool,
+    pub send: bool,
+}
+
+// endregion
+
+// region: item ledger
+
+/// A change of the amount of an item for a user, entries are never modified.
+#[derive(Debug, PartialEq, Eq, Deserialize, Serialize, Clone)]
+pub struct LedgerEntry {
+    /// Increasing with time, used to page through histories.
+    pub id: i64,
+    pub item_id: ItemId,
+    pub user_id: UserId,
+    /// User of the token which made the change: the user, an app service or an admin.
+    pub actor_user_id: UserId,
+    /// App of the token which made the change, `None` for admin tokens.
+    pub app_id: Option<AppId>,
+    pub delta: i32,
+    /// Amount of the user after the change.
+    pub balance: i32,
+    pub reason: Option<String>,
+    /// unix timestamp (seconds since 1970)
+    pub created_at_unix_timestamp: i64,
+}
+
+/// Entries of a history, most recent first.
+#[derive(Debug, PartialEq, Eq, Deserialize, Serialize, Clone)]
+pub struct LedgerPage {
+    pub entries: Vec<LedgerEntry>,
+    /// To pass as `before` to get the next page, `None` on the last page.
+    pub next_before: Option<i64>,
+}
+
+// endregion
+
+/// A root public key of the server, to verify biscuits without a request to the server.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct RootPublicKey {
+    /// Root key id, carried by the biscuits it signed.
+    pub id: u32,
+    /// Base64 of the key bytes.
+    pub public_key: String,
+}
+
+/// Current and retired root public keys of the server.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct PublicKeysResponse {
+    pub public_keys: Vec<RootPublicKey>,
 }